@@ -211,6 +211,11 @@ fn collect_garbage(st: &mut GcState) {
     }
 }
 
+/// The number of bytes currently allocated by the collector on this thread.
+pub fn bytes_allocated() -> usize {
+    GC_STATE.with(|st| st.borrow().bytes_allocated)
+}
+
 /// Immediately triggers a garbage collection on the current thread.
 ///
 /// This will panic if executed while a collection is currently in progress
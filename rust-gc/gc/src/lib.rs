@@ -33,7 +33,7 @@ mod trace;
 
 // We re-export the Trace method, as well as some useful internal methods for
 // managing collections or configuring the garbage collector.
-pub use gc::{finalizer_safe, force_collect};
+pub use gc::{bytes_allocated, finalizer_safe, force_collect};
 pub use trace::{Finalize, Trace};
 
 ////////
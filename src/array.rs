@@ -0,0 +1,144 @@
+use crate::value::Value;
+use std::collections::BTreeMap;
+
+// writes further than this past the end of the dense part go into the
+// sparse map instead of filling the gap with nulls
+const MAX_DENSE_GAP: usize = 1024;
+
+/// Elements of an array. Indices below `dense.len()` live in a Vec; anything
+/// written far past the end lives in an ordered map until the gap before it
+/// is filled. Missing elements read as null.
+#[derive(Debug, Default, Finalize)]
+pub struct ArrayStorage {
+    dense: Vec<Value>,
+    sparse: BTreeMap<usize, Value>,
+    length: usize,
+}
+
+unsafe impl gc::Trace for ArrayStorage {
+    custom_trace!(this, {
+        mark(&this.dense);
+        mark(&this.sparse);
+    });
+}
+
+impl From<Vec<Value>> for ArrayStorage {
+    fn from(dense: Vec<Value>) -> Self {
+        ArrayStorage {
+            length: dense.len(),
+            dense,
+            sparse: BTreeMap::new(),
+        }
+    }
+}
+
+impl ArrayStorage {
+    pub fn new() -> ArrayStorage {
+        ArrayStorage::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Whether an element was ever written at `index`, as opposed to a hole.
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.dense.len() || self.sparse.contains_key(&index)
+    }
+
+    pub fn get(&self, index: usize) -> Value {
+        match self.dense.get(index) {
+            Some(v) => v.clone(),
+            None => self.sparse.get(&index).cloned().unwrap_or(Value::Null),
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: Value) {
+        if index < self.dense.len() {
+            self.dense[index] = value;
+        } else if index - self.dense.len() <= MAX_DENSE_GAP {
+            while self.dense.len() < index {
+                let hole = self.dense.len();
+                let element = self.sparse.remove(&hole).unwrap_or(Value::Null);
+                self.dense.push(element);
+            }
+            self.dense.push(value);
+            self.sparse.remove(&index);
+            self.absorb_sparse();
+        } else {
+            self.sparse.insert(index, value);
+        }
+        if index >= self.length {
+            self.length = index + 1;
+        }
+    }
+
+    pub fn push(&mut self, value: Value) {
+        let index = self.length;
+        self.set(index, value);
+    }
+
+    pub fn pop(&mut self) -> Option<Value> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        if self.length < self.dense.len() {
+            self.dense.pop()
+        } else {
+            Some(self.sparse.remove(&self.length).unwrap_or(Value::Null))
+        }
+    }
+
+    pub fn last(&self) -> Option<Value> {
+        if self.length == 0 {
+            None
+        } else {
+            Some(self.get(self.length - 1))
+        }
+    }
+
+    /// Setting the length drops every element at or past it.
+    pub fn set_len(&mut self, length: usize) {
+        self.dense.truncate(length);
+        self.sparse.split_off(&length);
+        self.length = length;
+    }
+
+    /// Indices that hold an element, ascending.
+    pub fn indices<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        (0..self.dense.len()).chain(self.sparse.keys().cloned())
+    }
+
+    /// Every element up to the length, with holes as null.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Value> + 'a {
+        (0..self.length).map(move |i| self.get(i))
+    }
+
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.iter().collect()
+    }
+
+    /// Fill in every hole so all elements can be worked on as one slice.
+    pub fn make_dense(&mut self) -> &mut Vec<Value> {
+        if self.dense.len() < self.length {
+            for (index, value) in std::mem::take(&mut self.sparse) {
+                self.dense.resize(index, Value::Null);
+                self.dense.push(value);
+            }
+            self.dense.resize(self.length, Value::Null);
+        }
+        &mut self.dense
+    }
+
+    // move elements that are now contiguous with the dense part into it
+    fn absorb_sparse(&mut self) {
+        while let Some(value) = self.sparse.remove(&self.dense.len()) {
+            self.dense.push(value);
+        }
+    }
+}
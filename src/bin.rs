@@ -1,82 +1,486 @@
-use clap::App;
-use rustyline::{error::ReadlineError, Editor};
-use slither::{disassemble, Agent, Context, Interpreter, Parser, Scope, Value};
+use clap::{App, SubCommand};
+use gc::{Gc, GcCell};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::{error::ReadlineError, Editor, Helper};
+use slither::{
+    disassemble, inspector, repl::Session, tokenize_with_spans, Agent, AuditEvent, Context,
+    ErrorKind, Feature, TokenKind, Value,
+};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::rc::Rc;
 
 fn main() {
     let matches = App::new("slither")
         .version("0.1")
         .args_from_usage(
             r#"
-        [FILENAME]           'File to run'
-        -d, --disassemble    'Print disassembly instead of running'
-        -e, --eval=[code]    'Code to eval inline'
+        [FILENAME]              'File to run, or - to read from stdin'
+        -d, --disassemble       'Print disassembly instead of running'
+        -e, --eval=[code]       'Code to eval inline'
+        -p, --print=[code]      'Code to eval inline, printing the result'
+        --trace-ops             'Report operations still pending at exit'
+        --error-format=[format] 'Print errors as text (default) or json'
+        --feature=[name]...     'Enable an experimental language feature'
+        --audit=[file]          'Append a JSON line to file for every privileged call'
+        --warn-slow-jobs=[ms]   'Warn about jobs that block the event loop for ms or longer'
         "#,
         )
+        .subcommand(
+            SubCommand::with_name("test262")
+                .about("Run a directory of conformance tests")
+                .args_from_usage(
+                    "<DIR>              'Directory of .sl tests'
+                     --update-snapshots 'Rewrite snapshots that differ instead of failing'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doc")
+                .about("Print the documentation of a module")
+                .args_from_usage(
+                    "<FILE>            'Module to document'
+                     --format=[format] 'Print as markdown (default) or json'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctest")
+                .about("Check the `// => expected` examples in doc comments and markdown")
+                .args_from_usage("<PATH>... 'Files or directories of .sl and .md files'"),
+        )
+        .subcommand(
+            SubCommand::with_name("graph")
+                .about("Print the module graph of a program, its cycles and unused exports")
+                .args_from_usage(
+                    "<ENTRY>           'Module to start from'
+                     --format=[format] 'Print as json (default) or dot'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Add a dependency to slither.json and install it")
+                .args_from_usage("<PACKAGE> 'A name, name@version or git+ URL'"),
+        )
+        .subcommand(
+            SubCommand::with_name("install")
+                .about("Install the dependencies in slither.json, as locked by slither.lock"),
+        )
+        .subcommand(
+            SubCommand::with_name("vendor")
+                .about("Copy the dependencies into the project so installs work offline")
+                .args_from_usage("[DIR] 'Directory to copy them to, vendor by default'"),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manage the package cache")
+                .args_from_usage("[ACTION] 'list (default), clean or dir'"),
+        )
         .get_matches();
 
-    let source = if matches.is_present("FILENAME") {
+    if let Some(matches) = matches.subcommand_matches("test262") {
+        let update =
+            matches.is_present("update-snapshots") || slither::snapshot::update_requested();
+        run_conformance(matches.value_of("DIR").unwrap(), update);
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("doc") {
+        print_doc(
+            matches.value_of("FILE").unwrap(),
+            matches.value_of("format"),
+        );
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("doctest") {
+        run_doctests(matches.values_of("PATH").unwrap().collect());
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("graph") {
+        print_graph(
+            matches.value_of("ENTRY").unwrap(),
+            matches.value_of("format"),
+        );
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("add") {
+        run_packages(|config| {
+            let added = slither::package::add(config, matches.value_of("PACKAGE").unwrap())?;
+            println!("added {}@{}", added.name, added.version);
+            Ok(())
+        });
+        return;
+    }
+
+    if matches.subcommand_matches("install").is_some() {
+        run_packages(|config| {
+            for package in slither::package::install(config)? {
+                println!("installed {}@{}", package.name, package.version);
+            }
+            Ok(())
+        });
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("vendor") {
+        run_packages(|config| {
+            let dir = matches.value_of("DIR").unwrap_or("vendor");
+            for package in slither::package::vendor(config, dir)? {
+                println!("vendored {}@{}", package.name, package.version);
+            }
+            Ok(())
+        });
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("cache") {
+        run_packages(|config| match matches.value_of("ACTION") {
+            None | Some("list") => {
+                for (name, version) in slither::package::cached(config)? {
+                    println!("{}@{}", name, version);
+                }
+                Ok(())
+            }
+            Some("clean") => {
+                let removed = slither::package::clean_cache(config)?;
+                println!("removed {} cached packages", removed);
+                Ok(())
+            }
+            Some("dir") => {
+                println!("{}", config.cache.display());
+                Ok(())
+            }
+            Some(other) => Err(format!("unknown cache action '{}'", other)),
+        });
+        return;
+    }
+
+    let mut features = Vec::new();
+    for name in matches.values_of("feature").into_iter().flatten() {
+        match Feature::from_name(name) {
+            Some(f) => features.push(f),
+            None => {
+                let known = Feature::ALL.iter().map(|f| f.name()).collect::<Vec<_>>();
+                eprintln!(
+                    "unknown feature '{}', expected one of: {}",
+                    name,
+                    known.join(", ")
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let source = if matches.value_of("FILENAME") == Some("-") {
+        let mut source = String::new();
+        std::io::stdin().read_to_string(&mut source).unwrap();
+        source
+    } else if matches.is_present("FILENAME") {
         let filename = matches.value_of("FILENAME").unwrap();
         std::fs::read_to_string(filename).unwrap()
-    } else if matches.is_present("eval") {
-        matches.value_of("eval").unwrap().to_string()
+    } else if let Some(code) = matches.value_of("eval").or(matches.value_of("print")) {
+        code.to_string()
     } else {
-        start_repl();
+        start_repl(&features);
         return;
     };
 
+    let options = Options {
+        trace_ops: matches.is_present("trace-ops"),
+        json_errors: match matches.value_of("error-format") {
+            None | Some("text") => false,
+            Some("json") => true,
+            Some(other) => {
+                eprintln!("unknown error format '{}'", other);
+                std::process::exit(1);
+            }
+        },
+        slow_jobs: matches
+            .value_of("warn-slow-jobs")
+            .map(|ms| match ms.parse() {
+                Ok(ms) => std::time::Duration::from_millis(ms),
+                Err(_) => {
+                    eprintln!("--warn-slow-jobs takes a number of ms, not '{}'", ms);
+                    std::process::exit(1);
+                }
+            }),
+    };
+    let audit = matches.value_of("audit");
+
     if matches.is_present("disassemble") {
         disassemble(source.as_str());
-    } else if matches.is_present("eval") {
-        let mut agent = Agent::new();
-        let value = agent.run("eval", source.as_str());
-        agent.run_jobs();
-        match value {
-            Ok(v) => println!("{}", Value::inspect(&agent, &v)),
-            Err(e) => println!("Uncaught Exception: {}", Value::inspect(&agent, &e)),
+    } else if matches.is_present("eval") || matches.is_present("print") {
+        let mut agent = new_agent(options, &features, audit);
+        let promise = match agent.run_async("eval", source.as_str()) {
+            Ok(p) => p,
+            Err(e) => fail(&agent, options, &e, agent.error_kind(&e)),
         };
+        agent.run_jobs();
+        let result = promise.get_slot("result");
+        match promise.get_slot("promise state") {
+            Value::String(ref s) if s == "fulfilled" => {
+                if matches.is_present("print") {
+                    println!("{}", Value::inspect(&agent, &result));
+                }
+            }
+            Value::String(ref s) if s == "rejected" => {
+                fail(&agent, options, &result, ErrorKind::Exception)
+            }
+            _ => {
+                // nothing is left that could settle it
+                eprintln!("Warning: top-level await never resolved");
+                std::process::exit(13);
+            }
+        }
+        if options.trace_ops {
+            report_pending_operations(&agent);
+        }
     } else {
         let filename = matches.value_of("FILENAME").unwrap();
         let referrer = std::env::current_dir().unwrap().join("slither");
         let referrer = referrer.to_str().unwrap();
 
-        let mut agent = Agent::new();
-        agent.import(filename, referrer).unwrap();
+        let mut agent = new_agent(options, &features, audit);
+        // a script read from stdin can't be read again by a daemon
+        if filename != "-" {
+            agent.set_daemon_args(std::env::args_os().skip(1).collect());
+        }
+        let entry = std::env::current_dir().unwrap().join(filename);
+        if let Err(e) = slither::package::apply_permissions(&agent, &entry) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        let result = if filename == "-" {
+            let stdin = std::env::current_dir().unwrap().join("[stdin]");
+            agent.import_source(stdin.to_str().unwrap(), source.as_str())
+        } else {
+            agent.import(filename, referrer)
+        };
+        if let Err(e) = result {
+            fail(&agent, options, &e, agent.error_kind(&e));
+        }
         agent.run_jobs();
+        if options.trace_ops {
+            report_pending_operations(&agent);
+        }
     }
 }
 
-fn start_repl() {
+#[derive(Clone, Copy)]
+struct Options {
+    trace_ops: bool,
+    json_errors: bool,
+    slow_jobs: Option<std::time::Duration>,
+}
+
+fn new_agent(options: Options, features: &[Feature], audit: Option<&str>) -> Agent {
     let mut agent = Agent::new();
+    for feature in features {
+        agent.enable_feature(*feature);
+    }
+    agent.set_slow_job_threshold(options.slow_jobs);
+    if let Some(path) = audit {
+        let file = match std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        agent.set_audit_handler(move |_: &Agent, event: &AuditEvent| {
+            let line = format!("{}\n", event.to_json());
+            // one write per line so concurrent runs don't interleave
+            if let Err(e) = (&file).write_all(line.as_bytes()) {
+                eprintln!("audit log: {}", e);
+            }
+        });
+    }
+    agent.set_uncaught_exception_handler(move |agent: &Agent, e: Value, kind: ErrorKind| {
+        fail(agent, options, &e, kind);
+    });
+    agent
+}
 
-    agent.set_uncaught_exception_handler(|agent: &Agent, v: Value| {
-        println!("Uncaught Exception: {}", Value::inspect(agent, &v));
+// exit codes are distinct per kind so callers can tell failures apart
+fn fail(agent: &Agent, options: Options, e: &Value, kind: ErrorKind) -> ! {
+    if options.json_errors {
+        eprintln!("{}", agent.error_report(e, kind).to_json());
+    } else {
+        let prefix = match kind {
+            ErrorKind::Syntax => "",
+            ErrorKind::Exception => "Uncaught Exception: ",
+            ErrorKind::UnhandledRejection => "Unhandled Rejection: ",
+        };
+        eprintln!("{}{}", prefix, agent.describe_exception(e));
+    }
+    // the process exits with work still pending, report it first
+    if options.trace_ops {
+        report_pending_operations(agent);
+    }
+    std::process::exit(kind.exit_code());
+}
+
+fn report_pending_operations(agent: &Agent) {
+    let ops = agent.pending_operations();
+    eprintln!("{} pending operations at exit", ops.len());
+    for op in ops {
+        eprintln!("  #{} {}", op.id, op.kind);
+        for frame in op.stack {
+            eprintln!("      {}", frame);
+        }
+    }
+}
+
+fn print_doc(filename: &str, format: Option<&str>) {
+    let source = match std::fs::read_to_string(filename) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {}", filename, e);
+            std::process::exit(1);
+        }
+    };
+    let module = match slither::doc::extract(&source) {
+        Ok(module) => module,
+        Err(e) => {
+            eprintln!("{}: SyntaxError: {:?}", filename, e);
+            std::process::exit(1);
+        }
+    };
+    match format {
+        None | Some("markdown") => print!("{}", module.to_markdown(filename)),
+        Some("json") => println!("{}", module.to_json()),
+        Some(other) => {
+            eprintln!("unknown doc format '{}'", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_graph(entry: &str, format: Option<&str>) {
+    let graph = match slither::graph::build(entry) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match format {
+        None | Some("json") => println!("{}", graph.to_json()),
+        Some("dot") => {
+            print!("{}", graph.to_dot());
+            // the json has these as fields
+            for cycle in graph.cycles() {
+                let mut paths = cycle.iter().map(|m| graph.display(*m)).collect::<Vec<_>>();
+                paths.push(paths[0].clone());
+                eprintln!("cycle: {}", paths.join(" -> "));
+            }
+            for (m, name) in graph.unused_exports() {
+                eprintln!("unused export: {} in {}", name, graph.display(m));
+            }
+        }
+        Some(other) => {
+            eprintln!("unknown graph format '{}'", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_packages(f: impl FnOnce(&slither::package::Config) -> Result<(), String>) {
+    let config = slither::package::Config::from_env(std::env::current_dir().unwrap());
+    if let Err(e) = f(&config) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run_conformance(dir: &str, update_snapshots: bool) {
+    let report = match slither::conformance::run_suite(std::path::Path::new(dir), update_snapshots)
+    {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", dir, e);
+            std::process::exit(1);
+        }
+    };
+    for result in &report.results {
+        let status = match (result.passed(), result.expected_failure) {
+            (true, false) => "PASS",
+            (false, true) => "XFAIL",
+            (true, true) => "XPASS",
+            (false, false) => "FAIL",
+        };
+        println!("{:5} {}", status, result.name);
+        if let (Some(failure), false) = (&result.failure, result.expected_failure) {
+            for line in failure.lines() {
+                println!("      {}", line);
+            }
+        }
+    }
+    println!("{}", report.summary());
+    if !report.unexpected().is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn run_doctests(paths: Vec<&str>) {
+    let paths = paths.iter().map(std::path::Path::new).collect::<Vec<_>>();
+    let results = match slither::doctest::run_files(&paths) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for result in &results {
+        let status = if result.passed() { "PASS" } else { "FAIL" };
+        println!("{:5} {}:{}", status, result.file, result.line);
+        if let Some(failure) = &result.failure {
+            for line in failure.lines() {
+                println!("      {}", line);
+            }
+        }
+    }
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    println!("{} examples, {} failed", results.len(), failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn start_repl(features: &[Feature]) {
+    let mut agent = Agent::new();
+    for feature in features {
+        agent.enable_feature(*feature);
+    }
+
+    agent.set_uncaught_exception_handler(|agent: &Agent, v: Value, _| {
+        println!("Uncaught Exception: {}", agent.describe_exception(&v));
     });
 
-    let context = Context::new(Scope::new(Some(agent.root_scope.clone())));
+    let mut session = Session::new(&agent);
+    // shared with the helper, which completes from what is in scope
+    let agent = Rc::new(RefCell::new(agent));
 
-    let mut rl = Editor::<()>::new();
+    let mut rl = Editor::<ReplHelper>::new();
+    rl.set_helper(Some(ReplHelper {
+        agent: agent.clone(),
+        context: session.context().clone(),
+    }));
     loop {
-        let readline = rl.readline(">> ");
+        let readline = rl.readline(session.prompt());
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_ref());
-                let ast = match Parser::parse(&line) {
-                    Ok(a) => a,
-                    Err(e) => match Parser::parse((line + ";").as_str()) {
-                        Ok(a) => a,
-                        Err(_) => {
-                            println!("Uncaught Exception: {:?}", e);
-                            continue;
-                        }
-                    },
-                };
-                let index = agent.assembler.assemble(&ast);
-                let mut interpreter = Interpreter::new(index, context.clone());
-                let value = interpreter.run(&agent).unwrap();
-                agent.run_jobs();
-                match value {
-                    Ok(v) => println!("{}", Value::inspect(&agent, &v)),
-                    Err(e) => println!("Uncaught Exception: {}", Value::inspect(&agent, &e)),
+                if let Some(out) = session.input(&mut agent.borrow_mut(), &line) {
+                    println!("{}", out);
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -94,3 +498,57 @@ fn start_repl() {
         }
     }
 }
+
+// colors the line being typed and completes names in it
+struct ReplHelper {
+    agent: Rc<RefCell<Agent>>,
+    context: Gc<GcCell<Context>>,
+}
+
+impl Helper for ReplHelper {}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let agent = self.agent.borrow();
+        Ok(inspector::complete(&agent, &self.context, line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
+        None
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut end = 0;
+        for token in tokenize_with_spans(line) {
+            out.push_str(&line[end..token.start]);
+            let text = &line[token.start..token.end];
+            match token.kind {
+                TokenKind::Keyword => out.push_str(&format!("\x1b[35m{}\x1b[0m", text)),
+                TokenKind::Literal | TokenKind::Number => {
+                    out.push_str(&format!("\x1b[33m{}\x1b[0m", text))
+                }
+                TokenKind::String => out.push_str(&format!("\x1b[32m{}\x1b[0m", text)),
+                TokenKind::Regex | TokenKind::Invalid => {
+                    out.push_str(&format!("\x1b[31m{}\x1b[0m", text))
+                }
+                TokenKind::Symbol => out.push_str(&format!("\x1b[36m{}\x1b[0m", text)),
+                TokenKind::Comment => out.push_str(&format!("\x1b[90m{}\x1b[0m", text)),
+                _ => out.push_str(text),
+            }
+            end = token.end;
+        }
+        out.push_str(&line[end..]);
+        Cow::Owned(out)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
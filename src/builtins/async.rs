@@ -1,9 +1,950 @@
-use crate::{Agent, Value};
-use std::collections::HashMap;
+use crate::agent::Token;
+use crate::builtins::timers::{clear_timeout, set_timeout};
+use crate::interpreter::Context;
+use crate::intrinsics::promise::{new_promise_capability, promise_resolve_i};
+use crate::value::{ObjectKey, ObjectKind, Value};
+use crate::Agent;
+use gc::{Gc, GcCell};
+use num::ToPrimitive;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+fn number_slot(value: &Value, slot: &str) -> usize {
+    match value.get_slot(slot) {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn then(agent: &Agent, value: Value, on_ok: Value, on_err: Value) -> Result<Value, Value> {
+    let promise = promise_resolve_i(agent, agent.intrinsics.promise.clone(), value)?;
+    promise
+        .get(agent, ObjectKey::from("then"))?
+        .call(agent, promise, vec![on_ok, on_err])
+}
+
+fn pool_settle(agent: &Agent, state: &Value) -> Result<(), Value> {
+    let promise = state.get_slot("pool promise");
+    if state.get_slot("pool failed") == Value::from(true) {
+        return Ok(());
+    }
+    while number_slot(state, "pool running") < number_slot(state, "pool limit")
+        && state.get_slot("pool done") == Value::from(false)
+    {
+        let (iterator, next) = match state.get_slot("pool iterator") {
+            Value::Iterator(pair) => *pair,
+            _ => unreachable!(),
+        };
+        let step = next.call(agent, iterator, vec![]);
+        let step = match step {
+            Ok(step) => step,
+            Err(e) => return pool_fail(agent, state, e),
+        };
+        if step.get(agent, ObjectKey::from("done"))?.to_bool() {
+            state.set_slot("pool done", Value::from(true));
+            break;
+        }
+        let value = step.get(agent, ObjectKey::from("value"))?;
+        let index = number_slot(state, "pool index");
+        state.set_slot("pool index", Value::from((index + 1) as f64));
+        state.set_slot(
+            "pool running",
+            Value::from((number_slot(state, "pool running") + 1) as f64),
+        );
+        if let Value::Object(o) = state.get_slot("pool results") {
+            if let ObjectKind::Array(items) = &o.kind {
+                items.borrow_mut().push(Value::Null);
+            }
+        }
+        let result = state.get_slot("pool fn").call(
+            agent,
+            Value::Null,
+            vec![value, Value::from(index as f64)],
+        );
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => return pool_fail(agent, state, e),
+        };
+        let on_ok = Value::new_builtin_function(agent, pool_fulfilled);
+        on_ok.set_slot("pool state", state.clone());
+        on_ok.set_slot("pool index", Value::from(index as f64));
+        let on_err = Value::new_builtin_function(agent, pool_rejected);
+        on_err.set_slot("pool state", state.clone());
+        then(agent, result, on_ok, on_err)?;
+    }
+    if state.get_slot("pool done") == Value::from(true) && number_slot(state, "pool running") == 0 {
+        promise.get_slot("resolve").call(
+            agent,
+            Value::Null,
+            vec![state.get_slot("pool results")],
+        )?;
+    }
+    Ok(())
+}
+
+fn pool_fail(agent: &Agent, state: &Value, error: Value) -> Result<(), Value> {
+    // tasks already running are left to finish, no new ones are started
+    state.set_slot("pool failed", Value::from(true));
+    state
+        .get_slot("pool promise")
+        .get_slot("reject")
+        .call(agent, Value::Null, vec![error])?;
+    Ok(())
+}
+
+fn pool_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.as_ref().unwrap();
+    let state = f.get_slot("pool state");
+    let index = number_slot(f, "pool index");
+    if let Value::Object(o) = state.get_slot("pool results") {
+        if let ObjectKind::Array(items) = &o.kind {
+            items
+                .borrow_mut()
+                .set(index, args.get(0).cloned().unwrap_or(Value::Null));
+        }
+    }
+    state.set_slot(
+        "pool running",
+        Value::from((number_slot(&state, "pool running") - 1) as f64),
+    );
+    pool_settle(agent, &state)?;
+    Ok(Value::Null)
+}
+
+fn pool_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.as_ref().unwrap().get_slot("pool state");
+    state.set_slot(
+        "pool running",
+        Value::from((number_slot(&state, "pool running") - 1) as f64),
+    );
+    if state.get_slot("pool failed") == Value::from(false) {
+        pool_fail(agent, &state, args.get(0).cloned().unwrap_or(Value::Null))?;
+    }
+    Ok(Value::Null)
+}
+
+fn limit_argument(agent: &Agent, value: Option<&Value>) -> Result<usize, Value> {
+    match value {
+        Some(Value::Number(n)) if *n >= 1.0 && n.fract() == 0.0 => Ok(n.to_usize().unwrap()),
+        _ => Err(Value::new_error(agent, "limit must be a positive integer")),
+    }
+}
+
+/// Call `f(item, index)` for every item with at most `limit` calls pending
+/// at once, resolving with the results in order.
+fn pool(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let limit = limit_argument(agent, args.get(0))?;
+    let iterator = args.get(1).unwrap_or(&Value::Null).to_iterator(agent)?;
+    let f = args.get(2).unwrap_or(&Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+
+    // slots only live on custom objects
+    let state = Value::new_custom_object(Value::Null);
+    state.set_slot("pool promise", promise.clone());
+    state.set_slot("pool iterator", iterator);
+    state.set_slot("pool fn", f.clone());
+    state.set_slot("pool results", Value::new_array(agent));
+    state.set_slot("pool limit", Value::from(limit as f64));
+    state.set_slot("pool running", Value::from(0.0));
+    state.set_slot("pool index", Value::from(0.0));
+    state.set_slot("pool done", Value::from(false));
+    state.set_slot("pool failed", Value::from(false));
+    pool_settle(agent, &state)?;
+
+    Ok(promise)
+}
+
+fn get_semaphore(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("semaphore permits") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn acquire(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_semaphore(agent, ctx)?;
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    let permits = number_slot(&this, "semaphore permits");
+    if permits > 0 {
+        this.set_slot("semaphore permits", Value::from((permits - 1) as f64));
+        promise
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![])?;
+    } else if let Value::List(waiters) = this.get_slot("semaphore waiters") {
+        waiters.borrow_mut().push_back(promise.clone());
+    }
+    Ok(promise)
+}
+
+fn release_permit(agent: &Agent, this: &Value) -> Result<(), Value> {
+    let next = match this.get_slot("semaphore waiters") {
+        Value::List(waiters) => waiters.borrow_mut().pop_front(),
+        _ => unreachable!(),
+    };
+    match next {
+        // handed straight to the next waiter
+        Some(promise) => {
+            promise
+                .get_slot("resolve")
+                .call(agent, Value::Null, vec![])?;
+        }
+        None => {
+            let permits = number_slot(this, "semaphore permits");
+            if permits == number_slot(this, "semaphore limit") {
+                return Err(Value::new_error(agent, "released more times than acquired"));
+            }
+            this.set_slot("semaphore permits", Value::from((permits + 1) as f64));
+        }
+    }
+    Ok(())
+}
+
+fn release(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_semaphore(agent, ctx)?;
+    release_permit(agent, &this)?;
+    Ok(Value::Null)
+}
+
+fn run_acquired(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.as_ref().unwrap();
+    let this = f.get_slot("semaphore");
+    let result = match f.get_slot("semaphore fn").call(agent, Value::Null, vec![]) {
+        Ok(result) => result,
+        Err(e) => {
+            release_permit(agent, &this)?;
+            return Err(e);
+        }
+    };
+    let on_ok = Value::new_builtin_function(agent, run_fulfilled);
+    on_ok.set_slot("semaphore", this.clone());
+    let on_err = Value::new_builtin_function(agent, run_rejected);
+    on_err.set_slot("semaphore", this);
+    then(agent, result, on_ok, on_err)
+}
+
+fn run_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    release_permit(agent, &ctx.function.as_ref().unwrap().get_slot("semaphore"))?;
+    Ok(args.get(0).cloned().unwrap_or(Value::Null))
+}
+
+fn run_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    release_permit(agent, &ctx.function.as_ref().unwrap().get_slot("semaphore"))?;
+    Err(args.get(0).cloned().unwrap_or(Value::Null))
+}
+
+// acquire, call f, and release once whatever it returns has settled
+fn run(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_semaphore(agent, ctx)?;
+    let f = args.get(0).unwrap_or(&Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    let acquired = acquire(agent, vec![], ctx)?;
+    let on_acquired = Value::new_builtin_function(agent, run_acquired);
+    on_acquired.set_slot("semaphore", this);
+    on_acquired.set_slot("semaphore fn", f.clone());
+    acquired
+        .get(agent, ObjectKey::from("then"))?
+        .call(agent, acquired, vec![on_acquired])
+}
+
+fn available(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_semaphore(agent, ctx)?;
+    Ok(Value::from(number_slot(&this, "semaphore permits") as f64))
+}
+
+fn new_semaphore(agent: &Agent, ctx: &Context, permits: usize) -> Result<Value, Value> {
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let semaphore = Value::new_custom_object(proto);
+    semaphore.set_slot("semaphore permits", Value::from(permits as f64));
+    semaphore.set_slot("semaphore limit", Value::from(permits as f64));
+    semaphore.set_slot("semaphore waiters", Value::new_list());
+    Ok(semaphore)
+}
+
+fn semaphore(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let permits = limit_argument(agent, args.get(0))?;
+    new_semaphore(agent, ctx, permits)
+}
+
+fn mutex(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    new_semaphore(agent, ctx, 1)
+}
+
+pub fn new_abort_signal(agent: &Agent) -> Result<Value, Value> {
+    let signal = Value::new_custom_object(agent.intrinsics.object_prototype.clone());
+    signal.set_slot("abort pending", Value::new_list());
+    signal.set(agent, ObjectKey::from("aborted"), Value::from(false))?;
+    signal.set(agent, ObjectKey::from("reason"), Value::Null)?;
+    Ok(signal)
+}
+
+/// Mark the signal aborted and reject everything waiting on it.
+pub fn abort_signal(agent: &Agent, signal: &Value, reason: Value) -> Result<(), Value> {
+    if signal.get(agent, ObjectKey::from("aborted"))?.to_bool() {
+        return Ok(());
+    }
+    signal.set(agent, ObjectKey::from("aborted"), Value::from(true))?;
+    signal.set(agent, ObjectKey::from("reason"), reason.clone())?;
+    if let Value::List(pending) = signal.get_slot("abort pending") {
+        let pending = pending.borrow_mut().drain(..).collect::<Vec<Value>>();
+        for promise in pending {
+            #[cfg(feature = "os")]
+            crate::builtins::http::abort_request(&promise);
+            promise
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![reason.clone()])?;
+        }
+    }
+    Ok(())
+}
+
+fn signal_option(agent: &Agent, options: &Value) -> Result<Value, Value> {
+    if options.type_of() != "object" {
+        return Ok(Value::Null);
+    }
+    let signal = options.get(agent, ObjectKey::from("signal"))?;
+    if signal != Value::Null && !signal.has_slot("abort pending") {
+        return Err(Value::new_error(agent, "signal must be an AbortSignal"));
+    }
+    Ok(signal)
+}
+
+// rejects the promise with the signal's reason, now or when it aborts
+fn watch_signal(agent: &Agent, signal: &Value, promise: &Value) -> Result<bool, Value> {
+    if *signal == Value::Null {
+        return Ok(false);
+    }
+    if signal.get(agent, ObjectKey::from("aborted"))?.to_bool() {
+        let reason = signal.get(agent, ObjectKey::from("reason"))?;
+        promise
+            .get_slot("reject")
+            .call(agent, Value::Null, vec![reason])?;
+        return Ok(true);
+    }
+    if let Value::List(pending) = signal.get_slot("abort pending") {
+        pending.borrow_mut().push_back(promise.clone());
+    }
+    Ok(false)
+}
+
+fn unwatch_signal(promise: &Value) {
+    if !promise.has_slot("async signal") {
+        return;
+    }
+    if let Value::List(pending) = promise.get_slot("async signal").get_slot("abort pending") {
+        pending.borrow_mut().retain(|p| p != promise);
+    }
+}
+
+fn timed_out(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.as_ref().unwrap();
+    let promise = f.get_slot("async promise");
+    let (slot, value) = match f.get_slot("async outcome") {
+        Value::Tuple(t) => (t[0].clone(), t[1].clone()),
+        _ => unreachable!(),
+    };
+    let signal = f.get_slot("async timeout signal");
+    if signal != Value::Null {
+        abort_signal(agent, &signal, value.clone())?;
+    }
+    let slot = if slot == Value::from(true) {
+        "resolve"
+    } else {
+        "reject"
+    };
+    promise
+        .get_slot(slot)
+        .call(agent, Value::Null, vec![value])?;
+    Ok(Value::Null)
+}
+
+macro_rules! settled {
+    ($name:ident, $slot:expr) => {
+        // the timer would only keep the event loop alive
+        fn $name(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+            let f = ctx.function.as_ref().unwrap();
+            if let Value::Number(n) = f.get_slot("async timer") {
+                clear_timeout(agent, Token(n.to_usize().unwrap()));
+            }
+            let promise = f.get_slot("async promise");
+            unwatch_signal(&promise);
+            promise.get_slot($slot).call(agent, Value::Null, args)
+        }
+    };
+}
+
+settled!(timeout_fulfilled, "resolve");
+settled!(timeout_rejected, "reject");
+
+/// Settle with `value` unless `duration` passes first, in which case the
+/// outcome is used instead and `signal`, if any, is aborted.
+pub fn with_timeout(
+    agent: &Agent,
+    value: Value,
+    duration: Duration,
+    outcome: Result<Value, Value>,
+    signal: Value,
+) -> Result<Value, Value> {
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    let (fulfill, outcome) = match outcome {
+        Ok(v) => (true, v),
+        Err(e) => (false, e),
+    };
+    let on_timeout = Value::new_builtin_function(agent, timed_out);
+    on_timeout.set_slot("async promise", promise.clone());
+    on_timeout.set_slot(
+        "async outcome",
+        Value::Tuple(Box::new(vec![Value::from(fulfill), outcome])),
+    );
+    on_timeout.set_slot("async timeout signal", signal);
+    let timer = Value::from(set_timeout(agent, on_timeout, duration).0 as f64);
+
+    let on_ok = Value::new_builtin_function(agent, timeout_fulfilled);
+    let on_err = Value::new_builtin_function(agent, timeout_rejected);
+    for f in &[&on_ok, &on_err] {
+        f.set_slot("async promise", promise.clone());
+        f.set_slot("async timer", timer.clone());
+    }
+    // whichever settles first wins, the other is ignored
+    then(agent, value, on_ok, on_err)?;
+    Ok(promise)
+}
+
+fn duration_argument(agent: &Agent, value: Option<&Value>) -> Result<Duration, Value> {
+    match value {
+        Some(Value::Number(n)) if *n >= 0.0 => Ok(Duration::from_millis(n.to_u64().unwrap())),
+        _ => Err(Value::new_error(agent, "ms must be a positive number")),
+    }
+}
+
+/// timeout(promise, ms[, { signal }])
+fn timeout(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).cloned().unwrap_or(Value::Null);
+    let duration = duration_argument(agent, args.get(1))?;
+    let signal = signal_option(agent, args.get(2).unwrap_or(&Value::Null))?;
+    let error = Value::new_error(
+        agent,
+        &format!("timed out after {}ms", duration.as_millis()),
+    );
+    let promise = with_timeout(agent, value, duration, Err(error), Value::Null)?;
+    if !watch_signal(agent, &signal, &promise)? && signal != Value::Null {
+        promise.set_slot("async signal", signal);
+    }
+    Ok(promise)
+}
+
+/// deadline(ms, f) calls f with an AbortSignal that aborts after ms,
+/// rejecting if f's result has not settled by then.
+fn deadline(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let duration = duration_argument(agent, args.get(0))?;
+    let f = args.get(1).unwrap_or(&Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    let signal = new_abort_signal(agent)?;
+    let value = match f.call(agent, Value::Null, vec![signal.clone()]) {
+        Ok(v) => v,
+        Err(e) => {
+            let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+            promise
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![e])?;
+            return Ok(promise);
+        }
+    };
+    let error = Value::new_error(agent, "deadline exceeded");
+    with_timeout(agent, value, duration, Err(error), signal)
+}
+
+#[cfg(feature = "os")]
+fn random(_: &Agent) -> f64 {
+    rand::random::<f64>()
+}
+
+// without `os` the host supplies randomness
+#[cfg(not(feature = "os"))]
+fn random(agent: &Agent) -> f64 {
+    agent.host().random()
+}
+
+struct RetryOptions {
+    attempts: f64,
+    backoff: f64,
+    factor: f64,
+    max_delay: f64,
+    jitter: f64,
+}
+
+fn retry_options(agent: &Agent, options: &Value) -> Result<RetryOptions, Value> {
+    let mut result = RetryOptions {
+        attempts: 3.0,
+        backoff: 100.0,
+        factor: 2.0,
+        max_delay: 30_000.0,
+        jitter: 0.0,
+    };
+    if *options == Value::Null {
+        return Ok(result);
+    }
+    if options.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+    macro_rules! number {
+        ($name:expr, $field:ident, $valid:expr) => {
+            match options.get(agent, ObjectKey::from($name))? {
+                Value::Null => {}
+                Value::Number(n) if $valid(n) => result.$field = n,
+                _ => {
+                    return Err(Value::new_error(
+                        agent,
+                        concat!($name, " must be a positive number"),
+                    ))
+                }
+            }
+        };
+    }
+    number!("attempts", attempts, |n: f64| n >= 1.0);
+    number!("backoff", backoff, |n: f64| n >= 0.0);
+    number!("factor", factor, |n: f64| n >= 1.0);
+    number!("maxDelay", max_delay, |n: f64| n >= 0.0);
+    result.jitter = match options.get(agent, ObjectKey::from("jitter"))? {
+        Value::Null | Value::Boolean(false) => 0.0,
+        Value::Boolean(true) => 1.0,
+        Value::Number(n) if (0.0..=1.0).contains(&n) => n,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "jitter must be a boolean or a number between 0 and 1",
+            ))
+        }
+    };
+    Ok(result)
+}
+
+fn retry_attempt(agent: &Agent, state: &Value) -> Result<(), Value> {
+    let signal = state.get_slot("retry signal");
+    if signal != Value::Null && signal.get(agent, ObjectKey::from("aborted"))?.to_bool() {
+        // already rejected by the signal
+        return Ok(());
+    }
+    let attempt = number_slot(state, "retry attempt") + 1;
+    state.set_slot("retry attempt", Value::from(attempt as f64));
+    let on_ok = Value::new_builtin_function(agent, retry_fulfilled);
+    on_ok.set_slot("retry state", state.clone());
+    let on_err = Value::new_builtin_function(agent, retry_rejected);
+    on_err.set_slot("retry state", state.clone());
+    match state
+        .get_slot("retry fn")
+        .call(agent, Value::Null, vec![Value::from(attempt as f64)])
+    {
+        Ok(v) => {
+            then(agent, v, on_ok, on_err)?;
+        }
+        Err(e) => {
+            on_err.call(agent, Value::Null, vec![e])?;
+        }
+    }
+    Ok(())
+}
+
+fn retry_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.as_ref().unwrap().get_slot("retry state");
+    let promise = state.get_slot("retry promise");
+    unwatch_signal(&promise);
+    promise.get_slot("resolve").call(agent, Value::Null, args)?;
+    Ok(Value::Null)
+}
+
+fn retry_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.as_ref().unwrap().get_slot("retry state");
+    let promise = state.get_slot("retry promise");
+    let error = args.get(0).cloned().unwrap_or(Value::Null);
+    let attempt = number_slot(&state, "retry attempt");
+    let give_up = attempt >= number_slot(&state, "retry attempts") || {
+        let should = state.get_slot("retry if");
+        should != Value::Null
+            && !should
+                .call(
+                    agent,
+                    Value::Null,
+                    vec![error.clone(), Value::from(attempt as f64)],
+                )?
+                .to_bool()
+    };
+    if give_up {
+        unwatch_signal(&promise);
+        promise
+            .get_slot("reject")
+            .call(agent, Value::Null, vec![error])?;
+        return Ok(Value::Null);
+    }
+    let delay = match state.get_slot("retry delay") {
+        Value::Tuple(t) => match (&t[0], &t[1], &t[2], &t[3]) {
+            (
+                Value::Number(backoff),
+                Value::Number(factor),
+                Value::Number(max),
+                Value::Number(jitter),
+            ) => {
+                let delay = (backoff * factor.powi(attempt as i32 - 1)).min(*max);
+                delay * (1.0 - jitter * random(agent))
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    let again = Value::new_builtin_function(agent, retry_again);
+    again.set_slot("retry state", state);
+    set_timeout(agent, again, Duration::from_millis(delay as u64));
+    Ok(Value::Null)
+}
+
+fn retry_again(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.as_ref().unwrap().get_slot("retry state");
+    retry_attempt(agent, &state)?;
+    Ok(Value::Null)
+}
+
+/// retry(f, { attempts, backoff, factor, maxDelay, jitter, retryIf, signal })
+/// calls f(attempt) until its result fulfills, waiting exponentially longer
+/// between attempts.
+fn retry(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let f = args.get(0).unwrap_or(&Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    let options = args.get(1).unwrap_or(&Value::Null);
+    let o = retry_options(agent, options)?;
+    let signal = signal_option(agent, options)?;
+    let retry_if = if options.type_of() == "object" {
+        options.get(agent, ObjectKey::from("retryIf"))?
+    } else {
+        Value::Null
+    };
+    if retry_if != Value::Null && retry_if.type_of() != "function" {
+        return Err(Value::new_error(agent, "retryIf must be a function"));
+    }
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    if watch_signal(agent, &signal, &promise)? {
+        return Ok(promise);
+    }
+    if signal != Value::Null {
+        promise.set_slot("async signal", signal.clone());
+    }
+
+    let state = Value::new_custom_object(Value::Null);
+    state.set_slot("retry promise", promise.clone());
+    state.set_slot("retry fn", f.clone());
+    state.set_slot("retry if", retry_if);
+    state.set_slot("retry signal", signal);
+    state.set_slot("retry attempt", Value::from(0.0));
+    state.set_slot("retry attempts", Value::from(o.attempts.floor()));
+    state.set_slot(
+        "retry delay",
+        Value::Tuple(Box::new(vec![
+            Value::from(o.backoff),
+            Value::from(o.factor),
+            Value::from(o.max_delay),
+            Value::from(o.jitter),
+        ])),
+    );
+    retry_attempt(agent, &state)?;
+    Ok(promise)
+}
+
+fn get_channel(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("channel buffer") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn channel_list(channel: &Value, slot: &str) -> Gc<GcCell<VecDeque<Value>>> {
+    match channel.get_slot(slot) {
+        Value::List(list) => list,
+        _ => unreachable!(),
+    }
+}
+
+fn settled(agent: &Agent, slot: &str, value: Value) -> Result<Value, Value> {
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    promise
+        .get_slot(slot)
+        .call(agent, Value::Null, vec![value])?;
+    Ok(promise)
+}
+
+fn closed_error(agent: &Agent) -> Value {
+    Value::new_error(agent, "channel is closed")
+}
+
+/// Resolves once the value is buffered or handed to a receiver.
+fn send(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_channel(agent, ctx)?;
+    let value = args.get(0).cloned().unwrap_or(Value::Null);
+    if this.get_slot("channel closed") == Value::from(true) {
+        return settled(agent, "reject", closed_error(agent));
+    }
+    let receiver = channel_list(&this, "channel receivers")
+        .borrow_mut()
+        .pop_front();
+    if let Some(receiver) = receiver {
+        let result = Value::new_iter_result(agent, value, false)?;
+        receiver
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![result])?;
+        return settled(agent, "resolve", Value::Null);
+    }
+    let buffer = channel_list(&this, "channel buffer");
+    if buffer.borrow().len() < number_slot(&this, "channel capacity") {
+        buffer.borrow_mut().push_back(value);
+        return settled(agent, "resolve", Value::Null);
+    }
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    channel_list(&this, "channel senders")
+        .borrow_mut()
+        .push_back(Value::Tuple(Box::new(vec![value, promise.clone()])));
+    Ok(promise)
+}
+
+// the next value as an iterator result, done once closed and drained
+fn channel_next(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_channel(agent, ctx)?;
+    let buffer = channel_list(&this, "channel buffer");
+    let sender = channel_list(&this, "channel senders")
+        .borrow_mut()
+        .pop_front();
+    let value = match sender {
+        Some(Value::Tuple(sender)) => {
+            sender[1]
+                .get_slot("resolve")
+                .call(agent, Value::Null, vec![Value::Null])?;
+            // keep the order senders arrived in
+            let mut buffer = buffer.borrow_mut();
+            buffer.push_back(sender[0].clone());
+            buffer.pop_front()
+        }
+        _ => buffer.borrow_mut().pop_front(),
+    };
+    if let Some(value) = value {
+        let result = Value::new_iter_result(agent, value, false)?;
+        return settled(agent, "resolve", result);
+    }
+    if this.get_slot("channel closed") == Value::from(true) {
+        let result = Value::new_iter_result(agent, Value::Null, true)?;
+        return settled(agent, "resolve", result);
+    }
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    channel_list(&this, "channel receivers")
+        .borrow_mut()
+        .push_back(promise.clone());
+    Ok(promise)
+}
+
+fn received(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let result = args.get(0).unwrap_or(&Value::Null);
+    if result.get(agent, ObjectKey::from("done"))?.to_bool() {
+        Err(closed_error(agent))
+    } else {
+        result.get(agent, ObjectKey::from("value"))
+    }
+}
+
+/// Like next but resolves with the value, rejecting once closed and drained.
+fn receive(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let result = channel_next(agent, args, ctx)?;
+    result.get(agent, ObjectKey::from("then"))?.call(
+        agent,
+        result,
+        vec![Value::new_builtin_function(agent, received)],
+    )
+}
+
+/// Buffered values can still be received, blocked senders are rejected.
+fn close(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_channel(agent, ctx)?;
+    if this.get_slot("channel closed") == Value::from(true) {
+        return Ok(Value::Null);
+    }
+    this.set_slot("channel closed", Value::from(true));
+    this.set(agent, ObjectKey::from("closed"), Value::from(true))?;
+    let receivers = channel_list(&this, "channel receivers")
+        .borrow_mut()
+        .drain(..)
+        .collect::<Vec<Value>>();
+    for receiver in receivers {
+        let result = Value::new_iter_result(agent, Value::Null, true)?;
+        receiver
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![result])?;
+    }
+    let senders = channel_list(&this, "channel senders")
+        .borrow_mut()
+        .drain(..)
+        .collect::<Vec<Value>>();
+    for sender in senders {
+        if let Value::Tuple(sender) = sender {
+            sender[1]
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![closed_error(agent)])?;
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// new Channel(capacity = 0), an unbuffered channel hands each value
+/// directly from a sender to a receiver.
+fn channel(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let capacity = match args.get(0) {
+        None | Some(Value::Null) => 0,
+        Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => n.to_usize().unwrap(),
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "capacity must be a non-negative integer",
+            ))
+        }
+    };
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let channel = Value::new_custom_object(proto);
+    channel.set_slot("channel capacity", Value::from(capacity as f64));
+    channel.set_slot("channel closed", Value::from(false));
+    channel.set_slot("channel buffer", Value::new_list());
+    channel.set_slot("channel senders", Value::new_list());
+    channel.set_slot("channel receivers", Value::new_list());
+    channel.set(agent, ObjectKey::from("closed"), Value::from(false))?;
+    Ok(channel)
+}
+
+fn get_storage(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("async local storage") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+/// Call `f(...args)` with `store` as the current store, it stays current in
+/// promise reactions and timers started inside, including across awaits.
+fn storage_run(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_storage(agent, ctx)?;
+    let mut args = args.into_iter();
+    let store = args.next().unwrap_or(Value::Null);
+    let f = args.next().unwrap_or(Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    agent.with_async_store(this, store, || f.call(agent, Value::Null, args.collect()))
+}
+
+fn get_store(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_storage(agent, ctx)?;
+    Ok(agent.async_store(&this))
+}
+
+fn async_local_storage(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let storage = Value::new_custom_object(proto);
+    storage.set_slot("async local storage", Value::from(true));
+    Ok(storage)
+}
+
+macro_rules! constructor {
+    ($agent:expr, $constructor:ident, { $($name:expr => $fn:ident),* }) => {
+        constructor!($agent, $constructor, $agent.intrinsics.object_prototype, {
+            $($name => $fn),*
+        })
+    };
+    ($agent:expr, $constructor:ident, $base:expr, { $($name:expr => $fn:ident),* }) => {{
+        let c = Value::new_builtin_function($agent, $constructor);
+        let proto = Value::new_object($base.clone());
+        $(
+            proto
+                .set(
+                    $agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function($agent, $fn),
+                )
+                .unwrap();
+        )*
+        proto
+            .set($agent, ObjectKey::from("constructor"), c.clone())
+            .unwrap();
+        c.set($agent, ObjectKey::from("prototype"), proto).unwrap();
+        c
+    }};
+}
 
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
     module.insert("Promise".to_string(), agent.intrinsics.promise.clone());
+    module.insert("pool".to_string(), Value::new_builtin_function(agent, pool));
+    module.insert(
+        "retry".to_string(),
+        Value::new_builtin_function(agent, retry),
+    );
+    module.insert(
+        "timeout".to_string(),
+        Value::new_builtin_function(agent, timeout),
+    );
+    module.insert(
+        "deadline".to_string(),
+        Value::new_builtin_function(agent, deadline),
+    );
+    module.insert(
+        "Semaphore".to_string(),
+        constructor!(agent, semaphore, {
+            "acquire" => acquire,
+            "release" => release,
+            "run" => run,
+            "available" => available
+        }),
+    );
+    module.insert(
+        "Mutex".to_string(),
+        constructor!(agent, mutex, {
+            "lock" => acquire,
+            "unlock" => release,
+            "run" => run
+        }),
+    );
+    module.insert(
+        "Channel".to_string(),
+        constructor!(agent, channel, agent.intrinsics.async_iterator_prototype, {
+            "send" => send,
+            "next" => channel_next,
+            "receive" => receive,
+            "close" => close
+        }),
+    );
+    module.insert(
+        "AsyncLocalStorage".to_string(),
+        constructor!(agent, async_local_storage, {
+            "run" => storage_run,
+            "getStore" => get_store
+        }),
+    );
 
     module
 }
@@ -0,0 +1,793 @@
+use crate::agent::Agent;
+use crate::builtins::timers::now_ms;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use gc::GcCell;
+use num::ToPrimitive;
+use std::collections::{HashMap, VecDeque};
+
+// the items of a List slot, shared with the object holding it
+fn list(this: &Value, slot: &str) -> gc::Gc<GcCell<VecDeque<Value>>> {
+    match this.get_slot(slot) {
+        Value::List(list) => list,
+        _ => unreachable!(),
+    }
+}
+
+fn receiver(agent: &Agent, ctx: &Context, slot: &str) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot(slot) {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn new_instance(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    Ok(Value::new_custom_object(proto))
+}
+
+// iterating walks a snapshot, so changing the collection meanwhile is safe
+fn iterate(agent: &Agent, items: Vec<Value>) -> Value {
+    let it = Value::new_custom_object(agent.intrinsics.array_iterator_prototype.clone());
+    it.set_slot("array iterator next index", Value::from(0));
+    it.set_slot("iterated object", Value::new_array_from_vec(agent, items));
+    it
+}
+
+fn for_each_item<F: FnMut(Value)>(agent: &Agent, iterable: &Value, mut f: F) -> Result<(), Value> {
+    if *iterable == Value::Null {
+        return Ok(());
+    }
+    let (iterator, next) = match iterable.to_iterator(agent)? {
+        Value::Iterator(pair) => *pair,
+        _ => unreachable!(),
+    };
+    loop {
+        let step = next.call(agent, iterator.clone(), vec![])?;
+        if step.get(agent, ObjectKey::from("done"))?.to_bool() {
+            return Ok(());
+        }
+        f(step.get(agent, ObjectKey::from("value"))?);
+    }
+}
+
+// Deque, a double ended queue with constant time pushes and pops at
+// either end
+
+const DEQUE: &str = "deque items";
+
+fn deque(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let mut items = VecDeque::new();
+    for_each_item(agent, args.get(0).unwrap_or(&Value::Null), |v| {
+        items.push_back(v)
+    })?;
+    let deque = new_instance(agent, ctx)?;
+    deque.set_slot(DEQUE, Value::new_list_from_iter(items));
+    Ok(deque)
+}
+
+fn push_back(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let mut items = items.borrow_mut();
+    items.extend(args);
+    Ok(Value::from(items.len() as f64))
+}
+
+fn push_front(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let mut items = items.borrow_mut();
+    // pushed in order, so pushFront(a, b) leaves a at the front
+    for v in args.into_iter().rev() {
+        items.push_front(v);
+    }
+    Ok(Value::from(items.len() as f64))
+}
+
+fn pop_back(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let value = items.borrow_mut().pop_back();
+    Ok(value.unwrap_or(Value::Null))
+}
+
+fn pop_front(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let value = items.borrow_mut().pop_front();
+    Ok(value.unwrap_or(Value::Null))
+}
+
+fn peek_back(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let value = items.borrow().back().cloned();
+    Ok(value.unwrap_or(Value::Null))
+}
+
+fn peek_front(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let value = items.borrow().front().cloned();
+    Ok(value.unwrap_or(Value::Null))
+}
+
+// negative indices count from the back
+fn at(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let items = items.borrow();
+    let index = match args.get(0) {
+        Some(Value::Number(n)) if n.fract() == 0.0 => *n,
+        _ => return Err(Value::new_error(agent, "index must be an integer")),
+    };
+    let index = if index < 0.0 {
+        index + items.len() as f64
+    } else {
+        index
+    };
+    Ok(index
+        .to_usize()
+        .and_then(|i| items.get(i).cloned())
+        .unwrap_or(Value::Null))
+}
+
+fn deque_size(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let len = items.borrow().len();
+    Ok(Value::from(len as f64))
+}
+
+fn deque_clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    items.borrow_mut().clear();
+    Ok(Value::Null)
+}
+
+fn deque_iterator(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let items = list(&receiver(agent, ctx, DEQUE)?, DEQUE);
+    let items = items.borrow().iter().cloned().collect();
+    Ok(iterate(agent, items))
+}
+
+// PriorityQueue, a binary heap popping the item with the lowest priority
+// first, or the highest with `{ max: true }`. Items with the same priority
+// pop in the order they were pushed.
+
+const HEAP: &str = "priority queue heap";
+const NEXT: &str = "priority queue next";
+const MAX: &str = "priority queue max";
+
+// entries are (key, insertion order, item), the key being the priority,
+// negated for a max queue so the heap is always a min heap
+fn entry(value: &Value) -> (f64, f64, Value) {
+    match value {
+        Value::Tuple(entry) => match (&entry[0], &entry[1]) {
+            (Value::Number(key), Value::Number(order)) => (*key, *order, entry[2].clone()),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn before(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Tuple(a), Value::Tuple(b)) => match (&a[0], &a[1], &b[0], &b[1]) {
+            (Value::Number(a0), Value::Number(a1), Value::Number(b0), Value::Number(b1)) => {
+                (a0, a1) < (b0, b1)
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn sift_up(heap: &mut VecDeque<Value>, mut i: usize) {
+    while i > 0 {
+        let parent = (i - 1) / 2;
+        if !before(&heap[i], &heap[parent]) {
+            break;
+        }
+        heap.swap(i, parent);
+        i = parent;
+    }
+}
+
+fn sift_down(heap: &mut VecDeque<Value>, mut i: usize) {
+    loop {
+        let mut smallest = i;
+        for child in &[2 * i + 1, 2 * i + 2] {
+            if *child < heap.len() && before(&heap[*child], &heap[smallest]) {
+                smallest = *child;
+            }
+        }
+        if smallest == i {
+            return;
+        }
+        heap.swap(i, smallest);
+        i = smallest;
+    }
+}
+
+fn priority_queue(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let options = args.get(0).unwrap_or(&Value::Null);
+    let max = match options {
+        Value::Null => Value::Null,
+        _ => options.get(agent, ObjectKey::from("max"))?,
+    };
+    let max = match max {
+        Value::Null => false,
+        Value::Boolean(b) => b,
+        _ => return Err(Value::new_error(agent, "max must be a boolean")),
+    };
+    let queue = new_instance(agent, ctx)?;
+    queue.set_slot(HEAP, Value::new_list());
+    queue.set_slot(NEXT, Value::from(0));
+    queue.set_slot(MAX, Value::from(max));
+    Ok(queue)
+}
+
+// push(item, priority), the priority being the item itself if left out
+fn pq_push(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, HEAP)?;
+    let item = args.get(0).cloned().unwrap_or(Value::Null);
+    let priority = match args.get(1).unwrap_or(&item) {
+        Value::Number(n) if !n.is_nan() => *n,
+        _ => return Err(Value::new_error(agent, "priority must be a number")),
+    };
+    let key = if this.get_slot(MAX) == Value::from(true) {
+        -priority
+    } else {
+        priority
+    };
+    let order = match this.get_slot(NEXT) {
+        Value::Number(n) => n,
+        _ => unreachable!(),
+    };
+    this.set_slot(NEXT, Value::from(order + 1.0));
+    let heap = list(&this, HEAP);
+    let mut heap = heap.borrow_mut();
+    heap.push_back(Value::Tuple(Box::new(vec![
+        Value::from(key),
+        Value::from(order),
+        item,
+    ])));
+    let last = heap.len() - 1;
+    sift_up(&mut heap, last);
+    Ok(Value::from(heap.len() as f64))
+}
+
+fn pq_pop(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let heap = list(&receiver(agent, ctx, HEAP)?, HEAP);
+    let mut heap = heap.borrow_mut();
+    if heap.is_empty() {
+        return Ok(Value::Null);
+    }
+    let last = heap.len() - 1;
+    heap.swap(0, last);
+    let top = heap.pop_back().unwrap();
+    sift_down(&mut heap, 0);
+    Ok(entry(&top).2)
+}
+
+fn pq_peek(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let heap = list(&receiver(agent, ctx, HEAP)?, HEAP);
+    let top = heap.borrow().front().map(|top| entry(top).2);
+    Ok(top.unwrap_or(Value::Null))
+}
+
+fn pq_size(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let heap = list(&receiver(agent, ctx, HEAP)?, HEAP);
+    let len = heap.borrow().len();
+    Ok(Value::from(len as f64))
+}
+
+fn pq_clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let heap = list(&receiver(agent, ctx, HEAP)?, HEAP);
+    heap.borrow_mut().clear();
+    Ok(Value::Null)
+}
+
+// the items in the order they would pop, leaving the queue as it is
+fn pq_iterator(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let heap = list(&receiver(agent, ctx, HEAP)?, HEAP);
+    let mut entries = heap.borrow().iter().map(entry).collect::<Vec<_>>();
+    entries.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+    Ok(iterate(
+        agent,
+        entries.into_iter().map(|(_, _, item)| item).collect(),
+    ))
+}
+
+// BitSet, a set of small non-negative integers kept as bits
+
+const BITS: &str = "bit set bits";
+
+fn with_bits<R>(this: &Value, f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    match this.get_slot(BITS) {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Buffer(bytes) => f(&mut bytes.borrow_mut()),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+// bit sets grow to fit, so indices are capped to keep a typo from
+// allocating gigabytes
+const MAX_BIT: f64 = (1u64 << 32) as f64;
+
+fn bit_index(agent: &Agent, args: &[Value]) -> Result<usize, Value> {
+    match args.get(0) {
+        Some(Value::Number(n)) if *n >= 0.0 && *n < MAX_BIT && n.fract() == 0.0 => Ok(*n as usize),
+        _ => Err(Value::new_error(
+            agent,
+            "index must be an integer from 0 to 2^32 - 1",
+        )),
+    }
+}
+
+fn set_bit(bytes: &mut Vec<u8>, i: usize) {
+    if bytes.len() <= i / 8 {
+        bytes.resize(i / 8 + 1, 0);
+    }
+    bytes[i / 8] |= 1 << (i % 8);
+}
+
+fn bit_set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let set = new_instance(agent, ctx)?;
+    set.set_slot(BITS, Value::new_buffer_from_vec(agent, Vec::new()));
+    let mut indices = Vec::new();
+    for_each_item(agent, args.get(0).unwrap_or(&Value::Null), |v| {
+        indices.push(v)
+    })?;
+    for index in indices {
+        let i = bit_index(agent, &[index])?;
+        with_bits(&set, |bytes| set_bit(bytes, i));
+    }
+    Ok(set)
+}
+
+fn bit_add(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, BITS)?;
+    let i = bit_index(agent, &args)?;
+    with_bits(&this, |bytes| set_bit(bytes, i));
+    Ok(this)
+}
+
+fn bit_delete(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, BITS)?;
+    let i = bit_index(agent, &args)?;
+    let had = with_bits(&this, |bytes| match bytes.get_mut(i / 8) {
+        Some(byte) if *byte & (1 << (i % 8)) != 0 => {
+            *byte &= !(1 << (i % 8));
+            true
+        }
+        _ => false,
+    });
+    Ok(Value::from(had))
+}
+
+fn bit_has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, BITS)?;
+    let i = bit_index(agent, &args)?;
+    let has = with_bits(&this, |bytes| {
+        bytes.get(i / 8).is_some_and(|b| b & (1 << (i % 8)) != 0)
+    });
+    Ok(Value::from(has))
+}
+
+fn bit_size(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, BITS)?;
+    let count = with_bits(&this, |bytes| {
+        bytes.iter().map(|b| b.count_ones()).sum::<u32>()
+    });
+    Ok(Value::from(f64::from(count)))
+}
+
+fn bit_clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, BITS)?;
+    with_bits(&this, |bytes| bytes.clear());
+    Ok(Value::Null)
+}
+
+// the indices in the set, smallest first
+fn bit_iterator(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, BITS)?;
+    let indices = with_bits(&this, |bytes| {
+        let mut indices = Vec::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    indices.push(Value::from((i * 8 + bit) as f64));
+                }
+            }
+        }
+        indices
+    });
+    Ok(iterate(agent, indices))
+}
+
+// LruCache, a map of at most `capacity` entries dropping the least
+// recently used one to make room, optionally expiring entries `ttl` ms
+// after they are set. Keys are strings, numbers, booleans, records or
+// tuples of them.
+
+const TABLE: &str = "lru cache table";
+const ORDER: &str = "lru cache order";
+const TICK: &str = "lru cache tick";
+const CAPACITY: &str = "lru cache capacity";
+const TTL: &str = "lru cache ttl";
+const ON_EVICT: &str = "lru cache on evict";
+
+// the table maps encoded keys to entries. the order list holds
+// (tick, encoded key) for every use and is only cleaned up lazily, an
+// entry being current there if its tick matches the table
+struct Entry {
+    key: Value,
+    value: Value,
+    expires: Option<f64>,
+    tick: f64,
+}
+
+impl Entry {
+    fn from_value(value: &Value) -> Entry {
+        match value {
+            Value::Tuple(entry) => Entry {
+                key: entry[0].clone(),
+                value: entry[1].clone(),
+                expires: entry[2].as_f64(),
+                tick: entry[3].as_f64().unwrap(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Tuple(Box::new(vec![
+            self.key.clone(),
+            self.value.clone(),
+            self.expires.map_or(Value::Null, Value::from),
+            Value::from(self.tick),
+        ]))
+    }
+
+    fn expired(&self, now: f64) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+fn with_table<R>(this: &Value, f: impl FnOnce(&mut HashMap<String, Value>) -> R) -> R {
+    match this.get_slot(TABLE) {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Custom(table) => f(&mut table.borrow_mut()),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+// keys are compared by value, so equal records and tuples are the same key
+fn encode(out: &mut String, key: &Value) -> bool {
+    match key {
+        Value::String(s) => out.push_str(&format!("s{}:{}", s.len(), s)),
+        // 0 and -0 are the same key
+        Value::Number(..) | Value::Integer(..) => {
+            out.push_str(&format!("n{};", key.as_f64().unwrap() + 0.0))
+        }
+        Value::Boolean(b) => out.push_str(if *b { "t" } else { "f" }),
+        Value::Null => out.push('z'),
+        Value::Tuple(items) => {
+            out.push('(');
+            if !items.iter().all(|item| encode(out, item)) {
+                return false;
+            }
+            out.push(')');
+        }
+        Value::Record(fields) => {
+            out.push('{');
+            for (name, value) in fields.iter() {
+                out.push_str(&format!("{}:{}", name.len(), name));
+                if !encode(out, value) {
+                    return false;
+                }
+            }
+            out.push('}');
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn encode_key(agent: &Agent, key: Option<&Value>) -> Result<String, Value> {
+    let mut out = String::new();
+    match key {
+        Some(key) if *key != Value::Null && encode(&mut out, key) => Ok(out),
+        _ => Err(Value::new_error(
+            agent,
+            "key must be a string, number, boolean, record or tuple",
+        )),
+    }
+}
+
+fn number_slot(this: &Value, slot: &str) -> f64 {
+    this.get_slot(slot).as_f64().unwrap()
+}
+
+fn lru_cache(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let capacity = match args.get(0) {
+        Some(Value::Number(n)) if *n >= 1.0 && n.fract() == 0.0 => *n,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "capacity must be a positive integer",
+            ))
+        }
+    };
+    let options = args.get(1).unwrap_or(&Value::Null);
+    let option = |name: &str| match options {
+        Value::Null => Ok(Value::Null),
+        _ => options.get(agent, ObjectKey::from(name)),
+    };
+    let ttl = match option("ttl")? {
+        Value::Null => Value::Null,
+        Value::Number(n) if n > 0.0 => Value::from(n),
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "ttl must be a positive number of ms",
+            ))
+        }
+    };
+    let on_evict = option("onEvict")?;
+    if on_evict != Value::Null && on_evict.type_of() != "function" {
+        return Err(Value::new_error(agent, "onEvict must be a function"));
+    }
+    let cache = new_instance(agent, ctx)?;
+    cache.set_slot(TABLE, Value::new_custom_object(Value::Null));
+    cache.set_slot(ORDER, Value::new_list());
+    cache.set_slot(TICK, Value::from(0));
+    cache.set_slot(CAPACITY, Value::from(capacity));
+    cache.set_slot(TTL, ttl);
+    cache.set_slot(ON_EVICT, on_evict);
+    Ok(cache)
+}
+
+// tell onEvict, if there is one, that an entry is gone, the reason being
+// "expired" or "evicted"
+fn evicted(agent: &Agent, this: &Value, entry: Entry, reason: &str) -> Result<(), Value> {
+    let on_evict = this.get_slot(ON_EVICT);
+    if on_evict != Value::Null {
+        on_evict.call(
+            agent,
+            Value::Null,
+            vec![entry.key, entry.value, Value::from(reason)],
+        )?;
+    }
+    Ok(())
+}
+
+// make an entry the most recently used
+fn touch(this: &Value, encoded: String, mut entry: Entry) {
+    let tick = number_slot(this, TICK);
+    this.set_slot(TICK, Value::from(tick + 1.0));
+    entry.tick = tick;
+    let size = with_table(this, |table| {
+        table.insert(encoded.clone(), entry.to_value());
+        table.len()
+    });
+    let order = list(this, ORDER);
+    let mut order = order.borrow_mut();
+    order.push_back(Value::Tuple(Box::new(vec![
+        Value::from(tick),
+        Value::from(encoded),
+    ])));
+    // rebuild the order once it is mostly stale uses
+    if order.len() > 2 * size + 16 {
+        let mut current = with_table(this, |table| {
+            table
+                .iter()
+                .map(|(encoded, entry)| (Entry::from_value(entry).tick, encoded.clone()))
+                .collect::<Vec<_>>()
+        });
+        current.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        order.clear();
+        order.extend(current.into_iter().map(|(tick, encoded)| {
+            Value::Tuple(Box::new(vec![Value::from(tick), Value::from(encoded)]))
+        }));
+    }
+}
+
+// the entry for a key, dropping it if it has expired
+fn live(agent: &Agent, this: &Value, encoded: &str) -> Result<Option<Entry>, Value> {
+    let entry = match with_table(this, |table| table.get(encoded).map(Entry::from_value)) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    if entry.expired(now_ms(agent)) {
+        with_table(this, |table| table.remove(encoded));
+        evicted(agent, this, entry, "expired")?;
+        return Ok(None);
+    }
+    Ok(Some(entry))
+}
+
+fn lru_get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    let encoded = encode_key(agent, args.get(0))?;
+    match live(agent, &this, &encoded)? {
+        Some(entry) => {
+            let value = entry.value.clone();
+            touch(&this, encoded, entry);
+            Ok(value)
+        }
+        None => Ok(Value::Null),
+    }
+}
+
+// has doesn't count as a use, so it doesn't keep an entry from eviction
+fn lru_has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    let encoded = encode_key(agent, args.get(0))?;
+    Ok(Value::from(live(agent, &this, &encoded)?.is_some()))
+}
+
+fn lru_set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    let encoded = encode_key(agent, args.get(0))?;
+    let expires = this.get_slot(TTL).as_f64().map(|ttl| now_ms(agent) + ttl);
+    let entry = Entry {
+        key: args.get(0).cloned().unwrap(),
+        value: args.get(1).cloned().unwrap_or(Value::Null),
+        expires,
+        tick: 0.0,
+    };
+    touch(&this, encoded, entry);
+
+    let capacity = number_slot(&this, CAPACITY) as usize;
+    while with_table(&this, |table| table.len()) > capacity {
+        let use_ = list(&this, ORDER).borrow_mut().pop_front().unwrap();
+        let (tick, encoded) = match use_ {
+            Value::Tuple(use_) => (use_[0].as_f64().unwrap(), use_[1].clone()),
+            _ => unreachable!(),
+        };
+        let encoded = match encoded {
+            Value::String(s) => s.to_string(),
+            _ => unreachable!(),
+        };
+        let oldest = with_table(&this, |table| match table.get(&encoded) {
+            Some(entry) if Entry::from_value(entry).tick == tick => table.remove(&encoded),
+            _ => None,
+        });
+        if let Some(entry) = oldest {
+            let entry = Entry::from_value(&entry);
+            let reason = if entry.expired(now_ms(agent)) {
+                "expired"
+            } else {
+                "evicted"
+            };
+            evicted(agent, &this, entry, reason)?;
+        }
+    }
+    Ok(this)
+}
+
+fn lru_delete(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    let encoded = encode_key(agent, args.get(0))?;
+    let removed = with_table(&this, |table| table.remove(&encoded));
+    Ok(Value::from(removed.is_some()))
+}
+
+// entries that expired but weren't looked at since are still counted
+fn lru_size(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    let size = with_table(&this, |table| table.len());
+    Ok(Value::from(size as f64))
+}
+
+fn lru_clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    with_table(&this, |table| table.clear());
+    list(&this, ORDER).borrow_mut().clear();
+    Ok(Value::Null)
+}
+
+// [key, value] pairs of the entries that haven't expired, most recently
+// used first
+fn lru_iterator(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, TABLE)?;
+    let now = now_ms(agent);
+    let mut entries = with_table(&this, |table| {
+        table
+            .values()
+            .map(Entry::from_value)
+            .filter(|entry| !entry.expired(now))
+            .collect::<Vec<_>>()
+    });
+    entries.sort_by(|a, b| b.tick.partial_cmp(&a.tick).unwrap());
+    let pairs = entries
+        .into_iter()
+        .map(|entry| Value::new_array_from_vec(agent, vec![entry.key, entry.value]))
+        .collect();
+    Ok(iterate(agent, pairs))
+}
+
+macro_rules! constructor {
+    ($agent:expr, $constructor:ident, $iterator:ident, { $($name:expr => $fn:ident),* }) => {{
+        let c = Value::new_builtin_function($agent, $constructor);
+        let proto = Value::new_object($agent.intrinsics.object_prototype.clone());
+        $(
+            proto
+                .set(
+                    $agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function($agent, $fn),
+                )
+                .unwrap();
+        )*
+        proto
+            .set(
+                $agent,
+                ObjectKey::well_known_symbol("iterator"),
+                Value::new_builtin_function($agent, $iterator),
+            )
+            .unwrap();
+        proto
+            .set($agent, ObjectKey::from("constructor"), c.clone())
+            .unwrap();
+        c.set($agent, ObjectKey::from("prototype"), proto).unwrap();
+        c
+    }};
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "Deque".to_string(),
+        constructor!(agent, deque, deque_iterator, {
+            "pushBack" => push_back,
+            "pushFront" => push_front,
+            "popBack" => pop_back,
+            "popFront" => pop_front,
+            "peekBack" => peek_back,
+            "peekFront" => peek_front,
+            "at" => at,
+            "size" => deque_size,
+            "clear" => deque_clear
+        }),
+    );
+    module.insert(
+        "PriorityQueue".to_string(),
+        constructor!(agent, priority_queue, pq_iterator, {
+            "push" => pq_push,
+            "pop" => pq_pop,
+            "peek" => pq_peek,
+            "size" => pq_size,
+            "clear" => pq_clear
+        }),
+    );
+    module.insert(
+        "BitSet".to_string(),
+        constructor!(agent, bit_set, bit_iterator, {
+            "add" => bit_add,
+            "delete" => bit_delete,
+            "has" => bit_has,
+            "size" => bit_size,
+            "clear" => bit_clear
+        }),
+    );
+    module.insert(
+        "LruCache".to_string(),
+        constructor!(agent, lru_cache, lru_iterator, {
+            "get" => lru_get,
+            "set" => lru_set,
+            "has" => lru_has,
+            "delete" => lru_delete,
+            "size" => lru_size,
+            "clear" => lru_clear
+        }),
+    );
+
+    module
+}
@@ -0,0 +1,376 @@
+use crate::agent::Agent;
+use crate::builtins::tz::days_from_civil;
+use crate::interpreter::Context;
+use crate::intrinsics::url::{Path, Url};
+use crate::value::{ObjectKey, Value};
+use lazy_static::lazy_static;
+use num::ToPrimitive;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    // jars are shared with the http workers, which update them between
+    // redirects
+    pub static ref JARS: Mutex<HashMap<usize, CookieJar>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_JAR: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub host_only: bool,
+    pub path: String,
+    pub expires: Option<u64>,
+    pub secure: bool,
+    pub http_only: bool,
+    created: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    counter: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// RFC 6265 section 5.1.1, which accepts the many date formats servers send
+pub fn parse_date(input: &str) -> Option<u64> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    for token in input
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != ':')
+        .filter(|t| !t.is_empty())
+    {
+        let digits = token.chars().take_while(|c| c.is_ascii_digit()).count();
+        if time.is_none() && token.contains(':') {
+            let parts = token
+                .split(':')
+                .map(|p| p.parse::<i64>().ok())
+                .collect::<Option<Vec<i64>>>();
+            if let Some(p) = parts {
+                if p.len() == 3 {
+                    time = Some((p[0], p[1], p[2]));
+                    continue;
+                }
+            }
+        }
+        if day.is_none() && (digits == 1 || digits == 2) && digits == token.len() {
+            day = token.parse::<i64>().ok();
+        } else if month.is_none() && digits == 0 && token.len() >= 3 {
+            let prefix = token[..3].to_ascii_lowercase();
+            if let Some(i) = months.iter().position(|m| *m == prefix) {
+                month = Some(i as i64 + 1);
+            }
+        } else if year.is_none() && (digits == 2 || digits == 4) && digits == token.len() {
+            year = token.parse::<i64>().ok();
+        }
+    }
+    let (hour, minute, second) = time?;
+    let (day, month) = (day?, month?);
+    let year = match year? {
+        y if y < 70 => y + 2000,
+        y if y < 100 => y + 1900,
+        y => y,
+    };
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    if seconds < 0 {
+        Some(0)
+    } else {
+        Some(seconds as u64)
+    }
+}
+
+fn is_ip(host: &str) -> bool {
+    host.starts_with('[') || host.parse::<std::net::Ipv4Addr>().is_ok()
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && !is_ip(host))
+}
+
+fn default_path(url: &Url) -> String {
+    let path = match &url.path {
+        Path::Segments(s) if s.len() > 1 => &s[..s.len() - 1],
+        _ => return "/".to_string(),
+    };
+    path.iter().map(|s| format!("/{}", s)).collect()
+}
+
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+impl CookieJar {
+    /// Store a Set-Cookie header received from `url`. Cookies that fail the
+    /// domain rules are ignored, and an expiry in the past removes the cookie.
+    pub fn store(&mut self, url: &Url, header: &str) {
+        let host = url.host.clone().unwrap_or_default();
+        let mut parts = header.split(';');
+        let pair = parts.next().unwrap_or("");
+        let (name, value) = match pair.find('=') {
+            Some(i) => (pair[..i].trim(), pair[i + 1..].trim()),
+            None => return,
+        };
+        if name.is_empty() {
+            return;
+        }
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+            http_only: false,
+            created: self.counter,
+        };
+        self.counter += 1;
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = match attribute.find('=') {
+                Some(i) => (attribute[..i].trim(), attribute[i + 1..].trim()),
+                None => (attribute.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Some(t) = parse_date(value) {
+                        cookie.expires = Some(t);
+                    }
+                }
+                "max-age" => {
+                    if let Ok(n) = value.parse::<i64>() {
+                        max_age = Some(n);
+                    }
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_match(&host, &domain) || (!domain.contains('.') && domain != host) {
+                        return;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        // max-age wins over expires
+        match max_age {
+            Some(n) if n <= 0 => cookie.expires = Some(0),
+            Some(n) => cookie.expires = Some(now() + n as u64),
+            None => {}
+        }
+
+        let existing = self.cookies.iter().position(|c| {
+            c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+        });
+        if let Some(i) = existing {
+            cookie.created = self.cookies[i].created;
+            self.cookies.remove(i);
+        }
+        if cookie.expires.map_or(true, |t| t > now()) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// Cookies that apply to a request for `url`, longest paths first.
+    pub fn matching(&mut self, url: &Url) -> Vec<Cookie> {
+        let time = now();
+        self.cookies
+            .retain(|c| c.expires.map_or(true, |t| t > time));
+        let host = url.host.clone().unwrap_or_default();
+        let path = url.pathname();
+        let secure = url.scheme == "https" || url.scheme == "wss";
+        let mut cookies = self
+            .cookies
+            .iter()
+            .filter(|c| {
+                (if c.host_only {
+                    host == c.domain
+                } else {
+                    domain_match(&host, &c.domain)
+                }) && path_match(&path, &c.path)
+                    && (secure || !c.secure)
+            })
+            .cloned()
+            .collect::<Vec<Cookie>>();
+        cookies.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.created.cmp(&b.created))
+        });
+        cookies
+    }
+
+    pub fn header(&mut self, url: &Url) -> Option<String> {
+        let cookies = self.matching(url);
+        if cookies.is_empty() {
+            return None;
+        }
+        Some(
+            cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
+}
+
+fn get_id(agent: &Agent, ctx: &Context) -> Result<usize, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("cookie jar") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    match this.get_slot("cookie jar") {
+        Value::Number(n) => Ok(n.to_usize().unwrap()),
+        _ => unreachable!(),
+    }
+}
+
+pub fn jar_id(value: &Value) -> Option<usize> {
+    if value.has_slot("cookie jar") {
+        if let Value::Number(n) = value.get_slot("cookie jar") {
+            return n.to_usize();
+        }
+    }
+    None
+}
+
+fn url_argument(agent: &Agent, args: &[Value], i: usize) -> Result<Url, Value> {
+    let input = match args.get(i) {
+        Some(Value::String(s)) => s.to_string(),
+        Some(v) if v.has_slot("url href") => match v.get_slot("url href") {
+            Value::String(s) => s.to_string(),
+            _ => unreachable!(),
+        },
+        _ => return Err(Value::new_error(agent, "url must be a string")),
+    };
+    Url::parse(&input, None)
+        .map_err(|e| Value::new_error(agent, &format!("invalid URL '{}': {}", input, e)))
+}
+
+fn cookie_jar(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let id = NEXT_JAR.fetch_add(1, Ordering::SeqCst);
+    JARS.lock().unwrap().insert(id, CookieJar::default());
+    let jar = Value::new_custom_object(proto);
+    jar.set_slot("cookie jar", Value::from(id as f64));
+    Ok(jar)
+}
+
+fn set_cookie(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let id = get_id(agent, ctx)?;
+    let header = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "cookie must be a string")),
+    };
+    let url = url_argument(agent, &args, 1)?;
+    JARS.lock()
+        .unwrap()
+        .get_mut(&id)
+        .unwrap()
+        .store(&url, &header);
+    Ok(Value::Null)
+}
+
+fn get_cookies(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let id = get_id(agent, ctx)?;
+    let url = url_argument(agent, &args, 0)?;
+    let cookies = JARS.lock().unwrap().get_mut(&id).unwrap().matching(&url);
+    let mut values = Vec::new();
+    for cookie in cookies {
+        let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+        macro_rules! p {
+            ($name:expr, $value:expr) => {
+                o.set(agent, ObjectKey::from($name), $value)?;
+            };
+        }
+        p!("name", Value::from(cookie.name));
+        p!("value", Value::from(cookie.value));
+        p!("domain", Value::from(cookie.domain));
+        p!("path", Value::from(cookie.path));
+        p!(
+            "expires",
+            cookie
+                .expires
+                .map_or(Value::Null, |t| Value::from(t as f64 * 1000.0))
+        );
+        p!("secure", Value::from(cookie.secure));
+        p!("httpOnly", Value::from(cookie.http_only));
+        values.push(o);
+    }
+    Ok(Value::new_array_from_vec(agent, values))
+}
+
+fn get_cookie_string(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let id = get_id(agent, ctx)?;
+    let url = url_argument(agent, &args, 0)?;
+    let header = JARS.lock().unwrap().get_mut(&id).unwrap().header(&url);
+    Ok(Value::from(header.unwrap_or_default()))
+}
+
+fn clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let id = get_id(agent, ctx)?;
+    JARS.lock().unwrap().get_mut(&id).unwrap().cookies.clear();
+    Ok(Value::Null)
+}
+
+pub fn create_cookie_jar(agent: &Agent) -> Value {
+    let c = Value::new_builtin_function(agent, cookie_jar);
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("setCookie", set_cookie);
+    method!("getCookies", get_cookies);
+    method!("getCookieString", get_cookie_string);
+    method!("clear", clear);
+
+    proto
+        .set(agent, ObjectKey::from("constructor"), c.clone())
+        .unwrap();
+    c.set(agent, ObjectKey::from("prototype"), proto).unwrap();
+    c
+}
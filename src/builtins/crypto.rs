@@ -0,0 +1,261 @@
+use crate::agent::Agent;
+#[cfg(feature = "os")]
+use crate::builtins::fs::{spawn, FsResponse};
+use crate::interpreter::Context;
+#[cfg(feature = "os")]
+use crate::value::{ObjectKey, ObjectKind};
+use crate::value::Value;
+#[cfg(feature = "os")]
+use num::ToPrimitive;
+#[cfg(feature = "os")]
+use rand::RngCore;
+use std::collections::HashMap;
+
+// ids need randomness and verify reads files, only hashing works without os
+#[cfg(feature = "os")]
+const NANOID_ALPHABET: &[u8] = b"useandom-26T198340PX75pxJACKVERYMINDBUSHWOLF_GQZbfghjklqvwyzrict";
+#[cfg(feature = "os")]
+const MAX_NANOID_SIZE: usize = 1024;
+
+// the millisecond and counter of the last uuidV7, shared by every agent so
+// ids made on different threads still sort in the order they were made
+#[cfg(feature = "os")]
+static UUID_V7_CLOCK: std::sync::Mutex<(u64, u16)> = std::sync::Mutex::new((0, 0));
+
+#[rustfmt::skip]
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([
+                chunk[i * 4],
+                chunk[i * 4 + 1],
+                chunk[i * 4 + 2],
+                chunk[i * 4 + 3],
+            ]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut v = h;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v = [
+                t1.wrapping_add(t2),
+                v[0],
+                v[1],
+                v[2],
+                v[3].wrapping_add(t1),
+                v[4],
+                v[5],
+                v[6],
+            ];
+        }
+        for (a, b) in h.iter_mut().zip(v.iter()) {
+            *a = a.wrapping_add(*b);
+        }
+    }
+
+    let mut out = [0u8; 32];
+    for (i, word) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "os")]
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let mut out = String::with_capacity(36);
+    for (i, b) in bytes.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            out.push('-');
+        }
+        out += &format!("{:02x}", b);
+    }
+    out
+}
+
+#[cfg(feature = "os")]
+fn random_uuid(_agent: &Agent, _args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+    Ok(Value::from(format_uuid(&bytes)))
+}
+
+#[cfg(feature = "os")]
+fn uuid_v7(_agent: &Agent, _args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let d = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap();
+    let now = d.as_secs() * 1000 + u64::from(d.subsec_millis());
+
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes[6..]);
+    // ids made in the same millisecond count up in the 12 rand_a bits. a
+    // new millisecond starts the counter at a random value below half, and
+    // running out of counter, or the clock going back, borrows the next
+    // millisecond
+    let (ms, counter) = {
+        let mut clock = UUID_V7_CLOCK.lock().unwrap();
+        let (last, counter) = *clock;
+        *clock = if now > last {
+            (now, u16::from_be_bytes([bytes[6], bytes[7]]) & 0x7ff)
+        } else if counter < 0xfff {
+            (last, counter + 1)
+        } else {
+            (last + 1, 0)
+        };
+        *clock
+    };
+    // 48 bit big endian unix timestamp in milliseconds
+    for (i, b) in bytes.iter_mut().take(6).enumerate() {
+        *b = (ms >> (40 - i * 8)) as u8;
+    }
+    bytes[6] = 0x70 | (counter >> 8) as u8; // version 7
+    bytes[7] = counter as u8;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+    Ok(Value::from(format_uuid(&bytes)))
+}
+
+#[cfg(feature = "os")]
+fn nanoid(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let size = match args.get(0).unwrap_or(&Value::Null) {
+        Value::Null => 21,
+        Value::Number(n) if *n > MAX_NANOID_SIZE as f64 => {
+            return Err(Value::new_range_error(
+                agent,
+                &format!("size must be at most {}", MAX_NANOID_SIZE),
+            ))
+        }
+        Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => n.to_usize().unwrap(),
+        _ => return Err(Value::new_error(agent, "size must be a positive integer")),
+    };
+
+    let mut bytes = vec![0u8; size];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    // the alphabet has 64 entries, so masking keeps the distribution uniform
+    let id = bytes
+        .iter()
+        .map(|b| NANOID_ALPHABET[(b & 63) as usize] as char)
+        .collect::<String>();
+    Ok(Value::from(id))
+}
+
+fn hash_value(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    let canonical = super::json::canonicalize(agent, value)?;
+    Ok(Value::from(to_hex(&sha256(canonical.as_bytes()))))
+}
+
+#[cfg(feature = "os")]
+fn check_digest(what: &str, data: &[u8], expected: &str) -> FsResponse {
+    let digest = to_hex(&sha256(data));
+    if digest == expected {
+        FsResponse::Read(digest)
+    } else {
+        FsResponse::Error(format!(
+            "checksum mismatch for {}: expected sha256 {}, got {}",
+            what, expected, digest
+        ))
+    }
+}
+
+/// Check the SHA-256 of a file or Buffer against `{ sha256 }`, a hex digest.
+/// Resolves with the digest, or rejects when it doesn't match.
+#[cfg(feature = "os")]
+fn verify(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = args.get(1).unwrap_or(&Value::Null);
+    let expected = match options {
+        Value::Null => Value::Null,
+        _ => options.get(agent, ObjectKey::from("sha256"))?,
+    };
+    let expected = match expected {
+        Value::String(ref s) if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) => {
+            s.to_ascii_lowercase()
+        }
+        _ => return Err(Value::new_error(agent, "sha256 must be a hex digest")),
+    };
+    // buffers are copied so they can be hashed on the pool too
+    let (name, data) = match args.get(0) {
+        Some(Value::String(path)) => (path.to_string(), None),
+        Some(Value::Object(o)) => match &o.kind {
+            ObjectKind::Buffer(data) => ("buffer".to_string(), Some(data.borrow().clone())),
+            _ => return Err(Value::new_error(agent, "input must be a path or Buffer")),
+        },
+        _ => return Err(Value::new_error(agent, "input must be a path or Buffer")),
+    };
+    let path = name.clone();
+    spawn(agent, "crypto.verify", &path, move || {
+        match data.map_or_else(|| std::fs::read(&name), Ok) {
+            Ok(data) => check_digest(&name, &data, &expected),
+            Err(e) => FsResponse::Error(format!("{}", e)),
+        }
+    })
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("hashValue", hash_value);
+    #[cfg(feature = "os")]
+    {
+        method!("randomUUID", random_uuid);
+        method!("uuidV7", uuid_v7);
+        method!("nanoid", nanoid);
+        method!("verify", verify);
+        // reading a file to verify needs the same grant as reading it with fs
+        module["verify"].set_slot("capability", Value::from("fs"));
+        module["verify"].set_slot("operation", Value::from("crypto.verify"));
+    }
+
+    module
+}
@@ -0,0 +1,574 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::promise::{new_promise_capability, promise_resolve_i};
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::HashMap;
+
+struct Options {
+    delimiter: char,
+    quote: char,
+    header: bool,
+    columns: Option<Vec<String>>,
+}
+
+fn single_char(agent: &Agent, options: &Value, name: &str, default: char) -> Result<char, Value> {
+    match options.get(agent, ObjectKey::from(name))? {
+        Value::Null => Ok(default),
+        Value::String(ref s) if s.chars().count() == 1 => Ok(s.chars().next().unwrap()),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be a single character", name),
+        )),
+    }
+}
+
+fn string_list(agent: &Agent, value: &Value) -> Result<Vec<String>, Value> {
+    let length = match value.get(agent, ObjectKey::from("length"))? {
+        Value::Number(n) => n as usize,
+        _ => return Err(Value::new_error(agent, "columns must be an array")),
+    };
+    let mut list = Vec::with_capacity(length);
+    for i in 0..length {
+        match value.get(agent, ObjectKey::from(i))? {
+            Value::String(s) => list.push(s.to_string()),
+            _ => return Err(Value::new_error(agent, "column names must be strings")),
+        }
+    }
+    Ok(list)
+}
+
+impl Options {
+    fn from_value(agent: &Agent, options: &Value) -> Result<Options, Value> {
+        if *options == Value::Null {
+            return Ok(Options {
+                delimiter: ',',
+                quote: '"',
+                header: true,
+                columns: None,
+            });
+        }
+        if options.type_of() != "object" {
+            return Err(Value::new_error(agent, "options must be an object"));
+        }
+        let header = match options.get(agent, ObjectKey::from("header"))? {
+            Value::Null => true,
+            Value::Boolean(b) => b,
+            _ => return Err(Value::new_error(agent, "header must be a boolean")),
+        };
+        let columns = match options.get(agent, ObjectKey::from("columns"))? {
+            Value::Null => None,
+            columns => Some(string_list(agent, &columns)?),
+        };
+        Ok(Options {
+            delimiter: single_char(agent, options, "delimiter", ',')?,
+            quote: single_char(agent, options, "quote", '"')?,
+            header,
+            columns,
+        })
+    }
+}
+
+// Splits `text` into records. Unless `last` is set, a trailing record that
+// is not yet terminated by a newline is left unconsumed so that more input
+// can be appended to it. Returns the records and the number of bytes used.
+fn parse_records(
+    text: &str,
+    options: &Options,
+    last: bool,
+) -> Result<(Vec<Vec<String>>, usize), &'static str> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut consumed = 0;
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if in_quotes {
+            if c == options.quote {
+                if let Some(&(_, next)) = chars.peek() {
+                    if next == options.quote {
+                        chars.next();
+                        field.push(c);
+                        continue;
+                    }
+                } else if !last {
+                    // the next chunk may start with an escaped quote
+                    break;
+                }
+                in_quotes = false;
+            } else {
+                field.push(c);
+            }
+            continue;
+        }
+        match c {
+            c if c == options.quote && field.is_empty() && !quoted => {
+                quoted = true;
+                in_quotes = true;
+            }
+            c if c == options.delimiter => {
+                record.push(std::mem::replace(&mut field, String::new()));
+                quoted = false;
+            }
+            '\r' if chars.peek().map(|&(_, c)| c) == Some('\n') => {}
+            '\r' | '\n' => {
+                if !record.is_empty() || !field.is_empty() || quoted {
+                    record.push(std::mem::replace(&mut field, String::new()));
+                    records.push(std::mem::replace(&mut record, Vec::new()));
+                }
+                quoted = false;
+                consumed = i + 1;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if last {
+        if in_quotes {
+            return Err("unterminated quoted field");
+        }
+        if !record.is_empty() || !field.is_empty() || quoted {
+            record.push(field);
+            records.push(record);
+        }
+        consumed = text.len();
+    }
+    Ok((records, consumed))
+}
+
+fn new_row(agent: &Agent, header: &[String], record: Vec<String>) -> Result<Value, Value> {
+    let row = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (i, field) in record.into_iter().enumerate() {
+        let key = match header.get(i) {
+            Some(name) => ObjectKey::from(name.as_str()),
+            None => ObjectKey::from(i),
+        };
+        row.set(agent, key, Value::from(field))?;
+    }
+    Ok(row)
+}
+
+// Turns records into row values, taking the header from the first record
+// when it is not known yet.
+fn records_to_rows(
+    agent: &Agent,
+    options: &Options,
+    header: &mut Option<Vec<String>>,
+    records: Vec<Vec<String>>,
+) -> Result<Vec<Value>, Value> {
+    let mut rows = Vec::with_capacity(records.len());
+    for record in records {
+        if !options.header {
+            let fields = record.into_iter().map(Value::from).collect();
+            rows.push(Value::new_array_from_vec(agent, fields));
+        } else if let Some(header) = header {
+            rows.push(new_row(agent, header, record)?);
+        } else {
+            *header = Some(record);
+        }
+    }
+    Ok(rows)
+}
+
+fn parse(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let text = match args.get(0) {
+        Some(Value::String(s)) => s,
+        _ => return Err(Value::new_error(agent, "source must be a string")),
+    };
+    let options = Options::from_value(agent, args.get(1).unwrap_or(&Value::Null))?;
+    let (records, _) = parse_records(text, &options, true)
+        .map_err(|e| Value::new_error(agent, &format!("csv: {}", e)))?;
+    let mut header = options.columns.clone();
+    let rows = records_to_rows(agent, &options, &mut header, records)?;
+    Ok(Value::new_array_from_vec(agent, rows))
+}
+
+fn list_pop(list: &Value) -> Option<Value> {
+    if let Value::List(list) = list {
+        list.borrow_mut().pop_front()
+    } else {
+        unreachable!();
+    }
+}
+
+fn list_push(list: &Value, value: Value) {
+    if let Value::List(list) = list {
+        list.borrow_mut().push_back(value);
+    } else {
+        unreachable!();
+    }
+}
+
+fn list_is_empty(list: &Value) -> bool {
+    if let Value::List(list) = list {
+        list.borrow().is_empty()
+    } else {
+        unreachable!();
+    }
+}
+
+fn settle_waiters(agent: &Agent, stream: &Value) -> Result<(), Value> {
+    let waiters = stream.get_slot("csv waiters");
+    let rows = stream.get_slot("csv rows");
+    while !list_is_empty(&waiters) {
+        let result = if let Some(row) = list_pop(&rows) {
+            Value::new_iter_result(agent, row, false)?
+        } else if stream.get_slot("csv done") == Value::from(true) {
+            Value::new_iter_result(agent, Value::Null, true)?
+        } else {
+            break;
+        };
+        let promise = list_pop(&waiters).unwrap();
+        promise
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![result])?;
+    }
+    Ok(())
+}
+
+fn fail(agent: &Agent, stream: &Value, reason: Value) -> Result<(), Value> {
+    stream.set_slot("csv done", Value::from(true));
+    let waiters = stream.get_slot("csv waiters");
+    while let Some(promise) = list_pop(&waiters) {
+        promise
+            .get_slot("reject")
+            .call(agent, Value::Null, vec![reason.clone()])?;
+    }
+    Ok(())
+}
+
+// Requests the next chunk from the source when rows are awaited and no
+// request is in flight.
+fn pump(agent: &Agent, stream: &Value) -> Result<(), Value> {
+    settle_waiters(agent, stream)?;
+    if list_is_empty(&stream.get_slot("csv waiters"))
+        || stream.get_slot("csv done") == Value::from(true)
+        || stream.get_slot("csv pulling") == Value::from(true)
+    {
+        return Ok(());
+    }
+    stream.set_slot("csv pulling", Value::from(true));
+
+    let chunk = stream
+        .get_slot("csv next")
+        .call(agent, stream.get_slot("csv source"), vec![]);
+    let chunk = match chunk {
+        Ok(chunk) => chunk,
+        Err(e) => return fail(agent, stream, e),
+    };
+    let promise = promise_resolve_i(agent, agent.intrinsics.promise.clone(), chunk)?;
+
+    let on_fulfilled = Value::new_builtin_function(agent, on_chunk);
+    on_fulfilled.set_slot("csv stream", stream.clone());
+    let on_rejected = Value::new_builtin_function(agent, on_error);
+    on_rejected.set_slot("csv stream", stream.clone());
+
+    promise.get(agent, ObjectKey::from("then"))?.call(
+        agent,
+        promise,
+        vec![on_fulfilled, on_rejected],
+    )?;
+    Ok(())
+}
+
+fn append_chunk(agent: &Agent, stream: &Value, chunk: &Value) -> Result<(), Value> {
+    let mut text = match stream.get_slot("csv text") {
+        Value::String(s) => s.to_string(),
+        _ => unreachable!(),
+    };
+    match chunk {
+        Value::String(s) => text += s.as_str(),
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Buffer(b) => {
+                // multi-byte characters may be split across chunks
+                let mut bytes = match stream.get_slot("csv bytes") {
+                    Value::Object(o) => match &o.kind {
+                        ObjectKind::Buffer(pending) => pending.borrow().clone(),
+                        _ => unreachable!(),
+                    },
+                    _ => Vec::new(),
+                };
+                bytes.extend_from_slice(&b.borrow());
+                let valid = match std::str::from_utf8(&bytes) {
+                    Ok(s) => s.len(),
+                    Err(e) => {
+                        if e.error_len().is_some() {
+                            return Err(Value::new_error(agent, "csv: stream is not valid utf-8"));
+                        }
+                        e.valid_up_to()
+                    }
+                };
+                text += std::str::from_utf8(&bytes[..valid]).unwrap();
+                let rest = bytes[valid..].to_vec();
+                stream.set_slot("csv bytes", Value::new_buffer_from_vec(agent, rest));
+            }
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    "csv: chunks must be strings or buffers",
+                ))
+            }
+        },
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "csv: chunks must be strings or buffers",
+            ))
+        }
+    }
+    stream.set_slot("csv text", Value::from(text));
+    Ok(())
+}
+
+fn consume(agent: &Agent, stream: &Value, last: bool) -> Result<(), Value> {
+    let options = Options::from_value(agent, &stream.get_slot("csv options"))?;
+    let text = match stream.get_slot("csv text") {
+        Value::String(s) => s,
+        _ => unreachable!(),
+    };
+    if last {
+        if let Value::Object(o) = stream.get_slot("csv bytes") {
+            if let ObjectKind::Buffer(b) = &o.kind {
+                if !b.borrow().is_empty() {
+                    return Err(Value::new_error(agent, "csv: stream is not valid utf-8"));
+                }
+            }
+        }
+    }
+    let (records, consumed) = parse_records(&text, &options, last)
+        .map_err(|e| Value::new_error(agent, &format!("csv: {}", e)))?;
+    stream.set_slot("csv text", Value::from(text[consumed..].to_string()));
+
+    let mut header = match stream.get_slot("csv header") {
+        Value::Null => None,
+        header => Some(string_list(agent, &header)?),
+    };
+    let had_header = header.is_some();
+    let rows = records_to_rows(agent, &options, &mut header, records)?;
+    if let (false, Some(header)) = (had_header, header) {
+        let names = header.into_iter().map(Value::from).collect();
+        stream.set_slot("csv header", Value::new_array_from_vec(agent, names));
+    }
+    let queue = stream.get_slot("csv rows");
+    for row in rows {
+        list_push(&queue, row);
+    }
+    Ok(())
+}
+
+fn receive(agent: &Agent, stream: &Value, result: &Value) -> Result<(), Value> {
+    let done = result.get(agent, ObjectKey::from("done"))?.to_bool();
+    if !done {
+        append_chunk(agent, stream, &result.get(agent, ObjectKey::from("value"))?)?;
+    }
+    consume(agent, stream, done)?;
+    if done {
+        stream.set_slot("csv done", Value::from(true));
+    }
+    Ok(())
+}
+
+fn on_chunk(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let stream = ctx.function.as_ref().unwrap().get_slot("csv stream");
+    stream.set_slot("csv pulling", Value::from(false));
+    match receive(agent, &stream, args.get(0).unwrap_or(&Value::Null)) {
+        Ok(()) => pump(agent, &stream)?,
+        Err(e) => fail(agent, &stream, e)?,
+    }
+    Ok(Value::Null)
+}
+
+fn on_error(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let stream = ctx.function.as_ref().unwrap().get_slot("csv stream");
+    stream.set_slot("csv pulling", Value::from(false));
+    fail(agent, &stream, args.get(0).cloned().unwrap_or(Value::Null))?;
+    Ok(Value::Null)
+}
+
+fn stream_next(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("csv waiters") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    list_push(&this.get_slot("csv waiters"), promise.clone());
+    pump(agent, &this)?;
+    Ok(promise)
+}
+
+fn parse_stream(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let source = args.get(0).unwrap_or(&Value::Null);
+    let options = args.get(1).cloned().unwrap_or(Value::Null);
+    // validate eagerly so bad options throw here instead of rejecting later
+    let parsed = Options::from_value(agent, &options)?;
+
+    let (iterator, next) = match source.to_async_iterator(agent)? {
+        Value::Iterator(pair) => *pair,
+        _ => unreachable!(),
+    };
+
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get_slot("csv stream prototype");
+    let stream = Value::new_custom_object(proto);
+    stream.set_slot("csv source", iterator);
+    stream.set_slot("csv next", next);
+    stream.set_slot("csv options", options);
+    stream.set_slot("csv text", Value::from(""));
+    stream.set_slot("csv bytes", Value::Null);
+    stream.set_slot(
+        "csv header",
+        match parsed.columns {
+            Some(columns) => {
+                Value::new_array_from_vec(agent, columns.into_iter().map(Value::from).collect())
+            }
+            None => Value::Null,
+        },
+    );
+    stream.set_slot("csv rows", Value::new_list());
+    stream.set_slot("csv waiters", Value::new_list());
+    stream.set_slot("csv pulling", Value::from(false));
+    stream.set_slot("csv done", Value::from(false));
+    Ok(stream)
+}
+
+fn write_field(out: &mut String, field: &str, options: &Options) {
+    let needs_quotes = field.contains(options.delimiter)
+        || field.contains(options.quote)
+        || field.contains('\n')
+        || field.contains('\r');
+    if needs_quotes {
+        out.push(options.quote);
+        for c in field.chars() {
+            if c == options.quote {
+                out.push(c);
+            }
+            out.push(c);
+        }
+        out.push(options.quote);
+    } else {
+        out.push_str(field);
+    }
+}
+
+fn field_to_string(agent: &Agent, value: &Value) -> Result<String, Value> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(crate::num_util::to_string(*n)),
+        Value::String(s) => Ok(s.to_string()),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("csv: cannot serialize a {} field", value.type_of()),
+        )),
+    }
+}
+
+fn write_record(out: &mut String, fields: &[String], options: &Options) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(options.delimiter);
+        }
+        write_field(out, field, options);
+    }
+    out.push('\n');
+}
+
+fn is_list(value: &Value) -> bool {
+    match value {
+        Value::Tuple(..) => true,
+        Value::Object(o) => match o.kind {
+            ObjectKind::Array(..) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn stringify(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let rows = args.get(0).unwrap_or(&Value::Null);
+    if !is_list(rows) {
+        return Err(Value::new_error(agent, "rows must be an array"));
+    }
+    let options = Options::from_value(agent, args.get(1).unwrap_or(&Value::Null))?;
+    let rows = rows
+        .keys(agent)?
+        .into_iter()
+        .map(|k| rows.get(agent, k))
+        .collect::<Result<Vec<Value>, Value>>()?;
+
+    let mut out = String::new();
+    if rows.iter().all(is_list) {
+        for row in rows {
+            let fields = row
+                .keys(agent)?
+                .into_iter()
+                .map(|k| field_to_string(agent, &row.get(agent, k)?))
+                .collect::<Result<Vec<String>, Value>>()?;
+            write_record(&mut out, &fields, &options);
+        }
+        return Ok(Value::from(out));
+    }
+
+    let columns = match &options.columns {
+        Some(columns) => columns.clone(),
+        None => {
+            let mut columns = Vec::new();
+            for row in &rows {
+                for key in row.keys(agent)? {
+                    if let ObjectKey::Symbol(..) = key {
+                        continue;
+                    }
+                    let key = key.to_string();
+                    if !columns.contains(&key) {
+                        columns.push(key);
+                    }
+                }
+            }
+            columns
+        }
+    };
+    if options.header {
+        write_record(&mut out, &columns, &options);
+    }
+    for row in rows {
+        let fields = columns
+            .iter()
+            .map(|c| field_to_string(agent, &row.get(agent, ObjectKey::from(c.as_str()))?))
+            .collect::<Result<Vec<String>, Value>>()?;
+        write_record(&mut out, &fields, &options);
+    }
+    Ok(Value::from(out))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let stream_prototype = Value::new_object(agent.intrinsics.async_iterator_prototype.clone());
+    stream_prototype
+        .set(
+            agent,
+            ObjectKey::from("next"),
+            Value::new_builtin_function(agent, stream_next),
+        )
+        .unwrap();
+
+    let parse_stream = Value::new_builtin_function(agent, parse_stream);
+    parse_stream.set_slot("csv stream prototype", stream_prototype);
+
+    module.insert(
+        "parse".to_string(),
+        Value::new_builtin_function(agent, parse),
+    );
+    module.insert("parseStream".to_string(), parse_stream);
+    module.insert(
+        "stringify".to_string(),
+        Value::new_builtin_function(agent, stringify),
+    );
+
+    module
+}
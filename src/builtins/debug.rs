@@ -7,7 +7,7 @@ fn print(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     for v in args {
         inspected.push(Value::inspect(agent, &v));
     }
-    println!("{}", inspected.join(" "));
+    agent.print(&inspected.join(" "));
     Ok(Value::Null)
 }
 
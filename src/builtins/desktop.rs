@@ -0,0 +1,133 @@
+use crate::agent::Agent;
+use crate::builtins::fs::{spawn, FsResponse};
+use crate::interpreter::Context;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// the commands that can read the clipboard, write it, or open something
+// with the user's default application, tried in order until one is
+// installed
+#[cfg(target_os = "macos")]
+mod commands {
+    pub const READ: &[&[&str]] = &[&["pbpaste"]];
+    pub const WRITE: &[&[&str]] = &[&["pbcopy"]];
+    pub const OPEN: &[&[&str]] = &[&["open"]];
+}
+
+#[cfg(windows)]
+mod commands {
+    pub const READ: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+    pub const WRITE: &[&[&str]] = &[&["clip"]];
+    // the empty string is the window title start expects first
+    pub const OPEN: &[&[&str]] = &[&["cmd", "/C", "start", ""]];
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod commands {
+    pub const READ: &[&[&str]] = &[
+        &["wl-paste", "--no-newline"],
+        &["xclip", "-selection", "clipboard", "-out"],
+        &["xsel", "--clipboard", "--output"],
+    ];
+    pub const WRITE: &[&[&str]] = &[
+        &["wl-copy"],
+        &["xclip", "-selection", "clipboard", "-in"],
+        &["xsel", "--clipboard", "--input"],
+    ];
+    pub const OPEN: &[&[&str]] = &[&["xdg-open"]];
+}
+
+fn run(candidates: &[&[&str]], argument: Option<String>, input: Option<String>) -> FsResponse {
+    for command in candidates {
+        let mut child = match Command::new(command[0])
+            .args(&command[1..])
+            .args(argument.iter())
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return FsResponse::Error(format!("{}: {}", command[0], e)),
+        };
+        if let (Some(input), Some(mut stdin)) = (&input, child.stdin.take()) {
+            if let Err(e) = stdin.write_all(input.as_bytes()) {
+                return FsResponse::Error(format!("{}: {}", command[0], e));
+            }
+        }
+        return match child.wait_with_output() {
+            Ok(output) if output.status.success() => {
+                FsResponse::Read(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            Ok(output) => FsResponse::Error(format!(
+                "{} failed: {}",
+                command[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => FsResponse::Error(format!("{}: {}", command[0], e)),
+        };
+    }
+    let names = candidates.iter().map(|c| c[0]).collect::<Vec<_>>();
+    FsResponse::Error(format!("none of {} is installed", names.join(", ")))
+}
+
+fn read_clipboard(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    spawn(agent, "desktop.readClipboard", "", || {
+        run(commands::READ, None, None)
+    })
+}
+
+fn write_clipboard(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let text = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "text must be a string")),
+    };
+    spawn(agent, "desktop.writeClipboard", "", move || {
+        match run(commands::WRITE, None, Some(text)) {
+            FsResponse::Read(_) => FsResponse::Success,
+            response => response,
+        }
+    })
+}
+
+/// Open a URL or file with the application the user has set up for it.
+fn open(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let target = match args.get(0) {
+        Some(Value::String(s)) if !s.is_empty() => s.to_string(),
+        _ => return Err(Value::new_error(agent, "target must be a URL or path")),
+    };
+    let path = target.clone();
+    spawn(agent, "desktop.open", &path, move || {
+        match run(commands::OPEN, Some(target), None) {
+            FsResponse::Read(_) => FsResponse::Success,
+            response => response,
+        }
+    })
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            let f = Value::new_builtin_function(agent, $fn);
+            // these start other programs, so they need the same grant as
+            // standard:process
+            f.set_slot("capability", Value::from("process"));
+            f.set_slot("operation", Value::from(concat!("desktop.", $name)));
+            module.insert($name.to_string(), f);
+        };
+    }
+    method!("readClipboard", read_clipboard);
+    method!("writeClipboard", write_clipboard);
+    method!("open", open);
+
+    module
+}
@@ -0,0 +1,93 @@
+use crate::agent::Agent;
+use crate::diff::{self, Change};
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use std::collections::HashMap;
+
+fn string_args(agent: &Agent, args: &[Value]) -> Result<(String, String), Value> {
+    match (args.get(0), args.get(1)) {
+        (Some(Value::String(old)), Some(Value::String(new))) => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err(Value::new_error(agent, "expected two strings")),
+    }
+}
+
+fn changes_to_array<S: AsRef<str>>(
+    agent: &Agent,
+    changes: Vec<(Change, S)>,
+) -> Result<Value, Value> {
+    let mut items = Vec::with_capacity(changes.len());
+    for (change, value) in changes {
+        let item = Value::new_object(agent.intrinsics.object_prototype.clone());
+        item.set(agent, ObjectKey::from("op"), Value::from(change.name()))?;
+        item.set(agent, ObjectKey::from("value"), Value::from(value.as_ref()))?;
+        items.push(item);
+    }
+    Ok(Value::new_array_from_vec(agent, items))
+}
+
+/// One `{ op, value }` entry per line, `op` being `equal`, `delete` or
+/// `insert`.
+fn lines(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (old, new) = string_args(agent, &args)?;
+    changes_to_array(agent, diff::lines(&old, &new))
+}
+
+/// Like `lines`, for runs of words and the whitespace between them.
+fn words(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (old, new) = string_args(agent, &args)?;
+    changes_to_array(agent, diff::words(&old, &new))
+}
+
+fn unified(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (old, new) = string_args(agent, &args)?;
+    let options = args.get(2).cloned().unwrap_or(Value::Null);
+    let (mut context, mut from, mut to) = (3, "a".to_string(), "b".to_string());
+    if options != Value::Null {
+        if options.type_of() != "object" {
+            return Err(Value::new_error(agent, "options must be an object"));
+        }
+        match options.get(agent, ObjectKey::from("context"))? {
+            Value::Null => {}
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => context = n as usize,
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    "context must be a non-negative integer",
+                ))
+            }
+        }
+        for (name, slot) in &mut [("from", &mut from), ("to", &mut to)] {
+            match options.get(agent, ObjectKey::from(*name))? {
+                Value::Null => {}
+                Value::String(s) => **slot = s.to_string(),
+                _ => {
+                    return Err(Value::new_error(
+                        agent,
+                        &format!("{} must be a string", name),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(Value::from(diff::unified(&old, &new, context, &from, &to)))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "lines".to_string(),
+        Value::new_builtin_function(agent, lines),
+    );
+    module.insert(
+        "words".to_string(),
+        Value::new_builtin_function(agent, words),
+    );
+    module.insert(
+        "unified".to_string(),
+        Value::new_builtin_function(agent, unified),
+    );
+
+    module
+}
@@ -0,0 +1,34 @@
+use crate::interpreter::Context;
+use crate::{Agent, Value};
+use std::collections::HashMap;
+
+fn event_name(agent: &Agent, args: &[Value]) -> Result<String, Value> {
+    match args.get(0) {
+        Some(Value::String(name)) => Ok(name.to_string()),
+        _ => Err(Value::new_error(agent, "event name must be a string")),
+    }
+}
+
+fn on(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let name = event_name(agent, &args)?;
+    let listener = args.get(1).cloned().unwrap_or(Value::Null);
+    if listener.type_of() != "function" {
+        return Err(Value::new_error(agent, "listener must be a function"));
+    }
+    agent.add_event_listener(name, listener);
+    Ok(Value::Null)
+}
+
+fn off(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let name = event_name(agent, &args)?;
+    let listener = args.get(1).cloned().unwrap_or(Value::Null);
+    Ok(Value::from(agent.remove_event_listener(&name, &listener)))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert("on".to_string(), Value::new_builtin_function(agent, on));
+    module.insert("off".to_string(), Value::new_builtin_function(agent, off));
+
+    module
+}
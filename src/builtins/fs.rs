@@ -1,10 +1,11 @@
 use crate::agent::{Agent, MioMapType};
+use crate::builtins::trace;
 use crate::interpreter::Context;
 use crate::intrinsics::promise::new_promise_capability;
 use crate::value::{ObjectKey, Value};
 use lazy_static::lazy_static;
 use mio::{PollOpt, Ready, Registration, Token};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Mutex;
 
 lazy_static! {
@@ -13,14 +14,72 @@ lazy_static! {
 
 pub enum FsResponse {
     Read(String),
+    Bytes(Vec<u8>),
     Metadata(std::fs::Metadata),
     Exists(bool),
+    /// The metadata of something in a `MemoryFs`: its type, size and when
+    /// it was modified.
+    Stat(&'static str, u64, f64),
     Success,
     Error(String),
 }
 
+fn trace_op(agent: &Agent, promise: &Value, name: &str, path: &str) {
+    let span = trace::start_builtin_span(agent, name, vec![("fs.path", Value::from(path))]);
+    promise.set_slot("trace span", span);
+}
+
 pub fn handle(agent: &Agent, token: Token, promise: Value) {
     let fsr = RESPONSES.lock().unwrap().remove(&token).unwrap();
+    if promise.has_slot("trace span") {
+        let span = promise.get_slot("trace span");
+        match &fsr {
+            FsResponse::Error(s) => {
+                trace::end_builtin_span(agent, &span, Some(&Value::from(s.clone())))
+            }
+            _ => trace::end_builtin_span(agent, &span, None),
+        }
+    }
+    settle(agent, promise, fsr);
+}
+
+/// Run `op` on the worker pool and return a promise settled with what it
+/// responds, traced as `name` on `path`.
+pub fn spawn<F>(agent: &Agent, name: &str, path: &str, op: F) -> Result<Value, Value>
+where
+    F: FnOnce() -> FsResponse + Send + 'static,
+{
+    let promise = spawn_untraced(agent, op)?;
+    trace_op(agent, &promise, name, path);
+    Ok(promise)
+}
+
+/// Like `spawn`, without a span, for the tracer's own writes.
+pub fn spawn_untraced<F>(agent: &Agent, op: F) -> Result<Value, Value>
+where
+    F: FnOnce() -> FsResponse + Send + 'static,
+{
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.mio_token();
+
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+
+    agent.pool.execute(move || {
+        let response = op();
+        RESPONSES.lock().unwrap().insert(token, response);
+        set_readiness.set_readiness(Ready::readable()).unwrap();
+    });
+
+    Ok(promise)
+}
+
+fn settle(agent: &Agent, promise: Value, fsr: FsResponse) {
     match fsr {
         FsResponse::Read(s) => {
             promise
@@ -28,48 +87,49 @@ pub fn handle(agent: &Agent, token: Token, promise: Value) {
                 .call(agent, promise, vec![Value::from(s)])
                 .unwrap();
         }
+        FsResponse::Bytes(b) => {
+            let buffer = Value::new_buffer_from_vec(agent, b);
+            promise
+                .get_slot("resolve")
+                .call(agent, promise, vec![buffer])
+                .unwrap();
+        }
         FsResponse::Metadata(m) => {
-            let o = Value::new_object(agent.intrinsics.object_prototype.clone());
-            macro_rules! p {
-                ($target:expr, $name:expr, $value:expr) => {
-                    $target.set(agent, ObjectKey::from($name), $value).unwrap();
-                };
-            }
             let ft = m.file_type();
-            if ft.is_file() {
-                p!(o, "type", Value::from("file"));
+            let kind = if ft.is_file() {
+                "file"
             } else if ft.is_dir() {
-                p!(o, "type", Value::from("directory"));
+                "directory"
             } else if ft.is_symlink() {
-                p!(o, "type", Value::from("symlink"));
+                "symlink"
             } else {
                 unreachable!();
-            }
-            p!(o, "size", Value::from(m.len() as f64));
+            };
             macro_rules! t {
-                ($name:expr, $value:expr) => {
+                ($value:expr) => {{
                     let d = $value
                         .unwrap()
                         .duration_since(std::time::SystemTime::UNIX_EPOCH)
                         .unwrap();
                     let seconds = d.as_secs();
                     let subsec_millis = u64::from(d.subsec_millis());
-                    let ms = seconds * 1000 + subsec_millis;
-                    p!(o, $name, Value::from(ms as f64));
-                };
+                    (seconds * 1000 + subsec_millis) as f64
+                }};
             }
-            t!("modifiedAt", m.modified());
-            t!("accessedAt", m.accessed());
-            t!("createdAt", m.created());
-
-            let permissions = Value::new_object(agent.intrinsics.object_prototype.clone());
-            p!(
-                permissions,
-                "read",
-                Value::from(!m.permissions().readonly())
+            let o = metadata(
+                agent,
+                kind,
+                m.len() as f64,
+                [t!(m.modified()), t!(m.accessed()), t!(m.created())],
+                !m.permissions().readonly(),
             );
-            p!(o, "permissions", permissions);
-
+            promise
+                .get_slot("resolve")
+                .call(agent, promise, vec![o])
+                .unwrap();
+        }
+        FsResponse::Stat(kind, size, modified) => {
+            let o = metadata(agent, kind, size as f64, [modified; 3], true);
             promise
                 .get_slot("resolve")
                 .call(agent, promise, vec![o])
@@ -96,46 +156,264 @@ pub fn handle(agent: &Agent, token: Token, promise: Value) {
     }
 }
 
-fn read_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
-    if let Some(Value::String(filename)) = args.get(0) {
-        let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+// modified, accessed and created times are in ms since the epoch
+fn metadata(agent: &Agent, kind: &str, size: f64, times: [f64; 3], read: bool) -> Value {
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    macro_rules! p {
+        ($target:expr, $name:expr, $value:expr) => {
+            $target.set(agent, ObjectKey::from($name), $value).unwrap();
+        };
+    }
+    p!(o, "type", Value::from(kind));
+    p!(o, "size", Value::from(size));
+    p!(o, "modifiedAt", Value::from(times[0]));
+    p!(o, "accessedAt", Value::from(times[1]));
+    p!(o, "createdAt", Value::from(times[2]));
+
+    let permissions = Value::new_object(agent.intrinsics.object_prototype.clone());
+    p!(permissions, "read", Value::from(read));
+    p!(o, "permissions", permissions);
+    o
+}
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = agent.mio_token();
+/// The files `standard:fs` works on once a test calls `useMemoryFs` from
+/// `standard:test`, instead of the disk. A directory exists if it was
+/// created or something in it was, so writing a file needs no parents.
+#[derive(Default)]
+pub struct MemoryFs {
+    // contents and when they were written
+    files: BTreeMap<String, (String, f64)>,
+    directories: BTreeSet<String>,
+}
 
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
+impl MemoryFs {
+    pub fn write(&mut self, path: &str, contents: String, now: f64) {
+        self.files.insert(normalize(path), (contents, now));
+    }
 
-        let filename = filename.to_string();
-        agent
-            .pool
-            .execute(move || match std::fs::read_to_string(filename) {
-                Ok(s) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Read(s));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.files
+            .iter()
+            .map(|(k, (v, _))| (k.as_str(), v.as_str()))
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        let prefix = format!("{}/", path);
+        path == "/"
+            || self.directories.contains(path)
+            || self.directories.iter().any(|d| d.starts_with(&prefix))
+            || self.files.keys().any(|f| f.starts_with(&prefix))
+    }
+
+    fn call(&mut self, operation: &str, args: &[String], now: f64) -> FsResponse {
+        let path = normalize(&args[0]);
+        let not_found = || FsResponse::Error(format!("{}: no such file or directory", path));
+        match operation {
+            "readFile" => match self.files.get(&path) {
+                Some((contents, _)) => FsResponse::Read(contents.clone()),
+                None if self.is_dir(&path) => {
+                    FsResponse::Error(format!("{}: is a directory", path))
                 }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
+                None => not_found(),
+            },
+            "writeFile" if self.is_dir(&path) => {
+                FsResponse::Error(format!("{}: is a directory", path))
+            }
+            "writeFile" => {
+                self.files.insert(path, (args[1].clone(), now));
+                FsResponse::Success
+            }
+            "removeFile" => match self.files.remove(&path) {
+                Some(_) => FsResponse::Success,
+                None => not_found(),
+            },
+            "getMetadata" => match self.files.get(&path) {
+                Some((contents, modified)) => {
+                    FsResponse::Stat("file", contents.len() as u64, *modified)
                 }
-            });
+                None if self.is_dir(&path) => FsResponse::Stat("directory", 0, 0.0),
+                None => not_found(),
+            },
+            "copy" | "move" => {
+                let to = normalize(&args[1]);
+                let file = if operation == "copy" {
+                    self.files.get(&path).cloned()
+                } else {
+                    self.files.remove(&path)
+                };
+                match file {
+                    Some((contents, _)) => {
+                        self.files.insert(to, (contents, now));
+                        FsResponse::Success
+                    }
+                    None => not_found(),
+                }
+            }
+            "exists" => FsResponse::Exists(self.files.contains_key(&path) || self.is_dir(&path)),
+            "createDirectory" => {
+                if self.files.contains_key(&path) || self.is_dir(&path) {
+                    FsResponse::Error(format!("{}: already exists", path))
+                } else {
+                    self.directories.insert(path);
+                    FsResponse::Success
+                }
+            }
+            "removeDirectory" => {
+                let prefix = format!("{}/", path);
+                if !self.is_dir(&path) {
+                    not_found()
+                } else if self.files.keys().any(|f| f.starts_with(&prefix))
+                    || self.directories.iter().any(|d| d.starts_with(&prefix))
+                {
+                    FsResponse::Error(format!("{}: directory not empty", path))
+                } else {
+                    self.directories.remove(&path);
+                    FsResponse::Success
+                }
+            }
+            _ => FsResponse::Error(format!("{} is not supported in memory", operation)),
+        }
+    }
+}
 
-        Ok(promise)
+fn normalize(path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/".to_string(),
+        path => path.to_string(),
+    }
+}
+
+// answers right away from the memory fs when a test is using one
+fn in_memory(agent: &Agent, operation: &str, args: &[Value]) -> Option<Result<Value, Value>> {
+    let now = crate::builtins::timers::now_ms(agent);
+    let arity = if let "writeFile" | "copy" | "move" | "createSymbolicLink" = operation {
+        2
+    } else {
+        1
+    };
+    let args = args
+        .iter()
+        .take(arity)
+        .map(|a| match a {
+            Value::String(s) => Some(s.to_string()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|args| args.len() == arity)?;
+    let response = agent
+        .memory_fs
+        .borrow_mut()
+        .as_mut()?
+        .call(operation, &args, now);
+    Some(
+        new_promise_capability(agent, agent.intrinsics.promise.clone())
+            .inspect(|promise| settle(agent, promise.clone(), response)),
+    )
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Latin1,
+    Binary,
+}
+
+// how readFile turns the bytes of a file into what it resolves with
+struct ReadOptions {
+    encoding: Encoding,
+    strip_bom: bool,
+}
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn read_options(agent: &Agent, options: Option<&Value>) -> Result<ReadOptions, Value> {
+    let mut read = ReadOptions {
+        encoding: Encoding::Utf8,
+        strip_bom: false,
+    };
+    let options = match options {
+        None | Some(Value::Null) => return Ok(read),
+        Some(options) => options,
+    };
+    if options.has(agent, ObjectKey::from("encoding"))? {
+        read.encoding = match options.get(agent, ObjectKey::from("encoding"))? {
+            Value::String(ref e) if e.as_str() == "utf-8" || e.as_str() == "utf8" => Encoding::Utf8,
+            Value::String(ref e) if e.as_str() == "latin1" => Encoding::Latin1,
+            Value::String(ref e) if e.as_str() == "binary" => Encoding::Binary,
+            Value::Null => Encoding::Binary,
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    "encoding must be 'utf-8', 'latin1', 'binary' or null",
+                ))
+            }
+        };
+    }
+    read.strip_bom = match options.get(agent, ObjectKey::from("stripBom"))? {
+        Value::Null => false,
+        Value::Boolean(b) => b,
+        _ => return Err(Value::new_error(agent, "stripBom must be a boolean")),
+    };
+    Ok(read)
+}
+
+// a Buffer keeps its bytes as they are, BOM included
+fn decode(path: &str, mut bytes: Vec<u8>, options: &ReadOptions) -> FsResponse {
+    if options.strip_bom && options.encoding != Encoding::Binary && bytes.starts_with(BOM) {
+        bytes.drain(..BOM.len());
+    }
+    match options.encoding {
+        Encoding::Utf8 => match String::from_utf8(bytes) {
+            Ok(s) => FsResponse::Read(s),
+            Err(e) => FsResponse::Error(format!(
+                "{}: invalid UTF-8 at byte {}",
+                path,
+                e.utf8_error().valid_up_to()
+            )),
+        },
+        Encoding::Latin1 => FsResponse::Read(bytes.iter().map(|b| char::from(*b)).collect()),
+        Encoding::Binary => FsResponse::Bytes(bytes),
+    }
+}
+
+/// Read a file as UTF-8 text, which fails at the first invalid byte. The
+/// options can ask for `encoding: 'latin1'` text instead, or a Buffer with
+/// `encoding: 'binary'` or `null`, and `stripBom` drops a leading byte
+/// order mark from text.
+fn read_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = read_options(agent, args.get(1))?;
+    if let Some(Value::String(filename)) = args.get(0) {
+        let now = crate::builtins::timers::now_ms(agent);
+        let in_memory = agent
+            .memory_fs
+            .borrow_mut()
+            .as_mut()
+            .map(|memory| memory.call("readFile", std::slice::from_ref(filename), now));
+        if let Some(response) = in_memory {
+            let response = match response {
+                FsResponse::Read(s) => decode(filename, s.into_bytes(), &options),
+                response => response,
+            };
+            return new_promise_capability(agent, agent.intrinsics.promise.clone())
+                .inspect(|promise| settle(agent, promise.clone(), response));
+        }
+        let path = filename.to_string();
+        let filename = filename.to_string();
+        spawn(agent, "fs.readFile", &path, move || {
+            match std::fs::read(&filename) {
+                Ok(bytes) => decode(&filename, bytes, &options),
+                Err(e) => FsResponse::Error(format!("{}", e)),
+            }
+        })
     } else {
         Err(Value::new_error(agent, "filename must be a string"))
     }
 }
 
 fn write_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "writeFile", &args) {
+        return result;
+    }
     if let Some(Value::String(filename)) = args.get(0) {
         if let Some(Value::String(contents)) = args.get(1) {
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
@@ -147,10 +425,8 @@ fn write_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Val
                 .mio
                 .register(&registration, token, Ready::readable(), PollOpt::edge())
                 .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
+            agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+            trace_op(agent, &promise, "fs.writeFile", filename);
 
             let filename = filename.to_string();
             let contents = contents.to_string();
@@ -180,6 +456,9 @@ fn write_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Val
 }
 
 fn remove_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "removeFile", &args) {
+        return result;
+    }
     if let Some(Value::String(filename)) = args.get(0) {
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
@@ -190,10 +469,8 @@ fn remove_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Va
             .mio
             .register(&registration, token, Ready::readable(), PollOpt::edge())
             .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
+        agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+        trace_op(agent, &promise, "fs.removeFile", filename);
 
         let filename = filename.to_string();
         agent
@@ -219,6 +496,9 @@ fn remove_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Va
 }
 
 fn get_metadata(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "getMetadata", &args) {
+        return result;
+    }
     if let Some(Value::String(filename)) = args.get(0) {
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
@@ -229,10 +509,8 @@ fn get_metadata(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, V
             .mio
             .register(&registration, token, Ready::readable(), PollOpt::edge())
             .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
+        agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+        trace_op(agent, &promise, "fs.getMetadata", filename);
 
         let filename = filename.to_string();
         agent
@@ -261,6 +539,9 @@ fn get_metadata(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, V
 }
 
 fn copy(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "copy", &args) {
+        return result;
+    }
     if let Some(Value::String(from)) = args.get(0) {
         if let Some(Value::String(to)) = args.get(1) {
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
@@ -272,10 +553,8 @@ fn copy(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
                 .mio
                 .register(&registration, token, Ready::readable(), PollOpt::edge())
                 .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
+            agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+            trace_op(agent, &promise, "fs.copy", from);
 
             let from = from.to_string();
             let to = to.to_string();
@@ -303,6 +582,9 @@ fn copy(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
 }
 
 fn move_(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "move", &args) {
+        return result;
+    }
     if let Some(Value::String(from)) = args.get(0) {
         if let Some(Value::String(to)) = args.get(1) {
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
@@ -314,10 +596,8 @@ fn move_(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
                 .mio
                 .register(&registration, token, Ready::readable(), PollOpt::edge())
                 .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
+            agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+            trace_op(agent, &promise, "fs.move", from);
 
             let from = from.to_string();
             let to = to.to_string();
@@ -359,6 +639,9 @@ fn symlink(from: String, to: String) -> std::io::Result<()> {
 }
 
 fn create_symlink(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "createSymbolicLink", &args) {
+        return result;
+    }
     if let Some(Value::String(from)) = args.get(0) {
         if let Some(Value::String(to)) = args.get(1) {
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
@@ -370,10 +653,8 @@ fn create_symlink(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value,
                 .mio
                 .register(&registration, token, Ready::readable(), PollOpt::edge())
                 .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
+            agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+            trace_op(agent, &promise, "fs.createSymbolicLink", from);
 
             let from = from.to_string();
             let to = to.to_string();
@@ -401,6 +682,9 @@ fn create_symlink(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value,
 }
 
 fn exists(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "exists", &args) {
+        return result;
+    }
     if let Some(Value::String(filename)) = args.get(0) {
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
@@ -411,10 +695,8 @@ fn exists(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
             .mio
             .register(&registration, token, Ready::readable(), PollOpt::edge())
             .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
+        agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+        trace_op(agent, &promise, "fs.exists", filename);
 
         let filename = filename.to_string();
         agent.pool.execute(move || {
@@ -433,6 +715,9 @@ fn exists(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
 }
 
 fn create_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "createDirectory", &args) {
+        return result;
+    }
     if let Some(Value::String(filename)) = args.get(0) {
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
@@ -443,10 +728,8 @@ fn create_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Valu
             .mio
             .register(&registration, token, Ready::readable(), PollOpt::edge())
             .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
+        agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+        trace_op(agent, &promise, "fs.createDirectory", filename);
 
         let filename = filename.to_string();
         agent
@@ -472,6 +755,9 @@ fn create_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Valu
 }
 
 fn remove_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(result) = in_memory(agent, "removeDirectory", &args) {
+        return result;
+    }
     if let Some(Value::String(filename)) = args.get(0) {
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
@@ -482,10 +768,8 @@ fn remove_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Valu
             .mio
             .register(&registration, token, Ready::readable(), PollOpt::edge())
             .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
+        agent.insert_op(token, MioMapType::FS(registration, promise.clone()));
+        trace_op(agent, &promise, "fs.removeDirectory", filename);
 
         let filename = filename.to_string();
         agent
@@ -0,0 +1,37 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::promise::new_promise_capability;
+use crate::value::Value;
+use std::collections::HashMap;
+
+// files come from the host, which answers right away
+fn read_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(Value::String(filename)) = args.get(0) {
+        let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+        match agent.host().read_file(filename) {
+            Ok(s) => {
+                promise
+                    .get_slot("resolve")
+                    .call(agent, promise.clone(), vec![Value::from(s)])?
+            }
+            Err(e) => promise.get_slot("reject").call(
+                agent,
+                promise.clone(),
+                vec![Value::new_error(agent, e.as_str())],
+            )?,
+        };
+        Ok(promise)
+    } else {
+        Err(Value::new_error(agent, "filename must be a string"))
+    }
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "readFile".to_string(),
+        Value::new_builtin_function(agent, read_file),
+    );
+
+    module
+}
@@ -0,0 +1,926 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// elements that never have children in HTML
+const VOID: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+// elements whose content is not markup in HTML
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+
+// elements an opening tag of the same name implicitly closes
+const AUTO_CLOSE: [&str; 8] = ["li", "p", "option", "tr", "td", "th", "dt", "dd"];
+
+type Attributes = Vec<(String, String)>;
+
+enum Node {
+    Element(String, Attributes, Vec<Node>),
+    Text(String),
+    Comment(String),
+    Declaration(String),
+}
+
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = match rest[1..].find(';') {
+            Some(end) if end <= 10 => end + 1,
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let name = &rest[1..end];
+        let c = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{A0}'),
+            _ if name.starts_with("#x") || name.starts_with("#X") => {
+                u32::from_str_radix(&name[2..], 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+            }
+            _ if name.starts_with('#') => name[1..].parse().ok().and_then(std::char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    i: usize,
+    xml: bool,
+    // open elements, the document's children at the bottom
+    stack: Vec<(String, Attributes, Vec<Node>)>,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.i..]
+    }
+
+    fn push(&mut self, node: Node) {
+        self.stack.last_mut().unwrap().2.push(node);
+    }
+
+    // close the open elements down to `depth`
+    fn close(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            let (tag, attributes, children) = self.stack.pop().unwrap();
+            self.push(Node::Element(tag, attributes, children));
+        }
+    }
+
+    // everything up to `end`, skipping past it, or the rest of the source
+    fn until(&mut self, end: &str) -> &'a str {
+        let rest = self.rest();
+        match rest.find(end) {
+            Some(i) => {
+                self.i += i + end.len();
+                &rest[..i]
+            }
+            None => {
+                self.i = self.source.len();
+                rest
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+            .unwrap_or(rest.len());
+        self.i += end;
+        if self.xml {
+            rest[..end].to_string()
+        } else {
+            rest[..end].to_ascii_lowercase()
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.i += rest.len() - rest.trim_start().len();
+    }
+
+    fn attribute_value(&mut self) -> String {
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some(q) if q == '"' || q == '\'' => {
+                self.i += 1;
+                decode_entities(self.until(if q == '"' { "\"" } else { "'" }))
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                self.i += end;
+                decode_entities(&rest[..end])
+            }
+        }
+    }
+
+    fn open_tag(&mut self) {
+        self.i += 1;
+        let tag = self.name();
+        let mut attributes = Vec::new();
+        let mut self_closing = false;
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.is_empty() {
+                break;
+            } else if rest.starts_with('>') {
+                self.i += 1;
+                break;
+            } else if rest.starts_with("/>") {
+                self.i += 2;
+                self_closing = true;
+                break;
+            } else if rest.starts_with('/') || rest.starts_with('=') {
+                self.i += 1;
+                continue;
+            }
+            let name = self.name();
+            self.skip_whitespace();
+            let value = if self.rest().starts_with('=') {
+                self.i += 1;
+                self.skip_whitespace();
+                self.attribute_value()
+            } else {
+                String::new()
+            };
+            if !attributes.iter().any(|(n, _)| *n == name) {
+                attributes.push((name, value));
+            }
+        }
+
+        if !self.xml
+            && AUTO_CLOSE.contains(&tag.as_str())
+            && self.stack.len() > 1
+            && self.stack.last().unwrap().0 == tag
+        {
+            self.close(self.stack.len() - 1);
+        }
+        if self_closing || (!self.xml && VOID.contains(&tag.as_str())) {
+            self.push(Node::Element(tag, attributes, Vec::new()));
+        } else if !self.xml && RAW_TEXT.contains(&tag.as_str()) {
+            let rest = self.rest();
+            let end = rest
+                .to_ascii_lowercase()
+                .find(&format!("</{}", tag))
+                .unwrap_or(rest.len());
+            self.i += end;
+            self.until(">");
+            let children = if end == 0 {
+                Vec::new()
+            } else {
+                vec![Node::Text(rest[..end].to_string())]
+            };
+            self.push(Node::Element(tag, attributes, children));
+        } else {
+            self.stack.push((tag, attributes, Vec::new()));
+        }
+    }
+
+    fn close_tag(&mut self) {
+        self.i += 2;
+        let tag = self.name();
+        self.until(">");
+        // a stray closing tag is dropped
+        if let Some(depth) = self.stack.iter().skip(1).rposition(|(t, _, _)| *t == tag) {
+            self.close(depth + 1);
+        }
+    }
+
+    fn parse(mut self) -> Vec<Node> {
+        while self.i < self.source.len() {
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.i += 4;
+                let text = self.until("-->").to_string();
+                self.push(Node::Comment(text));
+            } else if rest.starts_with("<![CDATA[") {
+                self.i += 9;
+                let text = self.until("]]>").to_string();
+                self.push(Node::Text(text));
+            } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                self.i += 1;
+                let text = self.until(">").to_string();
+                self.push(Node::Declaration(text));
+            } else if rest.starts_with("</") {
+                self.close_tag();
+            } else if rest.starts_with('<')
+                && rest[1..].chars().next().map_or(false, char::is_alphabetic)
+            {
+                self.open_tag();
+            } else {
+                // a `<` that starts no tag is text
+                let end = rest[1..].find('<').map_or(rest.len(), |i| i + 1);
+                self.i += end;
+                let text = decode_entities(&rest[..end]);
+                match self.stack.last_mut().unwrap().2.last_mut() {
+                    Some(Node::Text(last)) => last.push_str(&text),
+                    _ => self.push(Node::Text(text)),
+                }
+            }
+        }
+        self.close(1);
+        self.stack.pop().unwrap().2
+    }
+}
+
+fn prototype(agent: &Agent) -> Value {
+    agent
+        .builtin("html", "parse")
+        .unwrap()
+        .get_slot("html node prototype")
+}
+
+fn new_node(agent: &Agent, kind: &str, xml: bool) -> Result<Value, Value> {
+    let node = Value::new_custom_object(prototype(agent));
+    node.set_slot("html xml", Value::from(xml));
+    node.set(agent, ObjectKey::from("type"), Value::from(kind))?;
+    Ok(node)
+}
+
+fn to_value(agent: &Agent, node: Node, xml: bool) -> Result<Value, Value> {
+    let (kind, text) = match node {
+        Node::Element(tag, attributes, children) => {
+            let value = new_node(agent, "element", xml)?;
+            value.set(agent, ObjectKey::from("tag"), Value::from(tag))?;
+            let attrs = Value::new_object(agent.intrinsics.object_prototype.clone());
+            for (name, v) in attributes {
+                attrs.set(agent, ObjectKey::from(name), Value::from(v))?;
+            }
+            value.set(agent, ObjectKey::from("attributes"), attrs)?;
+            let children = children_to_array(agent, children, xml)?;
+            value.set(agent, ObjectKey::from("children"), children)?;
+            return Ok(value);
+        }
+        Node::Text(text) => ("text", text),
+        Node::Comment(text) => ("comment", text),
+        Node::Declaration(text) => ("declaration", text),
+    };
+    let value = new_node(agent, kind, xml)?;
+    value.set(agent, ObjectKey::from("text"), Value::from(text))?;
+    Ok(value)
+}
+
+fn children_to_array(agent: &Agent, nodes: Vec<Node>, xml: bool) -> Result<Value, Value> {
+    let mut children = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        children.push(to_value(agent, node, xml)?);
+    }
+    Ok(Value::new_array_from_vec(agent, children))
+}
+
+fn parse_document(agent: &Agent, args: &[Value], xml: bool) -> Result<Value, Value> {
+    let source = match args.get(0) {
+        Some(Value::String(s)) => s,
+        _ => return Err(Value::new_error(agent, "source must be a string")),
+    };
+    let parser = Parser {
+        source,
+        i: 0,
+        xml,
+        stack: vec![(String::new(), Vec::new(), Vec::new())],
+    };
+    let document = new_node(agent, "document", xml)?;
+    let children = children_to_array(agent, parser.parse(), xml)?;
+    document.set(agent, ObjectKey::from("children"), children)?;
+    Ok(document)
+}
+
+fn parse(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    parse_document(agent, &args, false)
+}
+
+fn parse_xml(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    parse_document(agent, &args, true)
+}
+
+// reading the tree back goes through properties, so scripts can edit it or
+// build nodes of their own
+
+fn string_property(agent: &Agent, node: &Value, name: &str) -> Result<String, Value> {
+    match node.get(agent, ObjectKey::from(name))? {
+        Value::String(s) => Ok(s.to_string()),
+        Value::Null => Ok(String::new()),
+        Value::Number(n) => Ok(crate::num_util::to_string(n)),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("html: the {} of a node must be a string", name),
+        )),
+    }
+}
+
+fn node_type(agent: &Agent, node: &Value) -> Result<String, Value> {
+    if node.type_of() != "object" {
+        return Err(Value::new_error(agent, "html: expected a node"));
+    }
+    string_property(agent, node, "type")
+}
+
+fn children(agent: &Agent, node: &Value) -> Result<Vec<Value>, Value> {
+    let list = node.get(agent, ObjectKey::from("children"))?;
+    if list == Value::Null {
+        return Ok(Vec::new());
+    }
+    let length = match list.get(agent, ObjectKey::from("length"))? {
+        Value::Number(n) => n as usize,
+        _ => return Err(Value::new_error(agent, "html: children must be an array")),
+    };
+    (0..length)
+        .map(|i| list.get(agent, ObjectKey::from(i)))
+        .collect()
+}
+
+fn attributes(agent: &Agent, node: &Value) -> Result<Vec<(String, String)>, Value> {
+    let attrs = node.get(agent, ObjectKey::from("attributes"))?;
+    if attrs == Value::Null {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for key in attrs.keys(agent)? {
+        let value = match attrs.get(agent, key.clone())? {
+            Value::Null => continue,
+            Value::String(s) => s.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            Value::Number(n) => crate::num_util::to_string(n),
+            _ => return Err(Value::new_error(agent, "html: attributes must be strings")),
+        };
+        out.push((key.to_string(), value));
+    }
+    Ok(out)
+}
+
+fn is_xml(node: &Value) -> bool {
+    node.has_slot("html xml") && node.get_slot("html xml") == Value::from(true)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum AttrOp {
+    Equals,
+    Word,
+    Prefix,
+    Suffix,
+    Contains,
+    Dash,
+}
+
+enum Simple {
+    Tag(String),
+    Id(String),
+    Class(String),
+    Attribute(String, Option<(AttrOp, String)>),
+    FirstChild,
+    LastChild,
+    Not(Vec<Simple>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    Adjacent,
+    Sibling,
+}
+
+// the compounds of a complex selector, each with the combinator before it
+type Complex = Vec<(Combinator, Vec<Simple>)>;
+
+struct SelectorParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    xml: bool,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+            self.chars.next();
+            skipped = true;
+        }
+        skipped
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '-' || c == '_' || (c == ':' && self.xml) {
+                s.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        if s.is_empty() {
+            None
+        } else if self.xml {
+            Some(s)
+        } else {
+            Some(s.to_ascii_lowercase())
+        }
+    }
+
+    fn value(&mut self) -> Option<String> {
+        match self.chars.peek() {
+            Some(&q) if q == '"' || q == '\'' => {
+                self.chars.next();
+                let mut s = String::new();
+                loop {
+                    match self.chars.next()? {
+                        c if c == q => return Some(s),
+                        c => s.push(c),
+                    }
+                }
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c == ']' || c.is_whitespace() {
+                        break;
+                    }
+                    s.push(c);
+                    self.chars.next();
+                }
+                Some(s)
+            }
+        }
+    }
+
+    fn compound(&mut self) -> Option<Vec<Simple>> {
+        let mut simples = Vec::new();
+        let universal = self.chars.peek() == Some(&'*');
+        match self.chars.peek() {
+            Some('*') => {
+                self.chars.next();
+            }
+            Some(c) if c.is_alphabetic() => simples.push(Simple::Tag(self.ident()?)),
+            _ => {}
+        }
+        loop {
+            match self.chars.peek() {
+                Some('#') => {
+                    self.chars.next();
+                    let id = self.ident()?;
+                    simples.push(Simple::Id(id));
+                }
+                Some('.') => {
+                    self.chars.next();
+                    let class = self.ident()?;
+                    simples.push(Simple::Class(class));
+                }
+                Some('[') => {
+                    self.chars.next();
+                    self.skip_whitespace();
+                    let name = self.ident()?;
+                    self.skip_whitespace();
+                    let op = match self.chars.next()? {
+                        ']' => {
+                            simples.push(Simple::Attribute(name, None));
+                            continue;
+                        }
+                        '=' => AttrOp::Equals,
+                        c => {
+                            if self.chars.next()? != '=' {
+                                return None;
+                            }
+                            match c {
+                                '~' => AttrOp::Word,
+                                '^' => AttrOp::Prefix,
+                                '$' => AttrOp::Suffix,
+                                '*' => AttrOp::Contains,
+                                '|' => AttrOp::Dash,
+                                _ => return None,
+                            }
+                        }
+                    };
+                    self.skip_whitespace();
+                    let value = self.value()?;
+                    self.skip_whitespace();
+                    if self.chars.next()? != ']' {
+                        return None;
+                    }
+                    simples.push(Simple::Attribute(name, Some((op, value))));
+                }
+                Some(':') => {
+                    self.chars.next();
+                    match self.ident()?.as_str() {
+                        "first-child" => simples.push(Simple::FirstChild),
+                        "last-child" => simples.push(Simple::LastChild),
+                        "not" => {
+                            if self.chars.next()? != '(' {
+                                return None;
+                            }
+                            self.skip_whitespace();
+                            let inner = self.compound()?;
+                            self.skip_whitespace();
+                            if self.chars.next()? != ')' {
+                                return None;
+                            }
+                            simples.push(Simple::Not(inner));
+                        }
+                        _ => return None,
+                    }
+                }
+                _ => break,
+            }
+        }
+        if simples.is_empty() && !universal {
+            return None;
+        }
+        Some(simples)
+    }
+
+    fn complex(&mut self) -> Option<Complex> {
+        let mut complex = Vec::new();
+        let mut combinator = Combinator::Descendant;
+        self.skip_whitespace();
+        loop {
+            let compound = self.compound()?;
+            complex.push((combinator, compound));
+            let space = self.skip_whitespace();
+            combinator = match self.chars.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::Adjacent,
+                Some('~') => Combinator::Sibling,
+                None | Some(',') => return Some(complex),
+                _ if space => Combinator::Descendant,
+                _ => return None,
+            };
+            if combinator != Combinator::Descendant {
+                self.chars.next();
+                self.skip_whitespace();
+            }
+        }
+    }
+
+    fn selector(mut self) -> Option<Vec<Complex>> {
+        let mut group = vec![self.complex()?];
+        while self.chars.next() == Some(',') {
+            group.push(self.complex()?);
+        }
+        Some(group)
+    }
+}
+
+// an element on the path from the root of a query, with its element siblings
+#[derive(Clone)]
+struct Element {
+    node: Value,
+    siblings: Rc<Vec<Value>>,
+    index: usize,
+}
+
+fn matches_simple(agent: &Agent, simple: &Simple, element: &Element) -> Result<bool, Value> {
+    let node = &element.node;
+    let attribute = |name: &str| -> Result<Option<String>, Value> {
+        Ok(attributes(agent, node)?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v))
+    };
+    Ok(match simple {
+        Simple::Tag(tag) => string_property(agent, node, "tag")? == *tag,
+        Simple::Id(id) => attribute("id")?.as_ref() == Some(id),
+        Simple::Class(class) => {
+            attribute("class")?.map_or(false, |c| c.split_whitespace().any(|c| c == class))
+        }
+        Simple::Attribute(name, test) => match (attribute(name)?, test) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(v), Some((op, expected))) => match op {
+                AttrOp::Equals => v == *expected,
+                AttrOp::Word => v.split_whitespace().any(|w| w == expected),
+                AttrOp::Prefix => !expected.is_empty() && v.starts_with(expected.as_str()),
+                AttrOp::Suffix => !expected.is_empty() && v.ends_with(expected.as_str()),
+                AttrOp::Contains => !expected.is_empty() && v.contains(expected.as_str()),
+                AttrOp::Dash => v == *expected || v.starts_with(&format!("{}-", expected)),
+            },
+        },
+        Simple::FirstChild => element.index == 0,
+        Simple::LastChild => element.index + 1 == element.siblings.len(),
+        Simple::Not(inner) => !matches_compound(agent, inner, element)?,
+    })
+}
+
+fn matches_compound(agent: &Agent, compound: &[Simple], element: &Element) -> Result<bool, Value> {
+    for simple in compound {
+        if !matches_simple(agent, simple, element)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// whether the last compound of `parts` matches the end of `path`, and the
+// rest match through their combinators
+fn matches_path(
+    agent: &Agent,
+    parts: &[(Combinator, Vec<Simple>)],
+    path: &[Element],
+) -> Result<bool, Value> {
+    let ((combinator, compound), rest) = parts.split_last().unwrap();
+    let (element, ancestors) = path.split_last().unwrap();
+    if !matches_compound(agent, compound, element)? {
+        return Ok(false);
+    }
+    if rest.is_empty() {
+        return Ok(true);
+    }
+    match combinator {
+        Combinator::Descendant => {
+            for end in (1..path.len()).rev() {
+                if matches_path(agent, rest, &path[..end])? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Combinator::Child => Ok(!ancestors.is_empty() && matches_path(agent, rest, ancestors)?),
+        Combinator::Adjacent | Combinator::Sibling => {
+            let earliest = if *combinator == Combinator::Adjacent {
+                element.index.saturating_sub(1)
+            } else {
+                0
+            };
+            for index in (earliest..element.index).rev() {
+                let mut path = ancestors.to_vec();
+                path.push(Element {
+                    node: element.siblings[index].clone(),
+                    siblings: element.siblings.clone(),
+                    index,
+                });
+                if matches_path(agent, rest, &path)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+    }
+}
+
+fn collect(
+    agent: &Agent,
+    selector: &[Complex],
+    node: &Value,
+    path: &mut Vec<Element>,
+    first_only: bool,
+    out: &mut Vec<Value>,
+) -> Result<(), Value> {
+    let mut elements = Vec::new();
+    for child in children(agent, node)? {
+        if node_type(agent, &child)? == "element" {
+            elements.push(child);
+        }
+    }
+    let elements = Rc::new(elements);
+    for index in 0..elements.len() {
+        path.push(Element {
+            node: elements[index].clone(),
+            siblings: elements.clone(),
+            index,
+        });
+        for complex in selector {
+            if matches_path(agent, complex, path)? {
+                out.push(elements[index].clone());
+                break;
+            }
+        }
+        if !first_only || out.is_empty() {
+            collect(agent, selector, &elements[index], path, first_only, out)?;
+        }
+        path.pop();
+        if first_only && !out.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn query(
+    agent: &Agent,
+    args: &[Value],
+    ctx: &Context,
+    first_only: bool,
+) -> Result<Vec<Value>, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let source = match args.get(0) {
+        Some(Value::String(s)) => s,
+        _ => return Err(Value::new_error(agent, "selector must be a string")),
+    };
+    let selector = SelectorParser {
+        chars: source.chars().peekable(),
+        xml: is_xml(&this),
+    }
+    .selector()
+    .ok_or_else(|| Value::new_error(agent, &format!("invalid selector '{}'", source)))?;
+
+    // the receiver takes part in matching but is never a result
+    let mut path = Vec::new();
+    if node_type(agent, &this)? == "element" {
+        path.push(Element {
+            node: this.clone(),
+            siblings: Rc::new(vec![this.clone()]),
+            index: 0,
+        });
+    }
+    let mut out = Vec::new();
+    collect(agent, &selector, &this, &mut path, first_only, &mut out)?;
+    Ok(out)
+}
+
+/// Every element below the node matching a CSS selector, in document order.
+fn select(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let found = query(agent, &args, ctx, false)?;
+    Ok(Value::new_array_from_vec(agent, found))
+}
+
+fn select_one(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    Ok(query(agent, &args, ctx, true)?
+        .into_iter()
+        .next()
+        .unwrap_or(Value::Null))
+}
+
+fn write_text(agent: &Agent, node: &Value, out: &mut String) -> Result<(), Value> {
+    match node_type(agent, node)?.as_str() {
+        "text" => out.push_str(&string_property(agent, node, "text")?),
+        "element" | "document" => {
+            for child in children(agent, node)? {
+                write_text(agent, &child, out)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The text below the node, without markup.
+fn text(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let mut out = String::new();
+    write_text(agent, &this, &mut out)?;
+    Ok(Value::from(out))
+}
+
+fn escape(s: &str, attribute: bool, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write(agent: &Agent, node: &Value, xml: bool, raw: bool, out: &mut String) -> Result<(), Value> {
+    match node_type(agent, node)?.as_str() {
+        "document" => {
+            for child in children(agent, node)? {
+                write(agent, &child, xml, false, out)?;
+            }
+        }
+        "element" => {
+            let tag = string_property(agent, node, "tag")?;
+            out.push('<');
+            out.push_str(&tag);
+            for (name, value) in attributes(agent, node)? {
+                out.push(' ');
+                out.push_str(&name);
+                out.push_str("=\"");
+                escape(&value, true, out);
+                out.push('"');
+            }
+            let children = children(agent, node)?;
+            if !xml && VOID.contains(&tag.as_str()) {
+                out.push('>');
+                return Ok(());
+            }
+            if xml && children.is_empty() {
+                out.push_str("/>");
+                return Ok(());
+            }
+            out.push('>');
+            let raw = !xml && RAW_TEXT.contains(&tag.as_str());
+            for child in children {
+                write(agent, &child, xml, raw, out)?;
+            }
+            out.push_str("</");
+            out.push_str(&tag);
+            out.push('>');
+        }
+        "text" => {
+            let text = string_property(agent, node, "text")?;
+            if raw {
+                out.push_str(&text);
+            } else {
+                escape(&text, false, out);
+            }
+        }
+        "comment" => {
+            out.push_str("<!--");
+            out.push_str(&string_property(agent, node, "text")?);
+            out.push_str("-->");
+        }
+        "declaration" => {
+            out.push('<');
+            out.push_str(&string_property(agent, node, "text")?);
+            out.push('>');
+        }
+        kind => {
+            return Err(Value::new_error(
+                agent,
+                &format!("html: unknown node type '{}'", kind),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let mut out = String::new();
+    write(agent, &this, is_xml(&this), false, &mut out)?;
+    Ok(Value::from(out))
+}
+
+/// Serialize a node, parsed or built by hand, as HTML or with `{ xml: true }`
+/// as XML.
+fn serialize(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let node = args.get(0).cloned().unwrap_or(Value::Null);
+    let xml = match args.get(1) {
+        None | Some(Value::Null) => is_xml(&node),
+        Some(options) => match options.get(agent, ObjectKey::from("xml"))? {
+            Value::Null => is_xml(&node),
+            Value::Boolean(b) => b,
+            _ => return Err(Value::new_error(agent, "xml must be a boolean")),
+        },
+    };
+    let mut out = String::new();
+    write(agent, &node, xml, false, &mut out)?;
+    Ok(Value::from(out))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, f) in &[
+        ("select", select as crate::value::BuiltinFunction),
+        ("selectOne", select_one),
+        ("text", text),
+        ("toString", to_string),
+    ] {
+        let f = Value::new_builtin_function(agent, *f);
+        if *name == "toString" {
+            proto
+                .set(agent, ObjectKey::well_known_symbol("toString"), f.clone())
+                .unwrap();
+        }
+        proto.set(agent, ObjectKey::from(*name), f).unwrap();
+    }
+
+    let parse = Value::new_builtin_function(agent, parse);
+    parse.set_slot("html node prototype", proto);
+
+    let mut module = HashMap::new();
+    module.insert("parse".to_string(), parse);
+    module.insert(
+        "parseXml".to_string(),
+        Value::new_builtin_function(agent, parse_xml),
+    );
+    module.insert(
+        "serialize".to_string(),
+        Value::new_builtin_function(agent, serialize),
+    );
+
+    module
+}
@@ -0,0 +1,1077 @@
+use crate::agent::{Agent, MioMapType};
+use crate::builtins::cookie::JARS;
+use crate::builtins::r#async::{abort_signal, new_abort_signal};
+use crate::builtins::trace;
+use crate::interpreter::Context;
+use crate::intrinsics::promise::new_promise_capability;
+use crate::intrinsics::url::Url;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use lazy_static::lazy_static;
+use mio::{PollOpt, Ready, Registration};
+use num::ToPrimitive;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    // keyed by request id, since mio tokens are only unique per agent
+    static ref RESPONSES: Mutex<HashMap<usize, Result<Response, String>>> =
+        Mutex::new(HashMap::new());
+    static ref ABORTED: Mutex<HashSet<usize>> = Mutex::new(HashSet::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redirect {
+    Follow,
+    Error,
+    Manual,
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub timeout: Option<Duration>,
+    pub redirect: Redirect,
+    pub keep_alive: bool,
+    pub cookie_jar: Option<usize>,
+    pub proxy: Proxy,
+    /// The base64 SHA-256 digest the body of the response must have.
+    pub integrity: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub url: String,
+    pub redirected: bool,
+}
+
+pub fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    received: bool,
+    deadline: Option<Instant>,
+    id: usize,
+}
+
+impl Connection {
+    fn check(&self) -> Result<(), String> {
+        if ABORTED.lock().unwrap().contains(&self.id) {
+            return Err("aborted".to_string());
+        }
+        match self.deadline {
+            Some(d) if Instant::now() >= d => Err("request timed out".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // reads are polled so that aborts and deadlines are noticed promptly
+    fn fill(&mut self) -> Result<bool, String> {
+        let mut chunk = [0; 8192];
+        loop {
+            self.check()?;
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.received = true;
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                    return Ok(true);
+                }
+                Err(ref e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, String> {
+        loop {
+            if let Some(i) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buffer.drain(..i + 2).take(i).collect::<Vec<u8>>();
+                return Ok(String::from_utf8_lossy(&line).into_owned());
+            }
+            if !self.fill()? {
+                return Err("connection closed unexpectedly".to_string());
+            }
+        }
+    }
+
+    fn read_exact(&mut self, n: usize) -> Result<Vec<u8>, String> {
+        while self.buffer.len() < n {
+            if !self.fill()? {
+                return Err("connection closed unexpectedly".to_string());
+            }
+        }
+        Ok(self.buffer.drain(..n).collect())
+    }
+
+    fn read_to_end(&mut self) -> Result<Vec<u8>, String> {
+        while self.fill()? {}
+        Ok(std::mem::replace(&mut self.buffer, Vec::new()))
+    }
+
+    fn read_chunked(&mut self) -> Result<Vec<u8>, String> {
+        let mut body = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            let size = line.split(';').next().unwrap().trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| format!("invalid chunk size '{}'", size))?;
+            if size == 0 {
+                // trailers
+                while !self.read_line()?.is_empty() {}
+                return Ok(body);
+            }
+            body.extend(self.read_exact(size)?);
+            self.read_line()?;
+        }
+    }
+}
+
+fn connect(url: &Url, deadline: Option<Instant>) -> Result<TcpStream, String> {
+    let host = url.host.clone().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port.unwrap_or(80);
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("could not resolve '{}': {}", host, e))?;
+    let mut error = format!("could not resolve '{}'", host);
+    for addr in addrs {
+        let timeout = match deadline {
+            Some(d) if d > Instant::now() => d - Instant::now(),
+            Some(_) => return Err("request timed out".to_string()),
+            None => Duration::from_secs(30),
+        };
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                error = "request timed out".to_string();
+            }
+            Err(e) => error = e.to_string(),
+        }
+    }
+    Err(error)
+}
+
+/// Keep-alive connections, shared by every request an agent makes. The
+/// client only speaks HTTP/1.1 and a connection carries one request at a
+/// time, there is no HTTP/2 multiplexing; concurrent requests to a host
+/// each take a connection, up to `max_per_host`.
+pub struct Pool {
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+struct PoolState {
+    idle: HashMap<String, Vec<(TcpStream, Instant)>>,
+    active: HashMap<String, usize>,
+    max_per_host: usize,
+    idle_timeout: Duration,
+}
+
+impl Pool {
+    pub fn new() -> Pool {
+        Pool {
+            state: Mutex::new(PoolState {
+                idle: HashMap::new(),
+                active: HashMap::new(),
+                max_per_host: 6,
+                idle_timeout: Duration::from_secs(5),
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    pub fn configure(&self, max_per_host: Option<usize>, idle_timeout: Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(max) = max_per_host {
+            state.max_per_host = max;
+        }
+        if let Some(timeout) = idle_timeout {
+            state.idle_timeout = timeout;
+        }
+        self.available.notify_all();
+    }
+
+    // waits for a free slot for `key`, then hands out an idle connection if
+    // there is a fresh one
+    fn acquire(
+        &self,
+        key: &str,
+        deadline: Option<Instant>,
+        id: usize,
+    ) -> Result<Option<TcpStream>, String> {
+        let mut state = self.state.lock().unwrap();
+        while *state.active.get(key).unwrap_or(&0) >= state.max_per_host {
+            if ABORTED.lock().unwrap().contains(&id) {
+                return Err("aborted".to_string());
+            }
+            if deadline.map_or(false, |d| Instant::now() >= d) {
+                return Err("request timed out".to_string());
+            }
+            state = self
+                .available
+                .wait_timeout(state, Duration::from_millis(50))
+                .unwrap()
+                .0;
+        }
+        *state.active.entry(key.to_string()).or_insert(0) += 1;
+        let idle_timeout = state.idle_timeout;
+        let idle = state.idle.entry(key.to_string()).or_insert_with(Vec::new);
+        while let Some((stream, since)) = idle.pop() {
+            if since.elapsed() < idle_timeout {
+                return Ok(Some(stream));
+            }
+        }
+        Ok(None)
+    }
+
+    fn release(&self, key: &str, stream: Option<TcpStream>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(n) = state.active.get_mut(key) {
+            *n -= 1;
+        }
+        if let Some(stream) = stream {
+            state
+                .idle
+                .entry(key.to_string())
+                .or_insert_with(Vec::new)
+                .push((stream, Instant::now()));
+        }
+        self.available.notify_all();
+    }
+}
+
+// the error flag is set when nothing was received, which on a reused
+// connection means the server closed it while idle
+fn exchange(
+    mut connection: Connection,
+    out: &[u8],
+    method: &str,
+    keep_alive: bool,
+) -> Result<(Response, Option<TcpStream>), (String, bool)> {
+    connection
+        .stream
+        .write_all(out)
+        .map_err(|e| (e.to_string(), true))?;
+
+    let (version, status, status_text, headers) = loop {
+        let line = match connection.read_line() {
+            Ok(line) => line,
+            Err(e) => return Err((e, !connection.received)),
+        };
+        let mut parts = line.splitn(3, ' ');
+        let version = parts.next().unwrap_or("").to_string();
+        let status = parts.next().and_then(|s| s.parse::<u16>().ok());
+        let status = match status {
+            Some(s) if version.starts_with("HTTP/") => s,
+            _ => return Err((format!("invalid status line '{}'", line), false)),
+        };
+        let text = parts.next().unwrap_or("").to_string();
+        let mut headers = Vec::new();
+        loop {
+            let line = connection.read_line().map_err(|e| (e, false))?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(i) = line.find(':') {
+                headers.push((
+                    line[..i].trim().to_ascii_lowercase(),
+                    line[i + 1..].trim().to_string(),
+                ));
+            }
+        }
+        // interim responses are skipped
+        if (100..200).contains(&status) {
+            continue;
+        }
+        break (version, status, text, headers);
+    };
+
+    let mut framed = true;
+    let body = if method == "HEAD" || status == 204 || status == 304 {
+        Vec::new()
+    } else if header(&headers, "transfer-encoding")
+        .map_or(false, |t| t.to_ascii_lowercase().contains("chunked"))
+    {
+        connection.read_chunked().map_err(|e| (e, false))?
+    } else if let Some(length) = header(&headers, "content-length") {
+        let length = length
+            .parse::<usize>()
+            .map_err(|_| (format!("invalid content-length '{}'", length), false))?;
+        connection.read_exact(length).map_err(|e| (e, false))?
+    } else {
+        framed = false;
+        connection.read_to_end().map_err(|e| (e, false))?
+    };
+
+    let reusable = keep_alive
+        && framed
+        && version == "HTTP/1.1"
+        && connection.buffer.is_empty()
+        && !header(&headers, "connection").map_or(false, |c| c.eq_ignore_ascii_case("close"));
+    let response = Response {
+        status,
+        status_text,
+        headers,
+        body,
+        url: String::new(),
+        redirected: false,
+    };
+    Ok((
+        response,
+        if reusable {
+            Some(connection.stream)
+        } else {
+            None
+        },
+    ))
+}
+
+/// Where a request goes through. Only plain http requests are sent: there
+/// is no TLS client yet, so https URLs fail even with HTTPS_PROXY set, and
+/// there is no way to add CA certificates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Proxy {
+    Environment,
+    Direct,
+    Url(Url),
+}
+
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host.starts_with("127.") || host == "[::1]"
+}
+
+fn no_proxy(host: &str, port: u16) -> bool {
+    let list = std::env::var("NO_PROXY")
+        .or_else(|_| std::env::var("no_proxy"))
+        .unwrap_or_default();
+    list.split(',').map(|e| e.trim()).any(|entry| {
+        let (entry, entry_port) = match entry.rfind(':') {
+            Some(i) if !entry.ends_with(']') => (&entry[..i], entry[i + 1..].parse::<u16>().ok()),
+            _ => (entry, None),
+        };
+        let entry = entry.trim_start_matches('.').to_ascii_lowercase();
+        entry == "*"
+            || (!entry.is_empty()
+                && entry_port.map_or(true, |p| p == port)
+                && (host == entry || host.ends_with(&format!(".{}", entry))))
+    })
+}
+
+// HTTP_PROXY and NO_PROXY follow curl, except that loopback hosts are
+// never proxied
+fn proxy_for(setting: &Proxy, url: &Url) -> Result<Option<Url>, String> {
+    let input = match setting {
+        Proxy::Direct => return Ok(None),
+        Proxy::Url(u) => return Ok(Some(u.clone())),
+        Proxy::Environment => {
+            let host = url.host.clone().unwrap_or_default();
+            let port = url.port.unwrap_or(80);
+            if is_loopback(&host) || no_proxy(&host, port) {
+                return Ok(None);
+            }
+            let names: &[&str] = if url.scheme == "https" {
+                &["HTTPS_PROXY", "https_proxy"]
+            } else {
+                &["http_proxy", "HTTP_PROXY"]
+            };
+            match names
+                .iter()
+                .filter_map(|n| std::env::var(n).ok())
+                .find(|v| !v.is_empty())
+            {
+                Some(v) => v,
+                None => return Ok(None),
+            }
+        }
+    };
+    // scheme-less values like "proxy:3128" are common in the environment
+    let input = if input.contains("://") {
+        input
+    } else {
+        format!("http://{}", input)
+    };
+    Url::parse(&input, None)
+        .map(Some)
+        .map_err(|e| format!("invalid proxy '{}': {}", input, e))
+}
+
+pub fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+struct Hop {
+    url: Url,
+    method: String,
+    body: Vec<u8>,
+    cookie: Option<String>,
+    proxy: Option<Url>,
+}
+
+fn send_once(
+    request: &Request,
+    hop: &Hop,
+    deadline: Option<Instant>,
+    pool: &Pool,
+    id: usize,
+) -> Result<Response, String> {
+    let (url, method, body) = (&hop.url, hop.method.as_str(), &hop.body);
+    match url.scheme.as_str() {
+        "http" => {}
+        "https" => return Err("https is not supported, there is no TLS client".to_string()),
+        s => return Err(format!("unsupported protocol '{}:'", s)),
+    }
+
+    // proxies get the absolute URL as the request target
+    let target = match &hop.proxy {
+        Some(proxy) => {
+            if proxy.scheme != "http" {
+                return Err(format!("unsupported proxy protocol '{}:'", proxy.scheme));
+            }
+            let mut target = url.clone();
+            target.fragment = None;
+            target.href()
+        }
+        None => format!("{}{}", url.pathname(), url.search()),
+    };
+    let mut head = format!("{} {} HTTP/1.1\r\n", method, target);
+    if header(&request.headers, "host").is_none() {
+        head += &format!("Host: {}\r\n", url.host());
+    }
+    if header(&request.headers, "user-agent").is_none() {
+        head += "User-Agent: slither\r\n";
+    }
+    if header(&request.headers, "accept").is_none() {
+        head += "Accept: */*\r\n";
+    }
+    if let Some(proxy) = &hop.proxy {
+        if !proxy.username.is_empty() && header(&request.headers, "proxy-authorization").is_none() {
+            let credentials = format!(
+                "{}:{}",
+                crate::intrinsics::url::percent_decode(&proxy.username),
+                crate::intrinsics::url::percent_decode(&proxy.password)
+            );
+            head += &format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64(credentials.as_bytes())
+            );
+        }
+    }
+    for (name, value) in &request.headers {
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" | "connection" | "transfer-encoding" | "cookie" => {}
+            _ => head += &format!("{}: {}\r\n", name, value),
+        }
+    }
+    // cookies from the jar are added after any set explicitly
+    let cookie = match (header(&request.headers, "cookie"), hop.cookie.clone()) {
+        (Some(a), Some(b)) => Some(format!("{}; {}", a, b)),
+        (Some(a), None) => Some(a.to_string()),
+        (None, b) => b,
+    };
+    if let Some(cookie) = cookie {
+        head += &format!("Cookie: {}\r\n", cookie);
+    }
+    if !body.is_empty() || method == "POST" || method == "PUT" || method == "PATCH" {
+        head += &format!("Content-Length: {}\r\n", body.len());
+    }
+    if !request.keep_alive {
+        head += "Connection: close\r\n";
+    }
+    head += "\r\n";
+    let mut out = head.into_bytes();
+    out.extend_from_slice(body);
+
+    let server = hop.proxy.as_ref().unwrap_or(url);
+    let key = server.host();
+    loop {
+        let (stream, reused) = match pool.acquire(&key, deadline, id)? {
+            Some(stream) => (stream, true),
+            None => match connect(server, deadline) {
+                Ok(stream) => (stream, false),
+                Err(e) => {
+                    pool.release(&key, None);
+                    return Err(e);
+                }
+            },
+        };
+        if let Err(e) = stream.set_read_timeout(Some(Duration::from_millis(50))) {
+            pool.release(&key, None);
+            return Err(e.to_string());
+        }
+        let connection = Connection {
+            stream,
+            buffer: Vec::new(),
+            received: false,
+            deadline,
+            id,
+        };
+        match exchange(connection, &out, method, request.keep_alive) {
+            Ok((mut response, stream)) => {
+                pool.release(&key, stream);
+                response.url = url.href();
+                return Ok(response);
+            }
+            Err((_, true)) if reused => pool.release(&key, None),
+            Err((e, _)) => {
+                pool.release(&key, None);
+                return Err(e);
+            }
+        }
+    }
+}
+
+pub fn send(request: &Request, pool: &Pool, id: usize) -> Result<Response, String> {
+    let deadline = request.timeout.map(|t| Instant::now() + t);
+    let mut hop = Hop {
+        url: request.url.clone(),
+        method: request.method.clone(),
+        body: request.body.clone(),
+        cookie: None,
+        proxy: None,
+    };
+    for redirects in 0..=20 {
+        let jar = request.cookie_jar;
+        hop.cookie = jar.and_then(|id| JARS.lock().unwrap().get_mut(&id)?.header(&hop.url));
+        hop.proxy = proxy_for(&request.proxy, &hop.url)?;
+        let mut response = send_once(request, &hop, deadline, pool, id)?;
+        if let Some(id) = jar {
+            if let Some(jar) = JARS.lock().unwrap().get_mut(&id) {
+                for (name, value) in &response.headers {
+                    if name == "set-cookie" {
+                        jar.store(&hop.url, value);
+                    }
+                }
+            }
+        }
+        response.redirected = redirects > 0;
+        let status = response.status;
+        let location = match header(&response.headers, "location") {
+            Some(l) if request.redirect != Redirect::Manual && (300..400).contains(&status) => {
+                l.to_string()
+            }
+            _ => return Ok(response),
+        };
+        if request.redirect == Redirect::Error {
+            return Err(format!("unexpected redirect to '{}'", location));
+        }
+        hop.url = Url::parse(&location, Some(&hop.url))?;
+        if (status == 303 && hop.method != "HEAD")
+            || ((status == 301 || status == 302) && hop.method == "POST")
+        {
+            hop.method = "GET".to_string();
+            hop.body.clear();
+        }
+    }
+    Err("too many redirects".to_string())
+}
+
+/// A GET from outside any script, blocking the calling thread. Used by the
+/// package manager.
+pub fn get(url: &str) -> Result<Response, String> {
+    let request = Request {
+        method: "GET".to_string(),
+        url: Url::parse(url, None)?,
+        headers: Vec::new(),
+        body: Vec::new(),
+        timeout: Some(Duration::from_secs(60)),
+        redirect: Redirect::Follow,
+        keep_alive: false,
+        cookie_jar: None,
+        proxy: Proxy::Environment,
+        integrity: None,
+    };
+    send(&request, &Pool::new(), NEXT_ID.fetch_add(1, Ordering::SeqCst))
+}
+
+fn new_response(agent: &Agent, response: Response) -> Value {
+    let o = Value::new_custom_object(agent.intrinsics.http_response_prototype.get(agent));
+    macro_rules! p {
+        ($name:expr, $value:expr) => {
+            o.set(agent, ObjectKey::from($name), $value).unwrap();
+        };
+    }
+    p!("status", Value::from(f64::from(response.status)));
+    p!("statusText", Value::from(response.status_text));
+    p!(
+        "ok",
+        Value::from(response.status >= 200 && response.status < 300)
+    );
+    p!("url", Value::from(response.url));
+    p!("redirected", Value::from(response.redirected));
+    p!("bodyUsed", Value::from(false));
+
+    let mut joined: Vec<(String, String)> = Vec::new();
+    for (name, value) in response.headers {
+        match joined.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => {
+                *v += ", ";
+                *v += &value;
+            }
+            None => joined.push((name, value)),
+        }
+    }
+    // like browsers, type a body the server didn't by its first bytes
+    if !response.body.is_empty() && !joined.iter().any(|(n, _)| n == "content-type") {
+        if let Some(mime) = crate::builtins::mime::sniff(&response.body) {
+            joined.push(("content-type".to_string(), mime.to_string()));
+        }
+    }
+    let headers = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, value) in joined {
+        headers
+            .set(agent, ObjectKey::from(name), Value::from(value))
+            .unwrap();
+    }
+    p!("headers", headers);
+
+    o.set_slot(
+        "http body",
+        Value::new_buffer_from_vec(agent, response.body),
+    );
+    o
+}
+
+pub fn handle(agent: &Agent, promise: Value) {
+    let id = match promise.get_slot("http id") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    let response = RESPONSES.lock().unwrap().remove(&id).unwrap();
+    ABORTED.lock().unwrap().remove(&id);
+    let span = if promise.has_slot("trace span") {
+        promise.get_slot("trace span")
+    } else {
+        Value::Null
+    };
+    if promise.has_slot("http aborted") {
+        trace::end_builtin_span(agent, &span, Some(&Value::from("aborted")));
+        return;
+    }
+    if promise.has_slot("http signal") {
+        if let Value::List(pending) = promise.get_slot("http signal").get_slot("abort pending") {
+            pending.borrow_mut().retain(|p| *p != promise);
+        }
+    }
+    match response {
+        Ok(response) => {
+            trace::set_builtin_attribute(
+                &span,
+                "http.status_code",
+                Value::from(f64::from(response.status)),
+            );
+            trace::end_builtin_span(agent, &span, None);
+            let response = new_response(agent, response);
+            promise
+                .get_slot("resolve")
+                .call(agent, Value::Null, vec![response])
+                .unwrap();
+        }
+        Err(e) => {
+            trace::end_builtin_span(agent, &span, Some(&Value::from(e.clone())));
+            promise
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![Value::new_error(agent, &e)])
+                .unwrap();
+        }
+    }
+}
+
+fn to_string(agent: &Agent, value: &Value, what: &str) -> Result<String, Value> {
+    match value {
+        Value::String(s) => Ok(s.to_string()),
+        Value::Number(n) => Ok(crate::num_util::to_string(*n)),
+        v if v.has_slot("url href") || v.has_slot("url search params") => {
+            match v
+                .get(agent, ObjectKey::from("toString"))?
+                .call(agent, v.clone(), vec![])?
+            {
+                Value::String(s) => Ok(s.to_string()),
+                _ => unreachable!(),
+            }
+        }
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be a string", what),
+        )),
+    }
+}
+
+fn parse_request(
+    agent: &Agent,
+    args: &[Value],
+    redirect: Redirect,
+) -> Result<(Request, Value), Value> {
+    let input = to_string(agent, args.get(0).unwrap_or(&Value::Null), "url")?;
+    let url = match Url::parse(&input, None) {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(Value::new_error(
+                agent,
+                &format!("invalid URL '{}': {}", input, e),
+            ))
+        }
+    };
+    let mut request = Request {
+        method: "GET".to_string(),
+        url,
+        headers: Vec::new(),
+        body: Vec::new(),
+        timeout: None,
+        redirect,
+        keep_alive: true,
+        cookie_jar: None,
+        proxy: Proxy::Environment,
+        integrity: None,
+    };
+    let init = args.get(1).unwrap_or(&Value::Null);
+    if *init == Value::Null {
+        return Ok((request, Value::Null));
+    }
+    if init.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+
+    if let Value::String(m) = init.get(agent, ObjectKey::from("method"))? {
+        request.method = m.to_ascii_uppercase();
+    }
+    let headers = init.get(agent, ObjectKey::from("headers"))?;
+    if headers.type_of() == "object" {
+        for key in headers.keys(agent)? {
+            let value = headers.get(agent, key.clone())?;
+            request
+                .headers
+                .push((key.to_string(), to_string(agent, &value, "header value")?));
+        }
+    }
+    match init.get(agent, ObjectKey::from("body"))? {
+        Value::Null => {}
+        Value::String(s) => request.body = s.as_bytes().to_vec(),
+        Value::Object(ref o) if is_buffer(&o.kind) => {
+            if let ObjectKind::Buffer(b) = &o.kind {
+                request.body = b.borrow().clone();
+            }
+        }
+        v if v.has_slot("url search params") => {
+            request.body = to_string(agent, &v, "body")?.into_bytes();
+            if header(&request.headers, "content-type").is_none() {
+                request.headers.push((
+                    "Content-Type".to_string(),
+                    "application/x-www-form-urlencoded;charset=UTF-8".to_string(),
+                ));
+            }
+        }
+        _ => return Err(Value::new_error(agent, "body must be a string or Buffer")),
+    }
+    match init.get(agent, ObjectKey::from("timeout"))? {
+        Value::Null => {}
+        Value::Number(n) if n >= 0.0 => {
+            request.timeout = Some(Duration::from_millis(n.to_u64().unwrap_or(0)));
+        }
+        _ => return Err(Value::new_error(agent, "timeout must be a number")),
+    }
+    match init.get(agent, ObjectKey::from("redirect"))? {
+        Value::Null => {}
+        Value::String(ref r) if r.as_str() == "follow" => request.redirect = Redirect::Follow,
+        Value::String(ref r) if r.as_str() == "error" => request.redirect = Redirect::Error,
+        Value::String(ref r) if r.as_str() == "manual" => request.redirect = Redirect::Manual,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "redirect must be 'follow', 'error' or 'manual'",
+            ))
+        }
+    }
+    let jar = init.get(agent, ObjectKey::from("cookieJar"))?;
+    if jar != Value::Null {
+        match crate::builtins::cookie::jar_id(&jar) {
+            Some(id) => request.cookie_jar = Some(id),
+            None => return Err(Value::new_error(agent, "cookieJar must be a CookieJar")),
+        }
+    }
+    match init.get(agent, ObjectKey::from("proxy"))? {
+        Value::Null => {}
+        Value::Boolean(false) => request.proxy = Proxy::Direct,
+        v => {
+            let input = to_string(agent, &v, "proxy")?;
+            match Url::parse(&input, None) {
+                Ok(u) => request.proxy = Proxy::Url(u),
+                Err(e) => {
+                    return Err(Value::new_error(
+                        agent,
+                        &format!("invalid proxy '{}': {}", input, e),
+                    ))
+                }
+            }
+        }
+    }
+    match init.get(agent, ObjectKey::from("integrity"))? {
+        Value::Null => {}
+        Value::String(ref i) if i.starts_with("sha256-") && i.len() > 7 => {
+            request.integrity = Some(i[7..].to_string());
+        }
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "integrity must be a 'sha256-<base64 digest>' string",
+            ))
+        }
+    }
+    if let Value::Boolean(b) = init.get(agent, ObjectKey::from("keepAlive"))? {
+        request.keep_alive = b;
+    }
+    let signal = init.get(agent, ObjectKey::from("signal"))?;
+    if signal != Value::Null && !signal.has_slot("abort pending") {
+        return Err(Value::new_error(agent, "signal must be an AbortSignal"));
+    }
+    Ok((request, signal))
+}
+
+fn is_buffer(kind: &ObjectKind) -> bool {
+    if let ObjectKind::Buffer(..) = kind {
+        true
+    } else {
+        false
+    }
+}
+
+// padding is optional in subresource integrity digests
+fn check_integrity(request: &Request, response: Response) -> Result<Response, String> {
+    let expected = match &request.integrity {
+        Some(expected) => expected.trim_end_matches('='),
+        None => return Ok(response),
+    };
+    let digest = base64(&crate::builtins::crypto::sha256(&response.body));
+    if digest.trim_end_matches('=') == expected {
+        Ok(response)
+    } else {
+        Err(format!(
+            "integrity check failed for {}: expected sha256-{}, got sha256-{}",
+            request.url.href(),
+            request.integrity.as_ref().unwrap(),
+            digest
+        ))
+    }
+}
+
+pub fn spawn(agent: &Agent, request: Request, signal: Value) -> Result<Value, Value> {
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+
+    if signal != Value::Null {
+        if signal.get(agent, ObjectKey::from("aborted"))?.to_bool() {
+            let reason = signal.get(agent, ObjectKey::from("reason"))?;
+            promise
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![reason])?;
+            return Ok(promise);
+        }
+        if let Value::List(pending) = signal.get_slot("abort pending") {
+            pending.borrow_mut().push_back(promise.clone());
+        }
+        promise.set_slot("http signal", signal);
+    }
+
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.mio_token();
+
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    agent.insert_op(token, MioMapType::Http(registration, promise.clone()));
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    promise.set_slot("http id", Value::from(id as f64));
+
+    let pool = agent.http_pool.clone();
+    agent.pool.execute(move || {
+        let response = send(&request, &pool, id).and_then(|r| check_integrity(&request, r));
+        RESPONSES.lock().unwrap().insert(id, response);
+        set_readiness.set_readiness(Ready::readable()).unwrap();
+    });
+
+    Ok(promise)
+}
+
+// like spawn, but recorded as a span when tracing is configured
+fn spawn_traced(agent: &Agent, request: Request, signal: Value) -> Result<Value, Value> {
+    let span = trace::start_builtin_span(
+        agent,
+        &format!("HTTP {}", request.method),
+        vec![
+            ("http.method", Value::from(request.method.clone())),
+            ("http.url", Value::from(request.url.href())),
+        ],
+    );
+    let promise = spawn(agent, request, signal)?;
+    if promise.has_slot("http id") {
+        promise.set_slot("trace span", span);
+    } else {
+        trace::end_builtin_span(agent, &span, Some(&Value::from("aborted")));
+    }
+    Ok(promise)
+}
+
+fn request(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (request, signal) = parse_request(agent, &args, Redirect::Manual)?;
+    spawn_traced(agent, request, signal)
+}
+
+pub fn fetch(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (request, signal) = parse_request(agent, &args, Redirect::Follow)?;
+    spawn_traced(agent, request, signal)
+}
+
+/// configurePool({ maxConnectionsPerHost, idleTimeout }). Requests beyond
+/// the limit wait for a connection, as HTTP/1.1 can't share one.
+fn configure_pool(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = args.get(0).unwrap_or(&Value::Null);
+    if options.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+    let max = match options.get(agent, ObjectKey::from("maxConnectionsPerHost"))? {
+        Value::Null => None,
+        Value::Number(n) if n >= 1.0 => n.to_usize(),
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "maxConnectionsPerHost must be a positive number",
+            ))
+        }
+    };
+    let idle = match options.get(agent, ObjectKey::from("idleTimeout"))? {
+        Value::Null => None,
+        Value::Number(n) if n >= 0.0 => n.to_u64().map(Duration::from_millis),
+        _ => return Err(Value::new_error(agent, "idleTimeout must be a number")),
+    };
+    agent.http_pool.configure(max, idle);
+    Ok(Value::Null)
+}
+
+/// Stop a request whose signal aborted before the promise is rejected.
+pub fn abort_request(promise: &Value) {
+    if promise.has_slot("http id") {
+        if let Value::Number(id) = promise.get_slot("http id") {
+            ABORTED.lock().unwrap().insert(id.to_usize().unwrap());
+        }
+        // the worker still reports back, which is ignored
+        promise.set_slot("http aborted", Value::from(true));
+    }
+}
+
+fn abort_controller(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let controller = Value::new_custom_object(proto);
+    let signal = new_abort_signal(agent)?;
+    controller.set(agent, ObjectKey::from("signal"), signal.clone())?;
+    controller.set_slot("abort signal", signal);
+    Ok(controller)
+}
+
+fn abort(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("abort signal") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    let reason = match args.get(0) {
+        None | Some(Value::Null) => Value::new_error(agent, "aborted"),
+        Some(v) => v.clone(),
+    };
+    abort_signal(agent, &this.get_slot("abort signal"), reason)?;
+    Ok(Value::Null)
+}
+
+pub fn create_abort_controller(agent: &Agent) -> Value {
+    let c = Value::new_builtin_function(agent, abort_controller);
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    proto
+        .set(
+            agent,
+            ObjectKey::from("abort"),
+            Value::new_builtin_function(agent, abort),
+        )
+        .unwrap();
+    proto
+        .set(agent, ObjectKey::from("constructor"), c.clone())
+        .unwrap();
+    c.set(agent, ObjectKey::from("prototype"), proto).unwrap();
+    c
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "request".to_string(),
+        Value::new_builtin_function(agent, request),
+    );
+    module.insert(
+        "CookieJar".to_string(),
+        crate::builtins::cookie::create_cookie_jar(agent),
+    );
+    module.insert(
+        "configurePool".to_string(),
+        Value::new_builtin_function(agent, configure_pool),
+    );
+    module.insert(
+        "serve".to_string(),
+        Value::new_builtin_function(agent, crate::builtins::http_server::serve),
+    );
+    module.insert(
+        "Router".to_string(),
+        crate::builtins::http_router::create_router(agent),
+    );
+    module.insert(
+        "serveDirectory".to_string(),
+        Value::new_builtin_function(agent, crate::builtins::http_server::serve_directory),
+    );
+    module.insert(
+        "rateLimit".to_string(),
+        Value::new_builtin_function(agent, crate::builtins::http_middleware::rate_limit),
+    );
+    module.insert(
+        "timeout".to_string(),
+        Value::new_builtin_function(agent, crate::builtins::http_middleware::timeout),
+    );
+
+    module
+}
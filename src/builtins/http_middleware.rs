@@ -0,0 +1,180 @@
+use crate::agent::Agent;
+use crate::builtins::http_server::reason;
+use crate::builtins::r#async::with_timeout;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use lazy_static::lazy_static;
+use num::ToPrimitive;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// buckets that have refilled are dropped once a limiter tracks this many keys
+const MAX_BUCKETS: usize = 10_000;
+
+struct Limiter {
+    limit: f64,
+    // seconds to refill one token
+    refill: f64,
+    buckets: HashMap<String, (f64, Instant)>,
+}
+
+fn refilled(limit: f64, refill: f64, (tokens, updated): (f64, Instant), now: Instant) -> f64 {
+    let elapsed = now.duration_since(updated).as_secs_f64();
+    (tokens + elapsed / refill).min(limit)
+}
+
+impl Limiter {
+    // Err with the seconds until a token is available
+    fn take(&mut self, key: String) -> Result<(), u64> {
+        let now = Instant::now();
+        let (limit, refill) = (self.limit, self.refill);
+        if self.buckets.len() >= MAX_BUCKETS && !self.buckets.contains_key(&key) {
+            self.buckets
+                .retain(|_, bucket| refilled(limit, refill, *bucket, now) < limit);
+        }
+        let bucket = self.buckets.get(&key).cloned().unwrap_or((limit, now));
+        let tokens = refilled(limit, refill, bucket, now);
+        if tokens >= 1.0 {
+            self.buckets.insert(key, (tokens - 1.0, now));
+            Ok(())
+        } else {
+            self.buckets.insert(key, (tokens, now));
+            Err(((1.0 - tokens) * refill).ceil() as u64)
+        }
+    }
+}
+
+lazy_static! {
+    static ref LIMITERS: Mutex<HashMap<usize, Limiter>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_LIMITER: AtomicUsize = AtomicUsize::new(0);
+
+fn response(agent: &Agent, status: u16, headers: Vec<(&str, String)>) -> Result<Value, Value> {
+    let response = Value::new_object(agent.intrinsics.object_prototype.clone());
+    response.set(
+        agent,
+        ObjectKey::from("status"),
+        Value::from(f64::from(status)),
+    )?;
+    let h = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, value) in headers {
+        h.set(agent, ObjectKey::from(name), Value::from(value))?;
+    }
+    response.set(agent, ObjectKey::from("headers"), h)?;
+    response.set(agent, ObjectKey::from("body"), Value::from(reason(status)))?;
+    Ok(response)
+}
+
+fn next_argument(agent: &Agent, args: &[Value]) -> Result<Value, Value> {
+    let next = args.get(1).unwrap_or(&Value::Null);
+    if next.type_of() != "function" {
+        return Err(Value::new_error(agent, "next must be a function"));
+    }
+    Ok(next.clone())
+}
+
+fn client_key(agent: &Agent, function: &Value, request: &Value) -> Result<String, Value> {
+    let key = function.get_slot("http rate limit key");
+    if key != Value::Null {
+        return match key.call(agent, Value::Null, vec![request.clone()])? {
+            Value::String(s) => Ok(s.to_string()),
+            _ => Err(Value::new_error(agent, "rate limit key must be a string")),
+        };
+    }
+    match request.get(agent, ObjectKey::from("remoteAddress"))? {
+        Value::String(s) => Ok(s
+            .parse::<std::net::SocketAddr>()
+            .map(|a| a.ip().to_string())
+            .unwrap_or(s.to_string())),
+        _ => Ok(String::new()),
+    }
+}
+
+fn rate_limited(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let function = ctx.function.as_ref().unwrap();
+    let request = args.get(0).unwrap_or(&Value::Null);
+    let next = next_argument(agent, &args)?;
+    let id = match function.get_slot("http rate limit") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    let key = client_key(agent, function, request)?;
+    let taken = LIMITERS.lock().unwrap().get_mut(&id).unwrap().take(key);
+    match taken {
+        Ok(()) => next.call(agent, Value::Null, vec![]),
+        Err(wait) => response(agent, 429, vec![("Retry-After", wait.max(1).to_string())]),
+    }
+}
+
+fn number_option(agent: &Agent, options: &Value, name: &str, default: f64) -> Result<f64, Value> {
+    if options.type_of() != "object" {
+        return Ok(default);
+    }
+    match options.get(agent, ObjectKey::from(name))? {
+        Value::Null => Ok(default),
+        Value::Number(n) if n > 0.0 => Ok(n),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be a positive number", name),
+        )),
+    }
+}
+
+/// A token bucket per client address: `limit` requests, refilled evenly
+/// over `interval` milliseconds.
+pub fn rate_limit(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = args.get(0).unwrap_or(&Value::Null);
+    let limit = number_option(agent, options, "limit", 60.0)?;
+    let interval = number_option(agent, options, "interval", 60_000.0)?;
+    let key = if options.type_of() == "object" {
+        options.get(agent, ObjectKey::from("key"))?
+    } else {
+        Value::Null
+    };
+    if key != Value::Null && key.type_of() != "function" {
+        return Err(Value::new_error(agent, "key must be a function"));
+    }
+    let id = NEXT_LIMITER.fetch_add(1, Ordering::SeqCst);
+    LIMITERS.lock().unwrap().insert(
+        id,
+        Limiter {
+            limit,
+            refill: interval / limit / 1000.0,
+            buckets: HashMap::new(),
+        },
+    );
+    let middleware = Value::new_builtin_function(agent, rate_limited);
+    middleware.set_slot("http rate limit", Value::from(id as f64));
+    middleware.set_slot("http rate limit key", key);
+    Ok(middleware)
+}
+
+fn timed(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let duration = match ctx.function.as_ref().unwrap().get_slot("http timeout") {
+        Value::Number(n) => Duration::from_millis(n.to_u64().unwrap()),
+        _ => unreachable!(),
+    };
+    let next = next_argument(agent, &args)?;
+    let result = next.call(agent, Value::Null, vec![])?;
+    with_timeout(
+        agent,
+        result,
+        duration,
+        response(agent, 503, Vec::new()),
+        Value::Null,
+    )
+}
+
+/// Answer with 503 when the rest of the chain takes longer than `ms`.
+pub fn timeout(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let ms = match args.get(0).unwrap_or(&Value::Null) {
+        Value::Number(n) if *n >= 0.0 => *n,
+        _ => return Err(Value::new_error(agent, "timeout must be a number")),
+    };
+    let middleware = Value::new_builtin_function(agent, timed);
+    middleware.set_slot("http timeout", Value::from(ms));
+    Ok(middleware)
+}
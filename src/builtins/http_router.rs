@@ -0,0 +1,304 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::url::percent_decode;
+use crate::value::{ObjectKey, Value};
+use num::ToPrimitive;
+
+// routes are stored as (method, pattern, handler) tuples, "*" matches any method
+fn add_route(agent: &Agent, this: &Value, method: &str, args: &[Value]) -> Result<(), Value> {
+    let pattern = match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) if s.starts_with('/') => s.to_string(),
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "pattern must be a string starting with /",
+            ))
+        }
+    };
+    let handler = args.get(1).unwrap_or(&Value::Null);
+    if handler.type_of() != "function" {
+        return Err(Value::new_error(agent, "handler must be a function"));
+    }
+    let segments = pattern.split('/').filter(|s| !s.is_empty());
+    if segments.clone().any(|s| s == ":") || segments.rev().skip(1).any(|s| s == "*") {
+        return Err(Value::new_error(
+            agent,
+            &format!("invalid route pattern {}", pattern),
+        ));
+    }
+    if let Value::List(routes) = this.get_slot("http routes") {
+        routes.borrow_mut().push_back(Value::Tuple(Box::new(vec![
+            Value::from(method),
+            Value::from(pattern),
+            handler.clone(),
+        ])));
+    }
+    Ok(())
+}
+
+fn get_router(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("http routes") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+macro_rules! methods {
+    ($($name:ident => $method:expr),*) => {
+        $(
+            fn $name(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+                let this = get_router(agent, ctx)?;
+                add_route(agent, &this, $method, &args)?;
+                Ok(this)
+            }
+        )*
+    };
+}
+
+methods!(
+    get => "GET",
+    post => "POST",
+    put => "PUT",
+    patch => "PATCH",
+    delete => "DELETE",
+    options => "OPTIONS",
+    all => "*"
+);
+
+fn route(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_router(agent, ctx)?;
+    let method = match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) => s.to_ascii_uppercase(),
+        _ => return Err(Value::new_error(agent, "method must be a string")),
+    };
+    add_route(agent, &this, &method, args.get(1..).unwrap_or(&[]))?;
+    Ok(this)
+}
+
+fn use_middleware(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_router(agent, ctx)?;
+    let middleware = args.get(0).unwrap_or(&Value::Null);
+    if middleware.type_of() != "function" {
+        return Err(Value::new_error(agent, "middleware must be a function"));
+    }
+    if let Value::List(list) = this.get_slot("http middleware") {
+        list.borrow_mut().push_back(middleware.clone());
+    }
+    Ok(this)
+}
+
+fn match_pattern(pattern: &str, segments: &[String]) -> Option<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    let mut i = 0;
+    for part in pattern.split('/').filter(|s| !s.is_empty()) {
+        if part == "*" {
+            params.push(("*".to_string(), segments[i..].join("/")));
+            return Some(params);
+        }
+        let segment = segments.get(i)?;
+        if part.starts_with(':') {
+            params.push((part[1..].to_string(), segment.clone()));
+        } else if part != segment {
+            return None;
+        }
+        i += 1;
+    }
+    if i == segments.len() {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+fn default_response(agent: &Agent, status: u16, allow: Option<String>) -> Result<Value, Value> {
+    let response = Value::new_object(agent.intrinsics.object_prototype.clone());
+    response.set(
+        agent,
+        ObjectKey::from("status"),
+        Value::from(f64::from(status)),
+    )?;
+    let headers = Value::new_object(agent.intrinsics.object_prototype.clone());
+    if let Some(allow) = allow {
+        headers.set(agent, ObjectKey::from("Allow"), Value::from(allow))?;
+    }
+    response.set(agent, ObjectKey::from("headers"), headers)?;
+    response.set(
+        agent,
+        ObjectKey::from("body"),
+        Value::from(crate::builtins::http_server::reason(status)),
+    )?;
+    Ok(response)
+}
+
+fn not_found(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    default_response(agent, 404, None)
+}
+
+fn method_not_allowed(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let allow = match ctx.function.as_ref().unwrap().get_slot("http allow") {
+        Value::String(s) => s,
+        _ => unreachable!(),
+    };
+    default_response(agent, 405, Some(allow.to_string()))
+}
+
+fn call_chain(agent: &Agent, chain: Value, index: usize, request: Value) -> Result<Value, Value> {
+    let (f, last) = match &chain {
+        Value::Tuple(items) => (items[index].clone(), index + 1 == items.len()),
+        _ => unreachable!(),
+    };
+    if last {
+        f.call(agent, Value::Null, vec![request])
+    } else {
+        let next = Value::new_builtin_function(agent, next);
+        next.set_slot("http chain", chain);
+        next.set_slot("http chain index", Value::from((index + 1) as f64));
+        next.set_slot("http request", request.clone());
+        f.call(agent, Value::Null, vec![request, next])
+    }
+}
+
+fn next(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let function = ctx.function.as_ref().unwrap();
+    let index = match function.get_slot("http chain index") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    call_chain(
+        agent,
+        function.get_slot("http chain"),
+        index,
+        function.get_slot("http request"),
+    )
+}
+
+fn dispatch(agent: &Agent, router: &Value, request: Value) -> Result<Value, Value> {
+    if request.type_of() != "object" {
+        return Err(Value::new_error(agent, "request must be an object"));
+    }
+    let method = match request.get(agent, ObjectKey::from("method"))? {
+        Value::String(s) => s.to_string(),
+        _ => String::new(),
+    };
+    let path = match request.get(agent, ObjectKey::from("path"))? {
+        Value::String(s) => s.to_string(),
+        _ => "/".to_string(),
+    };
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect::<Vec<String>>();
+
+    let mut found = None;
+    let mut allowed: Vec<String> = Vec::new();
+    if let Value::List(routes) = router.get_slot("http routes") {
+        for route in routes.borrow().iter() {
+            if let Value::Tuple(route) = route {
+                let (m, pattern) = match (&route[0], &route[1]) {
+                    (Value::String(m), Value::String(p)) => (m.as_str(), p),
+                    _ => unreachable!(),
+                };
+                let params = match match_pattern(pattern, &segments) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                if m == "*" || m == method || (method == "HEAD" && m == "GET") {
+                    found = Some((route[2].clone(), params));
+                    break;
+                }
+                if !allowed.iter().any(|a| a == m) {
+                    allowed.push(m.to_string());
+                }
+                if m == "GET" && !allowed.iter().any(|a| a == "HEAD") {
+                    allowed.push("HEAD".to_string());
+                }
+            }
+        }
+    }
+
+    let handler = match found {
+        Some((handler, params)) => {
+            let object = Value::new_object(agent.intrinsics.object_prototype.clone());
+            for (name, value) in params {
+                object.set(agent, ObjectKey::from(name), Value::from(value))?;
+            }
+            request.set(agent, ObjectKey::from("params"), object)?;
+            handler
+        }
+        None if allowed.is_empty() => Value::new_builtin_function(agent, not_found),
+        None => {
+            let f = Value::new_builtin_function(agent, method_not_allowed);
+            f.set_slot("http allow", Value::from(allowed.join(", ")));
+            f
+        }
+    };
+
+    let mut chain = Vec::new();
+    if let Value::List(middleware) = router.get_slot("http middleware") {
+        chain.extend(middleware.borrow().iter().cloned());
+    }
+    chain.push(handler);
+    call_chain(agent, Value::Tuple(Box::new(chain)), 0, request)
+}
+
+fn handle(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_router(agent, ctx)?;
+    dispatch(agent, &this, args.get(0).unwrap_or(&Value::Null).clone())
+}
+
+fn bound_handle(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let router = ctx.function.as_ref().unwrap().get_slot("http router");
+    dispatch(agent, &router, args.get(0).unwrap_or(&Value::Null).clone())
+}
+
+fn router(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let proto = ctx
+        .function
+        .as_ref()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let router = Value::new_custom_object(proto);
+    router.set_slot("http routes", Value::new_list());
+    router.set_slot("http middleware", Value::new_list());
+    // so the router can be handed to serve() directly
+    let handler = Value::new_builtin_function(agent, bound_handle);
+    handler.set_slot("http router", router.clone());
+    router.set(agent, ObjectKey::from("handler"), handler)?;
+    Ok(router)
+}
+
+pub fn create_router(agent: &Agent) -> Value {
+    let c = Value::new_builtin_function(agent, router);
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("get", get);
+    method!("post", post);
+    method!("put", put);
+    method!("patch", patch);
+    method!("delete", delete);
+    method!("options", options);
+    method!("all", all);
+    method!("route", route);
+    method!("use", use_middleware);
+    method!("handle", handle);
+
+    proto
+        .set(agent, ObjectKey::from("constructor"), c.clone())
+        .unwrap();
+    c.set(agent, ObjectKey::from("prototype"), proto).unwrap();
+    c
+}
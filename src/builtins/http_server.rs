@@ -0,0 +1,1301 @@
+use crate::agent::{Agent, MioMapType};
+use crate::builtins::cookie::parse_date;
+use crate::builtins::mime;
+use crate::builtins::multipart::{self, boundary, Part, PartValue};
+use crate::builtins::tz::civil_from_days;
+use crate::interpreter::Context;
+use crate::intrinsics::form_data_prototype::new_form_data;
+use crate::intrinsics::promise::promise_resolve_i;
+use crate::intrinsics::url::{new_search_params, parse_form, percent_decode, Url};
+use crate::value::{ObjectKey, ObjectKind, Value};
+use lazy_static::lazy_static;
+use mio::{PollOpt, Ready, Registration, SetReadiness, Token};
+use num::ToPrimitive;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAX_HEAD: usize = 64 * 1024;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BODY: u64 = 16 * 1024 * 1024;
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    File(PathBuf, u64, u64),
+    // chunks from the event loop, None ends the body, the optional
+    // interval sends comment pings while the stream is idle
+    Stream(Receiver<Option<Vec<u8>>>, Option<Duration>),
+}
+
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Reply {
+    fn simple(status: u16) -> Reply {
+        Reply {
+            status,
+            headers: vec![(
+                "Content-Type".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+            )],
+            body: Body::Bytes(reason(status).as_bytes().to_vec()),
+        }
+    }
+}
+
+pub struct Incoming {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub form: Option<Vec<Part>>,
+    pub remote: String,
+    reply: Sender<Reply>,
+}
+
+struct Shared {
+    queue: VecDeque<Incoming>,
+    readiness: SetReadiness,
+}
+
+lazy_static! {
+    // keyed by server id, removed when the server is closed
+    static ref SERVERS: Mutex<HashMap<usize, Shared>> = Mutex::new(HashMap::new());
+    static ref REPLIES: Mutex<HashMap<usize, Sender<Reply>>> = Mutex::new(HashMap::new());
+    static ref STREAMS: Mutex<HashMap<usize, Sender<Option<Vec<u8>>>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+/// Format seconds since the epoch as an IMF-fixdate.
+pub fn http_date(secs: u64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// by extension, or by the first bytes of the file for unknown ones
+pub fn mime_type(path: &Path) -> String {
+    let mime = mime::from_path(path).or_else(|| {
+        let mut head = Vec::with_capacity(mime::SNIFF_LENGTH);
+        let file = std::fs::File::open(path).ok()?;
+        file.take(mime::SNIFF_LENGTH as u64)
+            .read_to_end(&mut head)
+            .ok()?;
+        mime::sniff(&head)
+    });
+    mime::content_type(mime.unwrap_or("application/octet-stream"))
+}
+
+fn read_line(reader: &mut BufReader<TcpStream>, limit: &mut usize) -> Result<String, u16> {
+    let mut line = Vec::new();
+    match reader
+        .by_ref()
+        .take(*limit as u64 + 1)
+        .read_until(b'\n', &mut line)
+    {
+        Ok(0) => Err(0),
+        Ok(n) if n > *limit => Err(431),
+        Ok(n) => {
+            *limit -= n;
+            while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            Ok(String::from_utf8_lossy(&line).into_owned())
+        }
+        Err(_) => Err(0),
+    }
+}
+
+fn read_chunked(reader: &mut BufReader<TcpStream>, max_body: u64) -> Result<Vec<u8>, u16> {
+    let mut body = Vec::new();
+    loop {
+        let mut limit = MAX_HEAD;
+        let line = read_line(reader, &mut limit).map_err(|_| 400u16)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| 400u16)?;
+        if (body.len() + size) as u64 > max_body {
+            return Err(413);
+        }
+        if size == 0 {
+            // trailers
+            while !read_line(reader, &mut limit)
+                .map_err(|_| 400u16)?
+                .is_empty()
+            {}
+            return Ok(body);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).map_err(|_| 400u16)?;
+        read_line(reader, &mut limit).map_err(|_| 400u16)?;
+    }
+}
+
+struct Parsed {
+    method: String,
+    target: String,
+    version: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    form: Option<Vec<Part>>,
+}
+
+// removes uploaded files once the response has been written
+struct Uploads(Vec<PathBuf>);
+
+impl Drop for Uploads {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// Ok(None) means the peer went away or sat idle, Err is a status to reply with
+fn read_request(reader: &mut BufReader<TcpStream>, max_body: u64) -> Result<Option<Parsed>, u16> {
+    let mut limit = MAX_HEAD;
+    let mut line = match read_line(reader, &mut limit) {
+        Ok(l) => l,
+        Err(0) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if line.is_empty() {
+        line = match read_line(reader, &mut limit) {
+            Ok(l) => l,
+            Err(0) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+    }
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v)) if !m.is_empty() && v.starts_with("HTTP/1.") => {
+            (m.to_string(), t.to_string(), v.to_string())
+        }
+        _ => return Err(400),
+    };
+    let mut headers = Vec::new();
+    loop {
+        let line = match read_line(reader, &mut limit) {
+            Ok(l) => l,
+            Err(0) => return Err(400),
+            Err(e) => return Err(e),
+        };
+        if line.is_empty() {
+            break;
+        }
+        match line.find(':') {
+            Some(i) => headers.push((
+                line[..i].trim().to_ascii_lowercase(),
+                line[i + 1..].trim().to_string(),
+            )),
+            None => return Err(400),
+        }
+    }
+    let boundary = crate::builtins::http::header(&headers, "content-type").and_then(boundary);
+    let (body, form) = match (
+        crate::builtins::http::header(&headers, "transfer-encoding"),
+        crate::builtins::http::header(&headers, "content-length"),
+    ) {
+        (Some(te), _) if te.to_ascii_lowercase().contains("chunked") => {
+            let body = read_chunked(reader, max_body)?;
+            match boundary {
+                Some(boundary) => {
+                    let form = multipart::parse(&body[..], &boundary).map_err(|_| 400u16)?;
+                    (Vec::new(), Some(form))
+                }
+                None => (body, None),
+            }
+        }
+        (Some(_), _) => return Err(501),
+        (None, Some(length)) => {
+            let length = length.parse::<u64>().map_err(|_| 400u16)?;
+            // refused before any of it is read
+            if length > max_body {
+                return Err(413);
+            }
+            match boundary {
+                // file parts go to disk while the body is being read
+                Some(boundary) => {
+                    let mut body = reader.by_ref().take(length);
+                    let form = multipart::parse(&mut body, &boundary).map_err(|_| 400u16)?;
+                    if body.limit() > 0 {
+                        multipart::remove_uploads(&form);
+                        return Err(400);
+                    }
+                    (Vec::new(), Some(form))
+                }
+                None => {
+                    let mut body = vec![0; length as usize];
+                    reader.read_exact(&mut body).map_err(|_| 400u16)?;
+                    (body, None)
+                }
+            }
+        }
+        (None, None) => (Vec::new(), None),
+    };
+    Ok(Some(Parsed {
+        method,
+        target,
+        version,
+        headers,
+        body,
+        form,
+    }))
+}
+
+fn write_chunk(stream: &mut TcpStream, chunk: &[u8]) -> bool {
+    // an empty chunk would end the body
+    chunk.is_empty()
+        || (stream
+            .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+            .is_ok()
+            && stream.write_all(chunk).is_ok()
+            && stream.write_all(b"\r\n").is_ok()
+            && stream.flush().is_ok())
+}
+
+fn write_stream(
+    stream: &mut TcpStream,
+    chunked: bool,
+    receiver: Receiver<Option<Vec<u8>>>,
+    ping: Option<Duration>,
+) -> bool {
+    let write = |stream: &mut TcpStream, chunk: &[u8]| {
+        if chunked {
+            write_chunk(stream, chunk)
+        } else {
+            stream.write_all(chunk).is_ok() && stream.flush().is_ok()
+        }
+    };
+    loop {
+        let next = match ping {
+            Some(interval) => receiver.recv_timeout(interval),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match next {
+            Ok(Some(chunk)) => {
+                if !write(stream, &chunk) {
+                    return false;
+                }
+            }
+            Ok(None) => return !chunked || stream.write_all(b"0\r\n\r\n").is_ok(),
+            Err(RecvTimeoutError::Timeout) => {
+                if !write(stream, b": ping\n\n") {
+                    return false;
+                }
+            }
+            // the script side failed, cut the body short
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+fn write_reply(
+    stream: &mut TcpStream,
+    head_only: bool,
+    chunked: bool,
+    mut keep_alive: bool,
+    reply: Reply,
+) -> bool {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", reply.status, reason(reply.status));
+    let mut has_length = false;
+    for (name, value) in &reply.headers {
+        let lower = name.to_ascii_lowercase();
+        if lower == "connection" || lower == "transfer-encoding" {
+            continue;
+        }
+        if lower == "content-length" {
+            has_length = true;
+        }
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    let length = match &reply.body {
+        Body::Bytes(b) => Some(b.len() as u64),
+        Body::File(_, _, len) => Some(*len),
+        Body::Stream(..) => None,
+    };
+    if reply.status != 204 && reply.status != 304 {
+        match length {
+            Some(length) if !has_length => {
+                head += &format!("Content-Length: {}\r\n", length);
+            }
+            Some(_) => {}
+            None if chunked => head += "Transfer-Encoding: chunked\r\n",
+            // without chunking the end of the body is the end of the connection
+            None => keep_alive = false,
+        }
+    }
+    head += &format!("Date: {}\r\n", http_date(now()));
+    head += if keep_alive {
+        "Connection: keep-alive\r\n\r\n"
+    } else {
+        "Connection: close\r\n\r\n"
+    };
+    if stream.write_all(head.as_bytes()).is_err() {
+        return false;
+    }
+    if head_only || reply.status == 204 || reply.status == 304 {
+        return stream.flush().is_ok();
+    }
+    let written = match reply.body {
+        Body::Bytes(b) => stream.write_all(&b).is_ok(),
+        Body::File(path, start, len) => match std::fs::File::open(&path) {
+            Ok(mut file) => {
+                file.seek(SeekFrom::Start(start)).is_ok()
+                    && std::io::copy(&mut file.take(len), stream).ok() == Some(len)
+            }
+            Err(_) => false,
+        },
+        Body::Stream(receiver, ping) => write_stream(stream, chunked, receiver, ping),
+    };
+    written && stream.flush().is_ok()
+}
+
+fn is_stream(reply: &Reply) -> bool {
+    if let Body::Stream(..) = reply.body {
+        true
+    } else {
+        false
+    }
+}
+
+fn serve_connection(server: usize, stream: TcpStream, max_body: u64) {
+    let remote = stream
+        .peer_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+    let _ = stream.set_read_timeout(Some(IDLE_TIMEOUT));
+    let mut writer = match stream.try_clone() {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    loop {
+        let Parsed {
+            method,
+            target,
+            version,
+            headers,
+            body,
+            form,
+        } = match read_request(&mut reader, max_body) {
+            Ok(Some(r)) => r,
+            Ok(None) => return,
+            Err(status) => {
+                write_reply(&mut writer, false, false, false, Reply::simple(status));
+                return;
+            }
+        };
+        let connection = crate::builtins::http::header(&headers, "connection")
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let keep_alive = if version == "HTTP/1.0" {
+            connection == "keep-alive"
+        } else {
+            connection != "close"
+        };
+        let head_only = method == "HEAD";
+        let _uploads = Uploads(
+            form.iter()
+                .flatten()
+                .filter_map(|p| match &p.value {
+                    PartValue::File(path, _) => Some(path.clone()),
+                    _ => None,
+                })
+                .collect(),
+        );
+
+        let (sender, receiver) = channel();
+        {
+            let mut servers = SERVERS.lock().unwrap();
+            match servers.get_mut(&server) {
+                Some(shared) => {
+                    shared.queue.push_back(Incoming {
+                        method,
+                        target,
+                        headers,
+                        body,
+                        form,
+                        remote: remote.clone(),
+                        reply: sender,
+                    });
+                    let _ = shared.readiness.set_readiness(Ready::readable());
+                }
+                None => return,
+            }
+        }
+        // dropped senders mean the server closed before replying
+        let reply = match receiver.recv() {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        let chunked = version != "HTTP/1.0";
+        let keep_alive = keep_alive && (chunked || !is_stream(&reply));
+        if !write_reply(&mut writer, head_only, chunked, keep_alive, reply) || !keep_alive {
+            return;
+        }
+    }
+}
+
+fn new_request(agent: &Agent, incoming: Incoming) -> Value {
+    let o = Value::new_custom_object(agent.intrinsics.http_request_prototype.get(agent));
+    macro_rules! p {
+        ($name:expr, $value:expr) => {
+            o.set(agent, ObjectKey::from($name), $value).unwrap();
+        };
+    }
+    let host = crate::builtins::http::header(&incoming.headers, "host").unwrap_or("localhost");
+    let base = Url::parse(&format!("http://{}/", host), None)
+        .unwrap_or_else(|_| Url::parse("http://localhost/", None).unwrap());
+    let url = Url::parse(&incoming.target, Some(&base)).unwrap_or_else(|_| base.clone());
+    let query = url.query.clone().unwrap_or_default();
+
+    p!("method", Value::from(incoming.method));
+    p!("url", Value::from(url.href()));
+    p!("path", Value::from(url.pathname()));
+    p!("query", new_search_params(agent, parse_form(&query)));
+    p!("remoteAddress", Value::from(incoming.remote));
+    p!("bodyUsed", Value::from(false));
+
+    let headers = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, value) in &incoming.headers {
+        let key = ObjectKey::from(name.clone());
+        let value = match headers.get(agent, key.clone()).unwrap() {
+            Value::String(s) => format!("{}, {}", s, value),
+            _ => value.clone(),
+        };
+        headers.set(agent, key, Value::from(value)).unwrap();
+    }
+    p!("headers", headers);
+
+    o.set_slot(
+        "http body",
+        Value::new_buffer_from_vec(agent, incoming.body),
+    );
+    if let Some(parts) = incoming.form {
+        o.set_slot("http form", form_entries(agent, parts));
+    }
+    o
+}
+
+// file parts are described rather than loaded, their temp files are
+// removed once the response has been written
+fn form_entries(agent: &Agent, parts: Vec<Part>) -> Value {
+    let entries = parts
+        .into_iter()
+        .map(|part| {
+            let value = match part.value {
+                PartValue::Text(data) => Value::from(String::from_utf8_lossy(&data).into_owned()),
+                PartValue::File(path, size) => {
+                    let file = Value::new_object(agent.intrinsics.object_prototype.clone());
+                    let filename = part.filename.unwrap_or_default();
+                    let content_type = part
+                        .content_type
+                        .unwrap_or_else(|| "application/octet-stream".to_string());
+                    let path = path.to_string_lossy().into_owned();
+                    macro_rules! p {
+                        ($name:expr, $value:expr) => {
+                            file.set(agent, ObjectKey::from($name), $value).unwrap();
+                        };
+                    }
+                    p!("filename", Value::from(filename));
+                    p!("type", Value::from(content_type));
+                    p!("size", Value::from(size as f64));
+                    p!("path", Value::from(path));
+                    file
+                }
+            };
+            (part.name, value)
+        })
+        .collect();
+    new_form_data(agent, entries)
+}
+
+fn to_bytes(agent: &Agent, value: &Value) -> Result<Vec<u8>, Value> {
+    match value {
+        Value::Null => Ok(Vec::new()),
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Buffer(b) => Ok(b.borrow().clone()),
+            _ => Err(Value::new_error(
+                agent,
+                "response body must be a string or Buffer",
+            )),
+        },
+        _ => Err(Value::new_error(
+            agent,
+            "response body must be a string or Buffer",
+        )),
+    }
+}
+
+fn to_reply(agent: &Agent, value: &Value) -> Result<Reply, Value> {
+    if let Value::String(s) = value {
+        return Ok(Reply {
+            status: 200,
+            headers: vec![(
+                "Content-Type".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+            )],
+            body: Body::Bytes(s.as_bytes().to_vec()),
+        });
+    }
+    if value.type_of() != "object" {
+        return Err(Value::new_error(
+            agent,
+            "handler must return a string or response object",
+        ));
+    }
+    let status = match value.get(agent, ObjectKey::from("status"))? {
+        Value::Null => 200,
+        Value::Number(n) if (100.0..1000.0).contains(&n) => n.to_u16().unwrap(),
+        _ => return Err(Value::new_error(agent, "status must be a number")),
+    };
+    let mut headers = Vec::new();
+    let h = value.get(agent, ObjectKey::from("headers"))?;
+    if h != Value::Null {
+        for key in h.keys(agent)? {
+            if let ObjectKey::Symbol(..) = key {
+                continue;
+            }
+            let v = h.get(agent, key.clone())?;
+            let v = match v {
+                Value::String(s) => s.to_string(),
+                Value::Number(n) => crate::num_util::to_string(n),
+                _ => return Err(Value::new_error(agent, "header values must be strings")),
+            };
+            headers.push((key.to_string(), v));
+        }
+    }
+    let body = if value.has_slot("http file") {
+        match value.get_slot("http file") {
+            Value::String(path) => {
+                let number = |slot| match value.get_slot(slot) {
+                    Value::Number(n) => n.to_u64().unwrap(),
+                    _ => unreachable!(),
+                };
+                let (start, length) = (number("http file start"), number("http file length"));
+                Body::File(PathBuf::from(path.as_str()), start, length)
+            }
+            _ => unreachable!(),
+        }
+    } else {
+        let body = value.get(agent, ObjectKey::from("body"))?;
+        if is_iterable(agent, &body)? {
+            let sse = headers.iter().any(|(n, v)| {
+                n.eq_ignore_ascii_case("content-type")
+                    && v.to_ascii_lowercase().starts_with("text/event-stream")
+            });
+            let ping = match value.get(agent, ObjectKey::from("pingInterval"))? {
+                Value::Null if sse => Some(Duration::from_secs(15)),
+                Value::Null => None,
+                Value::Number(n) if n == 0.0 => None,
+                Value::Number(n) if n > 0.0 => Some(Duration::from_millis(n.to_u64().unwrap())),
+                _ => {
+                    return Err(Value::new_error(
+                        agent,
+                        "pingInterval must be a positive number",
+                    ))
+                }
+            };
+            if sse
+                && !headers
+                    .iter()
+                    .any(|(n, _)| n.eq_ignore_ascii_case("cache-control"))
+            {
+                headers.push(("Cache-Control".to_string(), "no-cache".to_string()));
+            }
+            let receiver = start_stream(agent, &body, sse)?;
+            return Ok(Reply {
+                status,
+                headers,
+                body: Body::Stream(receiver, ping),
+            });
+        }
+        if let Value::String(..) = body {
+            if !headers
+                .iter()
+                .any(|(n, _)| n.eq_ignore_ascii_case("content-type"))
+            {
+                headers.push((
+                    "Content-Type".to_string(),
+                    "text/plain; charset=utf-8".to_string(),
+                ));
+            }
+        }
+        Body::Bytes(to_bytes(agent, &body)?)
+    };
+    Ok(Reply {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn is_iterable(agent: &Agent, value: &Value) -> Result<bool, Value> {
+    match value {
+        Value::Object(o) => match o.kind {
+            ObjectKind::Buffer(..) => Ok(false),
+            // a bare iterator object is accepted as well
+            _ => Ok(
+                value.has(agent, ObjectKey::well_known_symbol("asyncIterator"))?
+                    || value.has(agent, ObjectKey::well_known_symbol("iterator"))?
+                    || value.get(agent, ObjectKey::from("next"))?.type_of() == "function",
+            ),
+        },
+        _ => Ok(false),
+    }
+}
+
+fn sse_field(out: &mut String, name: &str, value: &str) {
+    for line in value.split('\n') {
+        out.push_str(name);
+        out.push_str(": ");
+        out.push_str(line.trim_end_matches('\r'));
+        out.push('\n');
+    }
+}
+
+// strings become data lines, objects with event/id/retry/data become those
+// fields, anything else is sent as JSON data
+fn frame(agent: &Agent, value: &Value, sse: bool) -> Result<Vec<u8>, Value> {
+    if !sse {
+        return to_bytes(agent, value);
+    }
+    let to_text = |v: &Value| -> Result<String, Value> {
+        match v {
+            Value::String(s) => Ok(s.to_string()),
+            Value::Number(n) => Ok(crate::num_util::to_string(*n)),
+            v => crate::builtins::json::stringify(agent, v, false, None),
+        }
+    };
+    let mut out = String::new();
+    let fields = ["event", "id", "retry", "data"];
+    let mut structured = false;
+    if value.type_of() == "object" {
+        for field in &fields {
+            structured = structured || value.has(agent, ObjectKey::from(*field))?;
+        }
+    }
+    if structured {
+        for field in &fields {
+            match value.get(agent, ObjectKey::from(*field))? {
+                Value::Null => {}
+                v if *field == "data" => sse_field(&mut out, "data", &to_text(&v)?),
+                // other fields are single line
+                v => sse_field(&mut out, field, to_text(&v)?.lines().next().unwrap_or("")),
+            }
+        }
+    } else {
+        sse_field(&mut out, "data", &to_text(value)?);
+    }
+    out.push('\n');
+    Ok(out.into_bytes())
+}
+
+fn stream_id(state: &Value) -> usize {
+    match state.get_slot("http stream") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn close_stream(agent: &Agent, state: &Value) {
+    STREAMS.lock().unwrap().remove(&stream_id(state));
+    if let Value::Iterator(pair) = state.get_slot("http stream iterator") {
+        let (iterator, _) = *pair;
+        if let Ok(f) = iterator.get(agent, ObjectKey::from("return")) {
+            if f.type_of() == "function" {
+                let _ = f.call(agent, iterator, vec![]);
+            }
+        }
+    }
+}
+
+fn stream_failed(agent: &Agent, state: &Value, error: Value) {
+    eprintln!("http stream failed: {}", Value::inspect(agent, &error));
+    close_stream(agent, state);
+}
+
+fn on_chunk(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.as_ref().unwrap().get_slot("http stream");
+    let id = stream_id(&state);
+    let result = args.get(0).unwrap_or(&Value::Null);
+    if result.get(agent, ObjectKey::from("done"))?.to_bool() {
+        if let Some(sender) = STREAMS.lock().unwrap().remove(&id) {
+            let _ = sender.send(None);
+        }
+        return Ok(Value::Null);
+    }
+    let value = result.get(agent, ObjectKey::from("value"))?;
+    let sse = state.get_slot("http stream sse").to_bool();
+    let chunk = match frame(agent, &value, sse) {
+        Ok(c) => c,
+        Err(e) => {
+            stream_failed(agent, &state, e);
+            return Ok(Value::Null);
+        }
+    };
+    let sent = match STREAMS.lock().unwrap().get(&id) {
+        Some(sender) => sender.send(Some(chunk)).is_ok(),
+        None => false,
+    };
+    if !sent {
+        // the client went away
+        close_stream(agent, &state);
+    } else if let Err(e) = pump(agent, &state) {
+        stream_failed(agent, &state, e);
+    }
+    Ok(Value::Null)
+}
+
+fn on_stream_error(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.as_ref().unwrap().get_slot("http stream");
+    let error = args.get(0).unwrap_or(&Value::Null).clone();
+    stream_failed(agent, &state, error);
+    Ok(Value::Null)
+}
+
+fn pump(agent: &Agent, state: &Value) -> Result<(), Value> {
+    let result = match state.get_slot("http stream iterator") {
+        Value::Iterator(pair) => {
+            let (iterator, next) = *pair;
+            next.call(agent, iterator, vec![])?
+        }
+        _ => unreachable!(),
+    };
+    let promise = promise_resolve_i(agent, agent.intrinsics.promise.clone(), result)?;
+    let on_chunk = Value::new_builtin_function(agent, on_chunk);
+    on_chunk.set_slot("http stream", state.clone());
+    let on_stream_error = Value::new_builtin_function(agent, on_stream_error);
+    on_stream_error.set_slot("http stream", state.clone());
+    promise.get(agent, ObjectKey::from("then"))?.call(
+        agent,
+        promise,
+        vec![on_chunk, on_stream_error],
+    )?;
+    Ok(())
+}
+
+fn start_stream(
+    agent: &Agent,
+    body: &Value,
+    sse: bool,
+) -> Result<Receiver<Option<Vec<u8>>>, Value> {
+    let iterator = if body.has(agent, ObjectKey::well_known_symbol("asyncIterator"))? {
+        body.to_async_iterator(agent)?
+    } else if body.has(agent, ObjectKey::well_known_symbol("iterator"))? {
+        body.to_iterator(agent)?
+    } else {
+        let next = body.get(agent, ObjectKey::from("next"))?;
+        Value::Iterator(Box::new((body.clone(), next)))
+    };
+    let (sender, receiver) = channel();
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    STREAMS.lock().unwrap().insert(id, sender);
+    let state = Value::new_custom_object(agent.intrinsics.object_prototype.clone());
+    state.set_slot("http stream", Value::from(id as f64));
+    state.set_slot("http stream iterator", iterator);
+    state.set_slot("http stream sse", Value::from(sse));
+    if let Err(e) = pump(agent, &state) {
+        STREAMS.lock().unwrap().remove(&id);
+        return Err(e);
+    }
+    Ok(receiver)
+}
+
+fn send_reply(id: usize, reply: Reply) {
+    if let Some(sender) = REPLIES.lock().unwrap().remove(&id) {
+        let _ = sender.send(reply);
+    }
+}
+
+fn reply_id(ctx: &Context) -> usize {
+    match ctx.function.as_ref().unwrap().get_slot("http reply") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn fail(agent: &Agent, id: usize, error: Value) {
+    eprintln!("http handler failed: {}", Value::inspect(agent, &error));
+    send_reply(id, Reply::simple(500));
+}
+
+fn on_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let id = reply_id(ctx);
+    match to_reply(agent, args.get(0).unwrap_or(&Value::Null)) {
+        Ok(reply) => send_reply(id, reply),
+        Err(e) => fail(agent, id, e),
+    }
+    Ok(Value::Null)
+}
+
+fn on_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    fail(
+        agent,
+        reply_id(ctx),
+        args.get(0).unwrap_or(&Value::Null).clone(),
+    );
+    Ok(Value::Null)
+}
+
+fn respond(agent: &Agent, id: usize, result: Result<Value, Value>) -> Result<(), Value> {
+    let value = match result {
+        Ok(v) => v,
+        Err(e) => {
+            fail(agent, id, e);
+            return Ok(());
+        }
+    };
+    let promise = promise_resolve_i(agent, agent.intrinsics.promise.clone(), value)?;
+    let on_fulfilled = Value::new_builtin_function(agent, on_fulfilled);
+    on_fulfilled.set_slot("http reply", Value::from(id as f64));
+    let on_rejected = Value::new_builtin_function(agent, on_rejected);
+    on_rejected.set_slot("http reply", Value::from(id as f64));
+    promise.get(agent, ObjectKey::from("then"))?.call(
+        agent,
+        promise,
+        vec![on_fulfilled, on_rejected],
+    )?;
+    Ok(())
+}
+
+fn call_handler_job(agent: &Agent, args: Vec<Value>) -> Result<(), Value> {
+    let id = match &args[2] {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    let result = args[0].call(agent, Value::Null, vec![args[1].clone()]);
+    respond(agent, id, result)
+}
+
+pub fn handle(agent: &Agent, token: Token, registration: Registration, server: Value) {
+    let id = match server.get_slot("http server id") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    let incoming = match SERVERS.lock().unwrap().get_mut(&id) {
+        Some(shared) => {
+            let _ = shared.readiness.set_readiness(Ready::empty());
+            shared.queue.drain(..).collect::<Vec<Incoming>>()
+        }
+        None => return,
+    };
+    let handler = server.get_slot("http server handler");
+    agent.insert_op(token, MioMapType::HttpServer(registration, server));
+    for request in incoming {
+        let reply = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        REPLIES.lock().unwrap().insert(reply, request.reply.clone());
+        let request = new_request(agent, request);
+        agent.enqueue_job(
+            call_handler_job,
+            vec![handler.clone(), request, Value::from(reply as f64)],
+        );
+    }
+}
+
+fn close(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let server = ctx.function.as_ref().unwrap().get_slot("http server");
+    if let Value::Number(id) = server.get_slot("http server id") {
+        SERVERS.lock().unwrap().remove(&id.to_usize().unwrap());
+    }
+    if let Value::Number(t) = server.get_slot("http server token") {
+        agent
+            .mio_map
+            .borrow_mut()
+            .remove(&Token(t.to_usize().unwrap()));
+    }
+    Ok(Value::Null)
+}
+
+fn listen(id: usize, listener: TcpListener, max_body: u64) {
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+                std::thread::spawn(move || serve_connection(id, stream, max_body));
+            }
+            Err(_) => {
+                if !SERVERS.lock().unwrap().contains_key(&id) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+    }
+}
+
+pub fn serve(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let address = match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "address must be a string")),
+    };
+    let mut handler = args.get(1).unwrap_or(&Value::Null).clone();
+    if handler.has_slot("http routes") {
+        handler = handler.get(agent, ObjectKey::from("handler"))?;
+    }
+    if handler.type_of() != "function" {
+        return Err(Value::new_error(agent, "handler must be a function"));
+    }
+    let options = args.get(2).unwrap_or(&Value::Null);
+    let max_body = if options.type_of() == "object" {
+        match options.get(agent, ObjectKey::from("maxBodySize"))? {
+            Value::Null => MAX_BODY,
+            Value::Number(n) if n >= 0.0 => n.to_u64().unwrap(),
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    "maxBodySize must be a positive number",
+                ))
+            }
+        }
+    } else {
+        MAX_BODY
+    };
+    let listener = match TcpListener::bind(&address) {
+        Ok(l) => l,
+        Err(e) => return Err(Value::new_error(agent, &format!("{}", e))),
+    };
+    let local = match listener.local_addr() {
+        Ok(a) => a,
+        Err(e) => return Err(Value::new_error(agent, &format!("{}", e))),
+    };
+    if let Err(e) = listener.set_nonblocking(true) {
+        return Err(Value::new_error(agent, &format!("{}", e)));
+    }
+
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.mio_token();
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    SERVERS.lock().unwrap().insert(
+        id,
+        Shared {
+            queue: VecDeque::new(),
+            readiness: set_readiness,
+        },
+    );
+
+    let server = Value::new_custom_object(agent.intrinsics.object_prototype.clone());
+    server.set_slot("http server id", Value::from(id as f64));
+    server.set_slot("http server token", Value::from(token.0 as f64));
+    server.set_slot("http server handler", handler);
+    server.set(
+        agent,
+        ObjectKey::from("address"),
+        Value::from(local.to_string()),
+    )?;
+    server.set(
+        agent,
+        ObjectKey::from("port"),
+        Value::from(f64::from(local.port())),
+    )?;
+    let close = Value::new_builtin_function(agent, close);
+    close.set_slot("http server", server.clone());
+    server.set(agent, ObjectKey::from("close"), close)?;
+    agent.insert_op(token, MioMapType::HttpServer(registration, server.clone()));
+
+    std::thread::spawn(move || listen(id, listener, max_body));
+
+    Ok(server)
+}
+
+fn file_response(
+    agent: &Agent,
+    status: u16,
+    headers: Vec<(String, String)>,
+    file: Option<(&Path, u64, u64)>,
+) -> Result<Value, Value> {
+    let response = Value::new_custom_object(agent.intrinsics.object_prototype.clone());
+    response.set(
+        agent,
+        ObjectKey::from("status"),
+        Value::from(f64::from(status)),
+    )?;
+    let h = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, value) in headers {
+        h.set(agent, ObjectKey::from(name), Value::from(value))?;
+    }
+    response.set(agent, ObjectKey::from("headers"), h)?;
+    match file {
+        Some((path, start, length)) => {
+            response.set_slot(
+                "http file",
+                Value::from(path.to_string_lossy().into_owned()),
+            );
+            response.set_slot("http file start", Value::from(start as f64));
+            response.set_slot("http file length", Value::from(length as f64));
+        }
+        None => {
+            response.set(
+                agent,
+                ObjectKey::from("body"),
+                Value::from(reason(status).to_string()),
+            )?;
+        }
+    }
+    Ok(response)
+}
+
+// None when the header is absent, malformed or lists several ranges,
+// in which case the whole file is sent
+fn parse_range(header: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim();
+    if !spec.starts_with("bytes=") || spec.contains(',') {
+        return None;
+    }
+    let spec = &spec[6..];
+    let dash = spec.find('-')?;
+    let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+    if first.is_empty() {
+        let suffix = last.parse::<u64>().ok()?;
+        if suffix == 0 || size == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((size.saturating_sub(suffix), size - 1)));
+    }
+    let first = first.parse::<u64>().ok()?;
+    let last = if last.is_empty() {
+        size.saturating_sub(1)
+    } else {
+        last.parse::<u64>().ok()?.min(size.saturating_sub(1))
+    };
+    if first >= size || first > last {
+        return Some(Err(()));
+    }
+    Some(Ok((first, last)))
+}
+
+fn string_property(agent: &Agent, value: &Value, name: &str) -> Result<Option<String>, Value> {
+    match value.get(agent, ObjectKey::from(name))? {
+        Value::String(s) => Ok(Some(s.to_string())),
+        _ => Ok(None),
+    }
+}
+
+fn serve_file(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let function = ctx.function.as_ref().unwrap();
+    let root = match function.get_slot("http root") {
+        Value::String(s) => PathBuf::from(s.as_str()),
+        _ => unreachable!(),
+    };
+    let index = match function.get_slot("http index") {
+        Value::String(s) => Some(s),
+        _ => None,
+    };
+    let request = args.get(0).unwrap_or(&Value::Null);
+    if request.type_of() != "object" {
+        return Err(Value::new_error(agent, "request must be an object"));
+    }
+    let method = string_property(agent, request, "method")?.unwrap_or_default();
+    if method != "GET" && method != "HEAD" {
+        let headers = vec![("Allow".to_string(), "GET, HEAD".to_string())];
+        return file_response(agent, 405, headers, None);
+    }
+    let pathname = string_property(agent, request, "path")?.unwrap_or_else(|| "/".to_string());
+    let request_headers = request.get(agent, ObjectKey::from("headers"))?;
+    let request_header = |name: &str| -> Result<Option<String>, Value> {
+        if request_headers == Value::Null {
+            Ok(None)
+        } else {
+            string_property(agent, &request_headers, name)
+        }
+    };
+
+    let mut path = root.clone();
+    for segment in pathname.split('/') {
+        let segment = percent_decode(segment);
+        if segment == ".."
+            || segment.contains('/')
+            || segment.contains('\\')
+            || segment.contains('\0')
+        {
+            return file_response(agent, 404, Vec::new(), None);
+        }
+        if !segment.is_empty() && segment != "." {
+            path.push(segment);
+        }
+    }
+    // symlinks may still lead outside the root
+    let mut path = match path.canonicalize() {
+        Ok(p) if p.starts_with(&root) => p,
+        _ => return file_response(agent, 404, Vec::new(), None),
+    };
+    if path.is_dir() {
+        if !pathname.ends_with('/') {
+            let mut location = format!("{}/", pathname);
+            if let Some(query) = request
+                .get(agent, ObjectKey::from("url"))
+                .ok()
+                .and_then(|u| match u {
+                    Value::String(s) => Url::parse(&s, None).ok(),
+                    _ => None,
+                })
+                .and_then(|u| u.query)
+            {
+                location += "?";
+                location += &query;
+            }
+            let headers = vec![("Location".to_string(), location)];
+            return file_response(agent, 301, headers, None);
+        }
+        match index {
+            Some(index) if path.join(index.as_str()).is_file() => path = path.join(index.as_str()),
+            _ => return file_response(agent, 404, Vec::new(), None),
+        }
+    }
+    let metadata = match std::fs::metadata(&path) {
+        Ok(m) if m.is_file() => m,
+        _ => return file_response(agent, 404, Vec::new(), None),
+    };
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let etag = format!("\"{:x}-{:x}\"", size, modified);
+
+    let mut headers = vec![
+        ("ETag".to_string(), etag.clone()),
+        ("Last-Modified".to_string(), http_date(modified)),
+        ("Accept-Ranges".to_string(), "bytes".to_string()),
+    ];
+
+    let not_modified = match request_header("if-none-match")? {
+        Some(tags) => tags
+            .split(',')
+            .map(|t| t.trim())
+            .any(|t| t == "*" || t == etag || t.trim_start_matches("W/") == etag),
+        None => match request_header("if-modified-since")?.and_then(|d| parse_date(&d)) {
+            Some(since) => modified <= since,
+            None => false,
+        },
+    };
+    if not_modified {
+        return file_response(agent, 304, headers, Some((&path, 0, 0)));
+    }
+
+    let range = match request_header("range")? {
+        Some(range) => match request_header("if-range")? {
+            Some(ref tag) if *tag != etag => None,
+            _ => parse_range(&range, size),
+        },
+        None => None,
+    };
+    headers.push(("Content-Type".to_string(), mime_type(&path)));
+    match range {
+        Some(Ok((first, last))) => {
+            headers.push((
+                "Content-Range".to_string(),
+                format!("bytes {}-{}/{}", first, last, size),
+            ));
+            file_response(agent, 206, headers, Some((&path, first, last - first + 1)))
+        }
+        Some(Err(())) => {
+            let headers = vec![("Content-Range".to_string(), format!("bytes */{}", size))];
+            file_response(agent, 416, headers, None)
+        }
+        None => file_response(agent, 200, headers, Some((&path, 0, size))),
+    }
+}
+
+pub fn serve_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let root = match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "root must be a string")),
+    };
+    let root = match Path::new(&root).canonicalize() {
+        Ok(r) => r,
+        Err(e) => return Err(Value::new_error(agent, &format!("{}: {}", root, e))),
+    };
+    let options = args.get(1).unwrap_or(&Value::Null);
+    let index = if options.type_of() == "object" {
+        match options.get(agent, ObjectKey::from("index"))? {
+            Value::Null => Value::from("index.html"),
+            Value::Boolean(false) => Value::Null,
+            Value::String(s) => Value::String(s),
+            _ => return Err(Value::new_error(agent, "index must be a string or false")),
+        }
+    } else {
+        Value::from("index.html")
+    };
+    let handler = Value::new_builtin_function(agent, serve_file);
+    handler.set_slot(
+        "http root",
+        Value::from(root.to_string_lossy().into_owned()),
+    );
+    handler.set_slot("http index", index);
+    Ok(handler)
+}
@@ -0,0 +1,452 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::{HashMap, HashSet};
+
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(agent: &Agent, out: &mut String, n: f64) -> Result<(), Value> {
+    if !n.is_finite() {
+        return Err(Value::new_error(
+            agent,
+            "cannot serialize a non-finite number",
+        ));
+    }
+    if n == 0.0 {
+        // -0 and 0 must produce the same output
+        out.push('0');
+    } else {
+        out.push_str(&crate::num_util::to_string(n));
+    }
+    Ok(())
+}
+
+struct Writer<'a> {
+    agent: &'a Agent,
+    out: String,
+    sort_keys: bool,
+    indent: Option<String>,
+    depth: usize,
+    seen: HashSet<*const ObjectKind>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(agent: &'a Agent, sort_keys: bool, indent: Option<String>) -> Writer<'a> {
+        Writer {
+            agent,
+            out: String::new(),
+            sort_keys,
+            indent,
+            depth: 0,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn separator(&mut self, first: bool) {
+        if !first {
+            self.out.push(',');
+        }
+        if let Some(indent) = &self.indent {
+            self.out.push('\n');
+            self.out.push_str(&indent.repeat(self.depth));
+        }
+    }
+
+    fn close(&mut self, c: char, empty: bool) {
+        self.depth -= 1;
+        if !empty {
+            self.separator(true);
+        }
+        self.out.push(c);
+    }
+
+    fn write_value(&mut self, value: &Value) -> Result<(), Value> {
+        let agent = self.agent;
+        match value {
+            Value::Null => self.out.push_str("null"),
+            Value::Boolean(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) => write_number(agent, &mut self.out, *n)?,
+            Value::String(s) => write_string(&mut self.out, s),
+            Value::Tuple(items) => {
+                self.out.push('[');
+                self.depth += 1;
+                for (i, item) in items.iter().enumerate() {
+                    self.separator(i == 0);
+                    self.write_value(item)?;
+                }
+                self.close(']', items.is_empty());
+            }
+            // fields are already sorted, whatever sort_keys says
+            Value::Record(fields) => {
+                self.out.push('{');
+                self.depth += 1;
+                for (i, (name, field)) in fields.iter().enumerate() {
+                    self.separator(i == 0);
+                    write_string(&mut self.out, name);
+                    self.out.push(':');
+                    if self.indent.is_some() {
+                        self.out.push(' ');
+                    }
+                    self.write_value(field)?;
+                }
+                self.close('}', fields.is_empty());
+            }
+            Value::Object(o) => {
+                if value.type_of() == "function" {
+                    return Err(Value::new_error(agent, "cannot serialize a function"));
+                }
+                let id = &o.kind as *const ObjectKind;
+                if !self.seen.insert(id) {
+                    return Err(Value::new_error(
+                        agent,
+                        "cannot serialize a circular structure",
+                    ));
+                }
+                match &o.kind {
+                    ObjectKind::Array(..) | ObjectKind::Buffer(..) => {
+                        let length = match value.get(agent, ObjectKey::from("length"))? {
+                            Value::Number(n) => n as usize,
+                            _ => 0,
+                        };
+                        self.out.push('[');
+                        self.depth += 1;
+                        for i in 0..length {
+                            self.separator(i == 0);
+                            self.write_value(&value.get(agent, ObjectKey::from(i))?)?;
+                        }
+                        self.close(']', length == 0);
+                    }
+                    _ => {
+                        let mut keys = value
+                            .keys(agent)?
+                            .into_iter()
+                            .filter_map(|k| match k {
+                                ObjectKey::Symbol(..) => None,
+                                k => Some(k.to_string()),
+                            })
+                            .collect::<Vec<String>>();
+                        if self.sort_keys {
+                            keys.sort();
+                        }
+                        self.out.push('{');
+                        self.depth += 1;
+                        for (i, key) in keys.iter().enumerate() {
+                            self.separator(i == 0);
+                            write_string(&mut self.out, key);
+                            self.out.push(':');
+                            if self.indent.is_some() {
+                                self.out.push(' ');
+                            }
+                            self.write_value(&value.get(agent, ObjectKey::from(key.as_str()))?)?;
+                        }
+                        self.close('}', keys.is_empty());
+                    }
+                }
+                self.seen.remove(&id);
+            }
+            Value::Symbol(..) => return Err(Value::new_error(agent, "cannot serialize a symbol")),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+struct Parser<'a> {
+    agent: &'a Agent,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(
+            self.agent,
+            &format!("json: {} at position {}", message, self.pos),
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.chars.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Value> {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Value> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some('{') => {
+                self.pos += 1;
+                let o = Value::new_object(self.agent.intrinsics.object_prototype.clone());
+                self.skip_whitespace();
+                if self.chars.get(self.pos) == Some(&'}') {
+                    self.pos += 1;
+                    return Ok(o);
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.chars.get(self.pos) != Some(&'"') {
+                        return Err(self.error("expected a string key"));
+                    }
+                    let key = self.parse_string()?;
+                    self.expect(':')?;
+                    let value = self.parse_value()?;
+                    o.set(self.agent, ObjectKey::from(key), value)?;
+                    self.skip_whitespace();
+                    match self.chars.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {
+                            self.pos += 1;
+                            return Ok(o);
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.chars.get(self.pos) == Some(&']') {
+                    self.pos += 1;
+                    return Ok(Value::new_array_from_vec(self.agent, items));
+                }
+                loop {
+                    items.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    match self.chars.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {
+                            self.pos += 1;
+                            return Ok(Value::new_array_from_vec(self.agent, items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('"') => Ok(Value::from(self.parse_string()?)),
+            Some('-') | Some('0'...'9') => self.parse_number(),
+            Some(_) => {
+                for (word, value) in &[
+                    ("true", Value::from(true)),
+                    ("false", Value::from(false)),
+                    ("null", Value::Null),
+                ] {
+                    let end = self.pos + word.len();
+                    if end <= self.chars.len()
+                        && self.chars[self.pos..end].iter().cloned().eq(word.chars())
+                    {
+                        self.pos = end;
+                        return Ok(value.clone());
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, Value> {
+        let start = self.pos;
+        if self.chars.get(self.pos) == Some(&'-') {
+            self.pos += 1;
+        }
+        let digits = |p: &mut Parser| {
+            let start = p.pos;
+            while let Some('0'...'9') = p.chars.get(p.pos) {
+                p.pos += 1;
+            }
+            p.pos - start
+        };
+        let int_start = self.pos;
+        let int_digits = digits(self);
+        if int_digits == 0 || (int_digits > 1 && self.chars[int_start] == '0') {
+            return Err(self.error("invalid number"));
+        }
+        if self.chars.get(self.pos) == Some(&'.') {
+            self.pos += 1;
+            if digits(self) == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some('e') | Some('E') = self.chars.get(self.pos) {
+            self.pos += 1;
+            if let Some('+') | Some('-') = self.chars.get(self.pos) {
+                self.pos += 1;
+            }
+            if digits(self) == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        let text = self.chars[start..self.pos].iter().collect::<String>();
+        Ok(Value::from(text.parse::<f64>().unwrap()))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, Value> {
+        if self.pos + 4 > self.chars.len() {
+            return Err(self.error("invalid unicode escape"));
+        }
+        let hex = self.chars[self.pos..self.pos + 4]
+            .iter()
+            .collect::<String>();
+        self.pos += 4;
+        u32::from_str_radix(&hex, 16).map_err(|_| self.error("invalid unicode escape"))
+    }
+
+    fn parse_string(&mut self) -> Result<String, Value> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let c = self.chars.get(self.pos).cloned();
+                    self.pos += 1;
+                    match c {
+                        Some('"') => out.push('"'),
+                        Some('\\') => out.push('\\'),
+                        Some('/') => out.push('/'),
+                        Some('b') => out.push('\u{8}'),
+                        Some('f') => out.push('\u{c}'),
+                        Some('n') => out.push('\n'),
+                        Some('r') => out.push('\r'),
+                        Some('t') => out.push('\t'),
+                        Some('u') => {
+                            let mut c = self.parse_hex4()?;
+                            if (0xd800..0xdc00).contains(&c)
+                                && self.chars.get(self.pos) == Some(&'\\')
+                            {
+                                // surrogate pair
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                c = 0x10000
+                                    + ((c - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            out.push(std::char::from_u32(c).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                Some(c) if (*c as u32) < 0x20 => {
+                    return Err(self.error("control character in string"))
+                }
+                Some(c) => {
+                    out.push(*c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+pub fn parse(agent: &Agent, text: &str) -> Result<Value, Value> {
+    let mut parser = Parser {
+        agent,
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected trailing data"));
+    }
+    Ok(value)
+}
+
+pub fn stringify(
+    agent: &Agent,
+    value: &Value,
+    sort_keys: bool,
+    indent: Option<String>,
+) -> Result<String, Value> {
+    let mut writer = Writer::new(agent, sort_keys, indent);
+    writer.write_value(value)?;
+    Ok(writer.out)
+}
+
+/// Serialize a value to canonical JSON: object keys are sorted, there is no
+/// insignificant whitespace, and numbers use the shortest round-trip form.
+/// Equal data always produces byte-identical output.
+pub fn canonicalize(agent: &Agent, value: &Value) -> Result<String, Value> {
+    stringify(agent, value, true, None)
+}
+
+fn stringify_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    let options = args.get(1).unwrap_or(&Value::Null);
+    if *options == Value::Null {
+        return Ok(Value::from(stringify(agent, value, false, None)?));
+    }
+    if options.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+    if options.get(agent, ObjectKey::from("canonical"))?.to_bool() {
+        return Ok(Value::from(canonicalize(agent, value)?));
+    }
+    let sort_keys = options.get(agent, ObjectKey::from("sortKeys"))?.to_bool();
+    let indent = match options.get(agent, ObjectKey::from("indent"))? {
+        Value::Null => None,
+        Value::Number(n) if n >= 0.0 => Some(" ".repeat(n as usize)),
+        Value::String(s) => Some(s.to_string()),
+        _ => return Err(Value::new_error(agent, "indent must be a number or string")),
+    };
+    Ok(Value::from(stringify(agent, value, sort_keys, indent)?))
+}
+
+fn parse_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::String(s)) => parse(agent, s),
+        _ => Err(Value::new_error(agent, "text must be a string")),
+    }
+}
+
+fn canonicalize_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    Ok(Value::from(canonicalize(agent, value)?))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("parse", parse_fn);
+    method!("stringify", stringify_fn);
+    method!("canonicalize", canonicalize_fn);
+
+    module
+}
@@ -0,0 +1,218 @@
+use crate::agent::Agent;
+use crate::builtins::fs::{spawn, FsResponse};
+use crate::builtins::timers::now_ms;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use lazy_static::lazy_static;
+use num::ToPrimitive;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_MAX_FILES: usize = 5;
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+lazy_static! {
+    static ref SINKS: Mutex<HashMap<usize, Arc<Mutex<Sink>>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// a log file and the lines waiting to be written to it. every write runs
+// on the worker pool, which runs jobs in any order, so each job writes
+// all the lines queued so far and lines land in the order they were
+// written
+struct Sink {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+    daily: bool,
+    file: Option<File>,
+    size: u64,
+    // the UTC day of the last line written to the file
+    day: Option<i64>,
+    pending: VecDeque<(String, i64)>,
+}
+
+impl Sink {
+    fn flush(&mut self) -> std::io::Result<()> {
+        while let Some((line, day)) = self.pending.pop_front() {
+            if self.file.is_none() {
+                self.open()?;
+            }
+            let len = line.len() as u64;
+            let full = self
+                .max_size
+                .is_some_and(|max| self.size > 0 && self.size + len > max);
+            let new_day = self.daily && self.day.is_some_and(|d| d != day);
+            if full || new_day {
+                self.rotate()?;
+            }
+            self.file.as_mut().unwrap().write_all(line.as_bytes())?;
+            self.size += len;
+            self.day = Some(day);
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> std::io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        PathBuf::from(path)
+    }
+
+    // app.log becomes app.log.1, app.log.1 becomes app.log.2 and so on,
+    // dropping the oldest past max_files. renames are atomic, and nothing
+    // is written while they happen, so no line lands in the wrong file
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file = None;
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let oldest = self.rotated(self.max_files);
+            if oldest.exists() {
+                std::fs::remove_file(oldest)?;
+            }
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.open()
+    }
+}
+
+fn sink(agent: &Agent, ctx: &Context) -> Result<(usize, Arc<Mutex<Sink>>), Value> {
+    let id = match ctx.function.as_ref().unwrap().get_slot("log sink") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    match SINKS.lock().unwrap().get(&id) {
+        Some(sink) => Ok((id, sink.clone())),
+        None => Err(Value::new_error(agent, "sink is closed")),
+    }
+}
+
+fn response(result: std::io::Result<()>) -> FsResponse {
+    match result {
+        Ok(()) => FsResponse::Success,
+        Err(e) => FsResponse::Error(format!("{}", e)),
+    }
+}
+
+// append a line, adding the newline if it doesn't end with one
+fn write(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let mut line = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "line must be a string")),
+    };
+    if !line.ends_with('\n') {
+        line.push('\n');
+    }
+    let (_, sink) = sink(agent, ctx)?;
+    let day = (now_ms(agent) / MS_PER_DAY).floor() as i64;
+    let path = {
+        let mut s = sink.lock().unwrap();
+        s.pending.push_back((line, day));
+        s.path.display().to_string()
+    };
+    spawn(agent, "log.write", &path, move || {
+        response(sink.lock().unwrap().flush())
+    })
+}
+
+// write what is queued and close the file; later writes throw
+fn close(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (id, sink) = sink(agent, ctx)?;
+    SINKS.lock().unwrap().remove(&id);
+    let path = sink.lock().unwrap().path.display().to_string();
+    spawn(agent, "log.close", &path, move || {
+        let mut s = sink.lock().unwrap();
+        let result = s.flush();
+        s.file = None;
+        response(result)
+    })
+}
+
+/// Append lines to the file at `path`, moving it aside to `path.1` once
+/// it would grow past `maxSize` bytes, or with `daily` when the first line
+/// of a new day (in UTC) is written. The `maxFiles` (5 by default) most
+/// recent files moved aside are kept.
+fn file_sink(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let path = match args.get(0) {
+        Some(Value::String(s)) => PathBuf::from(s.as_str()),
+        _ => return Err(Value::new_error(agent, "path must be a string")),
+    };
+    let options = args.get(1).unwrap_or(&Value::Null);
+    let option = |name: &str| match options {
+        Value::Null => Ok(Value::Null),
+        _ => options.get(agent, ObjectKey::from(name)),
+    };
+    let max_size = match option("maxSize")? {
+        Value::Null => None,
+        Value::Number(n) if n >= 1.0 => Some(n as u64),
+        _ => return Err(Value::new_error(agent, "maxSize must be a positive number")),
+    };
+    let max_files = match option("maxFiles")? {
+        Value::Null => DEFAULT_MAX_FILES,
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        _ => return Err(Value::new_error(agent, "maxFiles must be a whole number")),
+    };
+    let daily = match option("daily")? {
+        Value::Null => false,
+        Value::Boolean(b) => b,
+        _ => return Err(Value::new_error(agent, "daily must be a boolean")),
+    };
+
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    SINKS.lock().unwrap().insert(
+        id,
+        Arc::new(Mutex::new(Sink {
+            path,
+            max_size,
+            max_files,
+            daily,
+            file: None,
+            size: 0,
+            day: None,
+            pending: VecDeque::new(),
+        })),
+    );
+
+    let handle = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let w = Value::new_builtin_function(agent, write);
+    w.set_slot("log sink", Value::from(id as f64));
+    handle.set(agent, ObjectKey::from("write"), w)?;
+    let c = Value::new_builtin_function(agent, close);
+    c.set_slot("log sink", Value::from(id as f64));
+    handle.set(agent, ObjectKey::from("close"), c)?;
+    Ok(handle)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let f = Value::new_builtin_function(agent, file_sink);
+    // opening a log file needs the same grant as writing one with fs
+    f.set_slot("capability", Value::from("fs"));
+    f.set_slot("operation", Value::from("log.fileSink"));
+    module.insert("fileSink".to_string(), f);
+
+    module
+}
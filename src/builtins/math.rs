@@ -3,7 +3,7 @@ use crate::interpreter::Context;
 use crate::value::Value;
 use std::collections::HashMap;
 
-fn min(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+fn min(agent: &Agent, args: &[Value], _: &Context) -> Result<Value, Value> {
     if args.is_empty() {
         return Err(Value::new_error(agent, "argument must be a number"));
     }
@@ -11,7 +11,7 @@ fn min(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     let mut numbers = Vec::new();
     for arg in args {
         if let Value::Number(n) = arg {
-            numbers.push(n);
+            numbers.push(*n);
         } else {
             return Err(Value::new_error(agent, "argument must be a number"));
         }
@@ -22,7 +22,7 @@ fn min(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     Ok(Value::from(numbers[0]))
 }
 
-fn max(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+fn max(agent: &Agent, args: &[Value], _: &Context) -> Result<Value, Value> {
     if args.is_empty() {
         return Err(Value::new_error(agent, "argument must be a number"));
     }
@@ -30,7 +30,7 @@ fn max(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     let mut numbers = Vec::new();
     for arg in args {
         if let Value::Number(n) = arg {
-            numbers.push(n);
+            numbers.push(*n);
         } else {
             return Err(Value::new_error(agent, "argument must be a number"));
         }
@@ -44,8 +44,14 @@ fn max(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
 
-    module.insert("min".to_string(), Value::new_builtin_function(agent, min));
-    module.insert("max".to_string(), Value::new_builtin_function(agent, max));
+    module.insert(
+        "min".to_string(),
+        Value::new_slice_builtin_function(agent, min),
+    );
+    module.insert(
+        "max".to_string(),
+        Value::new_slice_builtin_function(agent, max),
+    );
 
     macro_rules! C {
         ($n:ident) => {
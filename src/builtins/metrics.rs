@@ -0,0 +1,552 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use gc::{Gc, GcCell};
+use std::collections::{HashMap, VecDeque};
+
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+fn registry(agent: &Agent) -> Value {
+    agent
+        .builtin("metrics", "counter")
+        .unwrap()
+        .get_slot("metrics registry")
+}
+
+fn new_list() -> Value {
+    Value::List(Gc::new(GcCell::new(VecDeque::new())))
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn escape(s: &str, quote: bool) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '"' if quote => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        crate::num_util::to_string(n)
+    }
+}
+
+// labels are kept as their rendered `a="1",b="2"` form, sorted by name so
+// the same set always lands on the same series
+fn labels(agent: &Agent, value: Option<&Value>) -> Result<String, Value> {
+    let value = match value {
+        None | Some(Value::Null) => return Ok(String::new()),
+        Some(v) if v.type_of() == "object" => v,
+        _ => return Err(Value::new_error(agent, "labels must be an object")),
+    };
+    let mut pairs = Vec::new();
+    for key in value.keys(agent)? {
+        let name = key.to_string();
+        if !valid_name(&name) || name.starts_with("__") {
+            return Err(Value::new_error(
+                agent,
+                &format!("invalid label name '{}'", name),
+            ));
+        }
+        let label = match value.get(agent, key)? {
+            Value::String(s) => s.to_string(),
+            Value::Number(n) => format_number(n),
+            Value::Boolean(b) => b.to_string(),
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    &format!("label {} must be a string", name),
+                ))
+            }
+        };
+        pairs.push((name, label));
+    }
+    pairs.sort();
+    Ok(pairs
+        .iter()
+        .map(|(n, v)| format!("{}=\"{}\"", n, escape(v, true)))
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+fn number(agent: &Agent, value: Option<&Value>, default: f64) -> Result<f64, Value> {
+    match value {
+        None | Some(Value::Null) => Ok(default),
+        Some(Value::Number(n)) => Ok(*n),
+        _ => Err(Value::new_error(agent, "value must be a number")),
+    }
+}
+
+// replaces the series for these labels with whatever f makes of the old one
+fn update<F>(metric: &Value, labels: String, f: F)
+where
+    F: FnOnce(Option<Value>) -> Value,
+{
+    if let Value::List(series) = metric.get_slot("metric series") {
+        let mut series = series.borrow_mut();
+        let key = Value::from(labels);
+        let index = series.iter().position(|entry| match entry {
+            Value::Tuple(pair) => pair[0] == key,
+            _ => unreachable!(),
+        });
+        match index {
+            Some(i) => {
+                let old = match &series[i] {
+                    Value::Tuple(pair) => pair[1].clone(),
+                    _ => unreachable!(),
+                };
+                series[i] = Value::Tuple(Box::new(vec![key, f(Some(old))]));
+            }
+            None => series.push_back(Value::Tuple(Box::new(vec![key, f(None)]))),
+        }
+    }
+}
+
+fn add(metric: &Value, labels: String, amount: f64) {
+    update(metric, labels, |old| match old {
+        Some(Value::Number(n)) => Value::from(n + amount),
+        _ => Value::from(amount),
+    });
+}
+
+fn get_metric(agent: &Agent, ctx: &Context, kind: &str) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("metric type") || this.get_slot("metric type") != Value::from(kind) {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    Ok(this)
+}
+
+fn counter_inc(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_metric(agent, ctx, "counter")?;
+    let amount = number(agent, args.get(0), 1.0)?;
+    if amount < 0.0 {
+        return Err(Value::new_error(agent, "counters can only increase"));
+    }
+    add(&this, labels(agent, args.get(1))?, amount);
+    Ok(Value::Null)
+}
+
+fn gauge_set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_metric(agent, ctx, "gauge")?;
+    let value = match args.get(0) {
+        Some(Value::Number(n)) => *n,
+        _ => return Err(Value::new_error(agent, "value must be a number")),
+    };
+    update(&this, labels(agent, args.get(1))?, |_| Value::from(value));
+    Ok(Value::Null)
+}
+
+fn gauge_inc(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_metric(agent, ctx, "gauge")?;
+    let amount = number(agent, args.get(0), 1.0)?;
+    add(&this, labels(agent, args.get(1))?, amount);
+    Ok(Value::Null)
+}
+
+fn gauge_dec(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_metric(agent, ctx, "gauge")?;
+    let amount = number(agent, args.get(0), 1.0)?;
+    add(&this, labels(agent, args.get(1))?, -amount);
+    Ok(Value::Null)
+}
+
+fn buckets(metric: &Value) -> Vec<f64> {
+    match metric.get_slot("metric buckets") {
+        Value::Tuple(bounds) => bounds
+            .iter()
+            .map(|bound| match bound {
+                Value::Number(n) => *n,
+                _ => unreachable!(),
+            })
+            .collect(),
+        _ => unreachable!(),
+    }
+}
+
+// a histogram series is (cumulative bucket counts, sum, count)
+fn empty_histogram(bounds: &[f64]) -> Value {
+    Value::Tuple(Box::new(vec![
+        Value::Tuple(Box::new(bounds.iter().map(|_| Value::from(0.0)).collect())),
+        Value::from(0.0),
+        Value::from(0.0),
+    ]))
+}
+
+fn histogram_observe(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_metric(agent, ctx, "histogram")?;
+    let value = match args.get(0) {
+        Some(Value::Number(n)) => *n,
+        _ => return Err(Value::new_error(agent, "value must be a number")),
+    };
+    let bounds = buckets(&this);
+    update(&this, labels(agent, args.get(1))?, |old| {
+        let old = old.unwrap_or_else(|| empty_histogram(&bounds));
+        match old {
+            Value::Tuple(parts) => {
+                let counts = match &parts[0] {
+                    Value::Tuple(counts) => counts
+                        .iter()
+                        .zip(&bounds)
+                        .map(|(count, bound)| match count {
+                            Value::Number(c) if value <= *bound => Value::from(c + 1.0),
+                            c => c.clone(),
+                        })
+                        .collect::<Vec<_>>(),
+                    _ => unreachable!(),
+                };
+                let (sum, count) = match (&parts[1], &parts[2]) {
+                    (Value::Number(s), Value::Number(c)) => (s + value, c + 1.0),
+                    _ => unreachable!(),
+                };
+                Value::Tuple(Box::new(vec![
+                    Value::Tuple(Box::new(counts)),
+                    Value::from(sum),
+                    Value::from(count),
+                ]))
+            }
+            _ => unreachable!(),
+        }
+    });
+    Ok(Value::Null)
+}
+
+fn register(agent: &Agent, args: &[Value], kind: &str) -> Result<Value, Value> {
+    let name = match args.get(0) {
+        Some(Value::String(s)) if valid_name(s) => s.to_string(),
+        Some(Value::String(s)) => {
+            return Err(Value::new_error(
+                agent,
+                &format!("invalid metric name '{}'", s),
+            ))
+        }
+        _ => return Err(Value::new_error(agent, "name must be a string")),
+    };
+    let help = match args.get(1) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "help must be a string")),
+    };
+
+    let registry = registry(agent);
+    if let Value::List(metrics) = registry.get_slot("metrics") {
+        for metric in metrics.borrow().iter() {
+            if metric.get_slot("metric name") == Value::from(name.clone()) {
+                if metric.get_slot("metric type") == Value::from(kind) {
+                    return Ok(metric.clone());
+                }
+                return Err(Value::new_error(
+                    agent,
+                    &format!("metric {} is already registered with another type", name),
+                ));
+            }
+        }
+    }
+
+    let proto = registry.get_slot(&format!("metrics {} prototype", kind));
+    let metric = Value::new_custom_object(proto);
+    metric.set_slot("metric name", Value::from(name.clone()));
+    metric.set_slot("metric help", Value::from(help));
+    metric.set_slot("metric type", Value::from(kind));
+    metric.set_slot("metric series", new_list());
+    metric.set(agent, ObjectKey::from("name"), Value::from(name))?;
+    if let Value::List(metrics) = registry.get_slot("metrics") {
+        metrics.borrow_mut().push_back(metric.clone());
+    }
+    Ok(metric)
+}
+
+fn counter(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    register(agent, &args, "counter")
+}
+
+fn gauge(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    register(agent, &args, "gauge")
+}
+
+fn histogram(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = args.get(2).unwrap_or(&Value::Null);
+    let bounds = if options.type_of() == "object" {
+        match options.get(agent, ObjectKey::from("buckets"))? {
+            Value::Null => DEFAULT_BUCKETS.to_vec(),
+            Value::Object(o) => match &o.kind {
+                ObjectKind::Array(items) => {
+                    let mut bounds = Vec::new();
+                    for item in items.borrow().iter() {
+                        match &item {
+                            Value::Number(n) if bounds.last().map_or(true, |l| n > l) => {
+                                bounds.push(*n)
+                            }
+                            _ => {
+                                return Err(Value::new_error(
+                                    agent,
+                                    "buckets must be increasing numbers",
+                                ))
+                            }
+                        }
+                    }
+                    bounds
+                }
+                _ => return Err(Value::new_error(agent, "buckets must be an array")),
+            },
+            _ => return Err(Value::new_error(agent, "buckets must be an array")),
+        }
+    } else {
+        DEFAULT_BUCKETS.to_vec()
+    };
+    let metric = register(agent, &args, "histogram")?;
+    if !metric.has_slot("metric buckets") {
+        metric.set_slot(
+            "metric buckets",
+            Value::Tuple(Box::new(bounds.into_iter().map(Value::from).collect())),
+        );
+    }
+    Ok(metric)
+}
+
+fn series_name(name: &str, labels: &str, extra: &str) -> String {
+    match (labels.is_empty(), extra.is_empty()) {
+        (true, true) => name.to_string(),
+        (false, true) => format!("{}{{{}}}", name, labels),
+        (true, false) => format!("{}{{{}}}", name, extra),
+        (false, false) => format!("{}{{{},{}}}", name, labels, extra),
+    }
+}
+
+fn render_metric(out: &mut String, metric: &Value) {
+    let name = match metric.get_slot("metric name") {
+        Value::String(s) => s,
+        _ => unreachable!(),
+    };
+    let kind = match metric.get_slot("metric type") {
+        Value::String(s) => s,
+        _ => unreachable!(),
+    };
+    if let Value::String(help) = metric.get_slot("metric help") {
+        if !help.is_empty() {
+            out.push_str(&format!("# HELP {} {}\n", name, escape(&help, false)));
+        }
+    }
+    out.push_str(&format!("# TYPE {} {}\n", name, kind));
+
+    let mut series = match metric.get_slot("metric series") {
+        Value::List(series) => series.borrow().iter().cloned().collect::<Vec<_>>(),
+        _ => unreachable!(),
+    };
+    if series.is_empty() {
+        let empty = if kind.as_str() == "histogram" {
+            empty_histogram(&buckets(metric))
+        } else {
+            Value::from(0.0)
+        };
+        series.push(Value::Tuple(Box::new(vec![Value::from(""), empty])));
+    }
+
+    for entry in series {
+        let (labels, value) = match entry {
+            Value::Tuple(pair) => match (&pair[0], &pair[1]) {
+                (Value::String(l), v) => (l.clone(), v.clone()),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        match value {
+            Value::Number(n) => {
+                out.push_str(&format!(
+                    "{} {}\n",
+                    series_name(&name, &labels, ""),
+                    format_number(n)
+                ));
+            }
+            Value::Tuple(parts) => {
+                if let (Value::Tuple(counts), Value::Number(sum), Value::Number(count)) =
+                    (&parts[0], &parts[1], &parts[2])
+                {
+                    for (bound, c) in buckets(metric).iter().zip(counts.iter()) {
+                        if let Value::Number(c) = c {
+                            let le = format!("le=\"{}\"", format_number(*bound));
+                            out.push_str(&format!(
+                                "{} {}\n",
+                                series_name(&format!("{}_bucket", name), &labels, &le),
+                                format_number(*c)
+                            ));
+                        }
+                    }
+                    let bucket = format!("{}_bucket", name);
+                    out.push_str(&format!(
+                        "{} {}\n",
+                        series_name(&bucket, &labels, "le=\"+Inf\""),
+                        format_number(*count)
+                    ));
+                    out.push_str(&format!(
+                        "{} {}\n",
+                        series_name(&format!("{}_sum", name), &labels, ""),
+                        format_number(*sum)
+                    ));
+                    out.push_str(&format!(
+                        "{} {}\n",
+                        series_name(&format!("{}_count", name), &labels, ""),
+                        format_number(*count)
+                    ));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn render_runtime(agent: &Agent, out: &mut String) {
+    let lag = agent.event_loop_lag();
+    let gauges = [
+        (
+            "slither_event_loop_lag_seconds",
+            "Time the last event loop turn spent running callbacks",
+            lag.as_secs() as f64 + f64::from(lag.subsec_nanos()) / 1e9,
+        ),
+        (
+            "slither_heap_bytes",
+            "Bytes allocated by the garbage collector",
+            gc::bytes_allocated() as f64,
+        ),
+        (
+            "slither_job_queue_length",
+            "Jobs waiting to run",
+            agent.queued_jobs() as f64,
+        ),
+        (
+            "slither_pending_operations",
+            "Timers, sockets and other operations the event loop is waiting on",
+            agent.mio_map.borrow().len() as f64,
+        ),
+    ];
+    for (name, help, value) in gauges.iter() {
+        out.push_str(&format!("# HELP {} {}\n", name, help));
+        out.push_str(&format!("# TYPE {} gauge\n", name));
+        out.push_str(&format!("{} {}\n", name, format_number(*value)));
+    }
+}
+
+/// Render every registered metric, followed by the runtime's own, in the
+/// Prometheus text exposition format.
+pub fn render_text(agent: &Agent) -> String {
+    let mut out = String::new();
+    if let Value::List(metrics) = registry(agent).get_slot("metrics") {
+        for metric in metrics.borrow().iter() {
+            render_metric(&mut out, metric);
+        }
+    }
+    render_runtime(agent, &mut out);
+    out
+}
+
+fn render(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::from(render_text(agent)))
+}
+
+fn serve_metrics(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let response = Value::new_object(agent.intrinsics.object_prototype.clone());
+    response.set(agent, ObjectKey::from("status"), Value::from(200.0))?;
+    let headers = Value::new_object(agent.intrinsics.object_prototype.clone());
+    headers.set(
+        agent,
+        ObjectKey::from("Content-Type"),
+        Value::from("text/plain; version=0.0.4; charset=utf-8"),
+    )?;
+    response.set(agent, ObjectKey::from("headers"), headers)?;
+    response.set(
+        agent,
+        ObjectKey::from("body"),
+        Value::from(render_text(agent)),
+    )?;
+    Ok(response)
+}
+
+// a request handler for http's serve, e.g. mounted at /metrics by a router
+fn handler(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::new_builtin_function(agent, serve_metrics))
+}
+
+fn prototype(agent: &Agent, methods: &[(&str, Value)]) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, f) in methods {
+        proto.set(agent, ObjectKey::from(*name), f.clone()).unwrap();
+    }
+    proto
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let registry = Value::new_custom_object(Value::Null);
+    registry.set_slot("metrics", new_list());
+    registry.set_slot(
+        "metrics counter prototype",
+        prototype(
+            agent,
+            &[("inc", Value::new_builtin_function(agent, counter_inc))],
+        ),
+    );
+    registry.set_slot(
+        "metrics gauge prototype",
+        prototype(
+            agent,
+            &[
+                ("set", Value::new_builtin_function(agent, gauge_set)),
+                ("inc", Value::new_builtin_function(agent, gauge_inc)),
+                ("dec", Value::new_builtin_function(agent, gauge_dec)),
+            ],
+        ),
+    );
+    registry.set_slot(
+        "metrics histogram prototype",
+        prototype(
+            agent,
+            &[(
+                "observe",
+                Value::new_builtin_function(agent, histogram_observe),
+            )],
+        ),
+    );
+
+    let counter = Value::new_builtin_function(agent, counter);
+    counter.set_slot("metrics registry", registry);
+
+    let mut module = HashMap::new();
+    module.insert("counter".to_string(), counter);
+    module.insert(
+        "gauge".to_string(),
+        Value::new_builtin_function(agent, gauge),
+    );
+    module.insert(
+        "histogram".to_string(),
+        Value::new_builtin_function(agent, histogram),
+    );
+    module.insert(
+        "render".to_string(),
+        Value::new_builtin_function(agent, render),
+    );
+    module.insert(
+        "handler".to_string(),
+        Value::new_builtin_function(agent, handler),
+    );
+
+    module
+}
@@ -0,0 +1,208 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKind, Value};
+use std::collections::HashMap;
+
+// extensions and their types, the usual extension of a type first
+const TYPES: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("txt", "text/plain"),
+    ("sl", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("ico", "image/x-icon"),
+    ("avif", "image/avif"),
+    ("pdf", "application/pdf"),
+    ("wasm", "application/wasm"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+];
+
+// how many bytes `sniff` looks at, enough for every signature below
+pub const SNIFF_LENGTH: usize = 512;
+
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+    TYPES
+        .iter()
+        .find(|(e, _)| *e == extension)
+        .map(|(_, mime)| *mime)
+}
+
+#[cfg(feature = "os")]
+pub fn from_path(path: &std::path::Path) -> Option<&'static str> {
+    from_extension(path.extension()?.to_str()?)
+}
+
+/// The usual extension of a type, ignoring parameters like `charset`.
+pub fn extension(mime: &str) -> Option<&'static str> {
+    let essence = mime.split(';').next().unwrap_or("").trim();
+    TYPES
+        .iter()
+        .find(|(_, m)| m.eq_ignore_ascii_case(essence))
+        .map(|(e, _)| *e)
+}
+
+/// A type as a Content-Type header, text being served as UTF-8.
+pub fn content_type(mime: &str) -> String {
+    if mime.starts_with("text/") && !mime.contains(';') {
+        format!("{}; charset=utf-8", mime)
+    } else {
+        mime.to_string()
+    }
+}
+
+/// Guess the type of content from its first bytes: the signatures of common
+/// binary formats, markup, and otherwise text if it is UTF-8 without
+/// control characters.
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    let bytes = &bytes[..bytes.len().min(SNIFF_LENGTH)];
+    let at =
+        |offset: usize, signature: &[u8]| bytes[offset.min(bytes.len())..].starts_with(signature);
+    let signatures: &[(bool, &str)] = &[
+        (at(0, b"\x89PNG\r\n\x1a\n"), "image/png"),
+        (at(0, b"\xFF\xD8\xFF"), "image/jpeg"),
+        (at(0, b"GIF87a") || at(0, b"GIF89a"), "image/gif"),
+        (at(0, b"RIFF") && at(8, b"WEBP"), "image/webp"),
+        (at(0, b"RIFF") && at(8, b"WAVE"), "audio/wav"),
+        (at(0, b"\x00\x00\x01\x00"), "image/x-icon"),
+        (at(4, b"ftypavif"), "image/avif"),
+        (at(4, b"ftyp"), "video/mp4"),
+        (at(0, b"\x1A\x45\xDF\xA3"), "video/webm"),
+        (at(0, b"ID3") || at(0, b"\xFF\xFB"), "audio/mpeg"),
+        (at(0, b"OggS"), "audio/ogg"),
+        (at(0, b"%PDF-"), "application/pdf"),
+        (at(0, b"PK\x03\x04"), "application/zip"),
+        (at(0, b"\x1F\x8B"), "application/gzip"),
+        (at(257, b"ustar"), "application/x-tar"),
+        (at(0, b"\x00asm"), "application/wasm"),
+        (at(0, b"wOFF"), "font/woff"),
+        (at(0, b"wOF2"), "font/woff2"),
+        (at(0, b"\x00\x01\x00\x00\x00"), "font/ttf"),
+        (at(0, b"OTTO"), "font/otf"),
+    ];
+    if let Some((_, mime)) = signatures.iter().find(|(matches, _)| *matches) {
+        return Some(mime);
+    }
+
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        // the sniffed bytes can end in the middle of a character
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap()
+        }
+        Err(_) => return None,
+    };
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0C'))
+    {
+        return None;
+    }
+    let start = text
+        .trim_start_matches('\u{FEFF}')
+        .trim_start()
+        .chars()
+        .take(14)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        Some("text/html")
+    } else if start.starts_with("<svg") {
+        Some("image/svg+xml")
+    } else if start.starts_with("<?xml") {
+        Some("application/xml")
+    } else {
+        Some("text/plain")
+    }
+}
+
+fn or_null(mime: Option<&'static str>) -> Value {
+    mime.map_or(Value::Null, Value::from)
+}
+
+// the type of a path or an extension, with or without the dot
+fn lookup(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::String(s)) => {
+            let name = s.rsplit(['/', '\\']).next().unwrap_or("");
+            let extension = match name.rfind('.') {
+                Some(i) => &name[i + 1..],
+                None => name,
+            };
+            Ok(or_null(from_extension(extension)))
+        }
+        _ => Err(Value::new_error(agent, "path must be a string")),
+    }
+}
+
+fn extension_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::String(mime)) => Ok(or_null(extension(mime))),
+        _ => Err(Value::new_error(agent, "type must be a string")),
+    }
+}
+
+fn sniff_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(Value::String(s)) = args.get(0) {
+        return Ok(or_null(sniff(s.as_bytes())));
+    }
+    if let Some(Value::Object(o)) = args.get(0) {
+        if let ObjectKind::Buffer(bytes) = &o.kind {
+            return Ok(or_null(sniff(&bytes.borrow())));
+        }
+    }
+    Err(Value::new_error(
+        agent,
+        "content must be a buffer or string",
+    ))
+}
+
+fn content_type_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::String(mime)) => Ok(Value::from(content_type(mime))),
+        _ => Err(Value::new_error(agent, "type must be a string")),
+    }
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("lookup", lookup);
+    method!("extension", extension_fn);
+    method!("sniff", sniff_fn);
+    method!("contentType", content_type_fn);
+
+    module
+}
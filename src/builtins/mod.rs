@@ -1,23 +1,141 @@
 use crate::agent::Agent;
-use crate::value::Value;
+use crate::value::{ObjectKind, Value};
 use std::collections::HashMap;
 
 mod r#async;
+#[cfg(feature = "os")]
+mod cookie;
+mod collections;
+pub mod crypto;
+mod csv;
 mod debug;
+#[cfg(feature = "os")]
+mod desktop;
+mod diff;
+mod events;
+#[cfg(feature = "os")]
 pub mod fs;
+#[cfg(not(feature = "os"))]
+mod host_fs;
+mod html;
+#[cfg(feature = "os")]
+pub mod http;
+#[cfg(feature = "os")]
+mod http_middleware;
+#[cfg(feature = "os")]
+mod http_router;
+#[cfg(feature = "os")]
+pub mod http_server;
+pub mod json;
+#[cfg(feature = "os")]
+mod log;
 mod math;
+mod metrics;
+pub mod mime;
+mod msgpack;
+#[cfg(feature = "os")]
+mod multipart;
+#[cfg(feature = "os")]
 pub mod net;
-mod timers;
+mod num;
+#[cfg(feature = "os")]
+pub mod process;
+mod runtime;
+#[cfg(feature = "os")]
+mod schedule;
+mod template;
+mod temporal;
+mod test;
+mod time;
+pub mod timers;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(feature = "os")]
+mod trace;
+mod tz;
+#[cfg(feature = "yaml")]
+mod yaml;
 
-pub fn create(agent: &Agent) -> HashMap<String, HashMap<String, Value>> {
-    let mut builtins = HashMap::new();
+/// The standard modules whose functions need to be granted to a module
+/// before it can call them.
+pub const CAPABILITIES: &[&str] = &["fs", "net", "http", "process"];
 
-    builtins.insert("debug".to_string(), debug::create(agent));
-    builtins.insert("timers".to_string(), timers::create(agent));
-    builtins.insert("fs".to_string(), fs::create(agent));
-    builtins.insert("net".to_string(), net::create(agent));
-    builtins.insert("math".to_string(), math::create(agent));
-    builtins.insert("async".to_string(), r#async::create(agent));
+/// The names of the standard modules this build has, for completing
+/// imports.
+pub fn names() -> Vec<&'static str> {
+    let mut names = vec![
+        "debug", "diff", "collections", "events", "timers", "template", "temporal", "time", "test",
+        "html", "fs", "runtime", "metrics", "mime", "math", "num", "json", "csv", "msgpack",
+        "crypto", "async",
+    ];
+    #[cfg(feature = "os")]
+    names.extend(&["net", "process", "schedule", "http", "trace", "log", "desktop"]);
+    #[cfg(feature = "toml")]
+    names.push("toml");
+    #[cfg(feature = "yaml")]
+    names.push("yaml");
+    names.sort_unstable();
+    names
+}
 
-    builtins
+/// Builds one standard module. Modules are only created the first time a
+/// script imports them, see `Agent::builtin`.
+pub fn create(agent: &Agent, name: &str) -> Option<HashMap<String, Value>> {
+    let module = match name {
+        "debug" => debug::create(agent),
+        "diff" => diff::create(agent),
+        "collections" => collections::create(agent),
+        "events" => events::create(agent),
+        "timers" => timers::create(agent),
+        "template" => template::create(agent),
+        "temporal" => temporal::create(agent),
+        "time" => time::create(agent),
+        "test" => test::create(agent),
+        "html" => html::create(agent),
+        #[cfg(feature = "os")]
+        "fs" => fs::create(agent),
+        #[cfg(not(feature = "os"))]
+        "fs" => host_fs::create(agent),
+        #[cfg(feature = "os")]
+        "net" => net::create(agent),
+        #[cfg(feature = "os")]
+        "process" => process::create(agent),
+        #[cfg(feature = "os")]
+        "schedule" => schedule::create(agent),
+        "runtime" => runtime::create(agent),
+        #[cfg(feature = "os")]
+        "http" => http::create(agent),
+        #[cfg(feature = "os")]
+        "trace" => trace::create(agent),
+        #[cfg(feature = "os")]
+        "log" => log::create(agent),
+        #[cfg(feature = "os")]
+        "desktop" => desktop::create(agent),
+        "metrics" => metrics::create(agent),
+        "mime" => mime::create(agent),
+        "math" => math::create(agent),
+        "num" => num::create(agent),
+        "async" => r#async::create(agent),
+        "crypto" => crypto::create(agent),
+        "json" => json::create(agent),
+        "csv" => csv::create(agent),
+        "msgpack" => msgpack::create(agent),
+        #[cfg(feature = "toml")]
+        "toml" => toml::create(agent),
+        #[cfg(feature = "yaml")]
+        "yaml" => yaml::create(agent),
+        _ => return None,
+    };
+    // calls are checked against the caller's grants, see `Agent::grant`
+    if CAPABILITIES.contains(&name) {
+        for (key, value) in &module {
+            if let Value::Object(o) = value {
+                if let ObjectKind::BuiltinFunction(..) = o.kind {
+                    value.set_slot("capability", Value::from(name));
+                    value.set_slot("operation", Value::from(key.as_str()));
+                }
+            }
+        }
+    }
+    Some(module)
 }
@@ -0,0 +1,398 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+
+// tuples and records have no msgpack counterpart, they are extensions
+// wrapping an array or a map
+const TUPLE_EXT: i8 = 1;
+const RECORD_EXT: i8 = 2;
+
+struct Encoder<'a> {
+    agent: &'a Agent,
+    out: Vec<u8>,
+    seen: HashSet<*const ObjectKind>,
+}
+
+impl<'a> Encoder<'a> {
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(self.agent, &format!("msgpack: {}", message))
+    }
+
+    fn write_length(&mut self, len: usize, fix: Option<(u8, usize)>, markers: [u8; 3]) {
+        match fix {
+            Some((base, max)) if len <= max => self.out.push(base | len as u8),
+            _ if len <= 0xff && markers[0] != 0 => {
+                self.out.push(markers[0]);
+                self.out.push(len as u8);
+            }
+            _ if len <= 0xffff => {
+                self.out.push(markers[1]);
+                self.out.write_u16::<BigEndian>(len as u16).unwrap();
+            }
+            _ => {
+                self.out.push(markers[2]);
+                self.out.write_u32::<BigEndian>(len as u32).unwrap();
+            }
+        }
+    }
+
+    fn write_number(&mut self, n: f64) {
+        let integral = n.fract() == 0.0
+            && n >= -9_223_372_036_854_775_808.0
+            && n < 18_446_744_073_709_551_616.0
+            && !(n == 0.0 && n.is_sign_negative());
+        if !integral {
+            self.out.push(0xcb);
+            self.out.write_f64::<BigEndian>(n).unwrap();
+        } else if n >= 0.0 {
+            let n = n as u64;
+            if n < 0x80 {
+                self.out.push(n as u8);
+            } else if n <= 0xff {
+                self.out.push(0xcc);
+                self.out.push(n as u8);
+            } else if n <= 0xffff {
+                self.out.push(0xcd);
+                self.out.write_u16::<BigEndian>(n as u16).unwrap();
+            } else if n <= 0xffff_ffff {
+                self.out.push(0xce);
+                self.out.write_u32::<BigEndian>(n as u32).unwrap();
+            } else {
+                self.out.push(0xcf);
+                self.out.write_u64::<BigEndian>(n).unwrap();
+            }
+        } else {
+            let n = n as i64;
+            if n >= -32 {
+                self.out.push(n as u8);
+            } else if n >= -0x80 {
+                self.out.push(0xd0);
+                self.out.push(n as u8);
+            } else if n >= -0x8000 {
+                self.out.push(0xd1);
+                self.out.write_i16::<BigEndian>(n as i16).unwrap();
+            } else if n >= -0x8000_0000 {
+                self.out.push(0xd2);
+                self.out.write_i32::<BigEndian>(n as i32).unwrap();
+            } else {
+                self.out.push(0xd3);
+                self.out.write_i64::<BigEndian>(n).unwrap();
+            }
+        }
+    }
+
+    fn write_array(&mut self, items: &[Value]) -> Result<(), Value> {
+        self.write_length(items.len(), Some((0x90, 15)), [0, 0xdc, 0xdd]);
+        for item in items {
+            self.write_value(item)?;
+        }
+        Ok(())
+    }
+
+    fn write_value(&mut self, value: &Value) -> Result<(), Value> {
+        let agent = self.agent;
+        match value {
+            Value::Null => self.out.push(0xc0),
+            Value::Boolean(b) => self.out.push(if *b { 0xc3 } else { 0xc2 }),
+            Value::Number(n) => self.write_number(*n),
+            Value::String(s) => {
+                self.write_length(s.len(), Some((0xa0, 31)), [0xd9, 0xda, 0xdb]);
+                self.out.extend_from_slice(s.as_bytes());
+            }
+            Value::Tuple(items) => {
+                let mut inner = Encoder {
+                    agent,
+                    out: Vec::new(),
+                    seen: std::mem::replace(&mut self.seen, HashSet::new()),
+                };
+                let result = inner.write_array(items);
+                self.seen = inner.seen;
+                result?;
+                self.write_length(inner.out.len(), None, [0xc7, 0xc8, 0xc9]);
+                self.out.push(TUPLE_EXT as u8);
+                self.out.extend_from_slice(&inner.out);
+            }
+            Value::Record(fields) => {
+                let mut inner = Encoder {
+                    agent,
+                    out: Vec::new(),
+                    seen: HashSet::new(),
+                };
+                inner.write_length(fields.len(), Some((0x80, 15)), [0, 0xde, 0xdf]);
+                for (name, field) in fields.iter() {
+                    inner.write_value(&Value::from(name.as_str()))?;
+                    inner.write_value(field)?;
+                }
+                self.write_length(inner.out.len(), None, [0xc7, 0xc8, 0xc9]);
+                self.out.push(RECORD_EXT as u8);
+                self.out.extend_from_slice(&inner.out);
+            }
+            Value::Object(o) => {
+                if value.type_of() == "function" {
+                    return Err(self.error("cannot serialize a function"));
+                }
+                let id = &o.kind as *const ObjectKind;
+                if !self.seen.insert(id) {
+                    return Err(self.error("cannot serialize a circular structure"));
+                }
+                match &o.kind {
+                    ObjectKind::Buffer(bytes) => {
+                        let bytes = bytes.borrow();
+                        self.write_length(bytes.len(), None, [0xc4, 0xc5, 0xc6]);
+                        self.out.extend_from_slice(&bytes);
+                    }
+                    ObjectKind::Array(items) => {
+                        let items = items.borrow().to_vec();
+                        self.write_array(&items)?;
+                    }
+                    _ => {
+                        let keys = value
+                            .keys(agent)?
+                            .into_iter()
+                            .filter(|k| match k {
+                                ObjectKey::Symbol(..) => false,
+                                _ => true,
+                            })
+                            .collect::<Vec<ObjectKey>>();
+                        self.write_length(keys.len(), Some((0x80, 15)), [0, 0xde, 0xdf]);
+                        for key in keys {
+                            let item = value.get(agent, key.clone())?;
+                            self.write_value(&Value::from(key.to_string()))?;
+                            self.write_value(&item)?;
+                        }
+                    }
+                }
+                self.seen.remove(&id);
+            }
+            Value::Symbol(..) => return Err(self.error("cannot serialize a symbol")),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+pub fn encode(agent: &Agent, value: &Value) -> Result<Vec<u8>, Value> {
+    let mut encoder = Encoder {
+        agent,
+        out: Vec::new(),
+        seen: HashSet::new(),
+    };
+    encoder.write_value(value)?;
+    Ok(encoder.out)
+}
+
+struct Decoder<'a, 'b> {
+    agent: &'a Agent,
+    data: Cursor<&'b [u8]>,
+}
+
+impl<'a, 'b> Decoder<'a, 'b> {
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(self.agent, &format!("msgpack: {}", message))
+    }
+
+    fn eof(&self) -> Value {
+        self.error("unexpected end of data")
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, Value> {
+        let remaining = self.data.get_ref().len() - self.data.position() as usize;
+        if len > remaining {
+            return Err(self.eof());
+        }
+        let mut bytes = vec![0; len];
+        self.data.read_exact(&mut bytes).unwrap();
+        Ok(bytes)
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, Value> {
+        match String::from_utf8(self.bytes(len)?) {
+            Ok(s) => Ok(Value::from(s)),
+            Err(_) => Err(self.error("string is not valid utf-8")),
+        }
+    }
+
+    fn array(&mut self, len: usize) -> Result<Vec<Value>, Value> {
+        let mut items = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            items.push(self.read_value()?);
+        }
+        Ok(items)
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value, Value> {
+        let map = Value::new_object(self.agent.intrinsics.object_prototype.clone());
+        for _ in 0..len {
+            let key = match self.read_value()? {
+                k @ Value::String(..) | k @ Value::Number(..) => k.to_object_key(self.agent)?,
+                _ => return Err(self.error("map keys must be strings or numbers")),
+            };
+            let value = self.read_value()?;
+            map.set(self.agent, key, value)?;
+        }
+        Ok(map)
+    }
+
+    fn ext(&mut self, len: usize) -> Result<Value, Value> {
+        let kind = self.data.read_i8().map_err(|_| self.eof())?;
+        let data = self.bytes(len)?;
+        if kind != TUPLE_EXT && kind != RECORD_EXT {
+            return Err(self.error(&format!("unsupported extension type {}", kind)));
+        }
+        let mut inner = Decoder {
+            agent: self.agent,
+            data: Cursor::new(&data),
+        };
+        let value = inner.read_value()?;
+        if kind == RECORD_EXT {
+            return self.record(value);
+        }
+        match value {
+            Value::Object(o) => match &o.kind {
+                ObjectKind::Array(items) => Ok(Value::Tuple(Box::new(items.borrow().to_vec()))),
+                _ => Err(self.error("invalid tuple")),
+            },
+            _ => Err(self.error("invalid tuple")),
+        }
+    }
+
+    fn record(&self, map: Value) -> Result<Value, Value> {
+        let agent = self.agent;
+        let fields = match &map {
+            Value::Object(o) if matches!(o.kind, ObjectKind::Ordinary) => map.keys(agent)?,
+            _ => return Err(self.error("invalid record")),
+        };
+        let mut record = Value::new_record();
+        for key in fields {
+            let value = map.get(agent, key.clone())?;
+            record
+                .insert_record_field(agent, key, value)
+                .map_err(|_| self.error("invalid record"))?;
+        }
+        Ok(record)
+    }
+
+    fn read_value(&mut self) -> Result<Value, Value> {
+        macro_rules! read {
+            ($method:ident) => {
+                self.data.$method::<BigEndian>().map_err(|_| self.eof())?
+            };
+        }
+        let marker = self.data.read_u8().map_err(|_| self.eof())?;
+        let value = match marker {
+            0x00...0x7f => Value::from(f64::from(marker)),
+            0x80...0x8f => self.map(usize::from(marker & 0x0f))?,
+            0x90...0x9f => {
+                let items = self.array(usize::from(marker & 0x0f))?;
+                Value::new_array_from_vec(self.agent, items)
+            }
+            0xa0...0xbf => self.string(usize::from(marker & 0x1f))?,
+            0xc0 => Value::Null,
+            0xc2 => Value::from(false),
+            0xc3 => Value::from(true),
+            0xc4...0xc6 => {
+                let len = match marker {
+                    0xc4 => usize::from(self.data.read_u8().map_err(|_| self.eof())?),
+                    0xc5 => usize::from(read!(read_u16)),
+                    _ => read!(read_u32) as usize,
+                };
+                Value::new_buffer_from_vec(self.agent, self.bytes(len)?)
+            }
+            0xc7...0xc9 => {
+                let len = match marker {
+                    0xc7 => usize::from(self.data.read_u8().map_err(|_| self.eof())?),
+                    0xc8 => usize::from(read!(read_u16)),
+                    _ => read!(read_u32) as usize,
+                };
+                self.ext(len)?
+            }
+            0xca => Value::from(f64::from(read!(read_f32))),
+            0xcb => Value::from(read!(read_f64)),
+            0xcc => Value::from(f64::from(self.data.read_u8().map_err(|_| self.eof())?)),
+            0xcd => Value::from(f64::from(read!(read_u16))),
+            0xce => Value::from(f64::from(read!(read_u32))),
+            0xcf => Value::from(read!(read_u64) as f64),
+            0xd0 => Value::from(f64::from(self.data.read_i8().map_err(|_| self.eof())?)),
+            0xd1 => Value::from(f64::from(read!(read_i16))),
+            0xd2 => Value::from(f64::from(read!(read_i32))),
+            0xd3 => Value::from(read!(read_i64) as f64),
+            0xd4...0xd8 => self.ext(1 << (marker - 0xd4))?,
+            0xd9 => {
+                let len = self.data.read_u8().map_err(|_| self.eof())?;
+                self.string(usize::from(len))?
+            }
+            0xda => {
+                let len = read!(read_u16);
+                self.string(usize::from(len))?
+            }
+            0xdb => {
+                let len = read!(read_u32);
+                self.string(len as usize)?
+            }
+            0xdc | 0xdd => {
+                let len = if marker == 0xdc {
+                    usize::from(read!(read_u16))
+                } else {
+                    read!(read_u32) as usize
+                };
+                let items = self.array(len)?;
+                Value::new_array_from_vec(self.agent, items)
+            }
+            0xde => {
+                let len = read!(read_u16);
+                self.map(usize::from(len))?
+            }
+            0xdf => {
+                let len = read!(read_u32);
+                self.map(len as usize)?
+            }
+            0xe0...0xff => Value::from(f64::from(marker as i8)),
+            _ => return Err(self.error(&format!("invalid marker 0x{:02x}", marker))),
+        };
+        Ok(value)
+    }
+}
+
+pub fn decode(agent: &Agent, data: &[u8]) -> Result<Value, Value> {
+    let mut decoder = Decoder {
+        agent,
+        data: Cursor::new(data),
+    };
+    let value = decoder.read_value()?;
+    if decoder.data.position() as usize != data.len() {
+        return Err(decoder.error("trailing data after value"));
+    }
+    Ok(value)
+}
+
+fn encode_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    Ok(Value::new_buffer_from_vec(agent, encode(agent, value)?))
+}
+
+fn decode_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(Value::Object(o)) = args.get(0) {
+        if let ObjectKind::Buffer(b) = &o.kind {
+            let data = b.borrow().clone();
+            return decode(agent, &data);
+        }
+    }
+    Err(Value::new_error(agent, "data must be a buffer"))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("encode", encode_fn);
+    method!("decode", decode_fn);
+
+    module
+}
@@ -0,0 +1,269 @@
+use crate::builtins::mime;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const MAX_LINE: usize = 8 * 1024;
+const READ_SIZE: usize = 64 * 1024;
+
+static NEXT_UPLOAD: AtomicUsize = AtomicUsize::new(0);
+
+pub enum PartValue {
+    Text(Vec<u8>),
+    // spooled to disk as it is read
+    File(PathBuf, u64),
+}
+
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub value: PartValue,
+}
+
+/// The boundary of a multipart/form-data content type, if it is one.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    for param in params {
+        let param = param.trim();
+        if let Some(i) = param.find('=') {
+            if param[..i].trim().eq_ignore_ascii_case("boundary") {
+                let value = param[i + 1..].trim().trim_matches('"');
+                if !value.is_empty() && value.len() <= 70 {
+                    return Some(value.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+struct Scanner<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> Scanner<R> {
+    fn fill(&mut self) -> Result<(), ()> {
+        let start = self.buffer.len();
+        self.buffer.resize(start + READ_SIZE, 0);
+        match self.reader.read(&mut self.buffer[start..]) {
+            Ok(n) if n > 0 => {
+                self.buffer.truncate(start + n);
+                Ok(())
+            }
+            _ => {
+                self.buffer.truncate(start);
+                Err(())
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, ()> {
+        loop {
+            if let Some(i) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line = self.buffer.drain(..=i).collect::<Vec<u8>>();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string());
+            }
+            if self.buffer.len() > MAX_LINE {
+                return Err(());
+            }
+            self.fill()?;
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<Vec<u8>, ()> {
+        while self.buffer.len() < n {
+            self.fill()?;
+        }
+        Ok(self.buffer.drain(..n).collect())
+    }
+
+    // hands everything before the delimiter to the sink and consumes the delimiter
+    fn read_until<F>(&mut self, delimiter: &[u8], mut sink: F) -> Result<(), ()>
+    where
+        F: FnMut(&[u8]) -> Result<(), ()>,
+    {
+        loop {
+            if let Some(i) = find(&self.buffer, delimiter) {
+                sink(&self.buffer[..i])?;
+                self.buffer.drain(..i + delimiter.len());
+                return Ok(());
+            }
+            let keep = delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                let n = self.buffer.len() - keep;
+                sink(&self.buffer[..n])?;
+                self.buffer.drain(..n);
+            }
+            self.fill()?;
+        }
+    }
+}
+
+// name="a"; filename="b", the RFC 5987 filename* form wins when present
+fn disposition(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut filename = None;
+    let mut extended = None;
+    for param in value.split(';').skip(1) {
+        let param = param.trim();
+        let i = match param.find('=') {
+            Some(i) => i,
+            None => continue,
+        };
+        let key = param[..i].trim().to_ascii_lowercase();
+        let raw = param[i + 1..].trim();
+        let value = if raw.starts_with('"') && raw.ends_with('"') && raw.len() >= 2 {
+            raw[1..raw.len() - 1].replace("\\\"", "\"")
+        } else {
+            raw.to_string()
+        };
+        match key.as_str() {
+            "name" => name = Some(value),
+            "filename" => filename = Some(value),
+            "filename*" => {
+                if let Some(i) = value.find("''") {
+                    extended = Some(crate::intrinsics::url::percent_decode(&value[i + 2..]));
+                }
+            }
+            _ => {}
+        }
+    }
+    // browsers used to send full client paths
+    let filename = extended
+        .or(filename)
+        .map(|f| f.rsplit(&['/', '\\'][..]).next().unwrap_or("").to_string());
+    (name, filename)
+}
+
+pub fn remove_uploads(parts: &[Part]) {
+    for part in parts {
+        if let PartValue::File(path, _) = &part.value {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn parse_parts<R: Read>(
+    scanner: &mut Scanner<R>,
+    boundary: &str,
+    parts: &mut Vec<Part>,
+) -> Result<(), ()> {
+    let first = format!("--{}", boundary);
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    // preamble
+    loop {
+        if scanner.read_line()? == first {
+            break;
+        }
+    }
+    loop {
+        let mut name = None;
+        let mut filename = None;
+        let mut content_type = None;
+        loop {
+            let line = scanner.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            let i = line.find(':').ok_or(())?;
+            let value = line[i + 1..].trim();
+            match line[..i].trim().to_ascii_lowercase().as_str() {
+                "content-disposition" => {
+                    let (n, f) = disposition(value);
+                    name = n;
+                    filename = f;
+                }
+                "content-type" => content_type = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let name = name.ok_or(())?;
+        let value = if filename.is_some() {
+            let path = std::env::temp_dir().join(format!(
+                "slither-upload-{}-{}",
+                std::process::id(),
+                NEXT_UPLOAD.fetch_add(1, Ordering::SeqCst)
+            ));
+            let mut file = File::create(&path).map_err(|_| ())?;
+            // registered first so a failed read still cleans up
+            parts.push(Part {
+                name: name.clone(),
+                filename: filename.clone(),
+                content_type: content_type.clone(),
+                value: PartValue::File(path.clone(), 0),
+            });
+            let mut size = 0;
+            let mut head = Vec::new();
+            scanner.read_until(&delimiter, |chunk| {
+                size += chunk.len() as u64;
+                let wanted = mime::SNIFF_LENGTH.saturating_sub(head.len());
+                head.extend_from_slice(&chunk[..wanted.min(chunk.len())]);
+                file.write_all(chunk).map_err(|_| ())
+            })?;
+            parts.pop();
+            // browsers leave the type out for files they don't recognize
+            if content_type.is_none() {
+                content_type = filename
+                    .as_deref()
+                    .and_then(|f| mime::from_path(Path::new(f)))
+                    .or_else(|| mime::sniff(&head))
+                    .map(str::to_string);
+            }
+            PartValue::File(path, size)
+        } else {
+            let mut data = Vec::new();
+            scanner.read_until(&delimiter, |chunk| {
+                data.extend_from_slice(chunk);
+                Ok(())
+            })?;
+            PartValue::Text(data)
+        };
+        parts.push(Part {
+            name,
+            filename,
+            content_type,
+            value,
+        });
+        match scanner.take(2)?.as_slice() {
+            b"--" => return Ok(()),
+            b"\r\n" => {}
+            _ => return Err(()),
+        }
+    }
+}
+
+/// Parse a multipart/form-data body as it is read. File parts are written
+/// to temporary files, which the caller is responsible for removing.
+pub fn parse<R: Read>(reader: R, boundary: &str) -> Result<Vec<Part>, ()> {
+    let mut scanner = Scanner {
+        reader,
+        buffer: Vec::new(),
+    };
+    let mut parts = Vec::new();
+    match parse_parts(&mut scanner, boundary, &mut parts) {
+        Ok(()) => {
+            // discard the epilogue
+            let _ = std::io::copy(&mut scanner.reader, &mut std::io::sink());
+            Ok(parts)
+        }
+        Err(()) => {
+            remove_uploads(&parts);
+            Err(())
+        }
+    }
+}
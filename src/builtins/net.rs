@@ -5,15 +5,145 @@ use crate::value::Value;
 use crate::IntoValue;
 use mio::{
     net::{TcpListener, TcpStream},
-    PollOpt, Ready, Token,
+    Evented, Poll, PollOpt, Ready, Token,
 };
 use std::collections::HashMap;
 use std::io::prelude::*;
+#[cfg(unix)]
+use std::os::unix::{
+    io::AsRawFd,
+    net::{UnixListener, UnixStream},
+};
+
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn take_error(&self) -> std::io::Result<Option<std::io::Error>> {
+        match self {
+            Stream::Tcp(s) => s.take_error(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.take_error(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => (&*s).read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => (&*s).read(buf),
+        }
+    }
+}
+
+impl Write for &Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => (&*s).write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => (&*s).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(s) => (&*s).flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => (&*s).flush(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    // the socket file is removed when the listener is dropped
+    #[cfg(unix)]
+    Unix(UnixListener, std::path::PathBuf),
+}
+
+impl Listener {
+    fn take_error(&self) -> std::io::Result<Option<std::io::Error>> {
+        match self {
+            Listener::Tcp(l) => l.take_error(),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => l.take_error(),
+        }
+    }
+
+    fn accept(&self) -> std::io::Result<Stream> {
+        match self {
+            Listener::Tcp(l) => l.accept().map(|(s, _)| Stream::Tcp(s)),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => {
+                let (s, _) = l.accept()?;
+                s.set_nonblocking(true)?;
+                Ok(Stream::Unix(s))
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Listener::Unix(_, path) = self {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+macro_rules! evented {
+    ($type:ident, $($tcp:pat => $inner:ident),*; $($unix:pat => $fd:ident),*) => {
+        impl Evented for $type {
+            fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> std::io::Result<()> {
+                match self {
+                    $($tcp => $inner.register(poll, token, interest, opts),)*
+                    $(#[cfg(unix)]
+                    $unix => mio::unix::EventedFd(&$fd.as_raw_fd()).register(poll, token, interest, opts),)*
+                }
+            }
+
+            fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> std::io::Result<()> {
+                match self {
+                    $($tcp => $inner.reregister(poll, token, interest, opts),)*
+                    $(#[cfg(unix)]
+                    $unix => mio::unix::EventedFd(&$fd.as_raw_fd()).reregister(poll, token, interest, opts),)*
+                }
+            }
+
+            fn deregister(&self, poll: &Poll) -> std::io::Result<()> {
+                match self {
+                    $($tcp => $inner.deregister(poll),)*
+                    $(#[cfg(unix)]
+                    $unix => mio::unix::EventedFd(&$fd.as_raw_fd()).deregister(poll),)*
+                }
+            }
+        }
+    };
+}
+
+evented!(Stream, Stream::Tcp(s) => s; Stream::Unix(s) => s);
+evented!(Listener, Listener::Tcp(l) => l; Listener::Unix(l, _) => l);
 
 #[derive(Debug, Finalize)]
 pub enum Net {
-    Client(TcpStream, Value),
-    Server(TcpListener, Value),
+    Client(Stream, Value),
+    Server(Listener, Value),
 }
 
 unsafe impl gc::Trace for Net {
@@ -66,7 +196,7 @@ fn get_or_create_reject(kind: &str, agent: &Agent, target: Value, value: Value)
     }
 }
 
-fn create_client(agent: &Agent, stream: TcpStream) -> Result<Value, Value> {
+fn create_client(agent: &Agent, stream: Stream) -> Result<Value, Value> {
     let token = agent.mio_token();
     if let Err(e) = agent
         .mio
@@ -74,14 +204,11 @@ fn create_client(agent: &Agent, stream: TcpStream) -> Result<Value, Value> {
     {
         Err(e.into_value(agent))
     } else {
-        let client = Value::new_custom_object(agent.intrinsics.net_client_prototype.clone());
+        let client = Value::new_custom_object(agent.intrinsics.net_client_prototype.get(agent));
         client.set_slot("net client buffer", Value::new_list());
         client.set_slot("net client queue", Value::new_list());
         client.set_slot("net client token", Value::from(token.0 as f64));
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::Net(Net::Client(stream, client.clone())));
+        agent.insert_op(token, MioMapType::Net(Net::Client(stream, client.clone())));
         Ok(client)
     }
 }
@@ -113,10 +240,7 @@ pub fn handle(agent: &Agent, token: Token, net: Net) {
                         get_or_create_reject("client", agent, client.clone(), e);
                     }
                 }
-                agent
-                    .mio_map
-                    .borrow_mut()
-                    .insert(token, MioMapType::Net(Net::Client(stream, client)));
+                agent.insert_op(token, MioMapType::Net(Net::Client(stream, client)));
             }
         },
         Net::Server(listener, server) => match listener.take_error() {
@@ -126,7 +250,7 @@ pub fn handle(agent: &Agent, token: Token, net: Net) {
             }
             Ok(None) => {
                 match listener.accept() {
-                    Ok((stream, ..)) => match create_client(agent, stream) {
+                    Ok(stream) => match create_client(agent, stream) {
                         Ok(client) => {
                             get_or_create_resolve("server", agent, server.clone(), client, false);
                         }
@@ -140,10 +264,7 @@ pub fn handle(agent: &Agent, token: Token, net: Net) {
                     }
                     _ => {}
                 }
-                agent
-                    .mio_map
-                    .borrow_mut()
-                    .insert(token, MioMapType::Net(Net::Server(listener, server)));
+                agent.insert_op(token, MioMapType::Net(Net::Server(listener, server)));
             }
         },
     }
@@ -157,7 +278,7 @@ fn connect(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
                 Err(e) => return Err(e.into_value(agent)),
             };
             match TcpStream::connect(&addr) {
-                Ok(v) => create_client(agent, v),
+                Ok(v) => create_client(agent, Stream::Tcp(v)),
                 Err(e) => Err(e.into_value(agent)),
             }
         }
@@ -165,6 +286,27 @@ fn connect(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
     }
 }
 
+fn create_server(agent: &Agent, listener: Listener) -> Result<Value, Value> {
+    let token = agent.mio_token();
+    match agent
+        .mio
+        .register(&listener, token, Ready::all(), PollOpt::edge())
+    {
+        Ok(_) => {
+            let server = Value::new_custom_object(agent.intrinsics.net_server_prototype.get(agent));
+            server.set_slot("net server buffer", Value::new_list());
+            server.set_slot("net server queue", Value::new_list());
+            server.set_slot("net server token", Value::from(token.0 as f64));
+            agent.insert_op(
+                token,
+                MioMapType::Net(Net::Server(listener, server.clone())),
+            );
+            Ok(server)
+        }
+        Err(e) => Err(e.into_value(agent)),
+    }
+}
+
 fn listen(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     match args.get(0).unwrap_or(&Value::Null) {
         Value::String(addr) => {
@@ -172,34 +314,66 @@ fn listen(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
                 Ok(v) => v,
                 Err(e) => return Err(e.into_value(agent)),
             };
-            let listener = match TcpListener::bind(&addr) {
+            match TcpListener::bind(&addr) {
+                Ok(v) => create_server(agent, Listener::Tcp(v)),
+                Err(e) => Err(e.into_value(agent)),
+            }
+        }
+        _ => Err(Value::new_error(agent, "address must be a string")),
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(path) => match UnixStream::connect(path.as_str()) {
+            Ok(v) => match v.set_nonblocking(true) {
+                Ok(()) => create_client(agent, Stream::Unix(v)),
+                Err(e) => Err(e.into_value(agent)),
+            },
+            Err(e) => Err(e.into_value(agent)),
+        },
+        _ => Err(Value::new_error(agent, "path must be a string")),
+    }
+}
+
+#[cfg(unix)]
+fn listen_unix(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(path) => {
+            let listener = match UnixListener::bind(path.as_str()) {
                 Ok(v) => v,
                 Err(e) => return Err(e.into_value(agent)),
             };
-            let token = agent.mio_token();
-            match agent
-                .mio
-                .register(&listener, token, Ready::all(), PollOpt::edge())
-            {
-                Ok(_) => {
-                    let server =
-                        Value::new_custom_object(agent.intrinsics.net_server_prototype.clone());
-                    server.set_slot("net server buffer", Value::new_list());
-                    server.set_slot("net server queue", Value::new_list());
-                    server.set_slot("net server token", Value::from(token.0 as f64));
-                    agent.mio_map.borrow_mut().insert(
-                        token,
-                        MioMapType::Net(Net::Server(listener, server.clone())),
-                    );
-                    Ok(server)
+            let listener = Listener::Unix(listener, std::path::PathBuf::from(path.as_str()));
+            if let Listener::Unix(l, _) = &listener {
+                if let Err(e) = l.set_nonblocking(true) {
+                    return Err(e.into_value(agent));
                 }
-                Err(e) => Err(e.into_value(agent)),
             }
+            create_server(agent, listener)
         }
-        _ => Err(Value::new_error(agent, "address must be a string")),
+        _ => Err(Value::new_error(agent, "path must be a string")),
     }
 }
 
+// TODO: named pipes
+#[cfg(not(unix))]
+fn connect_unix(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Err(Value::new_error(
+        agent,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
+#[cfg(not(unix))]
+fn listen_unix(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Err(Value::new_error(
+        agent,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
     module.insert(
@@ -210,6 +384,14 @@ pub fn create(agent: &Agent) -> HashMap<String, Value> {
         "listen".to_string(),
         Value::new_builtin_function(agent, listen),
     );
+    module.insert(
+        "connectUnix".to_string(),
+        Value::new_builtin_function(agent, connect_unix),
+    );
+    module.insert(
+        "listenUnix".to_string(),
+        Value::new_builtin_function(agent, listen_unix),
+    );
 
     module
 }
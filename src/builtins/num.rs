@@ -0,0 +1,228 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKind, Value};
+use std::collections::HashMap;
+
+// the loops below work on plain slices of f64 with independent lanes so
+// the compiler can vectorize them
+const LANES: usize = 8;
+
+// the numbers in an array, or the bytes of a buffer
+fn numbers(agent: &Agent, value: Option<&Value>, name: &str) -> Result<Vec<f64>, Value> {
+    if let Some(Value::Object(o)) = value {
+        match &o.kind {
+            ObjectKind::Array(items) => {
+                let items = items.borrow();
+                let mut out = Vec::with_capacity(items.len());
+                for item in items.iter() {
+                    match item.as_f64() {
+                        Some(n) => out.push(n),
+                        None => {
+                            return Err(Value::new_error(
+                                agent,
+                                &format!("{} must only contain numbers", name),
+                            ))
+                        }
+                    }
+                }
+                return Ok(out);
+            }
+            ObjectKind::Buffer(bytes) => {
+                return Ok(bytes.borrow().iter().map(|b| f64::from(*b)).collect());
+            }
+            _ => {}
+        }
+    }
+    Err(Value::new_error(
+        agent,
+        &format!("{} must be an array or buffer", name),
+    ))
+}
+
+fn non_empty(agent: &Agent, args: &[Value]) -> Result<Vec<f64>, Value> {
+    let xs = numbers(agent, args.get(0), "xs")?;
+    if xs.is_empty() {
+        return Err(Value::new_error(agent, "xs must not be empty"));
+    }
+    Ok(xs)
+}
+
+fn array(agent: &Agent, xs: Vec<f64>) -> Value {
+    Value::new_array_from_vec(agent, xs.into_iter().map(Value::from).collect())
+}
+
+fn total(xs: &[f64]) -> f64 {
+    let mut lanes = [0.0; LANES];
+    let chunks = xs.chunks_exact(LANES);
+    let rest = chunks.remainder().iter().sum::<f64>();
+    for chunk in chunks {
+        for (lane, x) in lanes.iter_mut().zip(chunk) {
+            *lane += x;
+        }
+    }
+    lanes.iter().sum::<f64>() + rest
+}
+
+// NaN wins, as it does for math.min and math.max
+fn fold(xs: &[f64], pick: fn(f64, f64) -> f64) -> f64 {
+    if xs.iter().any(|x| x.is_nan()) {
+        return f64::NAN;
+    }
+    let mut lanes = [xs[0]; LANES];
+    let chunks = xs.chunks_exact(LANES);
+    let rest = chunks.remainder().iter().fold(xs[0], |a, b| pick(a, *b));
+    for chunk in chunks {
+        for (lane, x) in lanes.iter_mut().zip(chunk) {
+            *lane = pick(*lane, *x);
+        }
+    }
+    lanes.iter().fold(rest, |a, b| pick(a, *b))
+}
+
+fn sum(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let xs = numbers(agent, args.get(0), "xs")?;
+    Ok(Value::from(total(&xs)))
+}
+
+fn mean(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let xs = non_empty(agent, &args)?;
+    Ok(Value::from(total(&xs) / xs.len() as f64))
+}
+
+fn min(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let xs = non_empty(agent, &args)?;
+    Ok(Value::from(fold(&xs, f64::min)))
+}
+
+fn max(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let xs = non_empty(agent, &args)?;
+    Ok(Value::from(fold(&xs, f64::max)))
+}
+
+fn cumsum(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let mut xs = numbers(agent, args.get(0), "xs")?;
+    let mut running = 0.0;
+    for x in &mut xs {
+        running += *x;
+        *x = running;
+    }
+    Ok(array(agent, xs))
+}
+
+fn dot(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let xs = numbers(agent, args.get(0), "xs")?;
+    let ys = numbers(agent, args.get(1), "ys")?;
+    if xs.len() != ys.len() {
+        return Err(Value::new_error(agent, "xs and ys must be the same length"));
+    }
+    let products = xs.iter().zip(&ys).map(|(x, y)| x * y).collect::<Vec<_>>();
+    Ok(Value::from(total(&products)))
+}
+
+// an operation on each element of xs and the matching element of ys, or
+// ys itself when it is a number
+fn elementwise(agent: &Agent, args: &[Value], op: fn(f64, f64) -> f64) -> Result<Value, Value> {
+    let mut xs = numbers(agent, args.get(0), "xs")?;
+    match args.get(1).and_then(Value::as_f64) {
+        Some(y) => {
+            for x in &mut xs {
+                *x = op(*x, y);
+            }
+        }
+        None => {
+            let ys = numbers(agent, args.get(1), "ys")?;
+            if xs.len() != ys.len() {
+                return Err(Value::new_error(agent, "xs and ys must be the same length"));
+            }
+            for (x, y) in xs.iter_mut().zip(&ys) {
+                *x = op(*x, *y);
+            }
+        }
+    }
+    Ok(array(agent, xs))
+}
+
+fn unary(agent: &Agent, args: &[Value], op: fn(f64) -> f64) -> Result<Value, Value> {
+    let mut xs = numbers(agent, args.get(0), "xs")?;
+    for x in &mut xs {
+        *x = op(*x);
+    }
+    Ok(array(agent, xs))
+}
+
+fn add(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    elementwise(agent, &args, |x, y| x + y)
+}
+
+fn sub(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    elementwise(agent, &args, |x, y| x - y)
+}
+
+fn mul(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    elementwise(agent, &args, |x, y| x * y)
+}
+
+fn div(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    elementwise(agent, &args, |x, y| x / y)
+}
+
+fn abs(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    unary(agent, &args, f64::abs)
+}
+
+fn sqrt(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    unary(agent, &args, f64::sqrt)
+}
+
+// the numbers from start up to but not including end
+fn range(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (start, end) = match (args.get(0).and_then(Value::as_f64), args.get(1)) {
+        (Some(end), None) | (Some(end), Some(Value::Null)) => (0.0, end),
+        (Some(start), Some(end)) => match end.as_f64() {
+            Some(end) => (start, end),
+            None => return Err(Value::new_error(agent, "end must be a number")),
+        },
+        (None, _) => return Err(Value::new_error(agent, "start must be a number")),
+    };
+    let step = match args.get(2) {
+        None | Some(Value::Null) => 1.0,
+        Some(step) => match step.as_f64() {
+            Some(step) if step != 0.0 && step.is_finite() => step,
+            _ => return Err(Value::new_error(agent, "step must be a non-zero number")),
+        },
+    };
+    let count = ((end - start) / step).ceil();
+    if !count.is_finite() || count > f64::from(u32::MAX) {
+        return Err(Value::new_error(agent, "range is too long"));
+    }
+    let count = count.max(0.0) as usize;
+    Ok(array(
+        agent,
+        (0..count).map(|i| start + i as f64 * step).collect(),
+    ))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("sum", sum);
+    method!("mean", mean);
+    method!("min", min);
+    method!("max", max);
+    method!("cumsum", cumsum);
+    method!("dot", dot);
+    method!("add", add);
+    method!("sub", sub);
+    method!("mul", mul);
+    method!("div", div);
+    method!("abs", abs);
+    method!("sqrt", sqrt);
+    method!("range", range);
+
+    module
+}
@@ -0,0 +1,614 @@
+use crate::agent::{Agent, MioMapType};
+use crate::interpreter::Context;
+use crate::intrinsics::promise::new_promise_capability;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use lazy_static::lazy_static;
+use mio::{PollOpt, Ready, Registration};
+use num::ToPrimitive;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref RESULTS: Mutex<HashMap<usize, Result<Output, String>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct Command {
+    program: String,
+    args: Vec<String>,
+}
+
+#[derive(Default)]
+struct Options {
+    cwd: Option<String>,
+    env: Vec<(String, String)>,
+    stdin: Option<Vec<u8>>,
+}
+
+struct Output {
+    // exit code of each command, None when killed by a signal
+    statuses: Vec<Option<i32>>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+fn read_all<R: Read + Send + 'static>(mut reader: R) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
+
+fn wait_all(children: Vec<Child>) -> Vec<Option<i32>> {
+    children
+        .into_iter()
+        .map(|mut c| c.wait().ok().and_then(|s| s.code()))
+        .collect()
+}
+
+// each command's stdout feeds the next one's stdin, stderr is collected from all of them
+fn run(pipeline: Vec<Command>, options: Options) -> Result<Output, String> {
+    let mut children: Vec<Child> = Vec::new();
+    let mut stderr = Vec::new();
+    for (i, command) in pipeline.into_iter().enumerate() {
+        let mut c = std::process::Command::new(&command.program);
+        c.args(&command.args);
+        if let Some(cwd) = &options.cwd {
+            c.current_dir(cwd);
+        }
+        for (k, v) in &options.env {
+            c.env(k, v);
+        }
+        match children.last_mut() {
+            Some(previous) => {
+                c.stdin(Stdio::from(previous.stdout.take().unwrap()));
+            }
+            None if options.stdin.is_some() => {
+                c.stdin(Stdio::piped());
+            }
+            None => {
+                c.stdin(Stdio::null());
+            }
+        }
+        c.stdout(Stdio::piped());
+        c.stderr(Stdio::piped());
+        let mut child = match c.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                // close the pipe so earlier commands can finish
+                drop(c);
+                for mut child in children {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return Err(format!("{}: {}", command.program, e));
+            }
+        };
+        stderr.push(read_all(child.stderr.take().unwrap()));
+        if i == 0 {
+            if let (Some(input), Some(mut pipe)) = (options.stdin.clone(), child.stdin.take()) {
+                std::thread::spawn(move || {
+                    let _ = pipe.write_all(&input);
+                });
+            }
+        }
+        children.push(child);
+    }
+    let stdout = read_all(children.last_mut().unwrap().stdout.take().unwrap());
+    let statuses = wait_all(children);
+    Ok(Output {
+        statuses,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_default())
+            .collect(),
+    })
+}
+
+pub fn handle(agent: &Agent, promise: Value) {
+    let id = match promise.get_slot("process id") {
+        Value::Number(n) => n.to_usize().unwrap(),
+        _ => unreachable!(),
+    };
+    let result = RESULTS.lock().unwrap().remove(&id).unwrap();
+    let (value, ok) = match result {
+        Ok(output) => settle(agent, &promise, output),
+        Err(e) => (Value::new_error(agent, &e), false),
+    };
+    let f = promise.get_slot(if ok { "resolve" } else { "reject" });
+    f.call(agent, Value::Null, vec![value]).unwrap();
+}
+
+fn settle(agent: &Agent, promise: &Value, output: Output) -> (Value, bool) {
+    let status = match output.statuses.last() {
+        Some(Some(code)) => Value::from(f64::from(*code)),
+        _ => Value::Null,
+    };
+    let failed = output.statuses.iter().position(|s| *s != Some(0));
+    // spawn() reports the status, sh() treats any failure in the pipeline as an error
+    let check = promise.get_slot("process check") == Value::from(true);
+    let value = match failed {
+        Some(i) if check => {
+            let message = match output.statuses[i] {
+                Some(code) => format!("command {} exited with code {}", i + 1, code),
+                None => format!("command {} was terminated by a signal", i + 1),
+            };
+            Value::new_error(agent, &message)
+        }
+        _ => Value::new_object(agent.intrinsics.object_prototype.clone()),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    value.set(agent, ObjectKey::from("status"), status).unwrap();
+    value
+        .set(agent, ObjectKey::from("stdout"), Value::from(stdout))
+        .unwrap();
+    value
+        .set(agent, ObjectKey::from("stderr"), Value::from(stderr))
+        .unwrap();
+    (value, !(check && failed.is_some()))
+}
+
+fn start(
+    agent: &Agent,
+    pipeline: Vec<Command>,
+    options: Options,
+    check: bool,
+) -> Result<Value, Value> {
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.mio_token();
+
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    agent.insert_op(token, MioMapType::Process(registration, promise.clone()));
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    promise.set_slot("process id", Value::from(id as f64));
+    promise.set_slot("process check", Value::from(check));
+
+    // not on the pool, commands can run for as long as they like
+    std::thread::spawn(move || {
+        let result = run(pipeline, options);
+        RESULTS.lock().unwrap().insert(id, result);
+        set_readiness.set_readiness(Ready::readable()).unwrap();
+    });
+
+    Ok(promise)
+}
+
+fn to_string(agent: &Agent, value: &Value) -> Result<String, Value> {
+    match value {
+        Value::String(s) => Ok(s.to_string()),
+        Value::Number(n) => Ok(crate::num_util::to_string(*n)),
+        v => {
+            let ts = v.get(agent, ObjectKey::well_known_symbol("toString"))?;
+            if ts.type_of() != "function" {
+                return Err(Value::new_error(
+                    agent,
+                    "value does not provide a :toString",
+                ));
+            }
+            match ts.call(agent, v.clone(), vec![])? {
+                Value::String(s) => Ok(s.to_string()),
+                _ => Err(Value::new_error(agent, ":toString must return a string")),
+            }
+        }
+    }
+}
+
+fn array_items(value: &Value) -> Option<Vec<Value>> {
+    match value {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(items) => Some(items.borrow().to_vec()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_options(agent: &Agent, options: &Value) -> Result<Options, Value> {
+    let mut result = Options::default();
+    if options == &Value::Null {
+        return Ok(result);
+    }
+    if options.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+    match options.get(agent, ObjectKey::from("cwd"))? {
+        Value::Null => {}
+        Value::String(s) => result.cwd = Some(s.to_string()),
+        _ => return Err(Value::new_error(agent, "cwd must be a string")),
+    }
+    let env = options.get(agent, ObjectKey::from("env"))?;
+    if env != Value::Null {
+        if env.type_of() != "object" {
+            return Err(Value::new_error(agent, "env must be an object"));
+        }
+        for key in env.keys(agent)? {
+            if let ObjectKey::String(name) = &key {
+                let value = env.get(agent, key.clone())?;
+                result.env.push((name.clone(), to_string(agent, &value)?));
+            }
+        }
+    }
+    match options.get(agent, ObjectKey::from("stdin"))? {
+        Value::Null => {}
+        Value::String(s) => result.stdin = Some(s.as_bytes().to_vec()),
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Buffer(b) => result.stdin = Some(b.borrow().clone()),
+            _ => return Err(Value::new_error(agent, "stdin must be a string or buffer")),
+        },
+        _ => return Err(Value::new_error(agent, "stdin must be a string or buffer")),
+    }
+    Ok(result)
+}
+
+fn spawn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let program = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "command must be a string")),
+    };
+    let arguments = match args.get(1).unwrap_or(&Value::Null) {
+        Value::Null => Vec::new(),
+        v => match array_items(v) {
+            Some(items) => items
+                .iter()
+                .map(|a| to_string(agent, a))
+                .collect::<Result<Vec<String>, Value>>()?,
+            None => return Err(Value::new_error(agent, "args must be an array")),
+        },
+    };
+    let options = parse_options(agent, args.get(2).unwrap_or(&Value::Null))?;
+    let command = Command {
+        program,
+        args: arguments,
+    };
+    start(agent, vec![command], options, false)
+}
+
+enum Piece {
+    Literal(String),
+    Value(Value),
+}
+
+#[derive(PartialEq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+// Splits a command line into words the way a shell would, without
+// expanding anything. Interpolated values are never split again, so they
+// need no quoting; an array value becomes one word per item.
+fn parse_command(agent: &Agent, pieces: Vec<Piece>) -> Result<Vec<Command>, Value> {
+    let mut pipeline = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = Quote::None;
+
+    macro_rules! end_word {
+        () => {
+            if let Some(w) = word.take() {
+                words.push(w);
+            }
+        };
+    }
+
+    for piece in pieces {
+        let literal = match piece {
+            Piece::Value(value) => {
+                match array_items(&value) {
+                    Some(items) if quote == Quote::None => {
+                        end_word!();
+                        for item in items {
+                            words.push(to_string(agent, &item)?);
+                        }
+                    }
+                    _ => word
+                        .get_or_insert_with(String::new)
+                        .push_str(&to_string(agent, &value)?),
+                }
+                continue;
+            }
+            Piece::Literal(s) => s,
+        };
+        let mut chars = literal.chars();
+        while let Some(c) = chars.next() {
+            match quote {
+                Quote::Single => match c {
+                    '\'' => quote = Quote::None,
+                    c => word.get_or_insert_with(String::new).push(c),
+                },
+                Quote::Double => match c {
+                    '"' => quote = Quote::None,
+                    '\\' => match chars.next() {
+                        Some(c) if c == '"' || c == '\\' || c == '$' || c == '`' => {
+                            word.get_or_insert_with(String::new).push(c)
+                        }
+                        Some(c) => {
+                            let w = word.get_or_insert_with(String::new);
+                            w.push('\\');
+                            w.push(c);
+                        }
+                        None => word.get_or_insert_with(String::new).push('\\'),
+                    },
+                    c => word.get_or_insert_with(String::new).push(c),
+                },
+                Quote::None => match c {
+                    '\'' => {
+                        word.get_or_insert_with(String::new);
+                        quote = Quote::Single;
+                    }
+                    '"' => {
+                        word.get_or_insert_with(String::new);
+                        quote = Quote::Double;
+                    }
+                    '\\' => {
+                        if let Some(c) = chars.next() {
+                            word.get_or_insert_with(String::new).push(c);
+                        }
+                    }
+                    '|' => {
+                        end_word!();
+                        if words.is_empty() {
+                            return Err(Value::new_error(agent, "empty command in pipeline"));
+                        }
+                        pipeline.push(words);
+                        words = Vec::new();
+                    }
+                    ';' | '&' | '<' | '>' | '(' | ')' => {
+                        return Err(Value::new_error(
+                            agent,
+                            &format!("unsupported shell syntax '{}'", c),
+                        ));
+                    }
+                    c if c.is_whitespace() => end_word!(),
+                    c => word.get_or_insert_with(String::new).push(c),
+                },
+            }
+        }
+    }
+    if quote != Quote::None {
+        return Err(Value::new_error(agent, "unterminated quote"));
+    }
+    end_word!();
+    if words.is_empty() {
+        return Err(Value::new_error(agent, "empty command in pipeline"));
+    }
+    pipeline.push(words);
+
+    Ok(pipeline
+        .into_iter()
+        .map(|mut words| {
+            let program = words.remove(0);
+            Command {
+                program,
+                args: words,
+            }
+        })
+        .collect())
+}
+
+/// sh`cmd ${value} | cmd` or sh('cmd | cmd', options)
+fn sh(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (pieces, options) = match args.get(0) {
+        Some(Value::String(s)) => (
+            vec![Piece::Literal(s.to_string())],
+            parse_options(agent, args.get(1).unwrap_or(&Value::Null))?,
+        ),
+        Some(v) => match array_items(v) {
+            // the strings of a tagged template, followed by its values
+            Some(strings) => {
+                let mut pieces = Vec::new();
+                let mut values = args[1..].iter();
+                for s in strings {
+                    match s {
+                        Value::String(s) => pieces.push(Piece::Literal(s.to_string())),
+                        _ => return Err(Value::new_error(agent, "invalid template strings")),
+                    }
+                    if let Some(v) = values.next() {
+                        pieces.push(Piece::Value(v.clone()));
+                    }
+                }
+                (pieces, Options::default())
+            }
+            None => return Err(Value::new_error(agent, "command must be a string")),
+        },
+        None => return Err(Value::new_error(agent, "command must be a string")),
+    };
+    let pipeline = parse_command(agent, pieces)?;
+    start(agent, pipeline, options, true)
+}
+
+// set in the environment of the copy of the program `daemonize` starts
+#[cfg(unix)]
+const DAEMON_ENV: &str = "SLITHER_DAEMONIZED";
+
+#[cfg(unix)]
+extern "C" {
+    fn setsid() -> i32;
+}
+
+#[cfg(unix)]
+fn output_file(agent: &Agent, options: &Value, name: &str) -> Result<Stdio, Value> {
+    let path = match options.get(agent, ObjectKey::from(name))? {
+        Value::Null => return Ok(Stdio::null()),
+        Value::String(s) => s.to_string(),
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                &format!("{} must be a string", name),
+            ))
+        }
+    };
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map(Stdio::from)
+        .map_err(|e| Value::new_error(agent, &format!("{}: {}", path, e)))
+}
+
+/// Keep running in the background, detached from the terminal, with
+/// stdout and stderr appended to the files given as `stdout` and `stderr`
+/// (discarded otherwise) and the process id written to `pidFile`.
+///
+/// Forking would lose the threads the runtime already started, so this
+/// starts the program again as a new session and exits. The program runs
+/// from the start in the background, and there `daemonize` returns, so it
+/// should be called before anything else happens. It fails unless the
+/// embedder said how to run the script again, see `Agent::set_daemon_args`,
+/// which the slither binary does for scripts run from a file.
+#[cfg(unix)]
+fn daemonize(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    use std::os::unix::process::CommandExt;
+
+    if std::env::var_os(DAEMON_ENV).is_some() {
+        std::env::remove_var(DAEMON_ENV);
+        return Ok(Value::Null);
+    }
+    let options = args.get(0).unwrap_or(&Value::Null);
+    if *options != Value::Null && options.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+    let field = |name| match options {
+        Value::Null => Ok(Value::Null),
+        _ => options.get(agent, ObjectKey::from(name)),
+    };
+    let pid_file = match field("pidFile")? {
+        Value::Null => None,
+        Value::String(s) => Some(s.to_string()),
+        _ => return Err(Value::new_error(agent, "pidFile must be a string")),
+    };
+    let (stdout, stderr) = match options {
+        Value::Null => (Stdio::null(), Stdio::null()),
+        _ => (
+            output_file(agent, options, "stdout")?,
+            output_file(agent, options, "stderr")?,
+        ),
+    };
+
+    let daemon_args = match agent.daemon_args() {
+        Some(args) => args,
+        None => {
+            return Err(Value::new_error(
+                agent,
+                "daemonize can only run a script from a file again",
+            ))
+        }
+    };
+    let program = std::env::current_exe().map_err(|e| Value::new_error(agent, &e.to_string()))?;
+    let mut command = std::process::Command::new(program);
+    command
+        .args(daemon_args)
+        .env(DAEMON_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    // a new session has no controlling terminal to hang up on it
+    unsafe {
+        command.pre_exec(|| {
+            if setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| Value::new_error(agent, &format!("could not daemonize: {}", e)))?;
+    if let Some(pid_file) = pid_file {
+        if let Err(e) = std::fs::write(&pid_file, format!("{}\n", child.id())) {
+            let _ = child.kill();
+            return Err(Value::new_error(agent, &format!("{}: {}", pid_file, e)));
+        }
+    }
+    std::process::exit(0);
+}
+
+#[cfg(not(unix))]
+fn daemonize(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Err(Value::new_error(
+        agent,
+        "daemonize is only supported on Unix",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn prctl(option: i32, ...) -> i32;
+}
+
+// the last title set, which may be longer than what the OS keeps
+static TITLE: Mutex<Option<String>> = Mutex::new(None);
+
+/// process.title is the last title set, or the program's file name.
+fn get_title(_: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(title) = &*TITLE.lock().unwrap() {
+        return Ok(Value::from(title.as_str()));
+    }
+    let program = std::env::current_exe().ok().and_then(|p| {
+        p.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+    Ok(program.map_or(Value::Null, Value::from))
+}
+
+/// Setting process.title changes the name `ps` and `top` show for the
+/// process. Linux keeps the first 15 bytes; elsewhere only the property
+/// changes.
+fn set_title(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let title = match args.get(0) {
+        Some(Value::String(s)) if !s.contains('\0') => s.to_string(),
+        _ => return Err(Value::new_error(agent, "title must be a string")),
+    };
+    #[cfg(target_os = "linux")]
+    {
+        const PR_SET_NAME: i32 = 15;
+        let name = std::ffi::CString::new(title.as_str()).unwrap();
+        if unsafe { prctl(PR_SET_NAME, name.as_ptr()) } == -1 {
+            let e = std::io::Error::last_os_error();
+            return Err(Value::new_error(agent, &e.to_string()));
+        }
+    }
+    *TITLE.lock().unwrap() = Some(title);
+    Ok(Value::Null)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "spawn".to_string(),
+        Value::new_builtin_function(agent, spawn),
+    );
+    module.insert("sh".to_string(), Value::new_builtin_function(agent, sh));
+    module.insert(
+        "daemonize".to_string(),
+        Value::new_builtin_function(agent, daemonize),
+    );
+
+    let process = Value::new_object(agent.intrinsics.object_prototype.clone());
+    process.define_accessor(
+        ObjectKey::from("title"),
+        true,
+        Value::new_builtin_function(agent, get_title),
+    );
+    let set = Value::new_builtin_function(agent, set_title);
+    // only functions exported directly are tagged in `builtins::create`
+    set.set_slot("capability", Value::from("process"));
+    set.set_slot("operation", Value::from("process.title"));
+    process.define_accessor(ObjectKey::from("title"), false, set);
+    module.insert("process".to_string(), process);
+
+    module
+}
@@ -0,0 +1,116 @@
+use crate::interpreter::Context;
+use crate::parser::Feature;
+use crate::{Agent, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Info {
+    version: &'static str,
+    build_features: Vec<&'static str>,
+    syntax: Vec<&'static str>,
+    // path to capabilities, empty when every module may use everything
+    permissions: BTreeMap<String, Vec<String>>,
+    modules: Vec<String>,
+    event_loop: &'static str,
+    promise_rejection_tracking: bool,
+}
+
+// the cargo features this build was compiled with
+fn build_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "os") {
+        features.push("os");
+    }
+    if cfg!(feature = "toml") {
+        features.push("toml");
+    }
+    if cfg!(feature = "yaml") {
+        features.push("yaml");
+    }
+    if cfg!(feature = "differential") {
+        features.push("differential");
+    }
+    features
+}
+
+// what this runtime is and how it is set up: the version, build features,
+// opt-in syntax, grants, standard modules created so far and who drives
+// the event loop
+fn info(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let info = Info {
+        version: env!("CARGO_PKG_VERSION"),
+        build_features: build_features(),
+        syntax: Feature::ALL
+            .iter()
+            .filter(|f| agent.has_feature(**f))
+            .map(|f| f.name())
+            .collect(),
+        permissions: agent.grants().into_iter().collect(),
+        modules: agent.loaded_builtin_modules(),
+        event_loop: if cfg!(feature = "os") { "mio" } else { "host" },
+        promise_rejection_tracking: agent.rejection_tracking(),
+    };
+    Ok(Value::from_rust(agent, &info))
+}
+
+// stop or start reporting rejected promises nothing handles
+fn set_promise_rejection_tracking(
+    agent: &Agent,
+    args: Vec<Value>,
+    _: &Context,
+) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::Boolean(enabled)) => {
+            agent.set_rejection_tracking(*enabled);
+            Ok(Value::Null)
+        }
+        _ => Err(Value::new_error(agent, "enabled must be a boolean")),
+    }
+}
+
+// timers, fs jobs, sockets and anything else keeping the event loop alive
+fn pending_ops(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::from_rust(agent, &agent.pending_operations()))
+}
+
+// report jobs slower than a threshold in ms to a callback, or stop with null
+fn watch_slow_jobs(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let threshold = args.get(0).unwrap_or(&Value::Null);
+    if *threshold == Value::Null {
+        agent.set_slow_job_threshold(None);
+        agent.set_slow_job_callback(Value::Null);
+        return Ok(Value::Null);
+    }
+    let ms = match threshold {
+        Value::Number(ms) if *ms >= 0.0 && ms.is_finite() => *ms,
+        _ => return Err(Value::new_error(agent, "threshold must be a number of ms")),
+    };
+    let callback = args.get(1).cloned().unwrap_or(Value::Null);
+    if callback != Value::Null && callback.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    agent.set_slow_job_threshold(Some(Duration::from_secs_f64(ms / 1000.0)));
+    agent.set_slow_job_callback(callback);
+    Ok(Value::Null)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "pendingOps".to_string(),
+        Value::new_builtin_function(agent, pending_ops),
+    );
+    module.insert(
+        "watchSlowJobs".to_string(),
+        Value::new_builtin_function(agent, watch_slow_jobs),
+    );
+    module.insert("info".to_string(), Value::new_builtin_function(agent, info));
+    module.insert(
+        "setPromiseRejectionTracking".to_string(),
+        Value::new_builtin_function(agent, set_promise_rejection_tracking),
+    );
+
+    module
+}
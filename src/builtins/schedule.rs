@@ -0,0 +1,266 @@
+use crate::agent::Agent;
+use crate::builtins::timers::{clear_timeout, now_ms, set_timeout};
+use crate::builtins::tz::civil_from_days;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use num::ToPrimitive;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// how far ahead to look before deciding an expression never matches
+const MAX_DAYS: i64 = 366 * 5;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+struct Field {
+    bits: u64,
+    any: bool,
+}
+
+impl Field {
+    fn has(&self, n: i64) -> bool {
+        self.bits & (1 << n) != 0
+    }
+}
+
+struct Cron {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+fn value(s: &str, names: &[&str], offset: u32) -> Option<u32> {
+    if let Some(i) = names.iter().position(|n| s.eq_ignore_ascii_case(n)) {
+        return Some(i as u32 + offset);
+    }
+    s.parse().ok()
+}
+
+// a comma separated list of *, n, a-b, each optionally followed by /step
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<Field> {
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.find('/') {
+            Some(i) => (&item[..i], item[i + 1..].parse().ok().filter(|s| *s > 0)?),
+            None => (item, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(i) = range.find('-') {
+            (
+                value(&range[..i], names, min)?,
+                value(&range[i + 1..], names, min)?,
+            )
+        } else {
+            let n = value(range, names, min)?;
+            // a/n means from a to the end
+            (n, if item.contains('/') { max } else { n })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        let mut n = start;
+        while n <= end {
+            bits |= 1 << n;
+            n += step;
+        }
+    }
+    Some(Field {
+        bits,
+        any: field == "*",
+    })
+}
+
+fn parse(expression: &str) -> Option<Cron> {
+    let expression = match expression.trim() {
+        "@yearly" | "@annually" => "0 0 1 1 *",
+        "@monthly" => "0 0 1 * *",
+        "@weekly" => "0 0 * * 0",
+        "@daily" | "@midnight" => "0 0 * * *",
+        "@hourly" => "0 * * * *",
+        e => e,
+    };
+    let fields = expression.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() != 5 {
+        return None;
+    }
+    let mut weekdays = parse_field(fields[4], 0, 7, &DAYS)?;
+    // both 0 and 7 are sunday
+    if weekdays.has(7) {
+        weekdays.bits |= 1;
+    }
+    Some(Cron {
+        minutes: parse_field(fields[0], 0, 59, &[])?,
+        hours: parse_field(fields[1], 0, 23, &[])?,
+        days: parse_field(fields[2], 1, 31, &[])?,
+        months: parse_field(fields[3], 1, 12, &MONTHS)?,
+        weekdays,
+    })
+}
+
+impl Cron {
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        if !self.months.has(month) {
+            return false;
+        }
+        // 1970-01-01 was a thursday
+        let weekday = (days + 4).rem_euclid(7);
+        // when both are restricted either one may match, like vixie cron
+        match (self.days.any, self.weekdays.any) {
+            (false, false) => self.days.has(day) || self.weekdays.has(weekday),
+            _ => self.days.has(day) && self.weekdays.has(weekday),
+        }
+    }
+
+    /// The first matching minute after `now`, in seconds since the epoch.
+    fn next(&self, now: i64) -> Option<i64> {
+        let mut t = (now.div_euclid(60) + 1) * 60;
+        let limit = now.div_euclid(86400) + MAX_DAYS;
+        loop {
+            let days = t.div_euclid(86400);
+            if days > limit {
+                return None;
+            }
+            if !self.matches_day(days) {
+                t = (days + 1) * 86400;
+                continue;
+            }
+            let seconds = t.rem_euclid(86400);
+            if !self.hours.has(seconds / 3600) {
+                t = (t.div_euclid(3600) + 1) * 3600;
+                continue;
+            }
+            if !self.minutes.has(seconds % 3600 / 60) {
+                t += 60;
+                continue;
+            }
+            return Some(t);
+        }
+    }
+}
+
+// returns false when the expression has no future matches
+fn schedule_next(agent: &Agent, tick: &Value) -> bool {
+    let expression = match tick.get_slot("cron expression") {
+        Value::String(s) => s,
+        _ => unreachable!(),
+    };
+    let cron = parse(&expression).unwrap();
+    let now = now_ms(agent);
+    match cron.next((now / 1000.0) as i64) {
+        Some(next) => {
+            let delay = (next as f64 * 1000.0 - now).max(0.0);
+            let token = set_timeout(agent, tick.clone(), Duration::from_millis(delay as u64));
+            if tick.get_slot("cron unref") == Value::from(true) {
+                agent.unref_op(token);
+            }
+            tick.set_slot("cron token", Value::from(token.0 as f64));
+            true
+        }
+        None => false,
+    }
+}
+
+fn tick(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let tick = ctx.function.as_ref().unwrap();
+    if tick.get_slot("cron cancelled") == Value::from(true) {
+        return Ok(Value::Null);
+    }
+    // scheduled first so a throwing callback keeps running
+    if !schedule_next(agent, tick) {
+        tick.set_slot("cron cancelled", Value::from(true));
+    }
+    tick.get_slot("cron callback")
+        .call(agent, Value::Null, vec![])?;
+    Ok(Value::Null)
+}
+
+fn cancel(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let tick = ctx.function.as_ref().unwrap().get_slot("cron tick");
+    tick.set_slot("cron cancelled", Value::from(true));
+    if let Value::Number(n) = tick.get_slot("cron token") {
+        clear_timeout(agent, mio::Token(n.to_usize().unwrap()));
+    }
+    Ok(Value::Null)
+}
+
+fn set_referenced(agent: &Agent, ctx: &Context, referenced: bool) {
+    let tick = ctx.function.as_ref().unwrap().get_slot("cron tick");
+    // remembered so the timer for every following match is unref'd too
+    tick.set_slot("cron unref", Value::from(!referenced));
+    if let Value::Number(n) = tick.get_slot("cron token") {
+        let token = mio::Token(n.to_usize().unwrap());
+        if referenced {
+            agent.ref_op(token);
+        } else {
+            agent.unref_op(token);
+        }
+    }
+}
+
+fn cron_ref(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    set_referenced(agent, ctx, true);
+    Ok(Value::Null)
+}
+
+fn cron_unref(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    set_referenced(agent, ctx, false);
+    Ok(Value::Null)
+}
+
+/// Run `callback` at every minute matching a five field cron expression,
+/// evaluated in UTC.
+fn cron(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let expression = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "expression must be a string")),
+    };
+    let callback = args.get(1).unwrap_or(&Value::Null);
+    if callback.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    if parse(&expression).is_none() {
+        return Err(Value::new_error(
+            agent,
+            &format!("invalid cron expression '{}'", expression),
+        ));
+    }
+
+    let t = Value::new_builtin_function(agent, tick);
+    t.set_slot("cron expression", Value::from(expression.clone()));
+    t.set_slot("cron callback", callback.clone());
+    t.set_slot("cron cancelled", Value::from(false));
+    t.set_slot("cron token", Value::Null);
+    t.set_slot("cron unref", Value::from(false));
+    if !schedule_next(agent, &t) {
+        return Err(Value::new_error(
+            agent,
+            &format!("cron expression '{}' never matches", expression),
+        ));
+    }
+
+    let handle = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let c = Value::new_builtin_function(agent, cancel);
+    c.set_slot("cron tick", t.clone());
+    handle.set(agent, ObjectKey::from("cancel"), c)?;
+    let r = Value::new_builtin_function(agent, cron_ref);
+    r.set_slot("cron tick", t.clone());
+    handle.set(agent, ObjectKey::from("ref"), r)?;
+    let u = Value::new_builtin_function(agent, cron_unref);
+    u.set_slot("cron tick", t);
+    handle.set(agent, ObjectKey::from("unref"), u)?;
+    Ok(handle)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert("cron".to_string(), Value::new_builtin_function(agent, cron));
+
+    module
+}
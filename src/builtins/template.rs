@@ -0,0 +1,430 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::HashMap;
+
+// how deep partials may include each other
+const MAX_PARTIAL_DEPTH: usize = 100;
+
+// A compiled template is a list of parts, each a slot object with a "kind":
+// text, variable, raw, section, inverted or partial. "value" holds the text
+// or the name, sections have "children" and partials their "indent".
+fn new_part(kind: &str, value: &str) -> Value {
+    let part = Value::new_custom_object(Value::Null);
+    part.set_slot("kind", Value::from(kind));
+    part.set_slot("value", Value::from(value));
+    part
+}
+
+struct Frame {
+    // the name of the section, empty for the top level
+    name: String,
+    kind: &'static str,
+    parts: Vec<Value>,
+    text: String,
+}
+
+impl Frame {
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::replace(&mut self.text, String::new());
+            self.parts.push(new_part("text", &text));
+        }
+    }
+}
+
+fn line_of(source: &str, i: usize) -> usize {
+    source[..i].matches('\n').count() + 1
+}
+
+fn compile_parts(agent: &Agent, source: &str) -> Result<Value, Value> {
+    let error = |i: usize, message: &str| {
+        Value::new_error(
+            agent,
+            &format!("template: line {}: {}", line_of(source, i), message),
+        )
+    };
+    let mut stack = vec![Frame {
+        name: String::new(),
+        kind: "",
+        parts: Vec::new(),
+        text: String::new(),
+    }];
+    let mut i = 0;
+    while let Some(start) = source[i..].find("{{").map(|s| s + i) {
+        stack.last_mut().unwrap().text.push_str(&source[i..start]);
+        let triple = source[start..].starts_with("{{{");
+        let close = if triple { "}}}" } else { "}}" };
+        let open = if triple { 3 } else { 2 };
+        let end = match source[start + open..].find(close) {
+            Some(e) => start + open + e,
+            None => return Err(error(start, "unclosed tag")),
+        };
+        let mut after = end + close.len();
+        let tag = source[start + open..end].trim();
+        let (sigil, name) = if triple {
+            ('{', tag)
+        } else {
+            match tag.chars().next() {
+                Some(c) if "#^/>!&=".contains(c) => (c, tag[1..].trim()),
+                _ => (' ', tag),
+            }
+        };
+        if sigil == '=' {
+            return Err(error(start, "custom delimiters are not supported"));
+        }
+        if name.is_empty() && sigil != '!' {
+            return Err(error(start, "empty tag"));
+        }
+
+        // a section, comment or partial tag alone on its line takes the
+        // whole line with it
+        let line_start = source[..start].rfind('\n').map_or(0, |n| n + 1);
+        let indent = &source[line_start..start];
+        let line_end = source[after..]
+            .find('\n')
+            .map_or(source.len(), |n| after + n + 1);
+        let standalone = "#^/>!".contains(sigil)
+            && indent.chars().all(|c| c == ' ' || c == '\t')
+            && source[after..line_end].trim().is_empty();
+        if standalone {
+            let text = &mut stack.last_mut().unwrap().text;
+            text.truncate(text.len() - indent.len());
+            after = line_end;
+        }
+
+        let frame = stack.last_mut().unwrap();
+        match sigil {
+            '!' => {}
+            '#' | '^' => {
+                frame.flush();
+                stack.push(Frame {
+                    name: name.to_string(),
+                    kind: if sigil == '#' { "section" } else { "inverted" },
+                    parts: Vec::new(),
+                    text: String::new(),
+                });
+            }
+            '/' => {
+                frame.flush();
+                if stack.len() == 1 {
+                    return Err(error(start, &format!("'{}' closes no section", name)));
+                }
+                let section = stack.pop().unwrap();
+                if section.name != name {
+                    return Err(error(
+                        start,
+                        &format!("'{}' closes section '{}'", name, section.name),
+                    ));
+                }
+                let part = new_part(section.kind, &section.name);
+                part.set_slot("children", Value::new_array_from_vec(agent, section.parts));
+                stack.last_mut().unwrap().parts.push(part);
+            }
+            '>' => {
+                frame.flush();
+                let part = new_part("partial", name);
+                part.set_slot("indent", Value::from(if standalone { indent } else { "" }));
+                frame.parts.push(part);
+            }
+            '{' | '&' => {
+                frame.flush();
+                frame.parts.push(new_part("raw", name));
+            }
+            _ => {
+                frame.flush();
+                frame.parts.push(new_part("variable", name));
+            }
+        }
+        i = after;
+    }
+    if stack.len() > 1 {
+        let name = stack.pop().unwrap().name;
+        return Err(error(source.len(), &format!("unclosed section '{}'", name)));
+    }
+    let mut frame = stack.pop().unwrap();
+    frame.text.push_str(&source[i..]);
+    frame.flush();
+    Ok(Value::new_array_from_vec(agent, frame.parts))
+}
+
+fn list(agent: &Agent, value: &Value) -> Result<Option<Vec<Value>>, Value> {
+    match value {
+        Value::Object(o) => match o.kind {
+            ObjectKind::Array(..) => {}
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    }
+    let length = match value.get(agent, ObjectKey::from("length"))? {
+        Value::Number(n) => n as usize,
+        _ => 0,
+    };
+    (0..length)
+        .map(|i| value.get(agent, ObjectKey::from(i)))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+// names are looked up in the innermost context that has them, dotted names
+// then walk down from there
+fn lookup(agent: &Agent, stack: &[Value], name: &str) -> Result<Value, Value> {
+    if name == "." {
+        return Ok(stack.last().cloned().unwrap_or(Value::Null));
+    }
+    let mut keys = name.split('.');
+    let first = ObjectKey::from(keys.next().unwrap());
+    let mut value = Value::Null;
+    for context in stack.iter().rev() {
+        if *context != Value::Null {
+            value = context.get(agent, first.clone())?;
+            if value != Value::Null {
+                break;
+            }
+        }
+    }
+    for key in keys {
+        if value == Value::Null {
+            break;
+        }
+        value = value.get(agent, ObjectKey::from(key))?;
+    }
+    Ok(value)
+}
+
+fn stringify(agent: &Agent, value: Value) -> Result<String, Value> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(s) => Ok(s.to_string()),
+        value => {
+            let ts = value.get(agent, ObjectKey::well_known_symbol("toString"))?;
+            if ts.type_of() != "function" {
+                return Err(Value::new_error(
+                    agent,
+                    "value does not provide a :toString",
+                ));
+            }
+            match ts.call(agent, value, vec![])? {
+                Value::String(s) => Ok(s.to_string()),
+                _ => Err(Value::new_error(agent, ":toString must return a string")),
+            }
+        }
+    }
+}
+
+fn escape_html(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn slot_string(part: &Value, slot: &str) -> String {
+    match part.get_slot(slot) {
+        Value::String(s) => s.to_string(),
+        _ => unreachable!(),
+    }
+}
+
+struct Renderer<'a> {
+    agent: &'a Agent,
+    partials: Value,
+    escape: bool,
+    depth: usize,
+}
+
+impl<'a> Renderer<'a> {
+    fn partial(&mut self, name: &str) -> Result<Option<Value>, Value> {
+        if self.partials == Value::Null {
+            return Ok(None);
+        }
+        match self.partials.get(self.agent, ObjectKey::from(name))? {
+            Value::Null => Ok(None),
+            Value::String(source) => Ok(Some(compile_parts(self.agent, &source)?)),
+            t if t.has_slot("template parts") => Ok(Some(t.get_slot("template parts"))),
+            _ => Err(Value::new_error(
+                self.agent,
+                &format!("partial '{}' must be a string or a template", name),
+            )),
+        }
+    }
+
+    fn render(
+        &mut self,
+        parts: &Value,
+        stack: &mut Vec<Value>,
+        out: &mut String,
+    ) -> Result<(), Value> {
+        let agent = self.agent;
+        for part in list(agent, parts)?.unwrap() {
+            let kind = slot_string(&part, "kind");
+            let value = slot_string(&part, "value");
+            match kind.as_str() {
+                "text" => out.push_str(&value),
+                "variable" | "raw" => {
+                    let mut v = lookup(agent, stack, &value)?;
+                    if v.type_of() == "function" {
+                        let this = stack.last().cloned().unwrap_or(Value::Null);
+                        v = v.call(agent, this, vec![])?;
+                    }
+                    let s = stringify(agent, v)?;
+                    if kind == "variable" && self.escape {
+                        escape_html(&s, out);
+                    } else {
+                        out.push_str(&s);
+                    }
+                }
+                "section" | "inverted" => {
+                    let v = lookup(agent, stack, &value)?;
+                    let items = list(agent, &v)?;
+                    let truthy = match &items {
+                        Some(items) => !items.is_empty(),
+                        None => v.to_bool(),
+                    };
+                    let children = part.get_slot("children");
+                    if kind == "inverted" {
+                        if !truthy {
+                            self.render(&children, stack, out)?;
+                        }
+                    } else if let Some(items) = items {
+                        for item in items {
+                            stack.push(item);
+                            self.render(&children, stack, out)?;
+                            stack.pop();
+                        }
+                    } else if truthy {
+                        stack.push(v);
+                        self.render(&children, stack, out)?;
+                        stack.pop();
+                    }
+                }
+                "partial" => {
+                    // a missing partial renders as nothing, like a missing name
+                    let parts = match self.partial(&value)? {
+                        Some(parts) => parts,
+                        None => continue,
+                    };
+                    if self.depth == MAX_PARTIAL_DEPTH {
+                        return Err(Value::new_error(agent, "template: partials nest too deep"));
+                    }
+                    self.depth += 1;
+                    let mut text = String::new();
+                    self.render(&parts, stack, &mut text)?;
+                    self.depth -= 1;
+                    let indent = slot_string(&part, "indent");
+                    for line in text.split_inclusive('\n') {
+                        out.push_str(&indent);
+                        out.push_str(line);
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn prototype(agent: &Agent) -> Value {
+    agent
+        .builtin("template", "compile")
+        .unwrap()
+        .get_slot("template prototype")
+}
+
+fn new_template(agent: &Agent, args: &[Value]) -> Result<Value, Value> {
+    let source = match args.get(0) {
+        Some(Value::String(s)) => s,
+        _ => return Err(Value::new_error(agent, "template must be a string")),
+    };
+    let escape = match args.get(1) {
+        None | Some(Value::Null) => true,
+        Some(options) => match options.get(agent, ObjectKey::from("escape"))? {
+            Value::Null => true,
+            Value::Boolean(b) => b,
+            _ => return Err(Value::new_error(agent, "escape must be a boolean")),
+        },
+    };
+    let template = Value::new_custom_object(prototype(agent));
+    template.set_slot("template parts", compile_parts(agent, source)?);
+    template.set_slot("template escape", Value::from(escape));
+    Ok(template)
+}
+
+fn render_template(
+    agent: &Agent,
+    template: &Value,
+    data: Value,
+    partials: Value,
+) -> Result<Value, Value> {
+    let mut renderer = Renderer {
+        agent,
+        partials,
+        escape: template.get_slot("template escape") == Value::from(true),
+        depth: 0,
+    };
+    let mut out = String::new();
+    renderer.render(
+        &template.get_slot("template parts"),
+        &mut vec![data],
+        &mut out,
+    )?;
+    Ok(Value::from(out))
+}
+
+/// Parse a template once, for rendering many times. With `{ escape: false }`
+/// `{{name}}` is not HTML escaped, for output that isn't HTML.
+fn compile(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    new_template(agent, &args)
+}
+
+fn render_method(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("template parts") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    let mut args = args.into_iter();
+    let data = args.next().unwrap_or(Value::Null);
+    let partials = args.next().unwrap_or(Value::Null);
+    render_template(agent, &this, data, partials)
+}
+
+/// Compile and render in one go: `render(source, data, partials, options)`.
+fn render(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = args.get(3).cloned().unwrap_or(Value::Null);
+    let template = new_template(
+        agent,
+        &[args.get(0).cloned().unwrap_or(Value::Null), options],
+    )?;
+    let data = args.get(1).cloned().unwrap_or(Value::Null);
+    let partials = args.get(2).cloned().unwrap_or(Value::Null);
+    render_template(agent, &template, data, partials)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    proto
+        .set(
+            agent,
+            ObjectKey::from("render"),
+            Value::new_builtin_function(agent, render_method),
+        )
+        .unwrap();
+
+    let compile = Value::new_builtin_function(agent, compile);
+    compile.set_slot("template prototype", proto);
+
+    let mut module = HashMap::new();
+    module.insert("compile".to_string(), compile);
+    module.insert(
+        "render".to_string(),
+        Value::new_builtin_function(agent, render),
+    );
+
+    module
+}
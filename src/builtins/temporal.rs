@@ -0,0 +1,1047 @@
+use super::tz::{self, Zone, MS_PER_DAY};
+use crate::interpreter::Context;
+use crate::value::{BuiltinFunction, ObjectKey, Value};
+use crate::Agent;
+use std::collections::HashMap;
+
+const KINDS: [&str; 4] = ["PlainDate", "PlainTime", "ZonedDateTime", "Duration"];
+
+const UNITS: [&str; 8] = [
+    "years",
+    "months",
+    "weeks",
+    "days",
+    "hours",
+    "minutes",
+    "seconds",
+    "milliseconds",
+];
+
+fn registry(agent: &Agent) -> Value {
+    agent
+        .builtin("temporal", "PlainDate")
+        .unwrap()
+        .get_slot("temporal registry")
+}
+
+fn new_value(agent: &Agent, kind: &str) -> Value {
+    let proto = registry(agent).get_slot(&format!("temporal {} prototype", kind));
+    let value = Value::new_custom_object(proto);
+    value.set_slot("temporal kind", Value::from(kind));
+    value
+}
+
+fn is_kind(value: &Value, kind: &str) -> bool {
+    value.has_slot("temporal kind") && value.get_slot("temporal kind") == Value::from(kind)
+}
+
+fn receiver(agent: &Agent, ctx: &Context, kind: &str) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if is_kind(&this, kind) {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn int_slot(value: &Value, slot: &str) -> i64 {
+    match value.get_slot(slot) {
+        Value::Number(n) => n as i64,
+        _ => unreachable!(),
+    }
+}
+
+fn integer(
+    agent: &Agent,
+    value: Option<&Value>,
+    name: &str,
+    default: Option<i64>,
+) -> Result<i64, Value> {
+    match (value, default) {
+        (None, Some(d)) | (Some(Value::Null), Some(d)) => Ok(d),
+        (Some(Value::Number(n)), _) if n.is_finite() && n.fract() == 0.0 => Ok(*n as i64),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be an integer", name),
+        )),
+    }
+}
+
+// a small reader for ISO 8601 strings
+struct Cursor<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(s: &'a str) -> Cursor<'a> {
+        Cursor {
+            s: s.as_bytes(),
+            i: 0,
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.i == self.s.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).cloned()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.i += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn digits(&mut self, n: usize) -> Option<i64> {
+        let mut value = 0;
+        for _ in 0..n {
+            match self.peek() {
+                Some(c) if c.is_ascii_digit() => value = value * 10 + i64::from(c - b'0'),
+                _ => return None,
+            }
+            self.i += 1;
+        }
+        Some(value)
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let start = self.i;
+        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            self.i += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.i])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn date(&mut self) -> Option<i64> {
+        let year = if self.eat(b'+') {
+            self.digits(6)?
+        } else if self.eat(b'-') {
+            -self.digits(6)?
+        } else {
+            self.digits(4)?
+        };
+        if !self.eat(b'-') {
+            return None;
+        }
+        let month = self.digits(2)?;
+        if !self.eat(b'-') {
+            return None;
+        }
+        let day = self.digits(2)?;
+        if month < 1 || month > 12 || day < 1 || day > tz::days_in_month(year, month) {
+            return None;
+        }
+        Some(tz::days_from_civil(year, month, day))
+    }
+
+    // milliseconds since midnight
+    fn time(&mut self) -> Option<i64> {
+        let hour = self.digits(2)?;
+        if !self.eat(b':') {
+            return None;
+        }
+        let minute = self.digits(2)?;
+        let mut ms = 0;
+        if self.eat(b':') {
+            ms += self.digits(2)? * 1000;
+            if self.eat(b'.') {
+                let start = self.i;
+                let fraction = self.number()?;
+                let len = (self.i - start) as u32;
+                ms += if len <= 3 {
+                    fraction * 10i64.pow(3 - len)
+                } else {
+                    fraction / 10i64.pow(len - 3)
+                };
+            }
+        }
+        if hour > 23 || minute > 59 || ms >= 60_000 {
+            return None;
+        }
+        Some((hour * 60 + minute) * 60_000 + ms)
+    }
+
+    // `Z` or `±hh:mm`, in seconds east of UTC
+    fn offset(&mut self) -> Option<Option<i64>> {
+        if self.eat(b'Z') || self.eat(b'z') {
+            return Some(Some(0));
+        }
+        let sign = match self.peek() {
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => return Some(None),
+        };
+        self.i += 1;
+        let hours = self.digits(2)?;
+        self.eat(b':');
+        let minutes = self.digits(2)?;
+        Some(Some(sign * (hours * 3600 + minutes * 60)))
+    }
+}
+
+fn format_date(days: i64) -> String {
+    let (year, month, day) = tz::civil_from_days(days);
+    if year >= 0 && year <= 9999 {
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    } else {
+        let sign = if year < 0 { '-' } else { '+' };
+        format!("{}{:06}-{:02}-{:02}", sign, year.abs(), month, day)
+    }
+}
+
+fn format_time(ms: i64) -> String {
+    let mut out = format!(
+        "{:02}:{:02}:{:02}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60
+    );
+    if ms % 1000 != 0 {
+        out += &format!(".{:03}", ms % 1000);
+    }
+    out
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Duration {
+    units: [i64; 8],
+}
+
+impl Duration {
+    fn sign(&self) -> i64 {
+        self.units
+            .iter()
+            .find(|&&n| n != 0)
+            .map_or(0, |n| n.signum())
+    }
+
+    fn negated(mut self) -> Duration {
+        for n in &mut self.units {
+            *n = -*n;
+        }
+        self
+    }
+
+    fn time_ms(&self) -> i64 {
+        let u = &self.units;
+        ((u[4] * 60 + u[5]) * 60 + u[6]) * 1000 + u[7]
+    }
+
+    // hours and below, for lengths of exact time
+    fn from_ms(ms: i64) -> Duration {
+        let mut d = Duration::default();
+        d.units[4] = ms / 3_600_000;
+        d.units[5] = ms / 60_000 % 60;
+        d.units[6] = ms / 1000 % 60;
+        d.units[7] = ms % 1000;
+        d
+    }
+
+    fn to_iso(&self) -> String {
+        let u = self.units.iter().map(|n| n.abs()).collect::<Vec<_>>();
+        let mut out = String::from(if self.sign() < 0 { "-P" } else { "P" });
+        for (n, unit) in u[..4].iter().zip(&["Y", "M", "W", "D"]) {
+            if *n != 0 {
+                out += &format!("{}{}", n, unit);
+            }
+        }
+        let seconds = u[6] * 1000 + u[7];
+        if u[4] != 0 || u[5] != 0 || seconds != 0 || self.sign() == 0 {
+            out.push('T');
+            if u[4] != 0 {
+                out += &format!("{}H", u[4]);
+            }
+            if u[5] != 0 {
+                out += &format!("{}M", u[5]);
+            }
+            if seconds != 0 || self.sign() == 0 {
+                out += &(seconds / 1000).to_string();
+                if seconds % 1000 != 0 {
+                    out += format!(".{:03}", seconds % 1000).trim_end_matches('0');
+                }
+                out.push('S');
+            }
+        }
+        out
+    }
+
+    fn parse(s: &str) -> Option<Duration> {
+        let mut c = Cursor::new(s);
+        let sign = if c.eat(b'-') {
+            -1
+        } else {
+            c.eat(b'+');
+            1
+        };
+        if !(c.eat(b'P') || c.eat(b'p')) {
+            return None;
+        }
+        let mut d = Duration::default();
+        let mut time = false;
+        let mut any = false;
+        while !c.done() {
+            if !time && (c.eat(b'T') || c.eat(b't')) {
+                time = true;
+                continue;
+            }
+            let n = c.number()?;
+            let unit = c.peek()?.to_ascii_uppercase();
+            c.i += 1;
+            let index = match (time, unit) {
+                (false, b'Y') => 0,
+                (false, b'M') => 1,
+                (false, b'W') => 2,
+                (false, b'D') => 3,
+                (true, b'H') => 4,
+                (true, b'M') => 5,
+                (true, b'S') => 6,
+                (true, b'.') => {
+                    // fractional seconds
+                    let start = c.i;
+                    let fraction = c.number()?;
+                    let len = (c.i - start) as u32;
+                    if !(c.eat(b'S') || c.eat(b's')) || len > 3 {
+                        return None;
+                    }
+                    d.units[6] = n;
+                    d.units[7] = fraction * 10i64.pow(3 - len);
+                    any = true;
+                    continue;
+                }
+                _ => return None,
+            };
+            d.units[index] = n;
+            any = true;
+        }
+        if any {
+            Some(if sign < 0 { d.negated() } else { d })
+        } else {
+            None
+        }
+    }
+}
+
+fn new_duration(agent: &Agent, d: Duration) -> Result<Value, Value> {
+    let sign = d.sign();
+    if d.units.iter().any(|n| n.signum() == -sign) {
+        return Err(Value::new_error(
+            agent,
+            "duration fields must all have the same sign",
+        ));
+    }
+    let value = new_value(agent, "Duration");
+    for (unit, n) in UNITS.iter().zip(&d.units) {
+        value.set_slot(unit, Value::from(*n as f64));
+    }
+    Ok(value)
+}
+
+// a Duration, an ISO 8601 string or an object of units
+fn duration_arg(agent: &Agent, value: Option<&Value>) -> Result<Duration, Value> {
+    let value = value.cloned().unwrap_or(Value::Null);
+    let mut d = Duration::default();
+    if is_kind(&value, "Duration") {
+        for (i, unit) in UNITS.iter().enumerate() {
+            d.units[i] = int_slot(&value, unit);
+        }
+        return Ok(d);
+    }
+    if let Value::String(s) = &value {
+        return Duration::parse(s)
+            .ok_or_else(|| Value::new_error(agent, &format!("invalid duration '{}'", s)));
+    }
+    if value.type_of() != "object" {
+        return Err(Value::new_error(agent, "invalid duration"));
+    }
+    for (i, unit) in UNITS.iter().enumerate() {
+        let n = value.get(agent, ObjectKey::from(*unit))?;
+        d.units[i] = integer(agent, Some(&n), unit, Some(0))?;
+    }
+    Ok(d)
+}
+
+fn new_date(agent: &Agent, days: i64) -> Value {
+    let value = new_value(agent, "PlainDate");
+    value.set_slot("days", Value::from(days as f64));
+    value
+}
+
+fn date_arg(agent: &Agent, value: Option<&Value>) -> Result<i64, Value> {
+    match value {
+        Some(v) if is_kind(v, "PlainDate") => Ok(int_slot(v, "days")),
+        Some(Value::String(s)) => {
+            let mut c = Cursor::new(s);
+            match c.date() {
+                Some(days) if c.done() => Ok(days),
+                _ => Err(Value::new_error(agent, &format!("invalid date '{}'", s))),
+            }
+        }
+        _ => Err(Value::new_error(agent, "expected a PlainDate")),
+    }
+}
+
+fn add_to_date(days: i64, d: &Duration) -> i64 {
+    let (year, month, day) = tz::civil_from_days(days);
+    let months = year * 12 + month - 1 + d.units[0] * 12 + d.units[1];
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+    // the 31st of a shorter month becomes its last day
+    let day = day.min(tz::days_in_month(year, month));
+    tz::days_from_civil(year, month, day) + d.units[2] * 7 + d.units[3] + d.time_ms() / MS_PER_DAY
+}
+
+fn new_time(agent: &Agent, ms: i64) -> Value {
+    let value = new_value(agent, "PlainTime");
+    value.set_slot("ms", Value::from(ms as f64));
+    value
+}
+
+fn time_arg(agent: &Agent, value: Option<&Value>) -> Result<i64, Value> {
+    match value {
+        Some(v) if is_kind(v, "PlainTime") => Ok(int_slot(v, "ms")),
+        Some(Value::String(s)) => {
+            let mut c = Cursor::new(s);
+            match c.time() {
+                Some(ms) if c.done() => Ok(ms),
+                _ => Err(Value::new_error(agent, &format!("invalid time '{}'", s))),
+            }
+        }
+        _ => Err(Value::new_error(agent, "expected a PlainTime")),
+    }
+}
+
+fn zone_arg(agent: &Agent, value: Option<&Value>) -> Result<Zone, Value> {
+    match value {
+        None | Some(Value::Null) => Ok(tz::zone("UTC").unwrap()),
+        Some(Value::String(name)) => tz::zone(name)
+            .ok_or_else(|| Value::new_error(agent, &format!("unknown time zone '{}'", name))),
+        _ => Err(Value::new_error(agent, "time zone must be a string")),
+    }
+}
+
+fn new_zoned(agent: &Agent, epoch_ms: i64, zone: &Zone) -> Value {
+    let value = new_value(agent, "ZonedDateTime");
+    value.set_slot("epoch", Value::from(epoch_ms as f64));
+    value.set_slot("zone", Value::from(zone.name.as_str()));
+    value
+}
+
+fn zoned_parts(agent: &Agent, value: &Value) -> Result<(i64, Zone), Value> {
+    let zone = zone_arg(agent, Some(&value.get_slot("zone")))?;
+    Ok((int_slot(value, "epoch"), zone))
+}
+
+// `2020-01-01T12:00[Europe/Paris]`, with an optional offset before the zone
+fn parse_zoned(s: &str) -> Option<(i64, Zone)> {
+    let mut c = Cursor::new(s);
+    let days = c.date()?;
+    let time = if c.eat(b'T') || c.eat(b't') || c.eat(b' ') {
+        c.time()?
+    } else {
+        0
+    };
+    let offset = c.offset()?;
+    let zone = if c.eat(b'[') {
+        let start = c.i;
+        while c.peek().map_or(false, |ch| ch != b']') {
+            c.i += 1;
+        }
+        let name = std::str::from_utf8(&c.s[start..c.i]).ok()?;
+        if !c.eat(b']') {
+            return None;
+        }
+        tz::zone(name)?
+    } else {
+        tz::zone(&tz::format_offset(offset?))?
+    };
+    if !c.done() {
+        return None;
+    }
+    let local = days * MS_PER_DAY + time;
+    let epoch = match offset {
+        Some(offset) => local - offset * 1000,
+        None => zone.epoch_ms_of_local(local),
+    };
+    Some((epoch, zone))
+}
+
+fn zoned_arg(agent: &Agent, value: Option<&Value>) -> Result<(i64, Zone), Value> {
+    match value {
+        Some(v) if is_kind(v, "ZonedDateTime") => zoned_parts(agent, v),
+        Some(Value::String(s)) => parse_zoned(s)
+            .ok_or_else(|| Value::new_error(agent, &format!("invalid date and time '{}'", s))),
+        _ => Err(Value::new_error(agent, "expected a ZonedDateTime")),
+    }
+}
+
+fn add_to_zoned(epoch_ms: i64, zone: &Zone, d: &Duration) -> i64 {
+    // calendar units move the wall clock, the rest is exact time
+    let local = zone.local_ms(epoch_ms);
+    let mut calendar = *d;
+    for n in &mut calendar.units[4..] {
+        *n = 0;
+    }
+    let days = add_to_date(local.div_euclid(MS_PER_DAY), &calendar);
+    zone.epoch_ms_of_local(days * MS_PER_DAY + local.rem_euclid(MS_PER_DAY)) + d.time_ms()
+}
+
+fn compare_numbers(a: i64, b: i64) -> Value {
+    Value::from(a.cmp(&b) as i32 as f64)
+}
+
+fn field_name(ctx: &Context) -> String {
+    match ctx.function.as_ref().unwrap().get_slot("temporal field") {
+        Value::String(s) => s.to_string(),
+        _ => unreachable!(),
+    }
+}
+
+fn date_fields(days: i64, field: &str) -> Value {
+    let (year, month, day) = tz::civil_from_days(days);
+    let n = match field {
+        "year" => year,
+        "month" => month,
+        "day" => day,
+        "dayOfWeek" => tz::day_of_week(days),
+        "dayOfYear" => days - tz::days_from_civil(year, 1, 1) + 1,
+        "daysInMonth" => tz::days_in_month(year, month),
+        "inLeapYear" => return Value::from(tz::is_leap_year(year)),
+        _ => unreachable!(),
+    };
+    Value::from(n as f64)
+}
+
+fn time_fields(ms: i64, field: &str) -> Value {
+    let n = match field {
+        "hour" => ms / 3_600_000,
+        "minute" => ms / 60_000 % 60,
+        "second" => ms / 1000 % 60,
+        "millisecond" => ms % 1000,
+        _ => unreachable!(),
+    };
+    Value::from(n as f64)
+}
+
+fn date_get(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "PlainDate")?;
+    Ok(date_fields(int_slot(&this, "days"), &field_name(ctx)))
+}
+
+fn plain_date(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let year = integer(agent, args.get(0), "year", None)?;
+    let month = integer(agent, args.get(1), "month", None)?;
+    let day = integer(agent, args.get(2), "day", None)?;
+    if month < 1 || month > 12 || day < 1 || day > tz::days_in_month(year, month) {
+        return Err(Value::new_error(agent, "invalid date"));
+    }
+    Ok(new_date(agent, tz::days_from_civil(year, month, day)))
+}
+
+fn date_from(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(new_date(agent, date_arg(agent, args.get(0))?))
+}
+
+fn date_compare(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let a = date_arg(agent, args.get(0))?;
+    let b = date_arg(agent, args.get(1))?;
+    Ok(compare_numbers(a, b))
+}
+
+fn date_add(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let days = int_slot(&receiver(agent, ctx, "PlainDate")?, "days");
+    let d = duration_arg(agent, args.get(0))?;
+    Ok(new_date(agent, add_to_date(days, &d)))
+}
+
+fn date_subtract(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let days = int_slot(&receiver(agent, ctx, "PlainDate")?, "days");
+    let d = duration_arg(agent, args.get(0))?;
+    Ok(new_date(agent, add_to_date(days, &d.negated())))
+}
+
+fn date_until(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let days = int_slot(&receiver(agent, ctx, "PlainDate")?, "days");
+    let other = date_arg(agent, args.get(0))?;
+    let mut d = Duration::default();
+    d.units[3] = other - days;
+    new_duration(agent, d)
+}
+
+fn date_equals(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let days = int_slot(&receiver(agent, ctx, "PlainDate")?, "days");
+    Ok(Value::from(days == date_arg(agent, args.get(0))?))
+}
+
+fn date_to_zoned(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let days = int_slot(&receiver(agent, ctx, "PlainDate")?, "days");
+    let zone = zone_arg(agent, args.get(0))?;
+    let time = match args.get(1) {
+        None | Some(Value::Null) => 0,
+        time => time_arg(agent, time)?,
+    };
+    let epoch = zone.epoch_ms_of_local(days * MS_PER_DAY + time);
+    Ok(new_zoned(agent, epoch, &zone))
+}
+
+fn date_to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let days = int_slot(&receiver(agent, ctx, "PlainDate")?, "days");
+    Ok(Value::from(format_date(days)))
+}
+
+fn time_get(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "PlainTime")?;
+    Ok(time_fields(int_slot(&this, "ms"), &field_name(ctx)))
+}
+
+fn plain_time(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let hour = integer(agent, args.get(0), "hour", None)?;
+    let minute = integer(agent, args.get(1), "minute", Some(0))?;
+    let second = integer(agent, args.get(2), "second", Some(0))?;
+    let ms = integer(agent, args.get(3), "millisecond", Some(0))?;
+    if hour < 0
+        || hour > 23
+        || minute < 0
+        || minute > 59
+        || second < 0
+        || second > 59
+        || ms < 0
+        || ms > 999
+    {
+        return Err(Value::new_error(agent, "invalid time"));
+    }
+    Ok(new_time(
+        agent,
+        ((hour * 60 + minute) * 60 + second) * 1000 + ms,
+    ))
+}
+
+fn time_from(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(new_time(agent, time_arg(agent, args.get(0))?))
+}
+
+fn time_compare(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let a = time_arg(agent, args.get(0))?;
+    let b = time_arg(agent, args.get(1))?;
+    Ok(compare_numbers(a, b))
+}
+
+// times wrap around midnight
+fn time_add(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = int_slot(&receiver(agent, ctx, "PlainTime")?, "ms");
+    let d = duration_arg(agent, args.get(0))?;
+    Ok(new_time(agent, (ms + d.time_ms()).rem_euclid(MS_PER_DAY)))
+}
+
+fn time_subtract(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = int_slot(&receiver(agent, ctx, "PlainTime")?, "ms");
+    let d = duration_arg(agent, args.get(0))?;
+    Ok(new_time(agent, (ms - d.time_ms()).rem_euclid(MS_PER_DAY)))
+}
+
+fn time_until(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = int_slot(&receiver(agent, ctx, "PlainTime")?, "ms");
+    let other = time_arg(agent, args.get(0))?;
+    new_duration(agent, Duration::from_ms(other - ms))
+}
+
+fn time_equals(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = int_slot(&receiver(agent, ctx, "PlainTime")?, "ms");
+    Ok(Value::from(ms == time_arg(agent, args.get(0))?))
+}
+
+fn time_to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = int_slot(&receiver(agent, ctx, "PlainTime")?, "ms");
+    Ok(Value::from(format_time(ms)))
+}
+
+fn zoned_get(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "ZonedDateTime")?;
+    let (epoch, zone) = zoned_parts(agent, &this)?;
+    let local = zone.local_ms(epoch);
+    let field = field_name(ctx);
+    Ok(match field.as_str() {
+        "epochMilliseconds" => Value::from(epoch as f64),
+        "timeZone" => Value::from(zone.name.as_str()),
+        "offset" => Value::from(tz::format_offset(zone.offset_at(epoch).0)),
+        "hour" | "minute" | "second" | "millisecond" => {
+            time_fields(local.rem_euclid(MS_PER_DAY), &field)
+        }
+        field => date_fields(local.div_euclid(MS_PER_DAY), field),
+    })
+}
+
+fn zoned_date_time(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let epoch = integer(agent, args.get(0), "epochMilliseconds", None)?;
+    let zone = zone_arg(agent, args.get(1))?;
+    Ok(new_zoned(agent, epoch, &zone))
+}
+
+fn zoned_from(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_arg(agent, args.get(0))?;
+    Ok(new_zoned(agent, epoch, &zone))
+}
+
+fn zoned_compare(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (a, _) = zoned_arg(agent, args.get(0))?;
+    let (b, _) = zoned_arg(agent, args.get(1))?;
+    Ok(compare_numbers(a, b))
+}
+
+fn zoned_add(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    let d = duration_arg(agent, args.get(0))?;
+    Ok(new_zoned(agent, add_to_zoned(epoch, &zone, &d), &zone))
+}
+
+fn zoned_subtract(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    let d = duration_arg(agent, args.get(0))?;
+    Ok(new_zoned(
+        agent,
+        add_to_zoned(epoch, &zone, &d.negated()),
+        &zone,
+    ))
+}
+
+fn zoned_until(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, _) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    let (other, _) = zoned_arg(agent, args.get(0))?;
+    new_duration(agent, Duration::from_ms(other - epoch))
+}
+
+fn zoned_equals(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    let (other, other_zone) = zoned_arg(agent, args.get(0))?;
+    Ok(Value::from(epoch == other && zone == other_zone))
+}
+
+fn zoned_with_time_zone(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, _) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    let zone = zone_arg(agent, args.get(0))?;
+    Ok(new_zoned(agent, epoch, &zone))
+}
+
+fn zoned_to_plain_date(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    Ok(new_date(agent, zone.local_ms(epoch).div_euclid(MS_PER_DAY)))
+}
+
+fn zoned_to_plain_time(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    Ok(new_time(agent, zone.local_ms(epoch).rem_euclid(MS_PER_DAY)))
+}
+
+fn zoned_to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (epoch, zone) = zoned_parts(agent, &receiver(agent, ctx, "ZonedDateTime")?)?;
+    let local = zone.local_ms(epoch);
+    Ok(Value::from(format!(
+        "{}T{}{}[{}]",
+        format_date(local.div_euclid(MS_PER_DAY)),
+        format_time(local.rem_euclid(MS_PER_DAY)),
+        tz::format_offset(zone.offset_at(epoch).0),
+        zone.name
+    )))
+}
+
+fn now(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let zone = zone_arg(agent, args.get(0))?;
+    let epoch = crate::builtins::timers::now_ms(agent) as i64;
+    Ok(new_zoned(agent, epoch, &zone))
+}
+
+fn duration_get(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "Duration")?;
+    match field_name(ctx).as_str() {
+        "sign" => Ok(Value::from(duration_arg(agent, Some(&this))?.sign() as f64)),
+        field => Ok(this.get_slot(field)),
+    }
+}
+
+fn duration(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let d = duration_arg(agent, args.get(0))?;
+    new_duration(agent, d)
+}
+
+fn duration_negated(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "Duration")?;
+    new_duration(agent, duration_arg(agent, Some(&this))?.negated())
+}
+
+fn duration_total(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "Duration")?;
+    let d = duration_arg(agent, Some(&this))?;
+    let total = duration_length(agent, &d)?;
+    let unit_ms = match args.get(0) {
+        Some(Value::String(unit)) => match unit.as_str() {
+            "weeks" => 7 * MS_PER_DAY,
+            "days" => MS_PER_DAY,
+            "hours" => 3_600_000,
+            "minutes" => 60_000,
+            "seconds" => 1000,
+            "milliseconds" => 1,
+            _ => return Err(Value::new_error(agent, &format!("invalid unit '{}'", unit))),
+        },
+        _ => return Err(Value::new_error(agent, "unit must be a string")),
+    };
+    Ok(Value::from(total as f64 / unit_ms as f64))
+}
+
+fn duration_to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, "Duration")?;
+    Ok(Value::from(duration_arg(agent, Some(&this))?.to_iso()))
+}
+
+fn duration_length(agent: &Agent, d: &Duration) -> Result<i64, Value> {
+    if d.units[0] != 0 || d.units[1] != 0 {
+        return Err(Value::new_error(
+            agent,
+            "years and months have no fixed length",
+        ));
+    }
+    Ok((d.units[2] * 7 + d.units[3]) * MS_PER_DAY + d.time_ms())
+}
+
+// `a + b`, unit by unit. when that mixes signs, as PT1H + -PT30M does,
+// exact time is rebalanced instead
+fn duration_plus(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let a = duration_arg(agent, Some(&receiver(agent, ctx, "Duration")?))?;
+    let b = duration_arg(agent, args.get(0))?;
+    let mut sum = a;
+    for (n, m) in sum.units.iter_mut().zip(&b.units) {
+        *n += m;
+    }
+    let sign = sum.sign();
+    if sum.units.iter().any(|n| n.signum() == -sign) && sum.units[..4] == [0; 4] {
+        sum = Duration::from_ms(sum.time_ms());
+    }
+    new_duration(agent, sum)
+}
+
+fn duration_minus(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let b = duration_arg(agent, args.get(0))?.negated();
+    duration_plus(agent, vec![new_duration(agent, b)?], ctx)
+}
+
+// durations of the same length are equal, so PT90M == PT1H30M
+fn duration_equals(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let a = duration_arg(agent, Some(&receiver(agent, ctx, "Duration")?))?;
+    let b = match args.get(0) {
+        Some(other) if is_kind(other, "Duration") => duration_arg(agent, Some(other))?,
+        _ => return Ok(Value::from(false)),
+    };
+    let equal = match (duration_length(agent, &a), duration_length(agent, &b)) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => a == b,
+    };
+    Ok(Value::from(equal))
+}
+
+fn duration_order(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let a = duration_arg(agent, Some(&receiver(agent, ctx, "Duration")?))?;
+    let b = duration_arg(agent, args.get(0))?;
+    Ok(compare_numbers(
+        duration_length(agent, &a)?,
+        duration_length(agent, &b)?,
+    ))
+}
+
+// `:compare` for the types with a static compare
+fn order_by(
+    agent: &Agent,
+    args: Vec<Value>,
+    ctx: &Context,
+    kind: &str,
+    compare: BuiltinFunction,
+) -> Result<Value, Value> {
+    let this = receiver(agent, ctx, kind)?;
+    let other = args.get(0).cloned().unwrap_or(Value::Null);
+    compare(agent, vec![this, other], ctx)
+}
+
+fn date_order(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    order_by(agent, args, ctx, "PlainDate", date_compare)
+}
+
+fn time_order(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    order_by(agent, args, ctx, "PlainTime", time_compare)
+}
+
+fn zoned_order(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    order_by(agent, args, ctx, "ZonedDateTime", zoned_compare)
+}
+
+// methods operators call as well, see `operator_method` in the
+// interpreter. names starting with `:` are only symbols
+const OPERATORS: &[(&str, &str)] = &[
+    ("toString", "toString"),
+    ("add", "add"),
+    ("subtract", "sub"),
+    ("equals", "equals"),
+    ("negated", "negate"),
+];
+
+fn prototype(
+    agent: &Agent,
+    getter: BuiltinFunction,
+    fields: &[&str],
+    methods: &[(&str, BuiltinFunction)],
+) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for field in fields {
+        let get = Value::new_builtin_function(agent, getter);
+        get.set_slot("temporal field", Value::from(*field));
+        proto.define_accessor(ObjectKey::from(*field), true, get);
+    }
+    for (name, f) in methods {
+        let f = Value::new_builtin_function(agent, *f);
+        if let Some(symbol) = name.strip_prefix(':') {
+            proto
+                .set(agent, ObjectKey::well_known_symbol(symbol), f)
+                .unwrap();
+            continue;
+        }
+        if let Some((_, symbol)) = OPERATORS.iter().find(|(method, _)| method == name) {
+            proto
+                .set(agent, ObjectKey::well_known_symbol(symbol), f.clone())
+                .unwrap();
+        }
+        proto.set(agent, ObjectKey::from(*name), f).unwrap();
+    }
+    proto
+}
+
+fn constructor(agent: &Agent, f: BuiltinFunction, statics: &[(&str, BuiltinFunction)]) -> Value {
+    let f = Value::new_builtin_function(agent, f);
+    for (name, s) in statics {
+        f.set(
+            agent,
+            ObjectKey::from(*name),
+            Value::new_builtin_function(agent, *s),
+        )
+        .unwrap();
+    }
+    f
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let date_fields = &[
+        "year",
+        "month",
+        "day",
+        "dayOfWeek",
+        "dayOfYear",
+        "daysInMonth",
+        "inLeapYear",
+    ];
+    let time_fields = &["hour", "minute", "second", "millisecond"];
+    let mut zoned_fields = vec!["epochMilliseconds", "timeZone", "offset"];
+    zoned_fields.extend(&date_fields[..4]);
+    zoned_fields.extend(time_fields);
+    let mut duration_fields = UNITS.to_vec();
+    duration_fields.push("sign");
+
+    let registry = Value::new_custom_object(Value::Null);
+    let prototypes = [
+        prototype(
+            agent,
+            date_get,
+            date_fields,
+            &[
+                ("add", date_add),
+                ("subtract", date_subtract),
+                ("until", date_until),
+                ("equals", date_equals),
+                ("toZonedDateTime", date_to_zoned),
+                ("toString", date_to_string),
+                (":compare", date_order),
+            ],
+        ),
+        prototype(
+            agent,
+            time_get,
+            time_fields,
+            &[
+                ("add", time_add),
+                ("subtract", time_subtract),
+                ("until", time_until),
+                ("equals", time_equals),
+                ("toString", time_to_string),
+                (":compare", time_order),
+            ],
+        ),
+        prototype(
+            agent,
+            zoned_get,
+            &zoned_fields,
+            &[
+                ("add", zoned_add),
+                ("subtract", zoned_subtract),
+                ("until", zoned_until),
+                ("equals", zoned_equals),
+                ("withTimeZone", zoned_with_time_zone),
+                ("toPlainDate", zoned_to_plain_date),
+                ("toPlainTime", zoned_to_plain_time),
+                ("toString", zoned_to_string),
+                (":compare", zoned_order),
+            ],
+        ),
+        prototype(
+            agent,
+            duration_get,
+            &duration_fields,
+            &[
+                ("negated", duration_negated),
+                ("total", duration_total),
+                ("toString", duration_to_string),
+                (":add", duration_plus),
+                (":sub", duration_minus),
+                (":equals", duration_equals),
+                (":compare", duration_order),
+            ],
+        ),
+    ];
+    for (kind, proto) in KINDS.iter().zip(prototypes.iter()) {
+        registry.set_slot(&format!("temporal {} prototype", kind), proto.clone());
+    }
+
+    let plain_date = constructor(
+        agent,
+        plain_date,
+        &[("from", date_from), ("compare", date_compare)],
+    );
+    plain_date.set_slot("temporal registry", registry);
+
+    let mut module = HashMap::new();
+    module.insert("PlainDate".to_string(), plain_date);
+    module.insert(
+        "PlainTime".to_string(),
+        constructor(
+            agent,
+            plain_time,
+            &[("from", time_from), ("compare", time_compare)],
+        ),
+    );
+    module.insert(
+        "ZonedDateTime".to_string(),
+        constructor(
+            agent,
+            zoned_date_time,
+            &[("from", zoned_from), ("compare", zoned_compare)],
+        ),
+    );
+    module.insert(
+        "Duration".to_string(),
+        constructor(agent, duration, &[("from", duration)]),
+    );
+    module.insert("now".to_string(), Value::new_builtin_function(agent, now));
+
+    module
+}
@@ -0,0 +1,143 @@
+use crate::builtins::timers::{now_ms, FakeClock};
+use crate::interpreter::Context;
+#[cfg(feature = "os")]
+use crate::value::ObjectKey;
+use crate::{Agent, Value};
+use std::collections::HashMap;
+
+// timers only fire as far as a loop of them is allowed to go
+const MAX_TIMERS: usize = 100_000;
+
+// switch timers and the clock `temporal.now` reads to a fake one, starting
+// at the given ms since the epoch or now
+fn use_fake_timers(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let now = match args.get(0).unwrap_or(&Value::Null) {
+        Value::Null => now_ms(agent),
+        Value::Number(n) if n.is_finite() => *n,
+        _ => return Err(Value::new_error(agent, "now must be a number of ms")),
+    };
+    *agent.fake_clock.borrow_mut() = Some(FakeClock::new(now));
+    Ok(Value::Null)
+}
+
+// timers still pending on the fake clock are dropped
+fn use_real_timers(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    *agent.fake_clock.borrow_mut() = None;
+    Ok(Value::Null)
+}
+
+// fire timers in the order they are due, including ones they set, and
+// return how many did
+fn advance(agent: &Agent, until: Option<f64>) -> Result<Value, Value> {
+    let mut fired = 0;
+    loop {
+        let callback = match &mut *agent.fake_clock.borrow_mut() {
+            Some(clock) => clock.next_due(until),
+            None => return Err(Value::new_error(agent, "fake timers are not in use")),
+        };
+        let callback = match callback {
+            Some(callback) => callback,
+            None => break,
+        };
+        if fired == MAX_TIMERS {
+            return Err(Value::new_error(
+                agent,
+                &format!("stopped after {} timers, they keep setting more", fired),
+            ));
+        }
+        callback.call(agent, Value::Null, vec![])?;
+        fired += 1;
+    }
+    if let (Some(until), Some(clock)) = (until, &mut *agent.fake_clock.borrow_mut()) {
+        clock.now = clock.now.max(until);
+    }
+    Ok(Value::from(fired as f64))
+}
+
+fn advance_timers(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let ms = match args.get(0) {
+        Some(Value::Number(ms)) if *ms >= 0.0 && ms.is_finite() => *ms,
+        _ => return Err(Value::new_error(agent, "ms must be a number")),
+    };
+    let now = match &*agent.fake_clock.borrow() {
+        Some(clock) => clock.now,
+        None => return Err(Value::new_error(agent, "fake timers are not in use")),
+    };
+    advance(agent, Some(now + ms))
+}
+
+fn run_all_timers(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    advance(agent, None)
+}
+
+fn pending_timers(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match &*agent.fake_clock.borrow() {
+        Some(clock) => Ok(Value::from(clock.pending() as f64)),
+        None => Err(Value::new_error(agent, "fake timers are not in use")),
+    }
+}
+
+// switch standard:fs to files kept in memory, optionally starting with an
+// object of paths and contents
+#[cfg(feature = "os")]
+fn use_memory_fs(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let mut fs = crate::builtins::fs::MemoryFs::default();
+    let files = args.get(0).unwrap_or(&Value::Null);
+    if files.type_of() == "object" {
+        let now = now_ms(agent);
+        for key in files.keys(agent)? {
+            match files.get(agent, key.clone())? {
+                Value::String(contents) => fs.write(&key.to_string(), contents.to_string(), now),
+                _ => return Err(Value::new_error(agent, "file contents must be strings")),
+            }
+        }
+    } else if *files != Value::Null {
+        return Err(Value::new_error(agent, "files must be an object"));
+    }
+    *agent.memory_fs.borrow_mut() = Some(fs);
+    Ok(Value::Null)
+}
+
+#[cfg(feature = "os")]
+fn use_real_fs(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    *agent.memory_fs.borrow_mut() = None;
+    Ok(Value::Null)
+}
+
+// the files in memory, as an object of paths and contents
+#[cfg(feature = "os")]
+fn memory_files(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    match &*agent.memory_fs.borrow() {
+        Some(fs) => {
+            for (path, contents) in fs.files() {
+                o.set(agent, ObjectKey::from(path), Value::from(contents))?;
+            }
+        }
+        None => return Err(Value::new_error(agent, "the memory fs is not in use")),
+    }
+    Ok(o)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("useFakeTimers", use_fake_timers);
+    method!("useRealTimers", use_real_timers);
+    method!("advanceTimers", advance_timers);
+    method!("runAllTimers", run_all_timers);
+    method!("pendingTimers", pending_timers);
+    #[cfg(feature = "os")]
+    {
+        method!("useMemoryFs", use_memory_fs);
+        method!("useRealFs", use_real_fs);
+        method!("memoryFiles", memory_files);
+    }
+
+    module
+}
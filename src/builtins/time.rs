@@ -0,0 +1,318 @@
+use super::tz::{self, Zone, MS_PER_DAY};
+use crate::interpreter::Context;
+use crate::{Agent, Value};
+use std::collections::HashMap;
+
+const DEFAULT_PATTERN: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+fn zone_arg(agent: &Agent, value: Option<&Value>) -> Result<Zone, Value> {
+    match value {
+        None | Some(Value::Null) => Ok(tz::zone("UTC").unwrap()),
+        Some(Value::String(name)) => tz::zone(name)
+            .ok_or_else(|| Value::new_error(agent, &format!("unknown time zone '{}'", name))),
+        _ => Err(Value::new_error(agent, "time zone must be a string")),
+    }
+}
+
+fn pattern_arg(agent: &Agent, value: Option<&Value>) -> Result<String, Value> {
+    match value {
+        None | Some(Value::Null) => Ok(DEFAULT_PATTERN.to_string()),
+        Some(Value::String(s)) => Ok(s.to_string()),
+        _ => Err(Value::new_error(agent, "pattern must be a string")),
+    }
+}
+
+/// zones() lists the zone names this build accepts, sorted. They are only
+/// the common zones built into slither, not the whole IANA database, see
+/// builtins/tz.rs.
+fn zones(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let names = tz::zone_names().into_iter().map(Value::from).collect();
+    Ok(Value::new_array_from_vec(agent, names))
+}
+
+/// Render `epoch_ms` in `zone` with strftime-style directives.
+pub fn format(epoch_ms: i64, pattern: &str, zone: &Zone) -> Result<String, String> {
+    let (offset, abbreviation) = zone.offset_at(epoch_ms);
+    let local = epoch_ms + offset * 1000;
+    let days = local.div_euclid(MS_PER_DAY);
+    let ms = local.rem_euclid(MS_PER_DAY);
+    let (year, month, day) = tz::civil_from_days(days);
+    let weekday = WEEKDAYS[tz::day_of_week(days) as usize - 1];
+
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out += &format!("{:04}", year),
+            Some('m') => out += &format!("{:02}", month),
+            Some('d') => out += &format!("{:02}", day),
+            Some('H') => out += &format!("{:02}", ms / 3_600_000),
+            Some('M') => out += &format!("{:02}", ms / 60_000 % 60),
+            Some('S') => out += &format!("{:02}", ms / 1000 % 60),
+            Some('L') => out += &format!("{:03}", ms % 1000),
+            Some('j') => {
+                out += &format!("{:03}", days - tz::days_from_civil(year, 1, 1) + 1);
+            }
+            Some('a') => out += &weekday[..3],
+            Some('A') => out += weekday,
+            Some('b') => out += &MONTHS[month as usize - 1][..3],
+            Some('B') => out += MONTHS[month as usize - 1],
+            Some('z') => out += &tz::format_offset(offset).replace(':', ""),
+            Some(':') if chars.next() == Some('z') => out += &tz::format_offset(offset),
+            Some('Z') => out += abbreviation,
+            Some('s') => out += &epoch_ms.div_euclid(1000).to_string(),
+            Some('%') => out.push('%'),
+            Some(c) => return Err(format!("unknown directive %{}", c)),
+            None => return Err("pattern ends with %".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+struct Fields {
+    year: i64,
+    month: i64,
+    day: i64,
+    ms: i64,
+    offset: Option<i64>,
+    epoch_seconds: Option<i64>,
+}
+
+struct Reader<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn eat(&mut self, c: u8) -> bool {
+        if self.s.get(self.i) == Some(&c) {
+            self.i += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // up to `max` digits, at least one
+    fn number(&mut self, max: usize) -> Option<i64> {
+        let start = self.i;
+        while self.i - start < max && self.s.get(self.i).map_or(false, u8::is_ascii_digit) {
+            self.i += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.i])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn letters(&mut self) -> &'a str {
+        let start = self.i;
+        while self.s.get(self.i).map_or(false, u8::is_ascii_alphabetic) {
+            self.i += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.i]).unwrap()
+    }
+
+    // a full or three letter name, as an index into `names`
+    fn name(&mut self, names: &[&str]) -> Option<i64> {
+        let word = self.letters();
+        names
+            .iter()
+            .position(|n| {
+                n.eq_ignore_ascii_case(word)
+                    || (word.len() == 3 && n[..3].eq_ignore_ascii_case(word))
+            })
+            .map(|i| i as i64)
+    }
+
+    fn offset(&mut self) -> Option<i64> {
+        if self.eat(b'Z') {
+            return Some(0);
+        }
+        let sign = if self.eat(b'-') {
+            -1
+        } else if self.eat(b'+') {
+            1
+        } else {
+            return None;
+        };
+        let hours = self.number(2)?;
+        self.eat(b':');
+        let minutes = self.number(2)?;
+        Some(sign * (hours * 3600 + minutes * 60))
+    }
+}
+
+fn read(text: &str, pattern: &str) -> Option<Fields> {
+    let mut fields = Fields {
+        year: 1970,
+        month: 1,
+        day: 1,
+        ms: 0,
+        offset: None,
+        epoch_seconds: None,
+    };
+    let mut r = Reader {
+        s: text.as_bytes(),
+        i: 0,
+    };
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                if !r.eat(b) {
+                    return None;
+                }
+            }
+            continue;
+        }
+        match chars.next()? {
+            'Y' => {
+                let negative = r.eat(b'-');
+                let year = r.number(6)?;
+                fields.year = if negative { -year } else { year };
+            }
+            'm' => fields.month = r.number(2)?,
+            'd' => fields.day = r.number(2)?,
+            'H' => hour = r.number(2)?,
+            'M' => minute = r.number(2)?,
+            'S' => second = r.number(2)?,
+            'L' => fields.ms = r.number(3)?,
+            'a' | 'A' => {
+                r.name(&WEEKDAYS)?;
+            }
+            'b' | 'B' => fields.month = r.name(&MONTHS)? + 1,
+            'z' => fields.offset = Some(r.offset()?),
+            ':' if chars.next()? == 'z' => fields.offset = Some(r.offset()?),
+            // abbreviations are ambiguous, the zone decides the offset
+            'Z' => {
+                r.letters();
+            }
+            's' => fields.epoch_seconds = Some(r.number(12)?),
+            '%' => {
+                if !r.eat(b'%') {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    if r.i != r.s.len()
+        || fields.month < 1
+        || fields.month > 12
+        || fields.day < 1
+        || fields.day > tz::days_in_month(fields.year, fields.month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    fields.ms += ((hour * 60 + minute) * 60 + second) * 1000;
+    Some(fields)
+}
+
+/// Read a timestamp written as `pattern` describes, in milliseconds since
+/// the epoch. Without an offset in the text it is a wall-clock time in
+/// `zone`.
+pub fn parse(text: &str, pattern: &str, zone: &Zone) -> Option<i64> {
+    let fields = read(text, pattern)?;
+    if let Some(seconds) = fields.epoch_seconds {
+        return Some(seconds * 1000);
+    }
+    let local = tz::days_from_civil(fields.year, fields.month, fields.day) * MS_PER_DAY + fields.ms;
+    Some(match fields.offset {
+        Some(offset) => local - offset * 1000,
+        None => zone.epoch_ms_of_local(local),
+    })
+}
+
+fn format_builtin(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let epoch_ms = match args.get(0) {
+        Some(Value::Number(n)) if n.is_finite() => n.floor() as i64,
+        _ => return Err(Value::new_error(agent, "timestamp must be a number")),
+    };
+    let pattern = pattern_arg(agent, args.get(1))?;
+    let zone = zone_arg(agent, args.get(2))?;
+    format(epoch_ms, &pattern, &zone)
+        .map(Value::from)
+        .map_err(|e| Value::new_error(agent, &e))
+}
+
+fn parse_builtin(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let text = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "text must be a string")),
+    };
+    let pattern = pattern_arg(agent, args.get(1))?;
+    let zone = zone_arg(agent, args.get(2))?;
+    match parse(&text, &pattern, &zone) {
+        Some(epoch_ms) => Ok(Value::from(epoch_ms as f64)),
+        None => Err(Value::new_error(
+            agent,
+            &format!("'{}' does not match '{}'", text, pattern),
+        )),
+    }
+}
+
+fn offset(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let zone = zone_arg(agent, args.get(0))?;
+    let epoch_ms = match args.get(1) {
+        Some(Value::Number(n)) if n.is_finite() => n.floor() as i64,
+        _ => return Err(Value::new_error(agent, "timestamp must be a number")),
+    };
+    Ok(Value::from(tz::format_offset(zone.offset_at(epoch_ms).0)))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "zones".to_string(),
+        Value::new_builtin_function(agent, zones),
+    );
+    module.insert(
+        "format".to_string(),
+        Value::new_builtin_function(agent, format_builtin),
+    );
+    module.insert(
+        "parse".to_string(),
+        Value::new_builtin_function(agent, parse_builtin),
+    );
+    module.insert(
+        "offset".to_string(),
+        Value::new_builtin_function(agent, offset),
+    );
+
+    module
+}
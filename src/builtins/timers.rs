@@ -1,19 +1,27 @@
-use crate::agent::{Agent, MioMapType};
+use crate::agent::{Agent, MioMapType, Token};
 use crate::interpreter::Context;
+#[cfg(feature = "os")]
 use crate::linked_list::LinkedList;
-use crate::value::Value;
+use crate::value::{ObjectKey, Value};
+#[cfg(feature = "os")]
 use lazy_static::lazy_static;
+#[cfg(feature = "os")]
 use mio::{PollOpt, Ready, Registration, SetReadiness};
 use num::ToPrimitive;
 use std::collections::HashMap;
+#[cfg(feature = "os")]
 use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+#[cfg(feature = "os")]
+use std::time::Instant;
 
+#[cfg(feature = "os")]
 struct TimerList {
     instant: Instant,
     timers: LinkedList<SetReadiness>,
 }
 
+#[cfg(feature = "os")]
 impl TimerList {
     fn new(instant: Instant, timer: SetReadiness) -> Self {
         let mut timers = LinkedList::new();
@@ -22,24 +30,36 @@ impl TimerList {
     }
 }
 
+#[cfg(feature = "os")]
 lazy_static! {
     static ref TIMERS: Mutex<LinkedList<TimerList>> = Mutex::new(LinkedList::new());
     static ref THREAD: std::thread::JoinHandle<()> = std::thread::spawn(move || loop {
         let mut timers = TIMERS.lock().unwrap();
-        if let Some(list) = timers.cursor().next() {
-            if Instant::now() >= list.instant {
-                while let Some(r) = list.timers.pop_front() {
-                    r.set_readiness(Ready::readable())
-                        .expect("failed to set timer readiness");
+        let wait = match timers.cursor().next() {
+            Some(list) => {
+                let now = Instant::now();
+                if now >= list.instant {
+                    while let Some(r) = list.timers.pop_front() {
+                        r.set_readiness(Ready::readable())
+                            .expect("failed to set timer readiness");
+                    }
+                    timers.pop_front();
+                    continue;
                 }
-                timers.pop_front();
+                Some(list.instant - now)
             }
-        } else {
-            std::thread::park();
+            None => None,
+        };
+        // insert() needs the lock to add a timer and wake us up
+        drop(timers);
+        match wait {
+            Some(duration) => std::thread::park_timeout(duration),
+            None => std::thread::park(),
         }
     });
 }
 
+#[cfg(feature = "os")]
 fn insert(instant: Instant, timer: SetReadiness) {
     let mut timers = TIMERS.lock().unwrap();
     let mut cursor = timers.cursor();
@@ -61,43 +81,237 @@ fn insert(instant: Instant, timer: SetReadiness) {
     timers.push_back(TimerList::new(instant, timer));
 }
 
-fn create_timeout(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+/// The clock timers run on once a test calls `useFakeTimers` from
+/// `standard:test`. Time only passes when the test advances it, and timers
+/// fire as it does.
+#[derive(Trace, Finalize)]
+pub struct FakeClock {
+    /// Milliseconds since the epoch.
+    pub now: f64,
+    // due time, token and callback of each timer, in the order they were set
+    timers: Vec<(f64, usize, Value)>,
+}
+
+impl FakeClock {
+    pub fn new(now: f64) -> FakeClock {
+        FakeClock {
+            now,
+            timers: Vec::new(),
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Remove the first timer due by `until`, or by the last timer with
+    /// None, and move the clock to when it is due.
+    pub fn next_due(&mut self, until: Option<f64>) -> Option<Value> {
+        let mut next: Option<usize> = None;
+        for (i, (due, _, _)) in self.timers.iter().enumerate() {
+            if until.is_some_and(|until| *due > until) {
+                continue;
+            }
+            match next {
+                // ties fire in the order they were set
+                Some(n) if self.timers[n].0 <= *due => {}
+                _ => next = Some(i),
+            }
+        }
+        let (due, _, callback) = self.timers.remove(next?);
+        self.now = self.now.max(due);
+        Some(callback)
+    }
+}
+
+/// Milliseconds since the epoch, from the fake clock if a test is using one.
+pub fn now_ms(agent: &Agent) -> f64 {
+    if let Some(clock) = &*agent.fake_clock.borrow() {
+        return clock.now;
+    }
+    agent.wall_clock_ms()
+}
+
+// timers set while a fake clock is in use only fire when it is advanced
+fn set_fake_timeout(agent: &Agent, callback: &Value, duration: Duration) -> Option<Token> {
+    if agent.fake_clock.borrow().is_none() {
+        return None;
+    }
+    let callback = with_storage(agent, callback.clone());
+    let token = agent.mio_token();
+    let mut clock = agent.fake_clock.borrow_mut();
+    let clock = clock.as_mut().unwrap();
+    let due = clock.now + duration.as_secs_f64() * 1000.0;
+    clock.timers.push((due, token.0, callback));
+    Some(token)
+}
+
+fn clear_fake_timeout(agent: &Agent, token: Token) -> bool {
+    match &mut *agent.fake_clock.borrow_mut() {
+        Some(clock) => {
+            let before = clock.timers.len();
+            clock.timers.retain(|(_, t, _)| *t != token.0);
+            clock.timers.len() != before
+        }
+        None => false,
+    }
+}
+
+fn call_with_storage(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.as_ref().unwrap();
+    let callback = f.get_slot("timer callback");
+    agent.with_async_storage(f.get_slot("async storage"), || {
+        callback.call(agent, Value::Null, args)
+    })
+}
+
+// the callback sees the AsyncLocalStorage stores of whoever set it
+fn with_storage(agent: &Agent, callback: Value) -> Value {
+    let storage = agent.capture_async_storage();
+    if storage == Value::new_tuple() {
+        callback
+    } else {
+        let f = Value::new_builtin_function(agent, call_with_storage);
+        f.set_slot("timer callback", callback);
+        f.set_slot("async storage", storage);
+        f
+    }
+}
+
+#[cfg(feature = "os")]
+pub fn set_timeout(agent: &Agent, callback: Value, duration: Duration) -> Token {
+    if let Some(token) = set_fake_timeout(agent, &callback, duration) {
+        return token;
+    }
+    let end = Instant::now() + duration;
+    let callback = with_storage(agent, callback);
+
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.mio_token();
+
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    agent.insert_op(token, MioMapType::Timer(registration, callback));
+
+    insert(end, set_readiness);
+    THREAD.thread().unpark();
+    token
+}
+
+/// Without the `os` feature the host keeps time.
+#[cfg(not(feature = "os"))]
+pub fn set_timeout(agent: &Agent, callback: Value, duration: Duration) -> Token {
+    if let Some(token) = set_fake_timeout(agent, &callback, duration) {
+        return token;
+    }
+    let callback = with_storage(agent, callback);
+    let token = agent.mio_token();
+    agent.insert_op(token, MioMapType::HostTimer(callback));
+    agent.host().set_timer(token.0, duration);
+    token
+}
+
+/// Drop a pending timer so it neither fires nor keeps the event loop alive.
+#[cfg(feature = "os")]
+pub fn clear_timeout(agent: &Agent, token: Token) {
+    if clear_fake_timeout(agent, token) {
+        return;
+    }
+    let mut map = agent.mio_map.borrow_mut();
+    if let Some(MioMapType::Timer(..)) = map.get(&token) {
+        map.remove(&token);
+    }
+}
+
+#[cfg(not(feature = "os"))]
+pub fn clear_timeout(agent: &Agent, token: Token) {
+    if clear_fake_timeout(agent, token) {
+        return;
+    }
+    let removed = agent.mio_map.borrow_mut().remove(&token).is_some();
+    if removed {
+        agent.host().clear_timer(token.0);
+    }
+}
+
+fn timer_token(agent: &Agent, ctx: &Context) -> Result<Token, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("timer token") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    match this.get_slot("timer token") {
+        Value::Number(n) => Ok(Token(n.to_usize().unwrap())),
+        _ => unreachable!(),
+    }
+}
+
+fn cancel(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    clear_timeout(agent, timer_token(agent, ctx)?);
+    Ok(Value::Null)
+}
+
+fn timer_ref(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    agent.ref_op(timer_token(agent, ctx)?);
+    ctx.scope.borrow().get_this(agent)
+}
+
+fn timer_unref(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    agent.unref_op(timer_token(agent, ctx)?);
+    ctx.scope.borrow().get_this(agent)
+}
+
+fn create_timeout(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     let callback = args.get(0).unwrap_or(&Value::Null);
     if callback.type_of() != "function" {
         return Err(Value::new_error(agent, "callback must be a function"));
     }
     match args.get(1).unwrap_or(&Value::Null) {
         Value::Number(n) => {
-            let end = Instant::now() + Duration::from_millis(n.to_u64().unwrap());
-
-            let (registration, set_readiness) = Registration::new2();
-            let token = agent.mio_token();
+            let token = set_timeout(
+                agent,
+                callback.clone(),
+                Duration::from_millis(n.to_u64().unwrap()),
+            );
 
-            agent
-                .mio
-                .register(&registration, token, Ready::readable(), PollOpt::edge())
-                .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::Timer(registration, callback.clone()));
-
-            insert(end, set_readiness);
-            THREAD.thread().unpark();
-
-            // TODO: return object with cancel()
-            Ok(Value::Null)
+            let proto = ctx.function.as_ref().unwrap().get_slot("timer prototype");
+            let handle = Value::new_custom_object(proto);
+            handle.set_slot("timer token", Value::from(token.0 as f64));
+            Ok(handle)
         }
         _ => Err(Value::new_error(agent, "duration must be a number")),
     }
 }
 
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    proto
+        .set(
+            agent,
+            ObjectKey::from("cancel"),
+            Value::new_builtin_function(agent, cancel),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("ref"),
+            Value::new_builtin_function(agent, timer_ref),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("unref"),
+            Value::new_builtin_function(agent, timer_unref),
+        )
+        .unwrap();
+    let create_timeout = Value::new_builtin_function(agent, create_timeout);
+    create_timeout.set_slot("timer prototype", proto);
+
     let mut module = HashMap::new();
-    module.insert(
-        "createTimeout".to_string(),
-        Value::new_builtin_function(agent, create_timeout),
-    );
+    module.insert("createTimeout".to_string(), create_timeout);
 
     module
 }
@@ -0,0 +1,691 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::HashMap;
+
+struct Parser<'a> {
+    agent: &'a Agent,
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(agent: &'a Agent, source: &str) -> Parser<'a> {
+        Parser {
+            agent,
+            chars: source.chars().collect(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(
+            self.agent,
+            &format!("toml: {} on line {}", message, self.line),
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Value> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while let Some(c) = self.peek() {
+                if c == '\n' {
+                    break;
+                }
+                self.bump();
+            }
+        }
+    }
+
+    // whitespace, comments and newlines, as allowed between array elements
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Value> {
+        self.skip_whitespace();
+        self.skip_comment();
+        self.eat('\r');
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            _ => Err(self.error("expected end of line")),
+        }
+    }
+
+    fn new_table(&self) -> Value {
+        Value::new_object(self.agent.intrinsics.object_prototype.clone())
+    }
+
+    fn own(&self, table: &Value, key: &str) -> Result<Option<Value>, Value> {
+        let key = ObjectKey::from(key);
+        if table.keys(self.agent)?.contains(&key) {
+            Ok(Some(table.get(self.agent, key)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // walk a dotted key from `table`, creating intermediate tables and
+    // stepping into the last element of arrays of tables
+    fn descend(&self, mut table: Value, path: &[String]) -> Result<Value, Value> {
+        for key in path {
+            table = match self.own(&table, key)? {
+                Some(v) => {
+                    if is_table(&v) {
+                        v
+                    } else if let Some(last) = last_table(&v) {
+                        last
+                    } else {
+                        return Err(self.error(&format!("'{}' is not a table", key)));
+                    }
+                }
+                None => {
+                    let t = self.new_table();
+                    table.set(self.agent, ObjectKey::from(key.as_str()), t.clone())?;
+                    t
+                }
+            };
+        }
+        Ok(table)
+    }
+
+    fn parse_key(&mut self) -> Result<Vec<String>, Value> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let part = match self.peek() {
+                Some('"') => {
+                    self.bump();
+                    self.parse_basic_string(false)?
+                }
+                Some('\'') => {
+                    self.bump();
+                    self.parse_literal_string(false)?
+                }
+                _ => {
+                    let mut part = String::new();
+                    while let Some(c) = self.peek() {
+                        match c {
+                            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' => {
+                                part.push(c);
+                                self.bump();
+                            }
+                            _ => break,
+                        }
+                    }
+                    if part.is_empty() {
+                        return Err(self.error("expected a key"));
+                    }
+                    part
+                }
+            };
+            path.push(part);
+            self.skip_whitespace();
+            if !self.eat('.') {
+                break;
+            }
+        }
+        Ok(path)
+    }
+
+    fn parse_key_value(&mut self, table: &Value) -> Result<(), Value> {
+        let path = self.parse_key()?;
+        self.expect('=')?;
+        self.skip_whitespace();
+        let value = self.parse_value()?;
+        let (last, prefix) = path.split_last().unwrap();
+        let target = self.descend(table.clone(), prefix)?;
+        if self.own(&target, last)?.is_some() {
+            return Err(self.error(&format!("duplicate key '{}'", last)));
+        }
+        target.set(self.agent, ObjectKey::from(last.as_str()), value)?;
+        Ok(())
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Value> {
+        let c = match self.bump() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(c @ 'u') | Some(c @ 'U') => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let mut code = 0;
+                for _ in 0..len {
+                    match self.bump().and_then(|c| c.to_digit(16)) {
+                        Some(d) => code = code * 16 + d,
+                        None => return Err(self.error("invalid unicode escape")),
+                    }
+                }
+                match std::char::from_u32(code) {
+                    Some(c) => c,
+                    None => return Err(self.error("invalid unicode escape")),
+                }
+            }
+            _ => return Err(self.error("invalid escape sequence")),
+        };
+        Ok(c)
+    }
+
+    fn parse_basic_string(&mut self, multiline: bool) -> Result<String, Value> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => {
+                    if !multiline {
+                        break;
+                    }
+                    if self.peek() == Some('"') && self.peek_at(1) == Some('"') {
+                        self.pos += 2;
+                        // up to two quotes may directly precede the delimiter
+                        while self.eat('"') {
+                            s.push('"');
+                        }
+                        break;
+                    }
+                    s.push('"');
+                }
+                Some('\\') => {
+                    if multiline {
+                        if let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+                            // line ending backslash trims all following whitespace
+                            self.skip_whitespace_and_newlines();
+                            continue;
+                        }
+                    }
+                    s.push(self.parse_escape()?);
+                }
+                Some('\n') if !multiline => return Err(self.error("unterminated string")),
+                Some(c) => s.push(c),
+            }
+        }
+        Ok(s)
+    }
+
+    fn skip_whitespace_and_newlines(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+            self.bump();
+        }
+    }
+
+    fn parse_literal_string(&mut self, multiline: bool) -> Result<String, Value> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('\'') => {
+                    if !multiline {
+                        break;
+                    }
+                    if self.peek() == Some('\'') && self.peek_at(1) == Some('\'') {
+                        self.pos += 2;
+                        while self.eat('\'') {
+                            s.push('\'');
+                        }
+                        break;
+                    }
+                    s.push('\'');
+                }
+                Some('\n') if !multiline => return Err(self.error("unterminated string")),
+                Some(c) => s.push(c),
+            }
+        }
+        Ok(s)
+    }
+
+    fn parse_string(&mut self, quote: char) -> Result<String, Value> {
+        self.bump();
+        let multiline = self.peek() == Some(quote) && self.peek_at(1) == Some(quote);
+        if multiline {
+            self.pos += 2;
+            // a newline right after the opening delimiter is trimmed
+            self.eat('\r');
+            self.eat('\n');
+        }
+        if quote == '"' {
+            self.parse_basic_string(multiline)
+        } else {
+            self.parse_literal_string(multiline)
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Value> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia();
+            if self.eat(']') {
+                break;
+            }
+            items.push(self.parse_value()?);
+            self.skip_trivia();
+            if !self.eat(',') {
+                self.skip_trivia();
+                self.expect(']')?;
+                break;
+            }
+        }
+        Ok(Value::new_array_from_vec(self.agent, items))
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, Value> {
+        self.bump();
+        let table = self.new_table();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(table);
+        }
+        loop {
+            self.parse_key_value(&table)?;
+            self.skip_whitespace();
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        Ok(table)
+    }
+
+    fn parse_atom(&mut self) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' | '+' | '.' | ':' => {
+                    s.push(c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        s
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Value> {
+        match self.peek() {
+            Some(c @ '"') | Some(c @ '\'') => Ok(Value::from(self.parse_string(c)?)),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            Some(_) => {
+                let mut atom = self.parse_atom();
+                if atom.is_empty() {
+                    return Err(self.error("expected a value"));
+                }
+                match atom.as_str() {
+                    "true" => return Ok(Value::from(true)),
+                    "false" => return Ok(Value::from(false)),
+                    _ => {}
+                }
+                if is_date(&atom) {
+                    // `1979-05-27 07:32:00` may separate date and time with a space,
+                    // normalize it to the `T` form
+                    if self.peek() == Some(' ')
+                        && self.peek_at(1).map_or(false, |c| c.is_ascii_digit())
+                    {
+                        self.bump();
+                        atom.push('T');
+                        atom += &self.parse_atom();
+                    }
+                    return Ok(Value::from(atom));
+                }
+                if atom.len() > 2 && atom.as_bytes()[2] == b':' {
+                    // local time
+                    return Ok(Value::from(atom));
+                }
+                match parse_number(&atom) {
+                    Some(n) => Ok(Value::from(n)),
+                    None => Err(self.error(&format!("invalid value '{}'", atom))),
+                }
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse(&mut self) -> Result<Value, Value> {
+        let root = self.new_table();
+        let mut current = root.clone();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    self.bump();
+                    let array = self.eat('[');
+                    let path = self.parse_key()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    let (last, prefix) = path.split_last().unwrap();
+                    let parent = self.descend(root.clone(), prefix)?;
+                    current = if array {
+                        let list = match self.own(&parent, last)? {
+                            Some(list) => list,
+                            None => {
+                                let list = Value::new_array(self.agent);
+                                parent.set(
+                                    self.agent,
+                                    ObjectKey::from(last.as_str()),
+                                    list.clone(),
+                                )?;
+                                list
+                            }
+                        };
+                        let table = self.new_table();
+                        match &list {
+                            Value::Object(o) => match &o.kind {
+                                ObjectKind::Array(values) => {
+                                    values.borrow_mut().push(table.clone())
+                                }
+                                _ => return Err(self.error(&format!("'{}' is not an array", last))),
+                            },
+                            _ => return Err(self.error(&format!("'{}' is not an array", last))),
+                        }
+                        table
+                    } else {
+                        self.descend(parent, std::slice::from_ref(last))?
+                    };
+                }
+                Some(_) => self.parse_key_value(&current)?,
+            }
+            self.end_of_line()?;
+        }
+        Ok(root)
+    }
+}
+
+fn is_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 10 && b[4] == b'-' && b[7] == b'-' && b[..4].iter().all(u8::is_ascii_digit)
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    let s = s.replace('_', "");
+    let (negative, digits) = if s.starts_with('-') {
+        (true, &s[1..])
+    } else if s.starts_with('+') {
+        (false, &s[1..])
+    } else {
+        (false, &s[..])
+    };
+    let n = match digits {
+        "inf" => std::f64::INFINITY,
+        "nan" => std::f64::NAN,
+        _ if digits.starts_with("0x") => i64::from_str_radix(&digits[2..], 16).ok()? as f64,
+        _ if digits.starts_with("0o") => i64::from_str_radix(&digits[2..], 8).ok()? as f64,
+        _ if digits.starts_with("0b") => i64::from_str_radix(&digits[2..], 2).ok()? as f64,
+        _ => {
+            if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse::<f64>().ok()?
+        }
+    };
+    Some(if negative { -n } else { n })
+}
+
+fn is_table(value: &Value) -> bool {
+    match value {
+        Value::Record(..) => true,
+        Value::Object(o) => match o.kind {
+            ObjectKind::Ordinary | ObjectKind::Custom(..) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn last_table(value: &Value) -> Option<Value> {
+    if let Value::Object(o) = value {
+        if let ObjectKind::Array(values) = &o.kind {
+            return values.borrow().last().filter(is_table);
+        }
+    }
+    None
+}
+
+fn is_array_of_tables(value: &Value) -> bool {
+    if let Value::Object(o) = value {
+        if let ObjectKind::Array(values) = &o.kind {
+            let values = values.borrow();
+            return !values.is_empty() && values.iter().all(|v| is_table(&v));
+        }
+    }
+    false
+}
+
+fn string_keys(agent: &Agent, value: &Value) -> Result<Vec<String>, Value> {
+    Ok(value
+        .keys(agent)?
+        .into_iter()
+        .filter_map(|k| match k {
+            ObjectKey::Symbol(..) => None,
+            k => Some(k.to_string()),
+        })
+        .collect())
+}
+
+fn write_key(out: &mut String, key: &str) {
+    let bare = !key.is_empty()
+        && key.chars().all(|c| match c {
+            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' => true,
+            _ => false,
+        });
+    if bare {
+        out.push_str(key);
+    } else {
+        super::json::write_string(out, key);
+    }
+}
+
+fn write_inline(agent: &Agent, out: &mut String, value: &Value) -> Result<(), Value> {
+    match value {
+        Value::Null => return Err(Value::new_error(agent, "toml: cannot represent null")),
+        Value::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => {
+            if n.is_nan() {
+                out.push_str("nan");
+            } else if n.is_infinite() {
+                out.push_str(if *n > 0.0 { "inf" } else { "-inf" });
+            } else {
+                out.push_str(&crate::num_util::to_string(*n));
+            }
+        }
+        Value::String(s) => super::json::write_string(out, s),
+        Value::Tuple(items) => write_inline_array(agent, out, items)?,
+        Value::Record(..) => write_inline_table(agent, out, value)?,
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => {
+                let values = values.borrow().to_vec();
+                write_inline_array(agent, out, &values)?;
+            }
+            _ if is_table(value) => write_inline_table(agent, out, value)?,
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    &format!("toml: cannot represent a {}", value.type_of()),
+                ))
+            }
+        },
+        Value::Symbol(..) => {
+            return Err(Value::new_error(agent, "toml: cannot represent a symbol"))
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn write_inline_table(agent: &Agent, out: &mut String, table: &Value) -> Result<(), Value> {
+    out.push('{');
+    for (i, key) in string_keys(agent, table)?.iter().enumerate() {
+        out.push_str(if i == 0 { " " } else { ", " });
+        write_key(out, key);
+        out.push_str(" = ");
+        write_inline(
+            agent,
+            out,
+            &table.get(agent, ObjectKey::from(key.as_str()))?,
+        )?;
+    }
+    out.push_str(" }");
+    Ok(())
+}
+
+fn write_inline_array(agent: &Agent, out: &mut String, items: &[Value]) -> Result<(), Value> {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_inline(agent, out, item)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+fn write_header(out: &mut String, path: &[String], array: bool) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(if array { "[[" } else { "[" });
+    for (i, key) in path.iter().enumerate() {
+        if i > 0 {
+            out.push('.');
+        }
+        write_key(out, key);
+    }
+    out.push_str(if array { "]]\n" } else { "]\n" });
+}
+
+fn write_table(
+    agent: &Agent,
+    out: &mut String,
+    path: &mut Vec<String>,
+    table: &Value,
+) -> Result<(), Value> {
+    let keys = string_keys(agent, table)?;
+    let mut nested = Vec::new();
+    for key in keys {
+        let value = table.get(agent, ObjectKey::from(key.as_str()))?;
+        if is_table(&value) || is_array_of_tables(&value) {
+            nested.push((key, value));
+        } else {
+            write_key(out, &key);
+            out.push_str(" = ");
+            write_inline(agent, out, &value)?;
+            out.push('\n');
+        }
+    }
+    for (key, value) in nested {
+        path.push(key);
+        if is_table(&value) {
+            write_header(out, path, false);
+            write_table(agent, out, path, &value)?;
+        } else if let Value::Object(o) = &value {
+            if let ObjectKind::Array(values) = &o.kind {
+                let values = values.borrow().to_vec();
+                for t in values {
+                    write_header(out, path, true);
+                    write_table(agent, out, path, &t)?;
+                }
+            }
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+pub fn stringify(agent: &Agent, value: &Value) -> Result<String, Value> {
+    if !is_table(value) {
+        return Err(Value::new_error(
+            agent,
+            "toml: top level value must be a table",
+        ));
+    }
+    let mut out = String::new();
+    write_table(agent, &mut out, &mut Vec::new(), value)?;
+    Ok(out)
+}
+
+fn parse(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) => Parser::new(agent, s).parse(),
+        _ => Err(Value::new_error(agent, "source must be a string")),
+    }
+}
+
+fn stringify_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    Ok(Value::from(stringify(agent, value)?))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("parse", parse);
+    method!("stringify", stringify_fn);
+
+    module
+}
@@ -0,0 +1,571 @@
+use crate::agent::Agent;
+use crate::builtins::crypto::to_hex;
+use crate::builtins::fs::{spawn_untraced, FsResponse};
+use crate::builtins::http::{self, Proxy, Redirect, Request};
+use crate::builtins::json::write_string;
+use crate::builtins::timers::set_timeout;
+use crate::interpreter::Context;
+use crate::intrinsics::promise::promise_resolve_i;
+use crate::intrinsics::url::Url;
+use crate::value::{ObjectKey, Value};
+use gc::{Gc, GcCell};
+use rand::RngCore;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// ended spans are exported once this many pile up, or after FLUSH_DELAY
+const BATCH_SIZE: usize = 64;
+const FLUSH_DELAY: Duration = Duration::from_millis(1000);
+
+fn tracer(agent: &Agent) -> Value {
+    agent
+        .builtin("trace", "startSpan")
+        .unwrap()
+        .get_slot("trace state")
+}
+
+fn enabled(state: &Value) -> bool {
+    state.get_slot("trace endpoint") != Value::Null || state.get_slot("trace file") != Value::Null
+}
+
+fn now() -> Value {
+    let d = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Value::from(d.as_nanos().to_string())
+}
+
+fn random_id(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn new_span(agent: &Agent, state: &Value, name: String, parent: &Value) -> Result<Value, Value> {
+    let span = Value::new_custom_object(state.get_slot("trace span prototype"));
+    let (trace_id, parent_id) = if parent.has_slot("span id") {
+        (parent.get_slot("span trace id"), parent.get_slot("span id"))
+    } else {
+        (Value::from(random_id(16)), Value::Null)
+    };
+    let span_id = Value::from(random_id(8));
+    span.set_slot("span name", Value::from(name.clone()));
+    span.set_slot("span trace id", trace_id.clone());
+    span.set_slot("span id", span_id.clone());
+    span.set_slot("span parent id", parent_id);
+    span.set_slot("span start", now());
+    span.set_slot("span end", Value::Null);
+    span.set_slot("span status", Value::Null);
+    span.set_slot(
+        "span attributes",
+        Value::List(Gc::new(GcCell::new(VecDeque::new()))),
+    );
+    span.set(agent, ObjectKey::from("name"), Value::from(name))?;
+    span.set(agent, ObjectKey::from("traceId"), trace_id)?;
+    span.set(agent, ObjectKey::from("spanId"), span_id)?;
+    Ok(span)
+}
+
+fn set_attribute_i(span: &Value, key: String, value: Value) {
+    if let Value::List(attributes) = span.get_slot("span attributes") {
+        let mut attributes = attributes.borrow_mut();
+        attributes.retain(|entry| match entry {
+            Value::Tuple(pair) => pair[0] != Value::from(key.clone()),
+            _ => unreachable!(),
+        });
+        attributes.push_back(Value::Tuple(Box::new(vec![Value::from(key), value])));
+    }
+}
+
+fn set_error(agent: &Agent, span: &Value, error: &Value) {
+    let message = match error {
+        Value::String(s) => s.to_string(),
+        e => match e.get(agent, ObjectKey::from("message")) {
+            Ok(Value::String(s)) => s.to_string(),
+            _ => Value::inspect(agent, e),
+        },
+    };
+    span.set_slot(
+        "span status",
+        Value::Tuple(Box::new(vec![Value::from("error"), Value::from(message)])),
+    );
+}
+
+fn end_span(agent: &Agent, span: &Value) -> Result<(), Value> {
+    if span.get_slot("span end") != Value::Null {
+        return Ok(());
+    }
+    span.set_slot("span end", now());
+    let state = tracer(agent);
+    if !enabled(&state) {
+        return Ok(());
+    }
+    let size = match state.get_slot("trace batch") {
+        Value::List(batch) => {
+            batch.borrow_mut().push_back(span.clone());
+            batch.borrow().len()
+        }
+        _ => unreachable!(),
+    };
+    if size >= BATCH_SIZE {
+        let promise = export(agent, &state)?;
+        ignore(agent, promise)?;
+    } else if state.get_slot("trace flush scheduled") == Value::from(false) {
+        state.set_slot("trace flush scheduled", Value::from(true));
+        let f = Value::new_builtin_function(agent, scheduled_flush);
+        // a pending export shouldn't hold the process open by itself
+        agent.unref_op(set_timeout(agent, f, FLUSH_DELAY));
+    }
+    Ok(())
+}
+
+/// Start a span around a builtin operation, or return null when nothing is
+/// being exported.
+pub fn start_builtin_span(agent: &Agent, name: &str, attributes: Vec<(&str, Value)>) -> Value {
+    let state = tracer(agent);
+    if !enabled(&state) {
+        return Value::Null;
+    }
+    let parent = agent.async_store(&state);
+    let span = new_span(agent, &state, name.to_string(), &parent).unwrap();
+    for (key, value) in attributes {
+        set_attribute_i(&span, key.to_string(), value);
+    }
+    span
+}
+
+/// Set an attribute on a span from `start_builtin_span`.
+pub fn set_builtin_attribute(span: &Value, key: &str, value: Value) {
+    if *span != Value::Null {
+        set_attribute_i(span, key.to_string(), value);
+    }
+}
+
+/// End a span from `start_builtin_span`, marking it failed if there was an
+/// error.
+pub fn end_builtin_span(agent: &Agent, span: &Value, error: Option<&Value>) {
+    if *span == Value::Null {
+        return;
+    }
+    if let Some(e) = error {
+        set_error(agent, span, e);
+    }
+    end_span(agent, span).unwrap();
+}
+
+fn write_attribute_value(agent: &Agent, out: &mut String, value: &Value) {
+    match value {
+        Value::Boolean(b) => out.push_str(&format!("{{\"boolValue\":{}}}", b)),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 => {
+            out.push_str(&format!("{{\"intValue\":\"{}\"}}", *n as i64))
+        }
+        Value::Number(n) if n.is_finite() => out.push_str(&format!("{{\"doubleValue\":{}}}", n)),
+        Value::String(s) => {
+            out.push_str("{\"stringValue\":");
+            write_string(out, s);
+            out.push('}');
+        }
+        v => {
+            out.push_str("{\"stringValue\":");
+            write_string(out, &Value::inspect(agent, v));
+            out.push('}');
+        }
+    }
+}
+
+fn write_attributes(agent: &Agent, out: &mut String, attributes: &[Value]) {
+    out.push('[');
+    for (i, entry) in attributes.iter().enumerate() {
+        if let Value::Tuple(pair) = entry {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"key\":");
+            if let Value::String(key) = &pair[0] {
+                write_string(out, key);
+            }
+            out.push_str(",\"value\":");
+            write_attribute_value(agent, out, &pair[1]);
+            out.push('}');
+        }
+    }
+    out.push(']');
+}
+
+fn string_slot(value: &Value, slot: &str) -> String {
+    match value.get_slot(slot) {
+        Value::String(s) => s.to_string(),
+        _ => String::new(),
+    }
+}
+
+// https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding
+fn to_otlp(agent: &Agent, state: &Value, spans: &[Value]) -> String {
+    let mut out = String::new();
+    out.push_str("{\"resourceSpans\":[{\"resource\":{\"attributes\":");
+    let service = Value::Tuple(Box::new(vec![
+        Value::from("service.name"),
+        state.get_slot("trace service"),
+    ]));
+    write_attributes(agent, &mut out, &[service]);
+    out.push_str("},\"scopeSpans\":[{\"scope\":{\"name\":\"slither\"},\"spans\":[");
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"traceId\":");
+        write_string(&mut out, &string_slot(span, "span trace id"));
+        out.push_str(",\"spanId\":");
+        write_string(&mut out, &string_slot(span, "span id"));
+        if let Value::String(parent) = span.get_slot("span parent id") {
+            out.push_str(",\"parentSpanId\":");
+            write_string(&mut out, &parent);
+        }
+        out.push_str(",\"name\":");
+        write_string(&mut out, &string_slot(span, "span name"));
+        out.push_str(",\"kind\":1,\"startTimeUnixNano\":");
+        write_string(&mut out, &string_slot(span, "span start"));
+        out.push_str(",\"endTimeUnixNano\":");
+        write_string(&mut out, &string_slot(span, "span end"));
+        out.push_str(",\"attributes\":");
+        if let Value::List(attributes) = span.get_slot("span attributes") {
+            let attributes = attributes.borrow().iter().cloned().collect::<Vec<_>>();
+            write_attributes(agent, &mut out, &attributes);
+        }
+        match span.get_slot("span status") {
+            Value::Tuple(status) => {
+                if status[0] == Value::from("ok") {
+                    out.push_str(",\"status\":{\"code\":1}");
+                } else {
+                    out.push_str(",\"status\":{\"code\":2,\"message\":");
+                    if let Value::String(message) = &status[1] {
+                        write_string(&mut out, message);
+                    }
+                    out.push('}');
+                }
+            }
+            _ => out.push_str(",\"status\":{}"),
+        }
+        out.push('}');
+    }
+    out.push_str("]}]}]}");
+    out
+}
+
+fn resolved(agent: &Agent) -> Result<Value, Value> {
+    promise_resolve_i(agent, agent.intrinsics.promise.clone(), Value::Null)
+}
+
+fn noop(_: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::Null)
+}
+
+fn ignore(agent: &Agent, promise: Value) -> Result<Value, Value> {
+    let f = Value::new_builtin_function(agent, noop);
+    promise
+        .get(agent, ObjectKey::from("then"))?
+        .call(agent, promise, vec![f.clone(), f])
+}
+
+// spans are written and posted for whoever made them, which needs the same
+// grant as doing it with standard:fs or standard:http
+fn check_output(
+    agent: &Agent,
+    capability: &str,
+    operation: &str,
+    destination: &Value,
+) -> Result<(), Value> {
+    let f = Value::new_builtin_function(agent, noop);
+    f.set_slot("capability", Value::from(capability));
+    f.set_slot("operation", Value::from(operation));
+    agent.check_capability(&f, std::slice::from_ref(destination))
+}
+
+fn check_outputs(agent: &Agent, state: &Value, operation: &str) -> Result<(), Value> {
+    let file = state.get_slot("trace file");
+    if file != Value::Null {
+        check_output(agent, "fs", operation, &file)?;
+    }
+    let endpoint = state.get_slot("trace endpoint");
+    if endpoint != Value::Null {
+        check_output(agent, "http", operation, &endpoint)?;
+    }
+    Ok(())
+}
+
+// sends everything batched so far, settling once it has been written
+fn export(agent: &Agent, state: &Value) -> Result<Value, Value> {
+    check_outputs(agent, state, "trace.export")?;
+    let spans = match state.get_slot("trace batch") {
+        Value::List(batch) => batch.borrow_mut().drain(..).collect::<Vec<_>>(),
+        _ => unreachable!(),
+    };
+    if spans.is_empty() {
+        return resolved(agent);
+    }
+    let body = to_otlp(agent, state, &spans);
+    let endpoint = state.get_slot("trace endpoint");
+
+    match state.get_slot("trace file") {
+        Value::String(path) => {
+            let path = path.to_string();
+            let line = format!("{}\n", body);
+            // one write per batch, so appends from the pool don't interleave
+            let written = spawn_untraced(agent, move || {
+                let written = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(line.as_bytes()));
+                match written {
+                    Ok(()) => FsResponse::Success,
+                    Err(e) => FsResponse::Error(e.to_string()),
+                }
+            })?;
+            if endpoint == Value::Null {
+                return Ok(written);
+            }
+            let then_post = Value::new_builtin_function(agent, post_written);
+            then_post.set_slot("trace endpoint", endpoint);
+            then_post.set_slot("trace body", Value::from(body));
+            written
+                .get(agent, ObjectKey::from("then"))?
+                .call(agent, written, vec![then_post])
+        }
+        _ => post(agent, &endpoint, body),
+    }
+}
+
+fn post(agent: &Agent, endpoint: &Value, body: String) -> Result<Value, Value> {
+    if let Value::String(endpoint) = endpoint {
+        let url = match Url::parse(endpoint, None) {
+            Ok(url) => url,
+            Err(e) => return Err(Value::new_error(agent, &e)),
+        };
+        let request = Request {
+            method: "POST".to_string(),
+            url,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into_bytes(),
+            timeout: Some(Duration::from_secs(10)),
+            redirect: Redirect::Follow,
+            keep_alive: true,
+            cookie_jar: None,
+            proxy: Proxy::Environment,
+            integrity: None,
+        };
+        let promise = http::spawn(agent, request, Value::Null)?;
+        let f = Value::new_builtin_function(agent, noop);
+        return promise
+            .get(agent, ObjectKey::from("then"))?
+            .call(agent, promise, vec![f]);
+    }
+
+    resolved(agent)
+}
+
+fn post_written(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.as_ref().unwrap();
+    match f.get_slot("trace body") {
+        Value::String(body) => post(agent, &f.get_slot("trace endpoint"), body.to_string()),
+        _ => unreachable!(),
+    }
+}
+
+fn scheduled_flush(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let state = tracer(agent);
+    state.set_slot("trace flush scheduled", Value::from(false));
+    let promise = export(agent, &state)?;
+    ignore(agent, promise)?;
+    Ok(Value::Null)
+}
+
+fn get_span(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("span id") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    Ok(this)
+}
+
+fn set_attribute(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_span(agent, ctx)?;
+    match args.get(0) {
+        Some(Value::String(key)) => {
+            let value = args.get(1).cloned().unwrap_or(Value::Null);
+            set_attribute_i(&this, key.to_string(), value);
+            Ok(this)
+        }
+        _ => Err(Value::new_error(agent, "key must be a string")),
+    }
+}
+
+fn set_status(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_span(agent, ctx)?;
+    match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) if s.as_str() == "ok" => {
+            this.set_slot(
+                "span status",
+                Value::Tuple(Box::new(vec![Value::from("ok"), Value::Null])),
+            );
+        }
+        Value::String(s) if s.as_str() == "error" => {
+            let message = args.get(1).cloned().unwrap_or(Value::from(""));
+            set_error(agent, &this, &message);
+        }
+        _ => return Err(Value::new_error(agent, "status must be 'ok' or 'error'")),
+    }
+    Ok(this)
+}
+
+fn end(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = get_span(agent, ctx)?;
+    end_span(agent, &this)?;
+    Ok(Value::Null)
+}
+
+fn configure(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let options = args.get(0).unwrap_or(&Value::Null);
+    if options.type_of() != "object" {
+        return Err(Value::new_error(agent, "options must be an object"));
+    }
+    let state = tracer(agent);
+    for (key, slot) in &[
+        ("endpoint", "trace endpoint"),
+        ("file", "trace file"),
+        ("serviceName", "trace service"),
+    ] {
+        match options.get(agent, ObjectKey::from(*key))? {
+            Value::Null => {}
+            v @ Value::String(..) => state.set_slot(slot, v),
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    &format!("{} must be a string", key),
+                ))
+            }
+        }
+    }
+    check_outputs(agent, &state, "trace.configure")?;
+    Ok(Value::Null)
+}
+
+fn start_span(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let name = match args.get(0) {
+        Some(Value::String(name)) => name.to_string(),
+        _ => return Err(Value::new_error(agent, "name must be a string")),
+    };
+    let state = tracer(agent);
+    let options = args.get(1).unwrap_or(&Value::Null);
+    let (parent, attributes) = if options.type_of() == "object" {
+        (
+            options.get(agent, ObjectKey::from("parent"))?,
+            options.get(agent, ObjectKey::from("attributes"))?,
+        )
+    } else {
+        (Value::Null, Value::Null)
+    };
+    if parent != Value::Null && !parent.has_slot("span id") {
+        return Err(Value::new_error(agent, "parent must be a span"));
+    }
+    let parent = if parent == Value::Null {
+        agent.async_store(&state)
+    } else {
+        parent
+    };
+
+    let span = new_span(agent, &state, name, &parent)?;
+    if attributes.type_of() == "object" {
+        for key in attributes.keys(agent)? {
+            let value = attributes.get(agent, key.clone())?;
+            set_attribute_i(&span, key.to_string(), value);
+        }
+    }
+    Ok(span)
+}
+
+// runs f with span as the parent of any span started inside it, including
+// across awaits and timers
+fn with_span(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let mut args = args.into_iter();
+    let span = args.next().unwrap_or(Value::Null);
+    if !span.has_slot("span id") {
+        return Err(Value::new_error(agent, "span must be a span"));
+    }
+    let f = args.next().unwrap_or(Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "callback must be a function"));
+    }
+    agent.with_async_store(tracer(agent), span, || {
+        f.call(agent, Value::Null, args.collect())
+    })
+}
+
+fn active_span(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(agent.async_store(&tracer(agent)))
+}
+
+fn flush(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    export(agent, &tracer(agent))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    proto
+        .set(
+            agent,
+            ObjectKey::from("setAttribute"),
+            Value::new_builtin_function(agent, set_attribute),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("setStatus"),
+            Value::new_builtin_function(agent, set_status),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("end"),
+            Value::new_builtin_function(agent, end),
+        )
+        .unwrap();
+
+    let state = Value::new_custom_object(Value::Null);
+    state.set_slot("trace span prototype", proto);
+    state.set_slot("trace endpoint", Value::Null);
+    state.set_slot("trace file", Value::Null);
+    state.set_slot("trace service", Value::from("slither"));
+    state.set_slot(
+        "trace batch",
+        Value::List(Gc::new(GcCell::new(VecDeque::new()))),
+    );
+    state.set_slot("trace flush scheduled", Value::from(false));
+
+    let start_span = Value::new_builtin_function(agent, start_span);
+    start_span.set_slot("trace state", state);
+
+    let mut module = HashMap::new();
+    module.insert(
+        "configure".to_string(),
+        Value::new_builtin_function(agent, configure),
+    );
+    module.insert("startSpan".to_string(), start_span);
+    module.insert(
+        "withSpan".to_string(),
+        Value::new_builtin_function(agent, with_span),
+    );
+    module.insert(
+        "activeSpan".to_string(),
+        Value::new_builtin_function(agent, active_span),
+    );
+    module.insert(
+        "flush".to_string(),
+        Value::new_builtin_function(agent, flush),
+    );
+
+    module
+}
@@ -0,0 +1,362 @@
+//! The time zones of standard:time and standard:temporal. They come from a
+//! table built into slither, not from the system's tzdata.
+//!
+//! The table has about 60 common zones rather than the whole IANA database,
+//! and keeps only the POSIX TZ rule each zone follows today. Offsets before
+//! a zone's latest rule change are not historically accurate: Sao Paulo
+//! stopped observing daylight saving time in 2019, but January 2018 still
+//! reads as -03:00 there rather than -02:00. Other zone names are rejected,
+//! while fixed `+hh:mm` and `-hh:mm` offsets always work.
+
+const ZONES: &[(&str, &str)] = &[
+    ("Africa/Cairo", "EET-2EEST,M4.5.5/0,M10.5.4/24"),
+    ("Africa/Johannesburg", "SAST-2"),
+    ("Africa/Lagos", "WAT-1"),
+    ("Africa/Nairobi", "EAT-3"),
+    ("America/Anchorage", "AKST9AKDT,M3.2.0,M11.1.0"),
+    ("America/Argentina/Buenos_Aires", "<-03>3"),
+    ("America/Bogota", "<-05>5"),
+    ("America/Chicago", "CST6CDT,M3.2.0,M11.1.0"),
+    ("America/Denver", "MST7MDT,M3.2.0,M11.1.0"),
+    ("America/Halifax", "AST4ADT,M3.2.0,M11.1.0"),
+    ("America/Los_Angeles", "PST8PDT,M3.2.0,M11.1.0"),
+    ("America/Mexico_City", "CST6"),
+    ("America/New_York", "EST5EDT,M3.2.0,M11.1.0"),
+    ("America/Phoenix", "MST7"),
+    ("America/Sao_Paulo", "<-03>3"),
+    ("America/St_Johns", "NST3:30NDT,M3.2.0,M11.1.0"),
+    ("America/Toronto", "EST5EDT,M3.2.0,M11.1.0"),
+    ("America/Vancouver", "PST8PDT,M3.2.0,M11.1.0"),
+    ("Asia/Bangkok", "<+07>-7"),
+    ("Asia/Dhaka", "<+06>-6"),
+    ("Asia/Dubai", "<+04>-4"),
+    ("Asia/Hong_Kong", "HKT-8"),
+    ("Asia/Jakarta", "WIB-7"),
+    ("Asia/Jerusalem", "IST-2IDT,M3.4.4/26,M10.5.0"),
+    ("Asia/Kathmandu", "<+0545>-5:45"),
+    ("Asia/Kolkata", "IST-5:30"),
+    ("Asia/Seoul", "KST-9"),
+    ("Asia/Shanghai", "CST-8"),
+    ("Asia/Singapore", "<+08>-8"),
+    ("Asia/Tehran", "<+0330>-3:30"),
+    ("Asia/Tokyo", "JST-9"),
+    ("Australia/Adelaide", "ACST-9:30ACDT,M10.1.0,M4.1.0/3"),
+    ("Australia/Brisbane", "AEST-10"),
+    ("Australia/Melbourne", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+    ("Australia/Perth", "AWST-8"),
+    ("Australia/Sydney", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+    ("Etc/UTC", "UTC0"),
+    ("Europe/Amsterdam", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Athens", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Berlin", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Brussels", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Bucharest", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Copenhagen", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Dublin", "GMT0IST,M3.5.0/1,M10.5.0"),
+    ("Europe/Helsinki", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Istanbul", "<+03>-3"),
+    ("Europe/Kyiv", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Lisbon", "WET0WEST,M3.5.0/1,M10.5.0"),
+    ("Europe/London", "GMT0BST,M3.5.0/1,M10.5.0"),
+    ("Europe/Madrid", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Moscow", "MSK-3"),
+    ("Europe/Oslo", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Paris", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Prague", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Rome", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Stockholm", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Vienna", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Warsaw", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Zurich", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Pacific/Auckland", "NZST-12NZDT,M9.5.0,M4.1.0/3"),
+    ("Pacific/Honolulu", "HST10"),
+    ("UTC", "UTC0"),
+];
+
+pub const MS_PER_DAY: i64 = 86_400_000;
+
+/// Days since 1970-01-01 of a proleptic gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (
+        if month <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        month,
+        day,
+    )
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 1 is monday, like ISO 8601
+pub fn day_of_week(days: i64) -> i64 {
+    // 1970-01-01 was a thursday
+    (days + 3).rem_euclid(7) + 1
+}
+
+/// `+01:00` for an offset east of UTC in seconds.
+pub fn format_offset(offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{}{:02}:{:02}", sign, offset / 3600, offset % 3600 / 60)
+}
+
+// `Mm.w.d/time`: weekday d of week w (5 is the last) of month m, at time
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Transition {
+    month: i64,
+    week: i64,
+    weekday: i64,
+    time: i64,
+}
+
+impl Transition {
+    // in local seconds since the epoch
+    fn local_seconds(self, year: i64) -> i64 {
+        let first = days_from_civil(year, self.month, 1);
+        // days from the 1st to the first matching weekday, sunday being 0
+        let mut day = (self.weekday - day_of_week(first) % 7).rem_euclid(7) + 1;
+        day += (self.week - 1) * 7;
+        while day > days_in_month(year, self.month) {
+            day -= 7;
+        }
+        (first + day - 1) * 86400 + self.time
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    std: (String, i64),
+    dst: Option<((String, i64), Transition, Transition)>,
+}
+
+struct RuleParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> RuleParser<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek() == Some(&c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let mut n = None;
+        while let Some(d) = self.chars.peek().and_then(|c| c.to_digit(10)) {
+            self.chars.next();
+            n = Some(n.unwrap_or(0) * 10 + i64::from(d));
+        }
+        n
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let mut name = String::new();
+        if self.eat('<') {
+            while let Some(c) = self.chars.next() {
+                if c == '>' {
+                    return Some(name);
+                }
+                name.push(c);
+            }
+            return None;
+        }
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_ascii_alphabetic() {
+                break;
+            }
+            name.push(c);
+            self.chars.next();
+        }
+        if name.len() < 3 {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    // [+-]hh[:mm[:ss]] in seconds
+    fn time(&mut self) -> Option<i64> {
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        let mut seconds = self.number()? * 3600;
+        if self.eat(':') {
+            seconds += self.number()? * 60;
+            if self.eat(':') {
+                seconds += self.number()?;
+            }
+        }
+        Some(sign * seconds)
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        if !(self.eat(',') && self.eat('M')) {
+            return None;
+        }
+        let month = self.number()?;
+        self.eat('.');
+        let week = self.number()?;
+        self.eat('.');
+        let weekday = self.number()?;
+        let time = if self.eat('/') { self.time()? } else { 7200 };
+        Some(Transition {
+            month,
+            week,
+            weekday,
+            time,
+        })
+    }
+
+    // offsets in the rule count west of UTC
+    fn rule(&mut self) -> Option<Rule> {
+        let std_name = self.name()?;
+        let std_offset = -self.time()?;
+        if self.chars.peek().is_none() {
+            return Some(Rule {
+                std: (std_name, std_offset),
+                dst: None,
+            });
+        }
+        let dst_name = self.name()?;
+        let dst_offset = match self.chars.peek() {
+            Some(',') => std_offset + 3600,
+            _ => -self.time()?,
+        };
+        let start = self.transition()?;
+        let end = self.transition()?;
+        Some(Rule {
+            std: (std_name, std_offset),
+            dst: Some(((dst_name, dst_offset), start, end)),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    rule: Rule,
+}
+
+/// The names of the zones `zone` knows, sorted. This is the built in table,
+/// a small part of the IANA database.
+pub fn zone_names() -> Vec<&'static str> {
+    ZONES.iter().map(|(name, _)| *name).collect()
+}
+
+/// Look up an IANA zone, ignoring case, or a fixed `±hh:mm` offset.
+pub fn zone(name: &str) -> Option<Zone> {
+    if name.starts_with('+') || name.starts_with('-') {
+        let offset = RuleParser {
+            chars: name.chars().peekable(),
+        }
+        .time()?;
+        if offset.abs() >= 86400 {
+            return None;
+        }
+        return Some(Zone {
+            name: format_offset(offset),
+            rule: Rule {
+                std: (format_offset(offset), offset),
+                dst: None,
+            },
+        });
+    }
+    let (name, rule) = ZONES
+        .iter()
+        .find(|(zone, _)| zone.eq_ignore_ascii_case(name))?;
+    let rule = RuleParser {
+        chars: rule.chars().peekable(),
+    }
+    .rule()
+    .expect("invalid zone rule");
+    Some(Zone {
+        name: name.to_string(),
+        rule,
+    })
+}
+
+impl Zone {
+    /// The offset east of UTC in seconds and its abbreviation at `epoch_ms`.
+    pub fn offset_at(&self, epoch_ms: i64) -> (i64, &str) {
+        let (std, (dst, start, end)) = match &self.rule.dst {
+            None => return (self.rule.std.1, &self.rule.std.0),
+            Some(dst) => (&self.rule.std, dst),
+        };
+        let t = epoch_ms.div_euclid(1000);
+        let (year, ..) = civil_from_days((t + std.1).div_euclid(86400));
+        // the start is given in standard time and the end in daylight time
+        let start = start.local_seconds(year) - std.1;
+        let end = end.local_seconds(year) - dst.1;
+        let in_dst = if start < end {
+            t >= start && t < end
+        } else {
+            t < end || t >= start
+        };
+        if in_dst {
+            (dst.1, &dst.0)
+        } else {
+            (std.1, &std.0)
+        }
+    }
+
+    /// The instant of a wall-clock time. A time skipped by a transition is
+    /// read with the offset from before it, and a repeated one resolves to
+    /// the earlier instant.
+    pub fn epoch_ms_of_local(&self, local_ms: i64) -> i64 {
+        let mut offsets = vec![self.rule.std.1];
+        if let Some(((_, dst), ..)) = &self.rule.dst {
+            offsets.push(*dst);
+        }
+        let valid = offsets
+            .iter()
+            .map(|offset| local_ms - offset * 1000)
+            .filter(|&t| self.local_ms(t) == local_ms)
+            .min();
+        match valid {
+            Some(t) => t,
+            None => local_ms - offsets.iter().min().unwrap() * 1000,
+        }
+    }
+
+    /// The wall-clock time at `epoch_ms`, in milliseconds since the epoch.
+    pub fn local_ms(&self, epoch_ms: i64) -> i64 {
+        epoch_ms + self.offset_at(epoch_ms).0 * 1000
+    }
+}
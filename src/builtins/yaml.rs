@@ -0,0 +1,779 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::{HashMap, HashSet};
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+fn is_sequence_entry(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+// cuts a trailing `# comment`, ignoring `#` inside quotes or glued to a word
+fn strip_comment(s: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' if previous == ' ' || "[{,:".contains(previous) => quote = Some(c),
+                '#' if previous == ' ' || previous == '\t' => return s[..i].trim_end(),
+                _ => {}
+            },
+        }
+        previous = c;
+    }
+    s.trim_end()
+}
+
+fn resolve_plain(s: &str) -> Value {
+    match s {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::from(true),
+        "false" | "False" | "FALSE" => return Value::from(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => {
+            return Value::from(std::f64::INFINITY)
+        }
+        "-.inf" | "-.Inf" | "-.INF" => return Value::from(std::f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Value::from(std::f64::NAN),
+        _ => {}
+    }
+    let (negative, digits) = if s.starts_with('-') {
+        (true, &s[1..])
+    } else if s.starts_with('+') {
+        (false, &s[1..])
+    } else {
+        (false, s)
+    };
+    let n = if digits.starts_with("0x") {
+        i64::from_str_radix(&digits[2..], 16).ok().map(|n| n as f64)
+    } else if digits.starts_with("0o") {
+        i64::from_str_radix(&digits[2..], 8).ok().map(|n| n as f64)
+    } else {
+        let b = digits.as_bytes();
+        let numeric = !b.is_empty()
+            && (b[0].is_ascii_digit() || (b[0] == b'.' && b.len() > 1 && b[1].is_ascii_digit()));
+        if numeric {
+            digits.parse::<f64>().ok()
+        } else {
+            None
+        }
+    };
+    match n {
+        Some(n) => Value::from(if negative { -n } else { n }),
+        None => Value::from(s.to_string()),
+    }
+}
+
+struct Flow<'a> {
+    agent: &'a Agent,
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Flow<'a> {
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(
+            self.agent,
+            &format!("yaml: {} on line {}", message, self.line),
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos >= self.chars.len()
+    }
+
+    fn parse_double_quoted(&mut self) -> Result<String, Value> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += 1;
+            match c {
+                '"' => break,
+                '\\' => {
+                    let e = match self.peek() {
+                        Some(e) => e,
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    self.pos += 1;
+                    let c = match e {
+                        '0' => '\0',
+                        'a' => '\u{7}',
+                        'b' => '\u{8}',
+                        't' | '\t' => '\t',
+                        'n' => '\n',
+                        'v' => '\u{b}',
+                        'f' => '\u{c}',
+                        'r' => '\r',
+                        'e' => '\u{1b}',
+                        ' ' => ' ',
+                        '"' => '"',
+                        '/' => '/',
+                        '\\' => '\\',
+                        'N' => '\u{85}',
+                        '_' => '\u{a0}',
+                        'x' | 'u' | 'U' => {
+                            let len = match e {
+                                'x' => 2,
+                                'u' => 4,
+                                _ => 8,
+                            };
+                            let mut code = 0;
+                            for _ in 0..len {
+                                match self.peek().and_then(|c| c.to_digit(16)) {
+                                    Some(d) => code = code * 16 + d,
+                                    None => return Err(self.error("invalid escape sequence")),
+                                }
+                                self.pos += 1;
+                            }
+                            match std::char::from_u32(code) {
+                                Some(c) => c,
+                                None => return Err(self.error("invalid escape sequence")),
+                            }
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    s.push(c);
+                }
+                c => s.push(c),
+            }
+        }
+        Ok(s)
+    }
+
+    fn parse_single_quoted(&mut self) -> Result<String, Value> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some('\'') => {
+                    self.pos += 1;
+                    if self.peek() == Some('\'') {
+                        self.pos += 1;
+                        s.push('\'');
+                    } else {
+                        break;
+                    }
+                }
+                Some(c) => {
+                    self.pos += 1;
+                    s.push(c);
+                }
+            }
+        }
+        Ok(s)
+    }
+
+    fn parse_plain(&mut self, in_flow: bool) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            if in_flow {
+                match c {
+                    ',' | '[' | ']' | '{' | '}' => break,
+                    ':' => match self.chars.get(self.pos + 1) {
+                        None | Some(' ') | Some(',') | Some(']') | Some('}') => break,
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            s.push(c);
+            self.pos += 1;
+        }
+        s.trim().to_string()
+    }
+
+    fn parse_value(&mut self, in_flow: bool) -> Result<Value, Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    if self.eat(']') {
+                        break;
+                    }
+                    items.push(self.parse_value(true)?);
+                    if !self.eat(',') {
+                        if self.eat(']') {
+                            break;
+                        }
+                        return Err(self.error("expected ',' or ']'"));
+                    }
+                }
+                Ok(Value::new_array_from_vec(self.agent, items))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let map = Value::new_object(self.agent.intrinsics.object_prototype.clone());
+                loop {
+                    if self.eat('}') {
+                        break;
+                    }
+                    let key = self.parse_value(true)?.to_object_key(self.agent)?;
+                    let value = if self.eat(':') {
+                        self.parse_value(true)?
+                    } else {
+                        Value::Null
+                    };
+                    map.set(self.agent, key, value)?;
+                    if !self.eat(',') {
+                        if self.eat('}') {
+                            break;
+                        }
+                        return Err(self.error("expected ',' or '}'"));
+                    }
+                }
+                Ok(map)
+            }
+            Some('"') => Ok(Value::from(self.parse_double_quoted()?)),
+            Some('\'') => Ok(Value::from(self.parse_single_quoted()?)),
+            _ => Ok(resolve_plain(&self.parse_plain(in_flow))),
+        }
+    }
+}
+
+struct Parser<'a> {
+    agent: &'a Agent,
+    lines: Vec<String>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(agent: &'a Agent, source: &str) -> Parser<'a> {
+        Parser {
+            agent,
+            lines: source
+                .lines()
+                .map(|l| l.trim_end_matches('\r').to_string())
+                .collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(
+            self.agent,
+            &format!("yaml: {} on line {}", message, self.pos + 1),
+        )
+    }
+
+    fn flow(&self, text: &str) -> Flow<'a> {
+        Flow {
+            agent: self.agent,
+            chars: text.chars().collect(),
+            pos: 0,
+            line: self.pos,
+        }
+    }
+
+    // moves to the next line with content and returns its indentation
+    fn next_content(&mut self) -> Result<Option<usize>, Value> {
+        while self.pos < self.lines.len() {
+            let line = &self.lines[self.pos];
+            if line == "..." || line == "---" || line.starts_with("--- ") {
+                // only the first document of a stream is read
+                return Ok(None);
+            }
+            if !is_blank(line) {
+                let indent = indent_of(line);
+                if line[indent..].starts_with('\t') {
+                    return Err(self.error("tabs are not allowed for indentation"));
+                }
+                return Ok(Some(indent));
+            }
+            self.pos += 1;
+        }
+        Ok(None)
+    }
+
+    fn content(&self, indent: usize) -> String {
+        self.lines[self.pos][indent..].to_string()
+    }
+
+    fn parse(&mut self) -> Result<Value, Value> {
+        while self.pos < self.lines.len() {
+            let line = &self.lines[self.pos];
+            if is_blank(line) || line.starts_with('%') {
+                self.pos += 1;
+            } else if line == "---" {
+                self.pos += 1;
+                break;
+            } else if line.starts_with("--- ") {
+                // `--- value` starts the document on the marker line
+                self.lines[self.pos] = format!("    {}", &line[4..]);
+                break;
+            } else {
+                break;
+            }
+        }
+        let value = match self.next_content()? {
+            Some(indent) => self.parse_block(indent)?,
+            None => Value::Null,
+        };
+        if self.next_content()?.is_some() {
+            return Err(self.error("unexpected content"));
+        }
+        Ok(value)
+    }
+
+    fn parse_block(&mut self, indent: usize) -> Result<Value, Value> {
+        let content = self.content(indent);
+        if is_sequence_entry(&content) {
+            self.parse_sequence(indent)
+        } else if self.split_key(&content)?.is_some() {
+            self.parse_mapping(indent)
+        } else {
+            self.pos += 1;
+            self.parse_inline(strip_comment(&content))
+        }
+    }
+
+    fn parse_nested(&mut self, parent: usize, compact: bool) -> Result<Value, Value> {
+        match self.next_content()? {
+            Some(indent) if indent > parent => self.parse_block(indent),
+            // sequences may sit at the same indentation as their key
+            Some(indent)
+                if compact && indent == parent && is_sequence_entry(&self.content(indent)) =>
+            {
+                self.parse_sequence(indent)
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value, Value> {
+        let mut items = Vec::new();
+        while let Some(i) = self.next_content()? {
+            if i != indent || !is_sequence_entry(&self.content(indent)) {
+                break;
+            }
+            let content = self.content(indent);
+            let rest = content[1..].trim_start();
+            if is_blank(rest) {
+                self.pos += 1;
+                items.push(self.parse_nested(indent, false)?);
+            } else {
+                // reparse the entry as if it started on its own line
+                let column = indent + content.len() - rest.len();
+                self.lines[self.pos] = format!("{}{}", " ".repeat(column), rest);
+                items.push(self.parse_block(column)?);
+            }
+        }
+        Ok(Value::new_array_from_vec(self.agent, items))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value, Value> {
+        let map = Value::new_object(self.agent.intrinsics.object_prototype.clone());
+        while let Some(i) = self.next_content()? {
+            if i != indent {
+                break;
+            }
+            let content = self.content(indent);
+            let (key, rest) = match self.split_key(&content)? {
+                Some(entry) => entry,
+                None => return Err(self.error("expected a mapping key")),
+            };
+            let rest = strip_comment(&rest).trim_start().to_string();
+            self.pos += 1;
+            let value = if rest.is_empty() {
+                self.parse_nested(indent, true)?
+            } else if rest.starts_with('|') || rest.starts_with('>') {
+                self.parse_block_scalar(&rest, indent)?
+            } else {
+                self.parse_inline(&rest)?
+            };
+            map.set(self.agent, ObjectKey::from(key.as_str()), value)?;
+        }
+        Ok(map)
+    }
+
+    fn split_key(&self, content: &str) -> Result<Option<(String, String)>, Value> {
+        if content.starts_with('"') || content.starts_with('\'') {
+            let mut flow = self.flow(content);
+            let key = if content.starts_with('"') {
+                flow.parse_double_quoted()?
+            } else {
+                flow.parse_single_quoted()?
+            };
+            if flow.eat(':') {
+                let rest = flow.chars[flow.pos..].iter().collect::<String>();
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Ok(Some((key, rest)));
+                }
+            }
+            return Ok(None);
+        }
+        if content.starts_with('[') || content.starts_with('{') {
+            return Ok(None);
+        }
+        let bytes = content.as_bytes();
+        for (i, b) in bytes.iter().enumerate() {
+            match b {
+                b'#' if i > 0 && bytes[i - 1] == b' ' => return Ok(None),
+                b':' if i + 1 == bytes.len() || bytes[i + 1] == b' ' => {
+                    let key = content[..i].trim_end();
+                    if key.is_empty() {
+                        return Ok(None);
+                    }
+                    return Ok(Some((key.to_string(), content[i + 1..].to_string())));
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse_inline(&mut self, text: &str) -> Result<Value, Value> {
+        let mut text = text.to_string();
+        if text.starts_with('[') || text.starts_with('{') {
+            // flow collections may continue over several lines
+            while !balanced(&text) && self.pos < self.lines.len() {
+                text.push(' ');
+                text += strip_comment(self.lines[self.pos].trim());
+                self.pos += 1;
+            }
+        }
+        let mut flow = self.flow(&text);
+        let value = flow.parse_value(false)?;
+        if !flow.at_end() {
+            return Err(self.error("unexpected characters after value"));
+        }
+        Ok(value)
+    }
+
+    fn parse_block_scalar(&mut self, header: &str, parent: usize) -> Result<Value, Value> {
+        let folded = header.starts_with('>');
+        let chomp = if header.contains('-') {
+            '-'
+        } else if header.contains('+') {
+            '+'
+        } else {
+            ' '
+        };
+        let mut lines = Vec::new();
+        let mut block_indent = None;
+        while self.pos < self.lines.len() {
+            let line = &self.lines[self.pos];
+            if line.trim().is_empty() {
+                lines.push(String::new());
+                self.pos += 1;
+                continue;
+            }
+            let indent = indent_of(line);
+            if indent <= parent || indent < *block_indent.get_or_insert(indent) {
+                break;
+            }
+            lines.push(line[block_indent.unwrap()..].to_string());
+            self.pos += 1;
+        }
+        let mut trailing = 0;
+        while lines.last().map_or(false, String::is_empty) {
+            lines.pop();
+            trailing += 1;
+        }
+        let mut s = String::new();
+        if folded {
+            let mut previous_text = false;
+            for line in &lines {
+                if line.is_empty() {
+                    s.push('\n');
+                    previous_text = false;
+                } else if line.starts_with(' ') {
+                    // more indented lines are kept as they are
+                    if previous_text {
+                        s.push('\n');
+                    }
+                    s += line;
+                    s.push('\n');
+                    previous_text = false;
+                } else {
+                    if previous_text {
+                        s.push(' ');
+                    }
+                    s += line;
+                    previous_text = true;
+                }
+            }
+            if s.ends_with('\n') {
+                s.pop();
+            }
+        } else {
+            s = lines.join("\n");
+        }
+        match chomp {
+            '-' => {}
+            '+' => {
+                s.push('\n');
+                s += &"\n".repeat(trailing);
+            }
+            _ => {
+                if !s.is_empty() {
+                    s.push('\n');
+                }
+            }
+        }
+        Ok(Value::from(s))
+    }
+}
+
+fn balanced(text: &str) -> bool {
+    let mut depth = 0;
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth <= 0
+}
+
+enum Node {
+    Scalar(String),
+    Sequence(Vec<Value>),
+    Mapping(Vec<(String, Value)>),
+}
+
+fn format_string(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.ends_with(':')
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.chars().any(char::is_control)
+        && match resolve_plain(s) {
+            Value::String(..) => true,
+            _ => false,
+        };
+    if plain {
+        s.to_string()
+    } else {
+        let mut out = String::new();
+        super::json::write_string(&mut out, s);
+        out
+    }
+}
+
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        ".nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { ".inf" } else { "-.inf" }.to_string()
+    } else {
+        crate::num_util::to_string(n)
+    }
+}
+
+struct Writer<'a> {
+    agent: &'a Agent,
+    out: String,
+    seen: HashSet<*const ObjectKind>,
+}
+
+impl<'a> Writer<'a> {
+    fn classify(&self, value: &Value) -> Result<Node, Value> {
+        let agent = self.agent;
+        let node = match value {
+            Value::Null => Node::Scalar("null".to_string()),
+            Value::Boolean(b) => Node::Scalar(b.to_string()),
+            Value::Number(n) => Node::Scalar(format_number(*n)),
+            Value::String(s) => Node::Scalar(format_string(s)),
+            Value::Tuple(items) => Node::Sequence(items.to_vec()),
+            Value::Record(fields) => Node::Mapping(fields.to_vec()),
+            Value::Object(o) => {
+                if value.type_of() == "function" {
+                    return Err(Value::new_error(agent, "yaml: cannot serialize a function"));
+                }
+                match &o.kind {
+                    ObjectKind::Array(values) => Node::Sequence(values.borrow().to_vec()),
+                    ObjectKind::Buffer(values) => Node::Sequence(
+                        values
+                            .borrow()
+                            .iter()
+                            .map(|b| Value::from(f64::from(*b)))
+                            .collect(),
+                    ),
+                    _ => {
+                        let mut entries = Vec::new();
+                        for key in value.keys(agent)? {
+                            if let ObjectKey::Symbol(..) = key {
+                                continue;
+                            }
+                            entries.push((key.to_string(), value.get(agent, key)?));
+                        }
+                        Node::Mapping(entries)
+                    }
+                }
+            }
+            Value::Symbol(..) => {
+                return Err(Value::new_error(agent, "yaml: cannot serialize a symbol"))
+            }
+            _ => unreachable!(),
+        };
+        Ok(match node {
+            Node::Sequence(ref items) if items.is_empty() => Node::Scalar("[]".to_string()),
+            Node::Mapping(ref entries) if entries.is_empty() => Node::Scalar("{}".to_string()),
+            node => node,
+        })
+    }
+
+    fn write_entries(
+        &mut self,
+        value: &Value,
+        node: Node,
+        indent: usize,
+        mut inline: bool,
+    ) -> Result<(), Value> {
+        let id = match value {
+            Value::Object(o) => Some(&o.kind as *const ObjectKind),
+            _ => None,
+        };
+        if let Some(id) = id {
+            if !self.seen.insert(id) {
+                return Err(Value::new_error(
+                    self.agent,
+                    "yaml: cannot serialize a circular structure",
+                ));
+            }
+        }
+        let pad = " ".repeat(indent);
+        match node {
+            Node::Sequence(items) => {
+                for item in items {
+                    if !inline {
+                        self.out += &pad;
+                    }
+                    inline = false;
+                    self.out.push('-');
+                    self.write_child(&item, indent + 2, true)?;
+                }
+            }
+            Node::Mapping(entries) => {
+                for (key, item) in entries {
+                    if !inline {
+                        self.out += &pad;
+                    }
+                    inline = false;
+                    self.out += &format_string(&key);
+                    self.out.push(':');
+                    self.write_child(&item, indent + 2, false)?;
+                }
+            }
+            Node::Scalar(..) => unreachable!(),
+        }
+        if let Some(id) = id {
+            self.seen.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn write_child(
+        &mut self,
+        value: &Value,
+        indent: usize,
+        in_sequence: bool,
+    ) -> Result<(), Value> {
+        match self.classify(value)? {
+            Node::Scalar(s) => {
+                self.out.push(' ');
+                self.out += &s;
+                self.out.push('\n');
+                Ok(())
+            }
+            node => {
+                if in_sequence {
+                    self.out.push(' ');
+                } else {
+                    self.out.push('\n');
+                }
+                self.write_entries(value, node, indent, in_sequence)
+            }
+        }
+    }
+}
+
+pub fn stringify(agent: &Agent, value: &Value) -> Result<String, Value> {
+    let mut writer = Writer {
+        agent,
+        out: String::new(),
+        seen: HashSet::new(),
+    };
+    match writer.classify(value)? {
+        Node::Scalar(s) => {
+            writer.out = s;
+            writer.out.push('\n');
+        }
+        node => writer.write_entries(value, node, 0, false)?,
+    }
+    Ok(writer.out)
+}
+
+fn parse(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0).unwrap_or(&Value::Null) {
+        Value::String(s) => Parser::new(agent, s).parse(),
+        _ => Err(Value::new_error(agent, "source must be a string")),
+    }
+}
+
+fn stringify_fn(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    Ok(Value::from(stringify(agent, value)?))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $fn));
+        };
+    }
+    method!("parse", parse);
+    method!("stringify", stringify_fn);
+
+    module
+}
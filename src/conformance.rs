@@ -0,0 +1,240 @@
+use crate::agent::{resolve_file, Agent};
+use crate::value::{ObjectKey, Value};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// a test still running after this long is counted as a failure
+const TEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The list of tests in a suite directory that are known to fail, one
+/// name per line. Lines starting with `#` are comments.
+pub const EXPECTED_FAILURES: &str = "expected-failures.txt";
+
+/// The outcome of one conformance test.
+#[derive(Debug)]
+pub struct TestResult {
+    /// The path of the test relative to the suite directory.
+    pub name: String,
+    /// Why the test failed, or None if it passed.
+    pub failure: Option<String>,
+    /// Whether the test is listed in the suite's expected failures.
+    pub expected_failure: bool,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// A failure that isn't expected, or an expected failure that now
+    /// passes and should come off the list.
+    pub fn is_unexpected(&self) -> bool {
+        self.passed() == self.expected_failure
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SuiteReport {
+    pub results: Vec<TestResult>,
+}
+
+impl SuiteReport {
+    pub fn unexpected(&self) -> Vec<&TestResult> {
+        self.results.iter().filter(|r| r.is_unexpected()).collect()
+    }
+
+    pub fn summary(&self) -> String {
+        let passed = self.results.iter().filter(|r| r.passed()).count();
+        let expected = self
+            .results
+            .iter()
+            .filter(|r| !r.passed() && r.expected_failure)
+            .count();
+        format!(
+            "{} tests, {} passed, {} expected failures, {} unexpected",
+            self.results.len(),
+            passed,
+            expected,
+            self.unexpected().len()
+        )
+    }
+}
+
+/// Run every `.sl` file under `dir` as a conformance test, each in a fresh
+/// agent.
+///
+/// A test is a module whose body may `await`. It passes if it runs to the
+/// end and the event loop drains without an uncaught exception or
+/// unhandled rejection. The comments a test starts with can change that:
+///
+/// - `// negative: SyntaxError` passes only if the test fails with an
+///   error of that name.
+/// - `// snapshot` compares what the test prints, and the error it fails
+///   with, to the `.snap` file next to it. With `update_snapshots` the file
+///   is rewritten instead.
+/// - `// mock: standard:net ./fake-net.sl` makes the test's imports of a
+///   module get the exports of another one instead, see
+///   `Agent::mock_module`. Paths are relative to the test.
+/// - `// fs: memory` gives the test an empty filesystem in memory, like
+///   `useMemoryFs()` from `standard:test`.
+///
+/// Files in a `fixtures` directory are not tests, they are there to be
+/// imported.
+pub fn run_suite(dir: &Path, update_snapshots: bool) -> std::io::Result<SuiteReport> {
+    let expected = match std::fs::read_to_string(dir.join(EXPECTED_FAILURES)) {
+        Ok(list) => list
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect(),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut files = Vec::new();
+    collect_tests(dir, &mut files)?;
+    files.sort();
+
+    let mut report = SuiteReport::default();
+    for file in files {
+        let name = file
+            .strip_prefix(dir)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .replace('\\', "/");
+        let source = std::fs::read_to_string(&file)?;
+        let failure = run_test(&file, &source, update_snapshots).err();
+        report.results.push(TestResult {
+            failure,
+            expected_failure: expected.contains(&name),
+            name,
+        });
+    }
+    Ok(report)
+}
+
+fn collect_tests(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().is_some_and(|n| n != "fixtures") {
+                collect_tests(&path, files)?;
+            }
+        } else if path.extension().map_or(false, |e| e == "sl") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Run one test, returning why it failed.
+pub fn run_test(path: &Path, source: &str, update_snapshots: bool) -> Result<(), String> {
+    let header = source
+        .lines()
+        .take_while(|l| l.starts_with("//"))
+        .map(|l| l.trim_start_matches("//").trim())
+        .collect::<Vec<_>>();
+    let negative = header
+        .iter()
+        .find_map(|l| l.strip_prefix("negative:"))
+        .map(|n| n.trim().to_string());
+    let snapshot = header.contains(&"snapshot");
+
+    let mut agent = Agent::new();
+    let uncaught = Rc::new(RefCell::new(None));
+    {
+        let uncaught = uncaught.clone();
+        agent.set_uncaught_exception_handler(move |_: &Agent, e: Value, _| {
+            let mut uncaught = uncaught.borrow_mut();
+            if uncaught.is_none() {
+                *uncaught = Some(e);
+            }
+        });
+    }
+    let output = Rc::new(RefCell::new(String::new()));
+    {
+        let output = output.clone();
+        agent.set_print_handler(move |_: &Agent, line: &str| {
+            let mut output = output.borrow_mut();
+            output.push_str(line);
+            output.push('\n');
+        });
+    }
+
+    if header.contains(&"fs: memory") {
+        *agent.memory_fs.borrow_mut() = Some(crate::builtins::fs::MemoryFs::default());
+    }
+    let referrer = path.to_str().unwrap();
+    for mock in header.iter().filter_map(|l| l.strip_prefix("mock:")) {
+        let (specifier, fake) = match mock.split_whitespace().collect::<Vec<_>>()[..] {
+            [specifier, fake] => (specifier, fake),
+            _ => return Err(format!("expected `mock: specifier fake`, got `{}`", mock)),
+        };
+        let specifier = if specifier.starts_with("standard:") {
+            specifier.to_string()
+        } else {
+            resolve_file(specifier, referrer)
+                .map_err(|e| format!("cannot resolve '{}': {}", specifier, e))?
+        };
+        let exports = agent
+            .module_exports(fake, referrer)
+            .map_err(|e| agent.describe_exception(&e))?;
+        agent.mock_module(&specifier, exports);
+    }
+
+    let error = match agent.run_async(referrer, source) {
+        Err(e) => Some(e),
+        Ok(promise) => {
+            if agent.run_jobs_for(TEST_TIMEOUT) {
+                return Err("timed out".to_string());
+            }
+            match promise.get_slot("promise state") {
+                Value::String(ref s) if s.as_str() == "rejected" => {
+                    Some(promise.get_slot("result"))
+                }
+                Value::String(ref s) if s.as_str() == "fulfilled" => uncaught.borrow_mut().take(),
+                _ => return Err("top-level await never resolved".to_string()),
+            }
+        }
+    };
+
+    if snapshot {
+        let mut output = output.borrow_mut();
+        if let Some(e) = &error {
+            output.push_str("error: ");
+            output.push_str(&agent.describe_exception(e));
+            output.push('\n');
+        }
+        crate::snapshot::check(&path.with_extension("snap"), &output, update_snapshots)?;
+    }
+
+    match (error, negative) {
+        (None, None) => Ok(()),
+        (Some(e), None) => Err(agent.describe_exception(&e)),
+        (None, Some(name)) => Err(format!("expected {} but the test passed", name)),
+        (Some(e), Some(name)) => {
+            if error_name(&agent, &e).as_ref() == Some(&name) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "expected {} but got {}",
+                    name,
+                    agent.describe_exception(&e)
+                ))
+            }
+        }
+    }
+}
+
+fn error_name(agent: &Agent, e: &Value) -> Option<String> {
+    if e.type_of() != "object" {
+        return None;
+    }
+    match e.get(agent, ObjectKey::from("name")) {
+        Ok(Value::String(name)) => Some(name.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,165 @@
+/// What happened to a piece of text between the old and the new version.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    Equal,
+    Delete,
+    Insert,
+}
+
+impl Change {
+    pub fn name(self) -> &'static str {
+        match self {
+            Change::Equal => "equal",
+            Change::Delete => "delete",
+            Change::Insert => "insert",
+        }
+    }
+
+    pub fn prefix(self) -> char {
+        match self {
+            Change::Equal => ' ',
+            Change::Delete => '-',
+            Change::Insert => '+',
+        }
+    }
+}
+
+/// Align `old` and `new` along their longest common subsequence, deletions
+/// coming before the insertions that replace them.
+pub fn diff<'a, T: PartialEq + ?Sized>(old: &[&'a T], new: &[&'a T]) -> Vec<(Change, &'a T)> {
+    // lengths of the common subsequences, filled in from the end
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push((Change::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            out.push((Change::Delete, old[i]));
+            i += 1;
+        } else {
+            out.push((Change::Insert, new[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// A diff of the lines of two strings.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<(Change, &'a str)> {
+    diff(
+        &old.lines().collect::<Vec<_>>(),
+        &new.lines().collect::<Vec<_>>(),
+    )
+}
+
+// runs of whitespace and of everything else, so the pieces join back into
+// the string
+fn split_words(s: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut space = None;
+    for (i, c) in s.char_indices() {
+        if space.map_or(false, |space| space != c.is_whitespace()) {
+            words.push(&s[start..i]);
+            start = i;
+        }
+        space = Some(c.is_whitespace());
+    }
+    if start < s.len() {
+        words.push(&s[start..]);
+    }
+    words
+}
+
+/// A diff of the words of two strings. Consecutive pieces with the same
+/// change are joined, whitespace included.
+pub fn words(old: &str, new: &str) -> Vec<(Change, String)> {
+    let mut out: Vec<(Change, String)> = Vec::new();
+    for (change, word) in diff(&split_words(old), &split_words(new)) {
+        match out.last_mut() {
+            Some((last, text)) if *last == change => text.push_str(word),
+            _ => out.push((change, word.to_string())),
+        }
+    }
+    out
+}
+
+/// Render the line diff of `old` and `new` in the unified format, with
+/// `context` unchanged lines around each hunk. Equal strings give an empty
+/// string.
+pub fn unified(old: &str, new: &str, context: usize, old_name: &str, new_name: &str) -> String {
+    let changes = lines(old, new);
+    if changes.iter().all(|(c, _)| *c == Change::Equal) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    // walk the hunks: runs of changes closer than twice the context
+    let mut i = 0;
+    // line numbers, 1-based, at `i`
+    let (mut old_line, mut new_line) = (1, 1);
+    while i < changes.len() {
+        let first = match changes[i..].iter().position(|(c, _)| *c != Change::Equal) {
+            Some(p) => i + p,
+            None => break,
+        };
+        let start = first.saturating_sub(context).max(i);
+        old_line += start - i;
+        new_line += start - i;
+        let mut end = first;
+        let mut equal_run = 0;
+        for (k, (c, _)) in changes.iter().enumerate().skip(first) {
+            if *c == Change::Equal {
+                equal_run += 1;
+                if equal_run > context * 2 {
+                    break;
+                }
+            } else {
+                equal_run = 0;
+                end = k + 1;
+            }
+        }
+        let end = (end + context).min(changes.len());
+        let hunk = &changes[start..end];
+        let old_count = hunk.iter().filter(|(c, _)| *c != Change::Insert).count();
+        let new_count = hunk.iter().filter(|(c, _)| *c != Change::Delete).count();
+        // an empty side is numbered from the line before it
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            if old_count == 0 {
+                old_line - 1
+            } else {
+                old_line
+            },
+            old_count,
+            if new_count == 0 {
+                new_line - 1
+            } else {
+                new_line
+            },
+            new_count,
+        ));
+        for (c, line) in hunk {
+            out.push(c.prefix());
+            out.push_str(line);
+            out.push('\n');
+        }
+        old_line += old_count;
+        new_line += new_count;
+        i = end;
+    }
+    out
+}
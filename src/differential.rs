@@ -0,0 +1,329 @@
+use crate::agent::Agent;
+use crate::value::Value;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The command run as the reference engine. It reads a JS program on stdin
+/// and prints what the program logs, like `node`.
+pub const ENGINE_ENV: &str = "SLITHER_REFERENCE_ENGINE";
+
+/// An expression from the subset of the language that means the same
+/// thing in slither and JS.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Array(Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Length(Box<Expr>),
+    Join(Box<Expr>, String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Type {
+    Number,
+    String,
+    Boolean,
+    Any,
+}
+
+impl Expr {
+    pub fn to_slither(&self) -> String {
+        self.render(false)
+    }
+
+    pub fn to_js(&self) -> String {
+        self.render(true)
+    }
+
+    fn render(&self, js: bool) -> String {
+        match self {
+            Expr::Number(n) => format!("{:?}", n),
+            Expr::String(s) => format!("'{}'", s),
+            Expr::Boolean(b) => b.to_string(),
+            Expr::Null => "null".to_string(),
+            Expr::Array(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|e| e.render(js))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expr::Unary(op, e) => format!("({}({}))", op, e.render(js)),
+            Expr::Binary(op, l, r) => {
+                // slither's equality is strict
+                let op = match (*op, js) {
+                    ("==", true) => "===",
+                    ("!=", true) => "!==",
+                    (op, _) => op,
+                };
+                format!("({} {} {})", l.render(js), op, r.render(js))
+            }
+            Expr::Length(e) => format!("({}).length", e.render(js)),
+            Expr::Join(e, sep) => format!("({}).join('{}')", e.render(js), sep),
+        }
+    }
+
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Array(items) => items.iter().collect(),
+            Expr::Unary(_, e) | Expr::Length(e) | Expr::Join(e, _) => vec![e],
+            Expr::Binary(_, l, r) => vec![l, r],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A small seeded generator, so a failing run can be repeated.
+pub struct Generator {
+    state: u64,
+}
+
+const NUMBERS: &[f64] = &[0.0, 1.0, 2.0, 3.0, 7.0, 10.0, 0.5, 1.5, 0.1, 0.2, 255.0];
+const STRINGS: &[&str] = &["", "a", "b", "ab", "a b"];
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        // xorshift gets stuck at zero
+        Generator {
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())].clone()
+    }
+
+    /// An expression of at most `depth` levels of operators.
+    pub fn expr(&mut self, depth: usize) -> Expr {
+        self.typed(Type::Any, depth)
+    }
+
+    fn typed(&mut self, ty: Type, depth: usize) -> Expr {
+        let ty = match ty {
+            Type::Any => self.pick(&[Type::Number, Type::String, Type::Boolean]),
+            ty => ty,
+        };
+        if depth == 0 || self.below(4) == 0 {
+            return self.literal(ty);
+        }
+        let d = depth - 1;
+        match ty {
+            Type::Number => match self.below(4) {
+                0 => Expr::Unary("-", Box::new(self.typed(Type::Number, d))),
+                1 => Expr::Length(Box::new(match self.below(2) {
+                    0 => self.typed(Type::String, d),
+                    _ => self.array(),
+                })),
+                _ => {
+                    let op = self.pick(&["+", "-", "*", "/", "%", "**"]);
+                    self.binary(op, Type::Number, d)
+                }
+            },
+            Type::String => match self.below(3) {
+                0 => Expr::Join(Box::new(self.array()), self.pick(STRINGS).to_string()),
+                _ => self.binary("+", Type::String, d),
+            },
+            _ => match self.below(5) {
+                // truthiness of any value
+                0 => Expr::Unary("!", Box::new(self.typed(Type::Any, d))),
+                1 => {
+                    let op = self.pick(&["==", "!="]);
+                    self.binary(op, Type::Any, d)
+                }
+                2 => {
+                    let op = self.pick(&["<", ">", "<=", ">="]);
+                    self.binary(op, Type::Number, d)
+                }
+                // typeof null is where the two disagree on purpose
+                3 => {
+                    let ty = self.pick(&[Type::Number, Type::String]);
+                    Expr::Unary("typeof ", Box::new(self.typed(ty, d)))
+                }
+                _ => {
+                    let op = self.pick(&["&&", "||"]);
+                    self.binary(op, Type::Boolean, d)
+                }
+            },
+        }
+    }
+
+    fn binary(&mut self, op: &'static str, operands: Type, depth: usize) -> Expr {
+        let l = self.typed(operands, depth);
+        let r = self.typed(operands, depth);
+        Expr::Binary(op, Box::new(l), Box::new(r))
+    }
+
+    fn literal(&mut self, ty: Type) -> Expr {
+        match ty {
+            Type::Number => Expr::Number(self.pick(NUMBERS)),
+            Type::String => Expr::String(self.pick(STRINGS).to_string()),
+            _ => match self.below(3) {
+                0 => Expr::Null,
+                _ => Expr::Boolean(self.below(2) == 0),
+            },
+        }
+    }
+
+    // only literals, so joining doesn't depend on how numbers are printed
+    fn array(&mut self) -> Expr {
+        let len = self.below(4);
+        let items = (0..len)
+            .map(|_| match self.below(3) {
+                0 => self.literal(Type::String),
+                1 => Expr::Null,
+                _ => self.literal(Type::Number),
+            })
+            .collect();
+        Expr::Array(items)
+    }
+}
+
+// numbers are compared by their bits so formatting doesn't get in the way
+const JS_PRELUDE: &str = r#"
+const show = (v) => {
+  if (typeof v === 'number') {
+    if (Number.isNaN(v)) return 'NaN';
+    const view = new DataView(new ArrayBuffer(8));
+    view.setFloat64(0, v);
+    return 'number ' + view.getBigUint64(0).toString(16);
+  }
+  if (typeof v === 'string') return JSON.stringify(v);
+  return String(v);
+};
+const run = (f) => {
+  try {
+    console.log(show(f()));
+  } catch (e) {
+    console.log('throws');
+  }
+};
+"#;
+
+fn show(agent: &Agent, result: Result<Value, Value>) -> String {
+    match result.map(Value::canonical) {
+        Ok(Value::Number(n)) if n.is_nan() => "NaN".to_string(),
+        Ok(Value::Number(n)) => format!("number {:x}", n.to_bits()),
+        Ok(Value::String(s)) => {
+            let mut out = String::new();
+            crate::builtins::json::write_string(&mut out, &s);
+            out
+        }
+        Ok(Value::Boolean(b)) => b.to_string(),
+        Ok(Value::Null) => "null".to_string(),
+        Ok(v) => Value::inspect(agent, &v),
+        Err(_) => "throws".to_string(),
+    }
+}
+
+/// Evaluate each expression in slither.
+pub fn slither_results(exprs: &[Expr]) -> Vec<String> {
+    let mut agent = Agent::new();
+    exprs
+        .iter()
+        .map(|e| {
+            let result = agent.run("differential.sl", &format!("{};", e.to_slither()));
+            show(&agent, result)
+        })
+        .collect()
+}
+
+/// Evaluate each expression in the reference engine named by `ENGINE_ENV`,
+/// `node` by default.
+pub fn reference_results(exprs: &[Expr]) -> std::io::Result<Vec<String>> {
+    let engine = std::env::var(ENGINE_ENV).unwrap_or_else(|_| "node".to_string());
+    let mut args = engine.split_whitespace();
+    let mut child = Command::new(args.next().unwrap_or("node"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut program = JS_PRELUDE.to_string();
+    for e in exprs {
+        program += &format!("run(() => {});\n", e.to_js());
+    }
+    child.stdin.take().unwrap().write_all(program.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("{} exited with {}", engine, output.status),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// An expression that evaluates differently in slither, reduced to the
+/// smallest part of it that still does.
+#[derive(Debug)]
+pub struct Divergence {
+    pub slither: String,
+    pub js: String,
+    pub slither_result: String,
+    pub reference_result: String,
+}
+
+fn diverges(expr: &Expr) -> std::io::Result<Option<Divergence>> {
+    let exprs = std::slice::from_ref(expr);
+    let slither_result = slither_results(exprs).remove(0);
+    let reference_result = reference_results(exprs)?.remove(0);
+    if slither_result == reference_result {
+        return Ok(None);
+    }
+    Ok(Some(Divergence {
+        slither: expr.to_slither(),
+        js: expr.to_js(),
+        slither_result,
+        reference_result,
+    }))
+}
+
+/// Generate `count` expressions from `seed` and compare how slither and the
+/// reference engine evaluate them.
+pub fn run(seed: u64, count: usize) -> std::io::Result<Vec<Divergence>> {
+    let mut generator = Generator::new(seed);
+    let exprs = (0..count).map(|_| generator.expr(4)).collect::<Vec<_>>();
+    let slither = slither_results(&exprs);
+    let reference = reference_results(&exprs)?;
+
+    let mut divergences = Vec::new();
+    for (i, expr) in exprs.iter().enumerate() {
+        if slither[i] == reference[i] {
+            continue;
+        }
+        let mut current = expr.clone();
+        let mut divergence = diverges(&current)?.unwrap();
+        'shrink: loop {
+            for child in current.children() {
+                if let Some(d) = diverges(child)? {
+                    divergence = d;
+                    current = child.clone();
+                    continue 'shrink;
+                }
+            }
+            break;
+        }
+        divergences.push(divergence);
+    }
+    Ok(divergences)
+}
@@ -0,0 +1,241 @@
+use crate::builtins::json::write_string;
+use crate::parser::{Comment, CommentKind, Error, FunctionKind, Node, ParseOptions, Parser};
+
+/// The documentation of a module: its `//!` comments and the items declared
+/// at its top level that are exported or have a doc comment.
+#[derive(Debug, Default, PartialEq)]
+pub struct ModuleDoc {
+    pub doc: String,
+    pub items: Vec<DocItem>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DocItem {
+    /// `function`, `async function`, `gen function`, `class`, `method` or
+    /// `binding`.
+    pub kind: &'static str,
+    pub name: String,
+    /// Parameter names, for functions and methods.
+    pub params: Vec<String>,
+    pub doc: String,
+    pub exported: bool,
+    /// The methods of a class.
+    pub members: Vec<DocItem>,
+}
+
+/// Parse `source` and collect its documentation.
+pub fn extract(source: &str) -> Result<ModuleDoc, Error> {
+    let options = ParseOptions {
+        top_level_await: true,
+        comments: true,
+        ..ParseOptions::default()
+    };
+    let mut module = ModuleDoc::default();
+    let mut module_doc = Vec::new();
+    if let Node::Block(_, stmts) = Parser::parse_with_options(source, options)? {
+        for stmt in &stmts {
+            let (comments, stmt) = uncomment(stmt);
+            let (exported, stmt) = match stmt {
+                Node::ExportDeclaration(decl) => (true, uncomment(decl).1),
+                stmt => (false, stmt),
+            };
+            for c in comments {
+                if c.kind == CommentKind::ModuleDoc {
+                    module_doc.push(c.text.clone());
+                }
+            }
+            if let Some(mut item) = item(comments, stmt) {
+                if exported || !item.doc.is_empty() {
+                    item.exported = exported;
+                    module.items.push(item);
+                }
+            }
+        }
+    }
+    module.doc = module_doc.join("\n");
+    Ok(module)
+}
+
+fn uncomment(node: &Node) -> (&[Comment], &Node) {
+    match node {
+        Node::Commented(comments, node) => (comments, node),
+        node => (&[], node),
+    }
+}
+
+fn doc_text(comments: &[Comment]) -> String {
+    comments
+        .iter()
+        .filter(|c| c.kind == CommentKind::Doc)
+        .map(|c| c.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn params(args: &[Node]) -> Vec<String> {
+    args.iter()
+        .filter_map(|a| match a {
+            Node::Identifier(name) => Some(name.clone()),
+            Node::Initializer(name, _) => match &**name {
+                Node::Identifier(name) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn function_kind(kind: FunctionKind) -> &'static str {
+    match kind {
+        FunctionKind::Async => "async function",
+        FunctionKind::Generator => "gen function",
+        _ => "function",
+    }
+}
+
+fn item(comments: &[Comment], node: &Node) -> Option<DocItem> {
+    let (kind, name, params, members) = match node {
+        Node::FunctionDeclaration(kind, name, args, _) => {
+            (function_kind(*kind), name, params(args), Vec::new())
+        }
+        Node::LexicalInitialization(name, init) => match &**init {
+            Node::FunctionExpression(kind, _, args, _)
+            | Node::ArrowFunctionExpression(kind, args, _) => {
+                (function_kind(*kind), name, params(args), Vec::new())
+            }
+            _ => ("binding", name, Vec::new(), Vec::new()),
+        },
+        Node::ClassDeclaration(name, _, fields) => {
+            let members = fields
+                .iter()
+                .filter_map(|f| match f {
+                    Node::Initializer(name, f) => match (&**name, uncomment(f)) {
+                        (
+                            Node::StringLiteral(name),
+                            (comments, Node::FunctionExpression(_, _, args, _)),
+                        ) => Some(DocItem {
+                            kind: "method",
+                            name: name.clone(),
+                            params: params(args),
+                            doc: doc_text(comments),
+                            exported: false,
+                            members: Vec::new(),
+                        }),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect();
+            ("class", name, Vec::new(), members)
+        }
+        _ => return None,
+    };
+    Some(DocItem {
+        kind,
+        name: name.clone(),
+        params,
+        doc: doc_text(comments),
+        exported: false,
+        members,
+    })
+}
+
+impl ModuleDoc {
+    /// Look up an item by name, or a method by `Class.method`, as for a
+    /// hover.
+    pub fn find(&self, name: &str) -> Option<&DocItem> {
+        let mut parts = name.splitn(2, '.');
+        let first = parts.next()?;
+        let item = self.items.iter().find(|i| i.name == first)?;
+        match parts.next() {
+            None => Some(item),
+            Some(member) => item.members.iter().find(|m| m.name == member),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"doc\":");
+        write_string(&mut out, &self.doc);
+        out.push_str(",\"items\":");
+        items_json(&mut out, &self.items);
+        out.push('}');
+        out
+    }
+
+    /// Render the module as markdown under a `# title` heading.
+    pub fn to_markdown(&self, title: &str) -> String {
+        let mut out = format!("# {}\n", title);
+        if !self.doc.is_empty() {
+            out.push('\n');
+            out.push_str(&self.doc);
+            out.push('\n');
+        }
+        for item in &self.items {
+            out.push('\n');
+            item.write_markdown(&mut out, 2);
+        }
+        out
+    }
+}
+
+fn items_json(out: &mut String, items: &[DocItem]) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"kind\":");
+        write_string(out, item.kind);
+        out.push_str(",\"name\":");
+        write_string(out, &item.name);
+        out.push_str(",\"params\":[");
+        for (i, param) in item.params.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_string(out, param);
+        }
+        out.push_str("],\"doc\":");
+        write_string(out, &item.doc);
+        out.push_str(",\"exported\":");
+        out.push_str(if item.exported { "true" } else { "false" });
+        out.push_str(",\"members\":");
+        items_json(out, &item.members);
+        out.push('}');
+    }
+    out.push(']');
+}
+
+impl DocItem {
+    /// How the item is declared, like `function add(a, b)`.
+    pub fn signature(&self) -> String {
+        match self.kind {
+            "class" | "binding" => format!("{} {}", self.kind, self.name),
+            "method" => format!("{}({})", self.name, self.params.join(", ")),
+            kind => format!("{} {}({})", kind, self.name, self.params.join(", ")),
+        }
+    }
+
+    /// The signature and doc comment, for a hover.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        self.write_markdown(&mut out, 2);
+        out
+    }
+
+    fn write_markdown(&self, out: &mut String, level: usize) {
+        out.push_str(&"#".repeat(level));
+        out.push_str(" `");
+        out.push_str(&self.signature());
+        out.push_str("`\n");
+        if !self.doc.is_empty() {
+            out.push('\n');
+            out.push_str(&self.doc);
+            out.push('\n');
+        }
+        for member in &self.members {
+            out.push('\n');
+            member.write_markdown(out, level + 1);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::agent::Agent;
+use crate::diff;
+use crate::parser::{tokenize_with_spans, TokenKind};
+use crate::value::Value;
+use std::path::{Path, PathBuf};
+
+/// A fenced code block, from a doc comment or a markdown file, with at
+/// least one line annotated with the value it should evaluate to:
+///
+/// ```text
+/// [1, 2, 3].length // => 3
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Example {
+    /// The line the code starts on, counting from 1.
+    pub line: usize,
+    pub steps: Vec<Step>,
+}
+
+/// The code of an example up to and including an annotated line, and the
+/// annotation. The code after the last annotation has no expected value,
+/// it only has to run.
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    /// The line of the annotation, or of the last line of code.
+    pub line: usize,
+    pub code: String,
+    pub expected: Option<String>,
+}
+
+/// The outcome of one example.
+#[derive(Debug)]
+pub struct ExampleResult {
+    pub file: String,
+    pub line: usize,
+    /// The annotations that didn't match, or None if they all did.
+    pub failure: Option<String>,
+}
+
+impl ExampleResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+const ANNOTATION: &str = "// =>";
+
+/// Collect the examples in the doc comments of a module, or with
+/// `markdown` in the code blocks of a markdown file. Blocks tagged with a
+/// language other than `sl`, `slither`, `js` or `javascript` are skipped.
+pub fn extract(source: &str, markdown: bool) -> Vec<Example> {
+    let mut lines = Vec::new();
+    if markdown {
+        lines.extend(source.lines().enumerate().map(|(i, l)| (i + 1, l)));
+    } else {
+        for token in tokenize_with_spans(source) {
+            if token.kind != TokenKind::Comment {
+                continue;
+            }
+            let text = source[token.start..token.end].trim_end_matches(&['\r', '\n'][..]);
+            let line = source[..token.start].matches('\n').count() + 1;
+            if let Some(text) = text.strip_prefix("///").or(text.strip_prefix("//!")) {
+                lines.push((line, text.strip_prefix(' ').unwrap_or(text)));
+            } else if let Some(text) = text.strip_prefix("/**") {
+                let text = text.strip_suffix("*/").unwrap_or(text);
+                for (i, l) in text.lines().enumerate() {
+                    let l = l.trim_start();
+                    let l = l.strip_prefix('*').unwrap_or(l);
+                    lines.push((line + i, l.strip_prefix(' ').unwrap_or(l)));
+                }
+            }
+        }
+    }
+
+    let mut examples = Vec::new();
+    let mut block: Option<(bool, Vec<(usize, &str)>)> = None;
+    for (line, text) in lines {
+        let fence = text.trim_start();
+        if !fence.starts_with("```") {
+            if let Some((_, code)) = &mut block {
+                code.push((line, text));
+            }
+            continue;
+        }
+        match block.take() {
+            Some((runnable, code)) => {
+                if runnable {
+                    examples.extend(example(&code));
+                }
+            }
+            None => {
+                let language = fence.trim_start_matches('`').trim();
+                let runnable = ["", "sl", "slither", "js", "javascript"].contains(&language);
+                block = Some((runnable, Vec::new()));
+            }
+        }
+    }
+    examples
+}
+
+fn example(code: &[(usize, &str)]) -> Option<Example> {
+    let mut steps = Vec::new();
+    let mut pending = String::new();
+    for &(line, text) in code {
+        match text.find(ANNOTATION) {
+            Some(i) => {
+                pending.push_str(&text[..i]);
+                steps.push(Step {
+                    line,
+                    code: std::mem::take(&mut pending),
+                    expected: Some(text[i + ANNOTATION.len()..].trim().to_string()),
+                });
+            }
+            None => {
+                pending.push_str(text);
+                pending.push('\n');
+            }
+        }
+    }
+    if steps.is_empty() {
+        return None;
+    }
+    if !pending.trim().is_empty() {
+        steps.push(Step {
+            line: code.last().unwrap().0,
+            code: pending,
+            expected: None,
+        });
+    }
+    Some(Example {
+        line: code[0].0,
+        steps,
+    })
+}
+
+/// Run an example in a fresh agent, a step at a time like lines typed into
+/// the REPL, and compare what each annotated step evaluates to with its
+/// annotation. A step that throws evaluates to `Uncaught` followed by the
+/// exception.
+///
+/// The examples of a module are given its filename and source, so they can
+/// use what it declares.
+pub fn run_example(example: &Example, module: Option<(&str, &str)>) -> Result<(), String> {
+    let mut agent = Agent::new();
+    let context = match module {
+        Some((filename, source)) => match agent.new_module_eval_context(filename, source) {
+            Ok(context) => context,
+            Err(e) => return Err(format!("Uncaught {}", agent.describe_exception(&e))),
+        },
+        None => agent.new_eval_context(),
+    };
+    let mut failures = Vec::new();
+    for step in &example.steps {
+        let result = agent.eval_in_context(&step.code, &context);
+        agent.run_jobs();
+        let actual = match result {
+            Ok(v) => Value::inspect(&agent, &v),
+            Err(e) => format!("Uncaught {}", Value::inspect(&agent, &e)),
+        };
+        let expected = match &step.expected {
+            Some(expected) => expected,
+            None if actual.starts_with("Uncaught ") => {
+                failures.push(format!("line {}: {}", step.line, actual));
+                continue;
+            }
+            None => continue,
+        };
+        if normalize(expected) != normalize(&actual) {
+            let mut failure = format!("line {}: {}", step.line, step.code.trim());
+            for (change, line) in diff::lines(expected, &actual) {
+                failure.push_str(&format!("\n  {} {}", change.prefix(), line));
+            }
+            failures.push(failure);
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n"))
+    }
+}
+
+// inspected values may be split over lines differently than annotations
+fn normalize(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Run the examples in the `.sl` and `.md` files at `paths`, searching
+/// directories recursively.
+pub fn run_files(paths: &[&Path]) -> std::io::Result<Vec<ExampleResult>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            collect_files(path, &mut files)?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    files.sort();
+
+    let mut results = Vec::new();
+    for file in files {
+        let source = std::fs::read_to_string(&file)?;
+        let markdown = file.extension().is_some_and(|e| e == "md");
+        let filename = std::fs::canonicalize(&file)?;
+        let module = if markdown {
+            None
+        } else {
+            Some((filename.to_str().unwrap(), source.as_str()))
+        };
+        for example in extract(&source, markdown) {
+            results.push(ExampleResult {
+                file: file.display().to_string(),
+                line: example.line,
+                failure: run_example(&example, module).err(),
+            });
+        }
+    }
+    Ok(results)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "sl" || e == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
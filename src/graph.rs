@@ -0,0 +1,300 @@
+use crate::agent::resolve_file;
+use crate::builtins::json::write_string;
+use crate::parser::{Node, ParseOptions, Parser};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The modules reachable from an entry file, as the resolver finds them.
+#[derive(Debug, PartialEq)]
+pub struct ModuleGraph {
+    /// In the order they were found, the entry first.
+    pub modules: Vec<ModuleNode>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ModuleNode {
+    /// The resolved path.
+    pub path: String,
+    pub imports: Vec<Import>,
+    /// The standard modules imported, like `debug`.
+    pub standard: Vec<String>,
+    pub exports: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Import {
+    pub specifier: String,
+    /// The index of the imported module.
+    pub module: usize,
+    /// The bindings imported by name. `None` for `import x from` and
+    /// `import "..."`, which may use any export.
+    pub names: Option<Vec<String>>,
+}
+
+fn exported_name(decl: &Node) -> Option<&str> {
+    match decl {
+        Node::LexicalInitialization(name, _) | Node::FunctionDeclaration(_, name, _, _) => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Resolve and parse every module `entry` imports, directly or not.
+pub fn build(entry: &str) -> Result<ModuleGraph, String> {
+    let referrer = std::env::current_dir().unwrap().join("slither");
+    let entry =
+        resolve_file(entry, referrer.to_str().unwrap()).map_err(|e| format!("{}: {}", entry, e))?;
+    let mut graph = ModuleGraph {
+        modules: Vec::new(),
+    };
+    let mut indices = HashMap::new();
+    indices.insert(entry.clone(), 0);
+    let mut pending = vec![entry];
+    // modules are numbered when first seen and parsed in that order
+    while graph.modules.len() < pending.len() {
+        let path = pending[graph.modules.len()].clone();
+        let source = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let options = ParseOptions {
+            top_level_await: true,
+            ..ParseOptions::default()
+        };
+        let ast = Parser::parse_with_options(&source, options)
+            .map_err(|e| format!("{}: SyntaxError: {:?}", path, e))?;
+        let mut node = ModuleNode {
+            path: path.clone(),
+            imports: Vec::new(),
+            standard: Vec::new(),
+            exports: Vec::new(),
+        };
+        if let Node::Block(_, stmts) = ast {
+            for stmt in stmts {
+                let (specifier, names) = match stmt {
+                    Node::ImportDeclaration(specifier)
+                    | Node::ImportDefaultDeclaration(specifier, _) => (specifier, None),
+                    Node::ImportNamedDeclaration(specifier, names) => (specifier, Some(names)),
+                    Node::ImportStandardDeclaration(namespace, _) => {
+                        if !node.standard.contains(&namespace) {
+                            node.standard.push(namespace);
+                        }
+                        continue;
+                    }
+                    Node::ExportDeclaration(decl) => {
+                        if let Some(name) = exported_name(&decl) {
+                            node.exports.push(name.to_string());
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let resolved = resolve_file(&specifier, &path)
+                    .map_err(|e| format!("{}: cannot resolve '{}': {}", path, specifier, e))?;
+                let module = *indices.entry(resolved.clone()).or_insert_with(|| {
+                    pending.push(resolved);
+                    pending.len() - 1
+                });
+                node.imports.push(Import {
+                    specifier,
+                    module,
+                    names,
+                });
+            }
+        }
+        graph.modules.push(node);
+    }
+    Ok(graph)
+}
+
+impl ModuleGraph {
+    /// Groups of modules that import each other, each in the order found. A
+    /// module importing itself is a cycle of one.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        // tarjan's strongly connected components
+        struct State {
+            index: Vec<Option<usize>>,
+            low: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<usize>,
+            next: usize,
+            components: Vec<Vec<usize>>,
+        }
+
+        fn visit(graph: &ModuleGraph, v: usize, s: &mut State) {
+            s.index[v] = Some(s.next);
+            s.low[v] = s.next;
+            s.next += 1;
+            s.stack.push(v);
+            s.on_stack[v] = true;
+            for import in &graph.modules[v].imports {
+                let w = import.module;
+                match s.index[w] {
+                    None => {
+                        visit(graph, w, s);
+                        s.low[v] = s.low[v].min(s.low[w]);
+                    }
+                    Some(index) if s.on_stack[w] => s.low[v] = s.low[v].min(index),
+                    _ => {}
+                }
+            }
+            if Some(s.low[v]) == s.index[v] {
+                let mut component = Vec::new();
+                loop {
+                    let w = s.stack.pop().unwrap();
+                    s.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                let self_import = graph.modules[v].imports.iter().any(|i| i.module == v);
+                if component.len() > 1 || self_import {
+                    component.sort();
+                    s.components.push(component);
+                }
+            }
+        }
+
+        let n = self.modules.len();
+        let mut state = State {
+            index: vec![None; n],
+            low: vec![0; n],
+            on_stack: vec![false; n],
+            stack: Vec::new(),
+            next: 0,
+            components: Vec::new(),
+        };
+        for v in 0..n {
+            if state.index[v].is_none() {
+                visit(self, v, &mut state);
+            }
+        }
+        state.components.sort();
+        state.components
+    }
+
+    /// Exports no module imports, as (module, name). The entry's exports
+    /// are its interface and never count as unused.
+    pub fn unused_exports(&self) -> Vec<(usize, &str)> {
+        let mut unused = Vec::new();
+        for (m, module) in self.modules.iter().enumerate().skip(1) {
+            let imports = self
+                .modules
+                .iter()
+                .flat_map(|other| &other.imports)
+                .filter(|i| i.module == m)
+                .collect::<Vec<_>>();
+            if imports.iter().any(|i| i.names.is_none()) {
+                continue;
+            }
+            for name in &module.exports {
+                if !imports
+                    .iter()
+                    .any(|i| i.names.as_ref().unwrap().contains(name))
+                {
+                    unused.push((m, name.as_str()));
+                }
+            }
+        }
+        unused
+    }
+
+    /// A module's path relative to the entry's directory where possible.
+    pub fn display(&self, module: usize) -> String {
+        let path = Path::new(&self.modules[module].path);
+        let base = Path::new(&self.modules[0].path).parent().unwrap();
+        path.strip_prefix(base)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"modules\":[");
+        for (m, module) in self.modules.iter().enumerate() {
+            if m > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"path\":");
+            write_string(&mut out, &self.display(m));
+            out.push_str(",\"imports\":[");
+            for (i, import) in module.imports.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"specifier\":");
+                write_string(&mut out, &import.specifier);
+                out.push_str(",\"path\":");
+                write_string(&mut out, &self.display(import.module));
+                out.push_str(",\"names\":");
+                match &import.names {
+                    Some(names) => string_list(&mut out, names.iter().map(String::as_str)),
+                    None => out.push_str("null"),
+                }
+                out.push('}');
+            }
+            out.push_str("],\"standard\":");
+            string_list(&mut out, module.standard.iter().map(String::as_str));
+            out.push_str(",\"exports\":");
+            string_list(&mut out, module.exports.iter().map(String::as_str));
+            out.push('}');
+        }
+        out.push_str("],\"cycles\":[");
+        for (i, cycle) in self.cycles().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let paths = cycle.iter().map(|m| self.display(*m)).collect::<Vec<_>>();
+            string_list(&mut out, paths.iter().map(String::as_str));
+        }
+        out.push_str("],\"unusedExports\":[");
+        for (i, (m, name)) in self.unused_exports().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"path\":");
+            write_string(&mut out, &self.display(*m));
+            out.push_str(",\"name\":");
+            write_string(&mut out, name);
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// The graph in graphviz's format, edges inside a cycle drawn red.
+    pub fn to_dot(&self) -> String {
+        let cycles = self.cycles();
+        let in_cycle = |a: usize, b: usize| cycles.iter().any(|c| c.contains(&a) && c.contains(&b));
+        let mut out = String::from("digraph modules {\n");
+        for m in 0..self.modules.len() {
+            out.push_str("  ");
+            write_string(&mut out, &self.display(m));
+            out.push_str(";\n");
+        }
+        for (m, module) in self.modules.iter().enumerate() {
+            for import in &module.imports {
+                out.push_str("  ");
+                write_string(&mut out, &self.display(m));
+                out.push_str(" -> ");
+                write_string(&mut out, &self.display(import.module));
+                if in_cycle(m, import.module) {
+                    out.push_str(" [color=red]");
+                }
+                out.push_str(";\n");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn string_list<'a>(out: &mut String, items: impl Iterator<Item = &'a str>) {
+    out.push('[');
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, item);
+    }
+    out.push(']');
+}
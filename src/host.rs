@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+/// What an embedder provides when slither is built without the `os`
+/// feature, such as in a web page where the host owns the event loop.
+pub trait Host {
+    /// Call `Agent::fire_timer(id)` once `duration` has passed.
+    fn set_timer(&self, id: usize, duration: Duration);
+
+    /// The timer was cancelled, and firing it now does nothing.
+    fn clear_timer(&self, _id: usize) {}
+
+    /// A monotonic clock, measured from any fixed point. `run_jobs_for`
+    /// relies on it for deadlines, and it never advances by default.
+    fn now(&self) -> Duration {
+        Duration::from_millis(0)
+    }
+
+    /// The wall clock, in milliseconds since the Unix epoch, for things like
+    /// `temporal.now` and cache expiry. It reads as the epoch by default.
+    fn now_ms(&self) -> f64 {
+        0.0
+    }
+
+    /// A number in [0, 1), used to jitter `retry` delays. By default it is
+    /// always 0, so retries wait the whole backoff.
+    fn random(&self) -> f64 {
+        0.0
+    }
+
+    /// Used by `standard:fs` and to load imported modules.
+    fn read_file(&self, path: &str) -> Result<String, String> {
+        Err(format!("cannot read {}, there is no filesystem", path))
+    }
+}
+
+/// The host until the embedder sets one. Timers never fire.
+pub(crate) struct NoHost;
+
+impl Host for NoHost {
+    fn set_timer(&self, _id: usize, _duration: Duration) {}
+}
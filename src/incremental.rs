@@ -0,0 +1,154 @@
+use crate::parser::{Error, Node, ParseOptions, Parser, TopLevelStatement};
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A program kept parsed while it is edited, for editors and watchers.
+/// An edit reparses the top-level statements it touches and keeps the
+/// others, so typing in a large file costs about as much as parsing the
+/// statement being typed in.
+pub struct Document {
+    source: String,
+    options: ParseOptions,
+    statements: Vec<TopLevelStatement>,
+    // the program ended before its source did, see `Parser::parse_top_level`
+    truncated: bool,
+    error: Option<Error>,
+}
+
+impl Document {
+    pub fn new(source: &str, options: ParseOptions) -> Document {
+        let mut document = Document {
+            source: source.to_string(),
+            options,
+            statements: Vec::new(),
+            truncated: false,
+            error: None,
+        };
+        document.parse();
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The top-level statements, as `Parser::parse` would return them.
+    pub fn statements(&self) -> impl Iterator<Item = &Node> {
+        self.statements.iter().map(|s| &s.node)
+    }
+
+    /// The whole program, or why the source doesn't parse.
+    pub fn ast(&self) -> Result<Node, Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut bindings = IndexMap::new();
+        for statement in &self.statements {
+            bindings.extend(statement.bindings.iter().cloned());
+        }
+        Ok(Parser::program(
+            bindings,
+            self.statements().cloned().collect(),
+        ))
+    }
+
+    /// Replace the bytes in `range` with `text`, like
+    /// `String::replace_range`, and reparse what that changed. Returns the
+    /// indices of the statements that were parsed again; the rest were kept
+    /// from before the edit.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Range<usize> {
+        let old_len = self.source.len();
+        self.source.replace_range(range.clone(), text);
+        if self.error.is_some() || self.truncated || self.statements.is_empty() {
+            return self.parse();
+        }
+        let new_len = self.source.len();
+        let shift = |offset: usize| offset + new_len - old_len;
+        let n = self.statements.len();
+        // the first statement starts at 0, so both are always found
+        let mut first = self
+            .statements
+            .iter()
+            .rposition(|s| s.start <= range.start)
+            .unwrap();
+        let mut last = self
+            .statements
+            .iter()
+            .rposition(|s| s.start < range.end)
+            .unwrap_or(0)
+            .max(first);
+        // without a semicolon the statement before may run on into the edit
+        while first > 0 && !self.statements[first - 1].ends {
+            first -= 1;
+        }
+        loop {
+            let start = self.statements[first].start;
+            let end = match self.statements.get(last + 1) {
+                Some(next) => shift(next.start),
+                None => new_len,
+            };
+            let fragment = &self.source[start..end];
+            let (mut parsed, complete) =
+                match Parser::parse_top_level(fragment, self.options.clone(), start == 0) {
+                    Ok(result) => result,
+                    // let the whole program decide which error it really is
+                    Err(_) => return self.parse(),
+                };
+            if last + 1 < n {
+                if !complete {
+                    return self.parse();
+                }
+                if parsed.last().is_some_and(|s| !s.ends) {
+                    last += 1;
+                    continue;
+                }
+            }
+            for statement in &mut parsed {
+                statement.start += start;
+            }
+            if let Some(statement) = parsed.first_mut() {
+                statement.start = start;
+            }
+            for statement in &mut self.statements[last + 1..] {
+                statement.start = shift(statement.start);
+            }
+            self.truncated = !complete;
+            let count = parsed.len();
+            self.statements.splice(first..=last, parsed);
+            self.check_bindings();
+            return first..first + count;
+        }
+    }
+
+    fn parse(&mut self) -> Range<usize> {
+        match Parser::parse_top_level(&self.source, self.options.clone(), true) {
+            Ok((statements, complete)) => {
+                self.statements = statements;
+                self.truncated = !complete;
+                self.error = None;
+                if let Some(statement) = self.statements.first_mut() {
+                    statement.start = 0;
+                }
+            }
+            Err(e) => {
+                self.statements.clear();
+                self.error = Some(e);
+            }
+        }
+        0..self.statements.len()
+    }
+
+    // statements parsed on their own can't see each other's names
+    fn check_bindings(&mut self) {
+        let mut names = HashSet::new();
+        let duplicate = self
+            .statements
+            .iter()
+            .flat_map(|s| &s.bindings)
+            .any(|(name, _)| !names.insert(name));
+        if duplicate {
+            self.error = Some(Error::DuplicateBinding);
+        }
+    }
+}
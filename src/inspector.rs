@@ -0,0 +1,315 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Property, Value};
+use gc::{Gc, GcCell};
+
+/// What `.type <expr>` shows in the REPL: the `typeof` of a value, and for
+/// objects what kind of object it is and the nearest prototype.
+pub fn type_description(agent: &Agent, value: &Value) -> String {
+    let mut out = value.type_of().to_string();
+    let slots = value.internal_slots();
+    let kind = slots
+        .iter()
+        .find(|(name, _)| name == "functionKind")
+        .or_else(|| slots.first());
+    if let Some((_, Value::String(kind))) = kind {
+        out += &format!(" ({})", kind);
+    }
+    if let Some(prototype) = value.prototype_chain().first() {
+        out += &format!(" inheriting {}", prototype_name(agent, prototype));
+    }
+    out
+}
+
+/// What `.inspect <expr>` shows in the REPL: the prototype chain of a
+/// value, its own properties without calling getters, and its internal
+/// slots.
+pub fn describe(agent: &Agent, value: &Value) -> String {
+    let mut out = type_description(agent, value);
+    if value.type_of() != "object" && value.type_of() != "function" {
+        out += &format!("\n{}", Value::inspect(agent, value));
+        return out;
+    }
+    let chain = value.prototype_chain();
+    if !chain.is_empty() {
+        let names = chain
+            .iter()
+            .map(|p| prototype_name(agent, p))
+            .collect::<Vec<_>>();
+        out += &format!("\nprototypes: {}", names.join(" -> "));
+    }
+    let properties = value.own_properties();
+    out += "\nproperties:";
+    if properties.is_empty() {
+        out += " none";
+    }
+    for (key, property, enumerable) in properties {
+        let hidden = if enumerable { "" } else { " (not enumerable)" };
+        match property {
+            Property::Data(v) => out += &format!("\n  {}: {}{}", key, summary(agent, &v), hidden),
+            Property::Accessor(get, set) => {
+                if get != Value::Null {
+                    out += &format!("\n  get {}: {}{}", key, summary(agent, &get), hidden);
+                }
+                if set != Value::Null {
+                    out += &format!("\n  set {}: {}{}", key, summary(agent, &set), hidden);
+                }
+            }
+        }
+    }
+    out += "\nslots:";
+    for (name, v) in value.internal_slots() {
+        out += &format!("\n  [[{}]]: {}", name, summary(agent, &v));
+    }
+    out
+}
+
+/// What `.ls` shows in the REPL: the bindings declared in a context, and
+/// with `globals` the ones every script sees too.
+pub fn list_scope(agent: &Agent, context: &Gc<GcCell<Context>>, globals: bool) -> String {
+    let mut lines = Vec::new();
+    let mut scope = Some(context.borrow().scope.clone());
+    while let Some(current) = scope {
+        let parent = current.borrow().parent();
+        if parent.is_none() && !globals {
+            break;
+        }
+        let current = current.borrow();
+        for (name, mutable) in current.names() {
+            let value = match current.get(agent, name) {
+                Ok(v) => summary(agent, &v),
+                Err(_) => "<uninitialized>".to_string(),
+            };
+            let keyword = if mutable { "let" } else { "const" };
+            lines.push(format!("{} {} = {}", keyword, name, value));
+        }
+        scope = parent;
+    }
+    if lines.is_empty() {
+        "no bindings".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// Run a REPL command, or return None if `line` isn't one. `.inspect` and
+/// `.type` evaluate the rest of the line in `context` first; `.ls` takes
+/// `globals` to include the global scope.
+pub fn repl_command(
+    agent: &mut Agent,
+    line: &str,
+    context: &Gc<GcCell<Context>>,
+) -> Option<Result<String, Value>> {
+    let line = line.trim();
+    let (command, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+    match command {
+        ".inspect" | ".type" => {
+            if rest.is_empty() {
+                return Some(Err(Value::new_error(
+                    agent,
+                    &format!("usage: {} <expression>", command),
+                )));
+            }
+            let result = agent.eval_in_context(rest, context);
+            agent.run_jobs();
+            Some(result.map(|v| {
+                if command == ".inspect" {
+                    describe(agent, &v)
+                } else {
+                    type_description(agent, &v)
+                }
+            }))
+        }
+        ".ls" => match rest {
+            "" => Some(Ok(list_scope(agent, context, false))),
+            "globals" => Some(Ok(list_scope(agent, context, true))),
+            _ => Some(Err(Value::new_error(agent, "usage: .ls [globals]"))),
+        },
+        _ => None,
+    }
+}
+
+// the intrinsic prototypes by name, then prototypes that name their
+// constructor
+fn prototype_name(agent: &Agent, prototype: &Value) -> String {
+    let intrinsics = &agent.intrinsics;
+    let known = [
+        (&intrinsics.object_prototype, "Object.prototype"),
+        (&intrinsics.array_prototype, "Array.prototype"),
+        (&intrinsics.function_prototype, "Function.prototype"),
+        (&intrinsics.boolean_prototype, "Boolean.prototype"),
+        (&intrinsics.string_prototype, "String.prototype"),
+        (&intrinsics.number_prototype, "Number.prototype"),
+        (&intrinsics.promise_prototype, "Promise.prototype"),
+        (&intrinsics.symbol_prototype, "Symbol.prototype"),
+        (&intrinsics.regex_prototype, "RegExp.prototype"),
+        (&intrinsics.error_prototype, "Error.prototype"),
+        (&intrinsics.iterator_prototype, "Iterator.prototype"),
+        (
+            &intrinsics.array_iterator_prototype,
+            "ArrayIterator.prototype",
+        ),
+        (&intrinsics.generator_prototype, "Generator.prototype"),
+        (
+            &intrinsics.async_iterator_prototype,
+            "AsyncIterator.prototype",
+        ),
+    ];
+    if let Some((_, name)) = known.iter().find(|(p, _)| *p == prototype) {
+        return name.to_string();
+    }
+    let constructor = prototype
+        .own_properties()
+        .into_iter()
+        .find_map(|(key, property, _)| match property {
+            Property::Data(c) if key == ObjectKey::from("constructor") => Some(c),
+            _ => None,
+        });
+    if let Some(constructor) = constructor {
+        if let Ok(Value::String(name)) = constructor.get(agent, ObjectKey::from("name")) {
+            return format!("{}.prototype", name);
+        }
+    }
+    summary(agent, prototype)
+}
+
+// a value on one line, without the properties of objects
+fn summary(agent: &Agent, value: &Value) -> String {
+    match value.type_of() {
+        "function" => match value.get(agent, ObjectKey::from("name")) {
+            Ok(Value::String(name)) if !name.is_empty() => format!("[Function {}]", name),
+            _ => "[Function]".to_string(),
+        },
+        "object" => match value.internal_slots().first() {
+            Some((_, Value::String(kind))) if kind.as_str() == "array" => {
+                let length = value.get(agent, ObjectKey::from("length"));
+                format!(
+                    "[Array({})]",
+                    length.map_or(0.0, |l| l.as_f64().unwrap_or(0.0))
+                )
+            }
+            _ if value
+                .prototype_chain()
+                .contains(&agent.intrinsics.error_prototype) =>
+            {
+                Value::inspect(agent, value)
+            }
+            _ => {
+                let count = value.own_properties().len();
+                if count == 0 {
+                    "{}".to_string()
+                } else {
+                    format!("{{ {} properties }}", count)
+                }
+            }
+        },
+        _ => Value::inspect(agent, value),
+    }
+}
+
+/// Complete the word before `pos` in a line typed into the REPL: a binding
+/// in scope, a property after `obj.`, or a standard module name in an
+/// import. Returns where the replaced text starts and the candidates for
+/// it. Properties are looked up without calling getters, so completing
+/// never runs code.
+pub fn complete(
+    agent: &Agent,
+    context: &Gc<GcCell<Context>>,
+    line: &str,
+    pos: usize,
+) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+        .map_or(0, |i| i + before[i..].chars().next().unwrap().len_utf8());
+    let word = &before[start..];
+    let rest = before[..start].trim_end();
+
+    let candidates = if let Some(rest) = rest.strip_suffix("standard:") {
+        if !is_import(rest) {
+            return (pos, Vec::new());
+        }
+        crate::builtins::names()
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| name.to_string())
+            .collect()
+    } else if rest.ends_with("from") && is_import(rest) {
+        crate::builtins::names()
+            .into_iter()
+            .map(|name| format!("standard:{}", name))
+            .filter(|name| name.starts_with(word))
+            .collect()
+    } else if let Some(dot) = word.rfind('.') {
+        let (path, prefix) = (&word[..dot], &word[dot + 1..]);
+        let value = match resolve(agent, context, path) {
+            Some(value) => value,
+            None => return (pos, Vec::new()),
+        };
+        let value = value.to_object(agent).unwrap_or(value);
+        let mut names = Vec::new();
+        for object in std::iter::once(value.clone()).chain(value.prototype_chain()) {
+            for (key, _, enumerable) in object.own_properties() {
+                if let ObjectKey::String(name) = &key {
+                    if enumerable && name.starts_with(prefix) {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+        return (start + dot + 1, sorted(names));
+    } else {
+        let mut names = Vec::new();
+        let mut scope = Some(context.borrow().scope.clone());
+        while let Some(current) = scope {
+            let current = current.borrow();
+            for (name, _) in current.names() {
+                if name.starts_with(word) {
+                    names.push(name.to_string());
+                }
+            }
+            scope = current.parent();
+        }
+        names
+    };
+    (start, sorted(candidates))
+}
+
+// whether the statement being typed is an import
+fn is_import(before: &str) -> bool {
+    let statement = before.rsplit(';').next().unwrap_or("");
+    statement.trim_start().starts_with("import")
+}
+
+// a dotted path of bindings and data properties
+fn resolve(agent: &Agent, context: &Gc<GcCell<Context>>, path: &str) -> Option<Value> {
+    let mut parts = path.split('.');
+    let name = parts.next()?;
+    let mut value = context.borrow().scope.borrow().get(agent, name).ok()?;
+    for part in parts {
+        let key = ObjectKey::from(part);
+        let object = value.to_object(agent).ok()?;
+        value = std::iter::once(object.clone())
+            .chain(object.prototype_chain())
+            .find_map(|o| {
+                o.own_properties()
+                    .into_iter()
+                    .find(|(k, _, _)| *k == key)
+                    .map(|(_, property, _)| property)
+            })
+            .and_then(|property| match property {
+                Property::Data(v) => Some(v),
+                Property::Accessor(..) => None,
+            })?;
+    }
+    Some(value)
+}
+
+fn sorted(mut names: Vec<String>) -> Vec<String> {
+    names.sort();
+    names.dedup();
+    names
+}
@@ -62,6 +62,8 @@ pub struct AssemblerFunctionInfo {
     pub kind: FunctionKind,
     pub name: Option<String>,
     pub parameters: Vec<String>,
+    /// The number of parameters before the first one with a default.
+    pub length: usize,
     pub position: usize,
 }
 
@@ -108,7 +110,9 @@ impl Assembler {
             Node::RegexLiteral(r) => self.visit_regex(r),
             Node::ObjectLiteral(inits) => self.visit_object(inits),
             Node::ArrayLiteral(exprs) => self.visit_array(exprs),
-            Node::TupleLiteral(exprs) => self.visit_tuple(exprs),
+            Node::TupleLiteral(exprs) => self.visit_tuple(exprs, false),
+            Node::ImmutableTupleLiteral(exprs) => self.visit_tuple(exprs, true),
+            Node::RecordLiteral(inits) => self.visit_record(inits),
             Node::TemplateLiteral(quasis, exprs) => self.visit_template(quasis, exprs),
             Node::Identifier(var) => self.visit_identifier(var),
             Node::Block(scope, stmts) => self.visit_block(scope, stmts),
@@ -160,6 +164,7 @@ impl Assembler {
                 self.visit_try(tryc, binding, catch, finally)
             }
             Node::MatchExpression(expr, arms) => self.visit_match(expr, arms),
+            Node::PipelineExpression(value, expr) => self.visit_pipeline(value, expr),
             Node::ImportDeclaration(..)
             | Node::ImportNamedDeclaration(..)
             | Node::ImportDefaultDeclaration(..)
@@ -167,7 +172,8 @@ impl Assembler {
                 self.load_null();
             }
             Node::ExportDeclaration(decl) => self.visit_export(decl),
-            Node::Initializer(..) => unreachable!(),
+            Node::Commented(_, node) => self.visit(node),
+            Node::Initializer(..) | Node::Getter(..) | Node::Setter(..) => unreachable!(),
             Node::MatchArm(..) => unreachable!(),
             Node::ObjectPattern(..) | Node::ArrayPattern(..) => unreachable!(),
         }
@@ -186,7 +192,17 @@ impl Assembler {
     }
 
     fn visit_number(&mut self, n: f64) {
-        self.load_f64(n);
+        // match patterns can hold negative literals, and -0 has to stay a float
+        if n.fract() == 0.0
+            && n >= f64::from(std::i32::MIN)
+            && n <= f64::from(std::i32::MAX)
+            && !(n == 0.0 && n.is_sign_negative())
+        {
+            self.push_op(Op::LoadI32);
+            self.push_u32(n as i32 as u32);
+        } else {
+            self.load_f64(n);
+        }
     }
 
     fn visit_string(&mut self, s: &str) {
@@ -217,35 +233,67 @@ impl Assembler {
         self.load_accumulator_with_register(&array);
     }
 
-    fn visit_tuple(&mut self, exprs: &[Node]) {
+    fn visit_tuple(&mut self, exprs: &[Node], immutable: bool) {
         let rscope = RegisterScope::new(self);
         let tuple = rscope.register();
         self.push_op(Op::CreateEmptyTuple);
         self.store_accumulator_in_register(&tuple);
         for expr in exprs {
             self.visit(expr);
+            if immutable {
+                self.push_op(Op::RequireImmutable);
+            }
             self.push_op(Op::StoreInTuple);
             self.push_u32(tuple.id);
         }
         self.load_accumulator_with_register(&tuple);
     }
 
-    fn visit_object(&mut self, inits: &[Node]) {
+    fn visit_record(&mut self, inits: &[Node]) {
         let rscope = RegisterScope::new(self);
-        let obj = rscope.register();
+        let record = rscope.register();
         let key = rscope.register();
-        self.push_op(Op::CreateEmptyObject);
-        self.store_accumulator_in_register(&obj);
+        self.push_op(Op::CreateEmptyRecord);
+        self.store_accumulator_in_register(&record);
         for init in inits {
             if let Node::Initializer(name, value) = init {
                 self.visit(name);
                 self.store_accumulator_in_register(&key);
                 self.visit(value);
-                self.push_op(Op::StoreInObjectLiteral);
-                self.push_u32(obj.id);
+                self.push_op(Op::StoreInRecord);
+                self.push_u32(record.id);
                 self.push_u32(key.id);
-            } else {
-                unreachable!();
+            }
+        }
+        self.load_accumulator_with_register(&record);
+    }
+
+    fn visit_object(&mut self, inits: &[Node]) {
+        let rscope = RegisterScope::new(self);
+        let obj = rscope.register();
+        let key = rscope.register();
+        self.push_op(Op::CreateEmptyObject);
+        self.store_accumulator_in_register(&obj);
+        for init in inits {
+            match init {
+                Node::Initializer(name, value) => {
+                    self.visit(name);
+                    self.store_accumulator_in_register(&key);
+                    self.visit(value);
+                    self.push_op(Op::StoreInObjectLiteral);
+                    self.push_u32(obj.id);
+                    self.push_u32(key.id);
+                }
+                Node::Getter(name, f) | Node::Setter(name, f) => {
+                    self.visit(name);
+                    self.store_accumulator_in_register(&key);
+                    self.visit(f);
+                    self.push_op(Op::DefineAccessor);
+                    self.push_u32(obj.id);
+                    self.push_u32(key.id);
+                    self.push_u8(if let Node::Getter(..) = init { 1 } else { 0 });
+                }
+                _ => unreachable!(),
             }
         }
         self.load_accumulator_with_register(&obj);
@@ -744,6 +792,13 @@ impl Assembler {
                     _ => unreachable!(),
                 })
                 .collect::<Vec<String>>(),
+            length: params
+                .iter()
+                .take_while(|n| match n {
+                    Node::Initializer(..) => false,
+                    _ => true,
+                })
+                .count(),
         };
         let id = self.function_info.len();
         self.function_info.push(info);
@@ -751,19 +806,27 @@ impl Assembler {
         self.jump(&mut end); // 5
 
         if let Node::Block(scope, stmts) = body {
-            for param in params {
-                if let Node::Initializer(name, init) = param {
-                    if let Node::Identifier(name) = &**name {
-                        let mut label = self.label();
-                        self.visit_identifier(name);
-                        self.jump_if_not_empty(&mut label);
-                        self.visit(init);
-                        self.overwrite_binding(name);
-                        self.mark(&mut label);
-                    } else {
-                        unreachable!();
-                    }
+            // missing arguments from the first default on are left empty, and
+            // filled in here in order, so defaults can use earlier parameters
+            let length = self.function_info[id].length;
+            for param in &params[length..] {
+                let (name, init) = match param {
+                    Node::Initializer(name, init) => match &**name {
+                        Node::Identifier(name) => (name, Some(init)),
+                        _ => unreachable!(),
+                    },
+                    Node::Identifier(name) => (name, None),
+                    _ => unreachable!(),
+                };
+                let mut label = self.label();
+                self.visit_identifier(name);
+                self.jump_if_not_empty(&mut label);
+                match init {
+                    Some(init) => self.visit(init),
+                    None => self.load_null(),
                 }
+                self.overwrite_binding(name);
+                self.mark(&mut label);
             }
             for (name, mutable) in &scope.bindings {
                 self.lexical_declaration(name, *mutable);
@@ -956,89 +1019,129 @@ impl Assembler {
         self.visit(expr);
         self.store_accumulator_in_register(&value);
 
+        let mut exhaustive = false;
         for arm in arms {
-            if let Node::MatchArm(test, consequent) = arm {
-                let mut next = self.label();
-                match &**test {
-                    Node::Identifier(binding) => {
-                        self.push_op(Op::EnterScope);
-                        self.lexical_declaration(binding, false);
-                        self.load_accumulator_with_register(&value);
-                        self.lexical_initialization(binding);
-                        self.visit(consequent);
-                        self.push_op(Op::ExitScope);
-                    }
-                    Node::StringLiteral(..) | Node::NumberLiteral(..) => {
-                        self.visit(test);
-                        self.push_op(Op::Eq);
-                        self.push_u32(value.id);
-                        self.jump_if_false(&mut next);
-                        self.visit(consequent);
-                    }
-                    Node::ObjectPattern(patterns, wildcard) => {
-                        self.push_op(Op::EnterScope);
-                        if *wildcard {
-                            for (binding, _pattern) in patterns {
-                                self.load_string(binding);
-                                self.push_op(Op::HasProperty); // TODO: replace with equality for pattern
-                                self.push_u32(value.id);
-                                self.jump_if_false(&mut next);
-                                self.lexical_declaration(binding, false);
-                                self.load_accumulator_with_register(&value);
-                                self.load_named_property(binding);
-                                self.lexical_initialization(binding);
-                            }
-                        } else {
-                            let mut head = self.label();
-
-                            self.load_accumulator_with_register(&value);
-                            self.call_runtime(RuntimeFunction::ObjectKeys);
-                            let keys = rscope.register();
-                            self.store_accumulator_in_register(&keys);
-
-                            let keys_length = rscope.register();
-                            self.call_runtime(RuntimeFunction::ListLength);
-                            self.store_accumulator_in_register(&keys_length);
-
-                            self.load_f64(patterns.len() as f64);
-                            self.push_op(Op::Eq);
-                            self.push_u32(keys_length.id);
-                            self.jump_if_false(&mut next);
-
-                            self.mark(&mut head);
-
-                            self.load_accumulator_with_register(&keys);
-                            self.call_runtime(RuntimeFunction::ListPopFront);
-                            self.jump_if_empty(&mut next);
-
-                            self.push_op(Op::HasProperty); // TODO: replace with equality for pattern
-                            self.push_u32(value.id);
-                            self.jump_if_false(&mut next);
-                            /*
-                            self.lexical_declaration(binding, false);
-                            self.load_accumulator_with_register(&value);
-                            self.load_named_property(binding);
-                            self.lexical_initialization(binding);
-                            */
-                            self.jump(&mut head);
-                        }
-                        self.visit(consequent);
-                        self.push_op(Op::ExitScope);
-                    }
-                    Node::ArrayPattern(_patterns, _wildcard) => unreachable!(),
-                    _ => unreachable!(),
+            if let Node::MatchArm(pattern, guard, consequent) = arm {
+                let mut fail = self.label();
+                self.push_op(Op::EnterScope);
+                self.visit_pattern(pattern, &value, &mut fail);
+                if let Some(guard) = guard {
+                    self.visit(guard);
+                    self.jump_if_false(&mut fail);
                 }
+                self.visit(consequent);
+                self.push_op(Op::ExitScope);
                 self.jump(&mut end);
-                self.mark(&mut next);
+                if fail.targets.is_empty() {
+                    // the arms after this one can't be reached
+                    exhaustive = true;
+                    break;
+                }
+                self.mark(&mut fail);
+                self.push_op(Op::ExitScope);
             } else {
                 unreachable!();
             }
         }
 
-        self.load_null();
+        if !exhaustive {
+            self.load_accumulator_with_register(&value);
+            self.call_runtime(RuntimeFunction::NoMatch);
+        }
         self.mark(&mut end);
     }
 
+    // jumps to `fail` unless the value in `value` matches `pattern`,
+    // binding names in the current scope as it goes
+    fn visit_pattern(&mut self, pattern: &Node, value: &Register, fail: &mut Label) {
+        match pattern {
+            Node::Identifier(binding) if binding == "_" => {}
+            Node::Identifier(binding) => {
+                self.lexical_declaration(binding, false);
+                self.load_accumulator_with_register(value);
+                self.lexical_initialization(binding);
+            }
+            Node::NumberLiteral(..)
+            | Node::StringLiteral(..)
+            | Node::TrueLiteral
+            | Node::FalseLiteral
+            | Node::NullLiteral => {
+                self.visit(pattern);
+                self.push_op(Op::Eq);
+                self.push_u32(value.id);
+                self.jump_if_false(fail);
+            }
+            Node::ObjectPattern(patterns, wildcard) => {
+                let rscope = RegisterScope::new(self);
+                let scratch = rscope.register();
+
+                self.load_accumulator_with_register(value);
+                self.push_op(Op::Typeof);
+                self.store_accumulator_in_register(&scratch);
+                self.load_string("object");
+                self.push_op(Op::Eq);
+                self.push_u32(scratch.id);
+                self.jump_if_false(fail);
+
+                if !*wildcard {
+                    self.load_accumulator_with_register(value);
+                    self.call_runtime(RuntimeFunction::ObjectKeys);
+                    self.call_runtime(RuntimeFunction::ListLength);
+                    self.store_accumulator_in_register(&scratch);
+                    self.load_f64(patterns.len() as f64);
+                    self.push_op(Op::Eq);
+                    self.push_u32(scratch.id);
+                    self.jump_if_false(fail);
+                }
+
+                for (key, pattern) in patterns {
+                    self.load_string(key);
+                    self.push_op(Op::HasProperty);
+                    self.push_u32(value.id);
+                    self.jump_if_false(fail);
+                    self.load_accumulator_with_register(value);
+                    self.load_named_property(key);
+                    self.store_accumulator_in_register(&scratch);
+                    self.visit_pattern(pattern, &scratch, fail);
+                }
+            }
+            Node::ArrayPattern(patterns, wildcard) => {
+                let rscope = RegisterScope::new(self);
+                let scratch = rscope.register();
+
+                self.load_accumulator_with_register(value);
+                self.call_runtime(RuntimeFunction::ArrayLength);
+                self.store_accumulator_in_register(&scratch);
+                self.load_f64(patterns.len() as f64);
+                self.push_op(if *wildcard {
+                    Op::GreaterThanOrEqual
+                } else {
+                    Op::Eq
+                });
+                self.push_u32(scratch.id);
+                self.jump_if_false(fail);
+
+                for (i, pattern) in patterns.iter().enumerate() {
+                    self.load_f64(i as f64);
+                    self.load_computed_property(value);
+                    self.store_accumulator_in_register(&scratch);
+                    self.visit_pattern(pattern, &scratch, fail);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // `%` can't be written as an identifier, so it can't clash with one
+    fn visit_pipeline(&mut self, value: &Node, expr: &Node) {
+        self.visit(value);
+        self.push_op(Op::EnterScope);
+        self.lexical_declaration("%", false);
+        self.lexical_initialization("%");
+        self.visit(expr);
+        self.push_op(Op::ExitScope);
+    }
+
     fn visit_export(&mut self, decl: &Node) {
         self.visit(decl);
     }
@@ -1098,11 +1201,6 @@ impl Assembler {
         self.jmp(label);
     }
 
-    fn jump_if_empty(&mut self, label: &mut Label) {
-        self.push_op(Op::JumpIfEmpty);
-        self.jmp(label);
-    }
-
     fn jump_if_not_empty(&mut self, label: &mut Label) {
         self.push_op(Op::JumpIfNotEmpty);
         self.jmp(label);
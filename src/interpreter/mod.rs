@@ -1,8 +1,8 @@
 use crate::module::Module;
-use crate::num_util::{f64_band, f64_bnot, f64_bor, f64_bxor, f64_shl, f64_shr};
+use crate::num_util::{f64_band, f64_bnot, f64_bor, f64_bxor, f64_shl, f64_shr, i32_mul};
 use crate::parser::FunctionKind;
 use crate::runtime::RuntimeFunction;
-use crate::value::{ObjectKey, ObjectKind};
+use crate::value::{Builtin, ObjectKey, ObjectKind};
 use crate::{Agent, Value};
 use byteorder::{LittleEndian, ReadBytesExt};
 use gc::{Gc, GcCell};
@@ -38,6 +38,7 @@ macro_rules! OPS {
             (LoadTrue, AccumulatorUse::Write),
             (LoadFalse, AccumulatorUse::Write),
             (LoadF64, AccumulatorUse::Write, OpArg::F64),
+            (LoadI32, AccumulatorUse::Write, OpArg::U32),
             (LoadString, AccumulatorUse::Write, OpArg::String),
             (LoadSymbol, AccumulatorUse::Write, OpArg::String),
 
@@ -46,8 +47,12 @@ macro_rules! OPS {
             (StoreInArrayLiteral, AccumulatorUse::Read, OpArg::Register, OpArg::U32),
             (CreateEmptyTuple, AccumulatorUse::Write),
             (StoreInTuple, AccumulatorUse::Read, OpArg::Register),
+            (CreateEmptyRecord, AccumulatorUse::Write),
+            (StoreInRecord, AccumulatorUse::Read, OpArg::Register, OpArg::Register),
+            (RequireImmutable, AccumulatorUse::ReadWrite),
             (CreateEmptyObject, AccumulatorUse::Write),
             (StoreInObjectLiteral, AccumulatorUse::Read, OpArg::Register, OpArg::Register),
+            (DefineAccessor, AccumulatorUse::Read, OpArg::Register, OpArg::Register, OpArg::Boolean),
             (NewFunction, AccumulatorUse::ReadWrite, OpArg::FunctionInfo),
             (FinishClass, AccumulatorUse::ReadWrite, OpArg::Register, OpArg::Register, OpArg::String),
 
@@ -77,7 +82,6 @@ macro_rules! OPS {
             (Jump, AccumulatorUse::None, OpArg::Position),
             (JumpIfTrue, AccumulatorUse::Read, OpArg::Position),
             (JumpIfFalse, AccumulatorUse::Read, OpArg::Position),
-            (JumpIfEmpty, AccumulatorUse::Read, OpArg::Position),
             (JumpIfNotEmpty, AccumulatorUse::Read, OpArg::Position),
 
             (PushTry, AccumulatorUse::None,OpArg::Position),
@@ -224,7 +228,7 @@ impl Scope {
         self.bindings.get_mut(name).unwrap().value = Some(value);
     }
 
-    fn get(&self, agent: &Agent, name: &str) -> Result<Value, Value> {
+    pub fn get(&self, agent: &Agent, name: &str) -> Result<Value, Value> {
         match self.bindings.get(name) {
             Some(Binding { value: Some(v), .. }) => Ok(v.clone()),
             Some(Binding {
@@ -238,6 +242,18 @@ impl Scope {
         }
     }
 
+    /// The names declared in this scope, not its parents, in the order they
+    /// were declared, and whether each can be reassigned.
+    pub fn names(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.bindings
+            .iter()
+            .map(|(name, binding)| (name.as_str(), binding.mutable))
+    }
+
+    pub fn parent(&self) -> Option<Gc<GcCell<Scope>>> {
+        self.parent.clone()
+    }
+
     fn set(&mut self, agent: &Agent, name: &str, value: Value) -> Result<(), Value> {
         match self.bindings.get_mut(name) {
             Some(b) => {
@@ -273,6 +289,8 @@ pub struct Context {
     pub scope: Gc<GcCell<Scope>>,
     pub interpreter: Option<Interpreter>,
     pub function: Option<Value>,
+    /// The filename of the module whose body this runs.
+    pub module: Option<String>,
     try_stack: Vec<usize>,
 }
 
@@ -282,9 +300,19 @@ impl Context {
             scope,
             interpreter: None,
             function: None,
+            module: None,
             try_stack: Vec::new(),
         }))
     }
+
+    // module bodies show up on the call stack as their filename
+    fn frame(&self) -> Value {
+        match (&self.function, &self.module) {
+            (Some(f), _) => f.clone(),
+            (None, Some(filename)) => Value::from(filename.as_str()),
+            (None, None) => Value::Null,
+        }
+    }
 }
 
 #[derive(Debug, Trace, Finalize)]
@@ -338,6 +366,18 @@ impl std::ops::IndexMut<usize> for Registers {
     }
 }
 
+/// The method an object has for an operator, under a well-known symbol
+/// like `:add`, so types builtins provide can work with operators.
+fn operator_method(agent: &Agent, value: &Value, symbol: &str) -> Result<Option<Value>, Value> {
+    if let Value::Object(..) = value {
+        let f = value.get(agent, ObjectKey::well_known_symbol(symbol))?;
+        if f.type_of() == "function" {
+            return Ok(Some(f));
+        }
+    }
+    Ok(None)
+}
+
 #[derive(Debug, Trace, Finalize)]
 pub struct Interpreter {
     pub accumulator: Value,
@@ -361,8 +401,26 @@ impl Interpreter {
     }
 
     pub fn run(&mut self, agent: &Agent) -> Result<Result<Value, Value>, SuspendValue> {
+        // suspended interpreters resume on a different stack, so frames are
+        // pushed for every context on entry and dropped again on exit
+        let depth = agent.frame_depth();
+        for ctx in &self.context {
+            agent.push_frame(ctx.borrow().frame());
+        }
+        let result = self.run_frames(agent);
+        agent.truncate_frames(depth);
+        // integers stay inside the interpreter
+        match result {
+            Ok(Ok(v)) => Ok(Ok(v.canonical())),
+            Ok(Err(e)) => Ok(Err(e.canonical())),
+            Err(SuspendValue(ref v)) => Err(SuspendValue(v.clone().canonical())),
+        }
+    }
+
+    fn run_frames(&mut self, agent: &Agent) -> Result<Result<Value, Value>, SuspendValue> {
         macro_rules! push_context {
             ($ctx:expr) => {
+                agent.push_frame($ctx.borrow().frame());
                 self.context.push($ctx);
                 unsafe {
                     std::ptr::write(
@@ -375,6 +433,7 @@ impl Interpreter {
 
         macro_rules! pop_context {
             () => {
+                agent.pop_frame();
                 self.context.pop().unwrap();
                 self.registers = *self.registers.last.take().unwrap();
             };
@@ -408,17 +467,56 @@ impl Interpreter {
             }};
         }
 
+        // an operator on something other than numbers calls the method the
+        // lhs has for it, `lhs[:add](rhs)` for `lhs + rhs`
+        macro_rules! overload {
+            ($lhsid:expr, $symbol:expr, $message:expr) => {{
+                let lhs = self.registers[$lhsid].clone();
+                match handle!(operator_method(agent, &lhs, $symbol)) {
+                    Some(f) => {
+                        let rhs = std::mem::replace(&mut self.accumulator, Value::Empty);
+                        self.accumulator = handle!(f.call(agent, lhs, vec![rhs]));
+                    }
+                    None => handle!(Err(Value::new_error(agent, $message))),
+                }
+            }};
+        }
+
         macro_rules! num_binop_num {
-            ($fn:expr) => {{
+            ($fn:expr, $symbol:expr) => {{
                 let lhsid = read_u32!() as usize;
-                match self.registers[lhsid] {
-                    Value::Number(ln) => match self.accumulator {
-                        Value::Number(rn) => {
+                match self.registers[lhsid].as_f64() {
+                    Some(ln) => match self.accumulator.as_f64() {
+                        Some(rn) => {
                             self.accumulator = Value::from($fn(ln, rn));
                         }
-                        _ => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
+                        None => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
+                    },
+                    None => overload!(lhsid, $symbol, "lhs must be a number"),
+                }
+            }};
+        }
+
+        // i32 arithmetic when both sides are integers and the result fits,
+        // f64 otherwise
+        macro_rules! int_binop_num {
+            ($int:expr, $fn:expr, $symbol:expr) => {{
+                let lhsid = read_u32!() as usize;
+                let result = match (&self.registers[lhsid], &self.accumulator) {
+                    (Value::Integer(ln), Value::Integer(rn)) => $int(*ln, *rn),
+                    _ => None,
+                };
+                match result {
+                    Some(n) => self.accumulator = Value::Integer(n),
+                    None => match self.registers[lhsid].as_f64() {
+                        Some(ln) => match self.accumulator.as_f64() {
+                            Some(rn) => {
+                                self.accumulator = Value::from($fn(ln, rn));
+                            }
+                            None => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
+                        },
+                        None => overload!(lhsid, $symbol, "lhs must be a number"),
                     },
-                    _ => handle!(Err(Value::new_error(agent, "lhs must be a number"))),
                 }
             }};
         }
@@ -426,14 +524,33 @@ impl Interpreter {
         macro_rules! num_binop_bool {
             ($fn:expr) => {{
                 let lhsid = read_u32!() as usize;
-                match self.registers[lhsid] {
-                    Value::Number(ln) => match self.accumulator {
-                        Value::Number(rn) => {
-                            self.accumulator = Value::from($fn(&ln, &rn));
+                if let (Value::Integer(ln), Value::Integer(rn)) =
+                    (&self.registers[lhsid], &self.accumulator)
+                {
+                    self.accumulator = Value::from($fn(ln, rn));
+                } else {
+                    match self.registers[lhsid].as_f64() {
+                        Some(ln) => match self.accumulator.as_f64() {
+                            Some(rn) => {
+                                self.accumulator = Value::from($fn(&ln, &rn));
+                            }
+                            None => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
+                        },
+                        // `:compare` returns a negative number, zero or a
+                        // positive number, like the ones `sort` takes
+                        None => {
+                            overload!(lhsid, "compare", "lhs must be a number");
+                            match self.accumulator.as_f64() {
+                                Some(order) => {
+                                    self.accumulator = Value::from($fn(&order, &0.0));
+                                }
+                                None => handle!(Err(Value::new_error(
+                                    agent,
+                                    ":compare must return a number"
+                                ))),
+                            }
                         }
-                        _ => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
-                    },
-                    _ => handle!(Err(Value::new_error(agent, "lhs must be a number"))),
+                    }
                 }
             }};
         }
@@ -445,6 +562,7 @@ impl Interpreter {
                     Some(context) => {
                         if let Some(pc) = context.borrow_mut().try_stack.pop() {
                             self.pc = pc;
+                            break;
                         }
                     }
                 }
@@ -512,6 +630,10 @@ impl Interpreter {
                     let n = read_f64!();
                     self.accumulator = Value::from(n);
                 }
+                Op::LoadI32 => {
+                    let n = read_u32!() as i32;
+                    self.accumulator = Value::Integer(n);
+                }
                 Op::LoadString => {
                     let sid = read_u32!() as usize;
                     let s = agent.assembler.string_table[sid].as_str();
@@ -706,29 +828,43 @@ impl Interpreter {
                             {
                                 slow_call!();
                             }
+                            ObjectKind::BuiltinFunction(Builtin::Slice(f), ..) if argc <= 4 => {
+                                let f = *f;
+                                let mut args = [Value::Null, Value::Null, Value::Null, Value::Null];
+                                for (i, arg) in args.iter_mut().take(argc).enumerate() {
+                                    *arg = self.registers[sargid + i].clone().canonical();
+                                }
+                                let receiver =
+                                    std::mem::replace(&mut self.registers[rid], Value::Empty);
+                                self.accumulator =
+                                    handle!(callee.call_slice(agent, f, receiver, &args[..argc]));
+                            }
                             ObjectKind::BuiltinFunction(..) => {
                                 slow_call!();
                             }
                             ObjectKind::BytecodeFunction {
                                 position,
                                 parameters,
+                                length,
                                 scope,
                                 kind,
-                                ..
                             } => {
                                 let scope = Scope::new(Some(scope.clone()));
                                 let ctx = Context::new(scope.clone());
+                                ctx.borrow_mut().function = Some(callee.clone());
                                 for (i, param) in parameters.iter().enumerate() {
                                     handle!(scope.borrow_mut().create(agent, param, false));
-                                    let value = if i >= argc {
-                                        Value::Empty
-                                    } else {
+                                    let value = if i < argc {
                                         self.registers[sargid + i].clone()
+                                    } else if i < *length {
+                                        Value::Null
+                                    } else {
+                                        Value::Empty
                                     };
                                     scope.borrow_mut().initialize(param, value);
                                 }
                                 if *kind & FunctionKind::Arrow == FunctionKind::Arrow {
-                                    // FIXME: doesn't have `this` vs inherited `this` needs to be clarified
+                                    // arrows take `this` from the scope they were created in
                                 } else if self.registers[rid].type_of() == "null" {
                                     scope.borrow_mut().this = Some(Value::Null);
                                 } else {
@@ -764,16 +900,18 @@ impl Interpreter {
                 }
                 Op::IteratorNext => {
                     let iid = read_u32!() as usize;
-                    if let Value::Iterator(iterator, next) = &self.registers[iid] {
-                        self.accumulator = handle!(next.call(agent, (**iterator).clone(), vec![]));
+                    if let Value::Iterator(pair) = &self.registers[iid] {
+                        let (iterator, next) = &**pair;
+                        self.accumulator = handle!(next.call(agent, iterator.clone(), vec![]));
                     } else {
                         unreachable!()
                     }
                 }
                 Op::AsyncIteratorNext => {
                     let iid = read_u32!() as usize;
-                    if let Value::Iterator(iterator, next) = &self.registers[iid] {
-                        let promise = handle!(next.call(agent, (**iterator).clone(), vec![]));
+                    if let Value::Iterator(pair) = &self.registers[iid] {
+                        let (iterator, next) = &**pair;
+                        let promise = handle!(next.call(agent, iterator.clone(), vec![]));
                         return Err(SuspendValue(promise));
                     } else {
                         unreachable!()
@@ -795,12 +933,6 @@ impl Interpreter {
                         self.pc = position;
                     }
                 }
-                Op::JumpIfEmpty => {
-                    let position = read_u32!() as usize;
-                    if self.accumulator == Value::Empty {
-                        self.pc = position;
-                    }
-                }
                 Op::JumpIfNotEmpty => {
                     let position = read_u32!() as usize;
                     if self.accumulator != Value::Empty {
@@ -855,11 +987,27 @@ impl Interpreter {
                 Op::StoreInTuple => {
                     let tid = read_u32!() as usize;
                     if let Value::Tuple(items) = &mut self.registers[tid] {
-                        items.push(std::mem::replace(&mut self.accumulator, Value::Empty));
+                        items.push(
+                            std::mem::replace(&mut self.accumulator, Value::Empty).canonical(),
+                        );
                     } else {
                         unreachable!();
                     }
                 }
+                Op::CreateEmptyRecord => {
+                    self.accumulator = Value::new_record();
+                }
+                Op::StoreInRecord => {
+                    let rid = read_u32!() as usize;
+                    let kid = read_u32!() as usize;
+                    let key = handle!(self.registers[kid].to_object_key(agent));
+                    let value = std::mem::replace(&mut self.accumulator, Value::Empty);
+                    handle!(self.registers[rid].insert_record_field(agent, key, value));
+                }
+                Op::RequireImmutable => {
+                    let value = std::mem::replace(&mut self.accumulator, Value::Empty);
+                    self.accumulator = handle!(value.require_immutable(agent));
+                }
                 Op::CreateEmptyObject => {
                     self.accumulator = Value::new_object(agent.intrinsics.object_prototype.clone());
                 }
@@ -869,6 +1017,13 @@ impl Interpreter {
                     let key = handle!(self.registers[kid].to_object_key(agent));
                     handle!(self.registers[oid].set(agent, key, self.accumulator.clone()));
                 }
+                Op::DefineAccessor => {
+                    let oid = read_u32!() as usize;
+                    let kid = read_u32!() as usize;
+                    let getter = read_u8!() == 1;
+                    let key = handle!(self.registers[kid].to_object_key(agent));
+                    self.registers[oid].define_accessor(key, getter, self.accumulator.clone());
+                }
                 Op::NewFunction => {
                     let id = read_u32!() as usize;
                     let info = &agent.assembler.function_info[id];
@@ -896,38 +1051,50 @@ impl Interpreter {
                 Op::Add => {
                     let lhsid = read_u32!() as usize;
                     match self.registers[lhsid] {
-                        Value::Number(ln) => match self.accumulator {
+                        Value::Integer(ln) => match self.accumulator {
+                            Value::Integer(rn) if ln.checked_add(rn).is_some() => {
+                                self.accumulator = Value::Integer(ln + rn);
+                            }
+                            Value::Integer(rn) => {
+                                self.accumulator = Value::from(f64::from(ln) + f64::from(rn));
+                            }
                             Value::Number(rn) => {
-                                self.accumulator = Value::from(ln + rn);
+                                self.accumulator = Value::from(f64::from(ln) + rn);
                             }
                             _ => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
                         },
+                        Value::Number(ln) => match self.accumulator.as_f64() {
+                            Some(rn) => {
+                                self.accumulator = Value::from(ln + rn);
+                            }
+                            None => handle!(Err(Value::new_error(agent, "rhs must be a number"))),
+                        },
                         Value::String(ref ls) => match self.accumulator {
                             Value::String(ref rs) => {
-                                self.accumulator = Value::from(format!("{}{}", ls, rs));
+                                let mut s = String::with_capacity(ls.len() + rs.len());
+                                s.push_str(ls);
+                                s.push_str(rs);
+                                self.accumulator = Value::from(s);
                             }
                             _ => handle!(Err(Value::new_error(agent, "rhs must be a string"))),
                         },
-                        _ => handle!(Err(Value::new_error(
-                            agent,
-                            "lhs must be a number or string"
-                        ))),
+                        _ => overload!(lhsid, "add", "lhs must be a number or string"),
                     }
                 }
-                Op::Sub => num_binop_num!(f64::sub),
-                Op::Mul => num_binop_num!(f64::mul),
-                Op::Div => num_binop_num!(f64::div),
-                Op::Mod => num_binop_num!(f64::rem),
-                Op::Pow => num_binop_num!(f64::powf),
-                Op::BitOR => num_binop_num!(f64_bor),
-                Op::BitXOR => num_binop_num!(f64_bxor),
-                Op::BitAND => num_binop_num!(f64_band),
-                Op::ShiftLeft => num_binop_num!(f64_shl),
-                Op::ShiftRight => num_binop_num!(f64_shr),
-                Op::GreaterThan => num_binop_bool!(f64::gt),
-                Op::LessThan => num_binop_bool!(f64::lt),
-                Op::GreaterThanOrEqual => num_binop_bool!(f64::ge),
-                Op::LessThanOrEqual => num_binop_bool!(f64::le),
+                Op::Sub => int_binop_num!(i32::checked_sub, f64::sub, "sub"),
+                Op::Mul => int_binop_num!(i32_mul, f64::mul, "mul"),
+                Op::Div => num_binop_num!(f64::div, "div"),
+                Op::Mod => num_binop_num!(f64::rem, "mod"),
+                Op::Pow => num_binop_num!(f64::powf, "pow"),
+                Op::BitOR => num_binop_num!(f64_bor, "bitOr"),
+                Op::BitXOR => num_binop_num!(f64_bxor, "bitXor"),
+                Op::BitAND => num_binop_num!(f64_band, "bitAnd"),
+                Op::ShiftLeft => num_binop_num!(f64_shl, "shiftLeft"),
+                Op::ShiftRight => num_binop_num!(f64_shr, "shiftRight"),
+                Op::GreaterThan => num_binop_bool!(PartialOrd::gt),
+                Op::LessThan => num_binop_bool!(PartialOrd::lt),
+                Op::GreaterThanOrEqual => num_binop_bool!(PartialOrd::ge),
+                Op::LessThanOrEqual => num_binop_bool!(PartialOrd::le),
                 Op::HasProperty => {
                     let lhsid = read_u32!() as usize;
                     let target = handle!(self.registers[lhsid].to_object(agent));
@@ -935,22 +1102,30 @@ impl Interpreter {
                     let r = handle!(target.has(agent, key));
                     self.accumulator = Value::from(r);
                 }
-                Op::Eq => {
-                    let lhsid = read_u32!() as usize;
-                    self.accumulator = Value::from(self.registers[lhsid] == self.accumulator);
-                }
-                Op::Neq => {
+                Op::Eq | Op::Neq => {
                     let lhsid = read_u32!() as usize;
-                    self.accumulator = Value::from(self.registers[lhsid] != self.accumulator);
+                    let lhs = self.registers[lhsid].clone();
+                    let equal = if lhs == self.accumulator {
+                        true
+                    } else {
+                        match handle!(operator_method(agent, &lhs, "equals")) {
+                            Some(f) => {
+                                let rhs = self.accumulator.clone();
+                                handle!(f.call(agent, lhs, vec![rhs])).to_bool()
+                            }
+                            None => false,
+                        }
+                    };
+                    self.accumulator = Value::from(equal == (op == Op::Eq));
                 }
                 Op::LNOT => {
                     self.accumulator = Value::from(!self.accumulator.to_bool());
                 }
-                Op::BitNOT => match self.accumulator {
-                    Value::Number(n) => {
+                Op::BitNOT => match self.accumulator.as_f64() {
+                    Some(n) => {
                         self.accumulator = Value::from(f64_bnot(n));
                     }
-                    _ => handle!(Err(Value::new_error(agent, "operand must be a number"))),
+                    None => handle!(Err(Value::new_error(agent, "operand must be a number"))),
                 },
                 Op::Typeof => {
                     self.accumulator = Value::from(self.accumulator.type_of());
@@ -958,11 +1133,28 @@ impl Interpreter {
                 Op::Void => {
                     self.accumulator = Value::Null;
                 }
+                // -0 and -i32::MIN don't fit in an i32
                 Op::UnSub => match self.accumulator {
-                    Value::Number(n) => {
-                        self.accumulator = Value::from(-n);
+                    Value::Integer(n) if n != 0 && n != i32::min_value() => {
+                        self.accumulator = Value::Integer(-n);
                     }
-                    _ => handle!(Err(Value::new_error(agent, "operand must be a number"))),
+                    _ => match self.accumulator.as_f64() {
+                        Some(n) => {
+                            self.accumulator = Value::from(-n);
+                        }
+                        None => {
+                            let operand = self.accumulator.clone();
+                            match handle!(operator_method(agent, &operand, "negate")) {
+                                Some(f) => {
+                                    self.accumulator = handle!(f.call(agent, operand, vec![]));
+                                }
+                                None => handle!(Err(Value::new_error(
+                                    agent,
+                                    "operand must be a number"
+                                ))),
+                            }
+                        }
+                    },
                 },
             }
         }
@@ -2,7 +2,7 @@ use crate::agent::Agent;
 use crate::interpreter::Context;
 use crate::value::{ObjectKey, Value};
 
-fn next(agent: &Agent, _args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+fn next(agent: &Agent, _args: &[Value], ctx: &Context) -> Result<Value, Value> {
     let o = ctx.scope.borrow().get_this(agent)?;
     if o.type_of() != "object" {
         return Err(Value::new_error(agent, "invalid receiver"));
@@ -37,7 +37,7 @@ pub fn create_array_iterator_prototype(agent: &Agent) -> Value {
         .set(
             agent,
             ObjectKey::from("next"),
-            Value::new_builtin_function(agent, next),
+            Value::new_slice_builtin_function(agent, next),
         )
         .unwrap();
 
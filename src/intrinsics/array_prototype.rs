@@ -1,4 +1,5 @@
 use crate::interpreter::Context;
+use crate::num_util::to_string;
 use crate::sort::merge_sort;
 use crate::value::{ObjectKey, ObjectKind};
 use crate::{Agent, Value};
@@ -27,17 +28,17 @@ fn builtin_sort(agent: &Agent, a: &Value, b: &Value) -> Result<std::cmp::Orderin
     }
 }
 
-fn sort(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+fn sort(agent: &Agent, args: &[Value], ctx: &Context) -> Result<Value, Value> {
     match ctx.scope.borrow().get_this(agent)? {
         Value::Object(o) => match &o.kind {
             ObjectKind::Array(values) => {
+                let mut values = values.borrow_mut();
+                let values = values.make_dense();
                 match args.get(0).unwrap_or(&Value::Null) {
-                    Value::Null => {
-                        merge_sort(&mut values.borrow_mut(), |a, b| -> Result<bool, Value> {
-                            Ok(builtin_sort(agent, a, b)? == std::cmp::Ordering::Less)
-                        })?
-                    }
-                    v => merge_sort(&mut values.borrow_mut(), |a, b| -> Result<bool, Value> {
+                    Value::Null => merge_sort(values, |a, b| -> Result<bool, Value> {
+                        Ok(builtin_sort(agent, a, b)? == std::cmp::Ordering::Less)
+                    })?,
+                    v => merge_sort(values, |a, b| -> Result<bool, Value> {
                         Ok(user_sort(agent, v, a, b)? == std::cmp::Ordering::Less)
                     })?,
                 };
@@ -49,27 +50,196 @@ fn sort(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value>
     }
 }
 
-fn iterator(agent: &Agent, _args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+fn push(agent: &Agent, args: &[Value], ctx: &Context) -> Result<Value, Value> {
+    match ctx.scope.borrow().get_this(agent)? {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => {
+                let mut values = values.borrow_mut();
+                if values.len() + args.len() > u32::MAX as usize {
+                    return Err(Value::new_range_error(agent, "invalid array length"));
+                }
+                for arg in args {
+                    values.push(arg.clone());
+                }
+                Ok(Value::from(values.len() as f64))
+            }
+            _ => Err(Value::new_error(agent, "invalid receiver")),
+        },
+        _ => Err(Value::new_error(agent, "invalid receiver")),
+    }
+}
+
+fn pop(agent: &Agent, _args: &[Value], ctx: &Context) -> Result<Value, Value> {
+    match ctx.scope.borrow().get_this(agent)? {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => Ok(values.borrow_mut().pop().unwrap_or(Value::Null)),
+            _ => Err(Value::new_error(agent, "invalid receiver")),
+        },
+        _ => Err(Value::new_error(agent, "invalid receiver")),
+    }
+}
+
+// the longest string `join` builds before giving up with a RangeError
+const MAX_JOIN_LENGTH: usize = 1 << 30;
+
+// nested arrays join with commas, and an array inside itself joins as ""
+fn element_string(agent: &Agent, value: Value, seen: &mut Vec<Value>) -> Result<String, Value> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(s) => Ok(s.to_string()),
+        Value::Number(n) => Ok(to_string(n)),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Object(ref o) if matches!(o.kind, ObjectKind::Array(..)) => {
+            if seen.contains(&value) {
+                return Ok(String::new());
+            }
+            seen.push(value.clone());
+            let joined = join_array(agent, &value, ",", seen);
+            seen.pop();
+            joined
+        }
+        v => {
+            let ts = v.get(agent, ObjectKey::well_known_symbol("toString"))?;
+            if ts.type_of() != "function" {
+                return Err(Value::new_error(
+                    agent,
+                    "value does not provide a :toString",
+                ));
+            }
+            match ts.call(agent, v, vec![])? {
+                Value::String(s) => Ok(s.to_string()),
+                _ => Err(Value::new_error(agent, ":toString must return a string")),
+            }
+        }
+    }
+}
+
+// only the elements that were written are converted, holes are only
+// counted, so a sparse array doesn't turn into a huge list first
+fn join_array(
+    agent: &Agent,
+    array: &Value,
+    separator: &str,
+    seen: &mut Vec<Value>,
+) -> Result<String, Value> {
+    let (length, elements) = match array {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => {
+                let values = values.borrow();
+                let elements = values
+                    .indices()
+                    .map(|i| (i, values.get(i)))
+                    .collect::<Vec<_>>();
+                (values.len(), elements)
+            }
+            _ => return Err(Value::new_error(agent, "invalid receiver")),
+        },
+        _ => return Err(Value::new_error(agent, "invalid receiver")),
+    };
+    let too_long = || Value::new_range_error(agent, "joined string is too long");
+    let mut total = separator
+        .len()
+        .checked_mul(length.saturating_sub(1))
+        .filter(|n| *n <= MAX_JOIN_LENGTH)
+        .ok_or_else(too_long)?;
+    let mut parts = Vec::with_capacity(elements.len());
+    for (index, value) in elements {
+        let part = element_string(agent, value, seen)?;
+        total += part.len();
+        if total > MAX_JOIN_LENGTH {
+            return Err(too_long());
+        }
+        parts.push((index, part));
+    }
+    // the output is allocated once, at its final length
+    let mut out = String::with_capacity(total);
+    let mut next = 0;
+    for (index, part) in parts {
+        for _ in next..index {
+            out.push_str(separator);
+        }
+        out.push_str(&part);
+        next = index;
+    }
+    for _ in next..length.saturating_sub(1) {
+        out.push_str(separator);
+    }
+    Ok(out)
+}
+
+fn join(agent: &Agent, args: &[Value], ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let separator = match args.get(0).unwrap_or(&Value::Null) {
+        Value::Null => ",",
+        Value::String(s) => s.as_str(),
+        _ => return Err(Value::new_error(agent, "separator must be a string")),
+    };
+    let mut seen = vec![this.clone()];
+    Ok(Value::from(join_array(agent, &this, separator, &mut seen)?))
+}
+
+fn iterator(agent: &Agent, _args: &[Value], ctx: &Context) -> Result<Value, Value> {
     let it = Value::new_custom_object(agent.intrinsics.array_iterator_prototype.clone());
     it.set_slot("array iterator next index", Value::from(0));
     it.set_slot("iterated object", ctx.scope.borrow().get_this(agent)?);
     Ok(it)
 }
 
+// `==` on buffers compares their bytes, other arrays are only equal to
+// themselves
+fn equals(agent: &Agent, args: &[Value], ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let other = args.get(0).unwrap_or(&Value::Null);
+    if let (Value::Object(a), Value::Object(b)) = (&this, other) {
+        if let (ObjectKind::Buffer(a), ObjectKind::Buffer(b)) = (&a.kind, &b.kind) {
+            return Ok(Value::from(*a.borrow() == *b.borrow()));
+        }
+    }
+    Ok(Value::from(this == *other))
+}
+
 pub fn create_array_prototype(agent: &Agent) -> Value {
     let p = Value::new_object(agent.intrinsics.object_prototype.clone());
 
     p.set(
         agent,
         ObjectKey::from("sort"),
-        Value::new_builtin_function(agent, sort),
+        Value::new_slice_builtin_function(agent, sort),
+    )
+    .unwrap();
+
+    p.set(
+        agent,
+        ObjectKey::from("push"),
+        Value::new_slice_builtin_function(agent, push),
+    )
+    .unwrap();
+
+    p.set(
+        agent,
+        ObjectKey::from("pop"),
+        Value::new_slice_builtin_function(agent, pop),
+    )
+    .unwrap();
+
+    p.set(
+        agent,
+        ObjectKey::from("join"),
+        Value::new_slice_builtin_function(agent, join),
     )
     .unwrap();
 
     p.set(
         agent,
         ObjectKey::well_known_symbol("iterator"),
-        Value::new_builtin_function(agent, iterator),
+        Value::new_slice_builtin_function(agent, iterator),
+    )
+    .unwrap();
+
+    p.set(
+        agent,
+        ObjectKey::well_known_symbol("equals"),
+        Value::new_slice_builtin_function(agent, equals),
     )
     .unwrap();
 
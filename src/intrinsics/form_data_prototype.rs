@@ -0,0 +1,110 @@
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use crate::Agent;
+
+fn get_entries(agent: &Agent, ctx: &Context) -> Result<Vec<(String, Value)>, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("form data") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    match this.get_slot("form data") {
+        Value::List(list) => Ok(list
+            .borrow()
+            .iter()
+            .map(|entry| match entry {
+                Value::Tuple(t) => match &t[0] {
+                    Value::String(name) => (name.to_string(), t[1].clone()),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            })
+            .collect()),
+        _ => unreachable!(),
+    }
+}
+
+fn name_argument(agent: &Agent, args: &[Value]) -> Result<String, Value> {
+    match args.get(0) {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        _ => Err(Value::new_error(agent, "name must be a string")),
+    }
+}
+
+fn get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let name = name_argument(agent, &args)?;
+    Ok(get_entries(agent, ctx)?
+        .into_iter()
+        .find(|(n, _)| *n == name)
+        .map_or(Value::Null, |(_, v)| v))
+}
+
+fn get_all(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let name = name_argument(agent, &args)?;
+    let values = get_entries(agent, ctx)?
+        .into_iter()
+        .filter(|(n, _)| *n == name)
+        .map(|(_, v)| v)
+        .collect();
+    Ok(Value::new_array_from_vec(agent, values))
+}
+
+fn has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let name = name_argument(agent, &args)?;
+    Ok(Value::from(
+        get_entries(agent, ctx)?.iter().any(|(n, _)| *n == name),
+    ))
+}
+
+fn entries(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let entries = get_entries(agent, ctx)?
+        .into_iter()
+        .map(|(n, v)| Value::Tuple(Box::new(vec![Value::from(n), v])))
+        .collect();
+    let array = Value::new_array_from_vec(agent, entries);
+    array
+        .get(agent, ObjectKey::well_known_symbol("iterator"))?
+        .call(agent, array, vec![])
+}
+
+/// Text fields are strings, file fields are objects describing the upload.
+pub fn new_form_data(agent: &Agent, entries: Vec<(String, Value)>) -> Value {
+    let o = Value::new_custom_object(agent.intrinsics.form_data_prototype.get(agent));
+    o.set_slot(
+        "form data",
+        Value::new_list_from_iter(
+            entries
+                .into_iter()
+                .map(|(n, v)| Value::Tuple(Box::new(vec![Value::from(n), v]))),
+        ),
+    );
+    o
+}
+
+pub fn create_form_data_prototype(agent: &Agent) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("get", get);
+    method!("getAll", get_all);
+    method!("has", has);
+    method!("entries", entries);
+    proto
+        .set(
+            agent,
+            ObjectKey::well_known_symbol("iterator"),
+            Value::new_builtin_function(agent, entries),
+        )
+        .unwrap();
+
+    proto
+}
@@ -4,7 +4,8 @@ use crate::value::{ObjectKey, Value};
 
 fn next(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     let this = ctx.scope.borrow().get_this(agent)?;
-    if let Value::WrappedContext(context, _) = this.get_slot("generator context") {
+    if let Value::WrappedContext(wrapped) = this.get_slot("generator context") {
+        let (context, _) = *wrapped;
         let mut args = args;
         if context.borrow_mut().interpreter.is_none() {
             Value::new_iter_result(agent, Value::Null, true)
@@ -31,7 +32,8 @@ fn next(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value>
 
 fn throw(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     let this = ctx.scope.borrow().get_this(agent)?;
-    if let Value::WrappedContext(context, _) = this.get_slot("generator context") {
+    if let Value::WrappedContext(wrapped) = this.get_slot("generator context") {
+        let (context, _) = *wrapped;
         let mut args = args;
         if context.borrow_mut().interpreter.is_none() {
             Value::new_iter_result(agent, Value::Null, true)
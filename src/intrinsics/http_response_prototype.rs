@@ -0,0 +1,119 @@
+use crate::interpreter::Context;
+use crate::intrinsics::form_data_prototype::new_form_data;
+use crate::intrinsics::promise::{new_promise_capability, promise_resolve_i};
+use crate::intrinsics::url::parse_form;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use crate::Agent;
+
+fn settle(agent: &Agent, result: Result<Value, Value>) -> Result<Value, Value> {
+    match result {
+        Ok(v) => promise_resolve_i(agent, agent.intrinsics.promise.clone(), v),
+        Err(e) => {
+            let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+            promise
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![e])?;
+            Ok(promise)
+        }
+    }
+}
+
+// bodies are already buffered, so consuming one only marks it used
+fn take_body(agent: &Agent, ctx: &Context) -> Result<Vec<u8>, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("http body") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    if this.get(agent, ObjectKey::from("bodyUsed"))? == Value::from(true) {
+        return Err(Value::new_error(agent, "body has already been used"));
+    }
+    this.set(agent, ObjectKey::from("bodyUsed"), Value::from(true))?;
+    if let Value::Object(o) = this.get_slot("http body") {
+        if let ObjectKind::Buffer(b) = &o.kind {
+            return Ok(b.borrow().clone());
+        }
+    }
+    unreachable!();
+}
+
+fn text(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let result =
+        take_body(agent, ctx).map(|b| Value::from(String::from_utf8_lossy(&b).into_owned()));
+    settle(agent, result)
+}
+
+fn json(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let result = take_body(agent, ctx)
+        .and_then(|b| crate::builtins::json::parse(agent, &String::from_utf8_lossy(&b)));
+    settle(agent, result)
+}
+
+fn array_buffer(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let result = take_body(agent, ctx).map(|b| Value::new_buffer_from_vec(agent, b));
+    settle(agent, result)
+}
+
+// multipart bodies are parsed while the request is read, urlencoded ones here
+fn form_data(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let result = take_body(agent, ctx).and_then(|body| {
+        let this = ctx.scope.borrow().get_this(agent)?;
+        if this.has_slot("http form") {
+            return Ok(this.get_slot("http form"));
+        }
+        let headers = this.get(agent, ObjectKey::from("headers"))?;
+        let content_type = match headers.get(agent, ObjectKey::from("content-type"))? {
+            Value::String(s) => s.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        if !content_type.starts_with("application/x-www-form-urlencoded") {
+            return Err(Value::new_error(agent, "body is not form data"));
+        }
+        let entries = parse_form(&String::from_utf8_lossy(&body))
+            .into_iter()
+            .map(|(n, v)| (n, Value::from(v)))
+            .collect();
+        Ok(new_form_data(agent, entries))
+    });
+    settle(agent, result)
+}
+
+// server requests share the body methods
+pub fn create_http_request_prototype(agent: &Agent) -> Value {
+    let proto = create_http_response_prototype(agent);
+    proto
+        .set(
+            agent,
+            ObjectKey::from("formData"),
+            Value::new_builtin_function(agent, form_data),
+        )
+        .unwrap();
+    proto
+}
+
+pub fn create_http_response_prototype(agent: &Agent) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    proto
+        .set(
+            agent,
+            ObjectKey::from("text"),
+            Value::new_builtin_function(agent, text),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("json"),
+            Value::new_builtin_function(agent, json),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("arrayBuffer"),
+            Value::new_builtin_function(agent, array_buffer),
+        )
+        .unwrap();
+
+    proto
+}
@@ -9,8 +9,9 @@ fn next(agent: &Agent, _args: Vec<Value>, ctx: &Context) -> Result<Value, Value>
     }
     let mapper = o.get_slot("mapper");
     let iterated = o.get_slot("iterated");
-    let result = if let Value::Iterator(iterator, next) = iterated {
-        next.call(agent, *iterator, vec![])?
+    let result = if let Value::Iterator(pair) = iterated {
+        let (iterator, next) = *pair;
+        next.call(agent, iterator, vec![])?
     } else {
         unreachable!();
     };
@@ -3,11 +3,15 @@ mod array_prototype;
 mod async_iterator_prototype;
 mod boolean_prototype;
 mod error_prototype;
+pub mod form_data_prototype;
 mod function_prototype;
 mod generator_prototype;
+mod http_response_prototype;
 mod iterator_map_prototype;
 mod iterator_prototype;
+#[cfg(feature = "os")]
 mod net_client_prototype;
+#[cfg(feature = "os")]
 mod net_server_prototype;
 mod number_prototype;
 mod object_prototype;
@@ -18,6 +22,7 @@ mod regex_prototype;
 mod string_prototype;
 mod symbol;
 mod symbol_prototype;
+pub mod url;
 
 pub use perform_await::perform_await;
 
@@ -26,11 +31,15 @@ pub use array_prototype::create_array_prototype;
 pub use async_iterator_prototype::create_async_iterator_prototype;
 pub use boolean_prototype::create_boolean_prototype;
 pub use error_prototype::create_error_prototype;
+pub use form_data_prototype::create_form_data_prototype;
 pub use function_prototype::create_function_prototype;
 pub use generator_prototype::create_generator_prototype;
+pub use http_response_prototype::{create_http_request_prototype, create_http_response_prototype};
 pub use iterator_map_prototype::create_iterator_map_prototype;
 pub use iterator_prototype::create_iterator_prototype;
+#[cfg(feature = "os")]
 pub use net_client_prototype::create_net_client_prototype;
+#[cfg(feature = "os")]
 pub use net_server_prototype::create_net_server_prototype;
 pub use number_prototype::create_number_prototype;
 pub use object_prototype::create_object_prototype;
@@ -40,3 +49,6 @@ pub use regex_prototype::create_regex_prototype;
 pub use string_prototype::create_string_prototype;
 pub use symbol::create_symbol;
 pub use symbol_prototype::create_symbol_prototype;
+pub use url::{
+    create_url, create_url_prototype, create_url_search_params, create_url_search_params_prototype,
+};
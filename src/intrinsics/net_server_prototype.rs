@@ -40,6 +40,28 @@ fn close(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     }
 }
 
+fn token(agent: &Agent, ctx: &Context) -> Result<mio::Token, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("net server token") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    match this.get_slot("net server token") {
+        Value::Number(t) => Ok(mio::Token(t.to_usize().unwrap())),
+        _ => unreachable!(),
+    }
+}
+
+fn ref_(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    agent.ref_op(token(agent, ctx)?);
+    ctx.scope.borrow().get_this(agent)
+}
+
+// an unref'd server no longer keeps the event loop alive on its own
+fn unref(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    agent.unref_op(token(agent, ctx)?);
+    ctx.scope.borrow().get_this(agent)
+}
+
 pub fn create_net_server_prototype(agent: &Agent) -> Value {
     let proto = Value::new_object(agent.intrinsics.async_iterator_prototype.clone());
 
@@ -59,5 +81,21 @@ pub fn create_net_server_prototype(agent: &Agent) -> Value {
         )
         .unwrap();
 
+    proto
+        .set(
+            agent,
+            ObjectKey::from("ref"),
+            Value::new_builtin_function(agent, ref_),
+        )
+        .unwrap();
+
+    proto
+        .set(
+            agent,
+            ObjectKey::from("unref"),
+            Value::new_builtin_function(agent, unref),
+        )
+        .unwrap();
+
     proto
 }
@@ -3,12 +3,24 @@ use crate::interpreter::Context;
 use crate::num_util;
 use crate::value::{ObjectKey, ObjectKind, Value};
 
-fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+fn to_string(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     let this = ctx.scope.borrow().get_this(agent)?;
+    let radix = match args.get(0) {
+        None | Some(Value::Null) => 10,
+        Some(v) => match v.as_f64() {
+            Some(r) if r.fract() == 0.0 && (2.0..=36.0).contains(&r) => r as u32,
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    "radix must be an integer from 2 to 36",
+                ))
+            }
+        },
+    };
 
     match this {
         Value::Object(o) => match o.kind {
-            ObjectKind::Number(n) => Ok(Value::from(num_util::to_string(n))),
+            ObjectKind::Number(n) => Ok(Value::from(num_util::to_radix_string(n, radix))),
             _ => Err(Value::new_error(agent, "invalid receiver")),
         },
         _ => Err(Value::new_error(agent, "invalid receiver")),
@@ -5,7 +5,8 @@ use crate::{Agent, Value};
 
 pub fn on_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     let f = ctx.function.as_ref().unwrap();
-    if let Value::WrappedContext(context, promise) = f.get_slot("async context") {
+    if let Value::WrappedContext(wrapped) = f.get_slot("async context") {
+        let (context, promise) = *wrapped;
         let mut args = args;
         let mut interpreter = context.borrow_mut().interpreter.take().unwrap();
         interpreter.accumulator = args.remove(0);
@@ -28,7 +29,11 @@ pub fn on_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Va
                 context.borrow_mut().interpreter = Some(interpreter);
                 let mut c = c;
                 let value = std::mem::replace(&mut c.0, Value::Null);
-                perform_await(agent, Value::WrappedContext(context, promise), value)?;
+                perform_await(
+                    agent,
+                    Value::WrappedContext(Box::new((context, promise))),
+                    value,
+                )?;
             }
         }
         Ok(Value::Null)
@@ -39,7 +44,8 @@ pub fn on_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Va
 
 pub fn on_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     let f = ctx.function.as_ref().unwrap();
-    if let Value::WrappedContext(context, promise) = f.get_slot("async context") {
+    if let Value::WrappedContext(wrapped) = f.get_slot("async context") {
+        let (context, promise) = *wrapped;
         let mut args = args;
         let mut interpreter = context.borrow_mut().interpreter.take().unwrap();
         interpreter.exception = Some(args.remove(0));
@@ -62,7 +68,11 @@ pub fn on_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Val
                 context.borrow_mut().interpreter = Some(interpreter);
                 let mut c = c;
                 let value = std::mem::replace(&mut c.0, Value::Null);
-                perform_await(agent, Value::WrappedContext(context, promise), value)?;
+                perform_await(
+                    agent,
+                    Value::WrappedContext(Box::new((context, promise))),
+                    value,
+                )?;
             }
         }
         Ok(Value::Null)
@@ -1,4 +1,4 @@
-use crate::agent::Agent;
+use crate::agent::{Agent, PromiseHook};
 use crate::interpreter::Context;
 use crate::value::{ObjectKey, Value};
 
@@ -42,7 +42,13 @@ pub fn promise_reaction_job(agent: &Agent, args: Vec<Value>) -> Result<(), Value
             handler_result = Err(argument);
         }
     } else {
-        handler_result = handler.call(agent, Value::Null, vec![argument]);
+        agent.promise_hook(PromiseHook::Before, &promise);
+        handler_result = agent.with_async_storage(reaction.get_slot("async storage"), || {
+            agent.with_async_frames(reaction.get_slot("async frames"), || {
+                handler.call(agent, Value::Null, vec![argument])
+            })
+        });
+        agent.promise_hook(PromiseHook::After, &promise);
     }
 
     if promise != Value::Null {
@@ -65,6 +71,7 @@ fn fulfill_promise(agent: &Agent, promise: Value, value: Value) -> Result<Value,
     promise.set_slot("promise state", Value::from("fulfilled"));
     promise.set_slot("fulfill reactions", Value::Null);
     promise.set_slot("reject reactions", Value::Null);
+    agent.promise_hook(PromiseHook::Resolve, &promise);
     trigger_promise_reactions(agent, reactions, value)
 }
 
@@ -74,8 +81,9 @@ fn reject_promise(agent: &Agent, promise: Value, reason: Value) -> Result<Value,
     promise.set_slot("promise state", Value::from("rejected"));
     promise.set_slot("fulfill reactions", Value::Null);
     promise.set_slot("reject reactions", Value::Null);
-    if promise.has_slot("promise handled") {
-        agent.uncaught_exception(reason.clone());
+    agent.promise_hook(PromiseHook::Resolve, &promise);
+    if !promise.has_slot("promise handled") {
+        agent.track_rejection(promise.clone());
     }
     trigger_promise_reactions(agent, reactions, reason)
 }
@@ -164,6 +172,7 @@ fn promise(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Val
     promise.set_slot("promise state", Value::from("pending"));
     promise.set_slot("fulfill reactions", Value::new_list());
     promise.set_slot("reject reactions", Value::new_list());
+    agent.promise_hook(PromiseHook::Init, &promise);
 
     let ResolvingFunctions { resolve, reject } = create_resolving_functions(agent, &promise);
 
@@ -244,6 +253,27 @@ fn promise_reject(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Valu
     Ok(capability)
 }
 
+fn promise_with_resolvers(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let c = ctx.scope.borrow().get_this(agent)?;
+    if c.type_of() != "object" && c.type_of() != "function" {
+        return Err(Value::new_error(agent, "this must be an object"));
+    }
+    let capability = new_promise_capability(agent, c)?;
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    o.set(
+        agent,
+        ObjectKey::from("resolve"),
+        capability.get_slot("resolve"),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("reject"),
+        capability.get_slot("reject"),
+    )?;
+    o.set(agent, ObjectKey::from("promise"), capability)?;
+    Ok(o)
+}
+
 pub fn create_promise(agent: &Agent) -> Value {
     let p = Value::new_builtin_function(agent, promise);
 
@@ -265,6 +295,12 @@ pub fn create_promise(agent: &Agent) -> Value {
         Value::new_builtin_function(agent, promise_reject),
     )
     .unwrap();
+    p.set(
+        agent,
+        ObjectKey::from("withResolvers"),
+        Value::new_builtin_function(agent, promise_with_resolvers),
+    )
+    .unwrap();
     agent
         .intrinsics
         .promise_prototype
@@ -20,15 +20,25 @@ fn promise_proto_then(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<
         on_rejected = Value::Null;
     }
 
+    // handlers continue the stack and storage that attached them
+    let frames = agent.capture_frames();
+    let storage = agent.capture_async_storage();
+
     let fulfill_reaction = Value::new_custom_object(Value::Null);
     fulfill_reaction.set_slot("kind", Value::from("resolve"));
     fulfill_reaction.set_slot("promise", promise.clone());
     fulfill_reaction.set_slot("handler", on_fulfilled);
+    fulfill_reaction.set_slot("async frames", frames.clone());
+    fulfill_reaction.set_slot("async storage", storage.clone());
 
     let reject_reaction = Value::new_custom_object(Value::Null);
     reject_reaction.set_slot("kind", Value::from("reject"));
     reject_reaction.set_slot("promise", promise.clone());
     reject_reaction.set_slot("handler", on_rejected);
+    reject_reaction.set_slot("async frames", frames);
+    reject_reaction.set_slot("async storage", storage);
+
+    this.set_slot("promise handled", Value::from(true));
 
     let state = this.get_slot("promise state");
     if let Value::String(s) = &state {
@@ -41,7 +51,6 @@ fn promise_proto_then(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<
                 }
                 if let Value::List(reactions) = &this.get_slot("reject reactions") {
                     reactions.borrow_mut().push_back(reject_reaction);
-                    this.set_slot("promise handled", Value::from(true));
                 } else {
                     unreachable!();
                 }
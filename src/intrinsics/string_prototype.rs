@@ -2,6 +2,7 @@ use crate::interpreter::Context;
 use crate::value::{ObjectKey, ObjectKind};
 use crate::{Agent, Value};
 use unic::normal::StrNormalForm;
+use unic::segment::{Graphemes, Words};
 
 fn normalize(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
     if let Value::Object(o) = ctx.scope.borrow().get_this(agent)? {
@@ -35,6 +36,44 @@ fn normalize(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Va
     }
 }
 
+fn this_string(agent: &Agent, ctx: &Context) -> Result<String, Value> {
+    if let Value::Object(o) = ctx.scope.borrow().get_this(agent)? {
+        if let ObjectKind::String(s) = &o.kind {
+            return Ok(s.iter().collect());
+        }
+    }
+    Err(Value::new_error(agent, "invalid receiver"))
+}
+
+// iterate the pieces of the string through an array iterator
+fn iterate<'a>(agent: &Agent, pieces: impl Iterator<Item = &'a str>) -> Value {
+    let pieces = pieces.map(Value::from).collect();
+    let it = Value::new_custom_object(agent.intrinsics.array_iterator_prototype.clone());
+    it.set_slot("array iterator next index", Value::from(0));
+    it.set_slot("iterated object", Value::new_array_from_vec(agent, pieces));
+    it
+}
+
+/// The user-perceived characters of the string, so emoji and combining
+/// marks stay in one piece.
+fn graphemes(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let s = this_string(agent, ctx)?;
+    Ok(iterate(agent, Graphemes::new(&s)))
+}
+
+fn grapheme_length(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let s = this_string(agent, ctx)?;
+    Ok(Value::from(Graphemes::new(&s).count() as f64))
+}
+
+/// The words of the string, skipping the whitespace and punctuation between
+/// them.
+fn words(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let s = this_string(agent, ctx)?;
+    let words = Words::new(&s, |w| w.chars().any(char::is_alphanumeric));
+    Ok(iterate(agent, words))
+}
+
 pub fn create_string_prototype(agent: &Agent) -> Value {
     let p = Value::new_object(agent.intrinsics.object_prototype.clone());
 
@@ -45,5 +84,26 @@ pub fn create_string_prototype(agent: &Agent) -> Value {
     )
     .unwrap();
 
+    p.set(
+        agent,
+        ObjectKey::from("graphemes"),
+        Value::new_builtin_function(agent, graphemes),
+    )
+    .unwrap();
+
+    p.set(
+        agent,
+        ObjectKey::from("graphemeLength"),
+        Value::new_builtin_function(agent, grapheme_length),
+    )
+    .unwrap();
+
+    p.set(
+        agent,
+        ObjectKey::from("words"),
+        Value::new_builtin_function(agent, words),
+    )
+    .unwrap();
+
     p
 }
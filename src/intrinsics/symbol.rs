@@ -4,7 +4,7 @@ use crate::value::{ObjectKey, Value};
 
 fn symbol(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
     let desc = match args.get(0) {
-        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(Value::String(s)) => Ok(Some(s.to_string())),
         Some(_) => Err(Value::new_error(agent, "invalid description")),
         None => Ok(None),
     }?;
@@ -13,7 +13,7 @@ fn symbol(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Valu
 
 fn private(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
     let desc = match args.get(0) {
-        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(Value::String(s)) => Ok(Some(s.to_string())),
         Some(_) => Err(Value::new_error(agent, "invalid description")),
         None => Ok(None),
     }?;
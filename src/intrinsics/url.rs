@@ -0,0 +1,903 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Path {
+    Opaque(String),
+    Segments(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub scheme: String,
+    pub username: String,
+    pub password: String,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub path: Path,
+    pub query: Option<String>,
+    pub fragment: Option<String>,
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+fn is_special(scheme: &str) -> bool {
+    scheme == "file" || default_port(scheme).is_some()
+}
+
+const C0: &[u8] = b"";
+const FRAGMENT: &[u8] = b" \"<>`";
+const QUERY: &[u8] = b" \"#<>";
+const SPECIAL_QUERY: &[u8] = b" \"#<>'";
+const PATH: &[u8] = b" \"#<>?`{}";
+const USERINFO: &[u8] = b" \"#<>?`{}/:;=@[\\]^|";
+
+fn percent_encode(input: &str, set: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len());
+    for &b in input.as_bytes() {
+        if b < 0x20 || b > 0x7e || set.contains(&b) {
+            out += &format!("%{:02X}", b);
+        } else {
+            out.push(b as char);
+        }
+    }
+    out
+}
+
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// RFC 3492, used for non-ASCII host labels
+fn punycode(input: &str) -> Option<String> {
+    const BASE: u32 = 36;
+    const TMIN: u32 = 1;
+    const TMAX: u32 = 26;
+
+    fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+        let mut delta = if first { delta / 700 } else { delta / 2 };
+        delta += delta / points;
+        let mut k = 0;
+        while delta > ((BASE - TMIN) * TMAX) / 2 {
+            delta /= BASE - TMIN;
+            k += BASE;
+        }
+        k + (BASE - TMIN + 1) * delta / (delta + 38)
+    }
+
+    fn digit(d: u32) -> char {
+        if d < 26 {
+            (b'a' + d as u8) as char
+        } else {
+            (b'0' + (d - 26) as u8) as char
+        }
+    }
+
+    let chars = input.chars().map(|c| c as u32).collect::<Vec<u32>>();
+    let mut output = chars
+        .iter()
+        .filter(|&&c| c < 0x80)
+        .map(|&c| c as u8 as char)
+        .collect::<String>();
+    let basic = output.len() as u32;
+    let mut handled = basic;
+    if basic > 0 {
+        output.push('-');
+    }
+    let mut n = 128;
+    let mut delta: u32 = 0;
+    let mut bias = 72;
+    while (handled as usize) < chars.len() {
+        let m = *chars.iter().filter(|&&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+        for &c in &chars {
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = if k <= bias {
+                        TMIN
+                    } else if k >= bias + TMAX {
+                        TMAX
+                    } else {
+                        k - bias
+                    };
+                    if q < t {
+                        break;
+                    }
+                    output.push(digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(digit(q));
+                bias = adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta += 1;
+        n += 1;
+    }
+    Some(output)
+}
+
+fn parse_host(input: &str, special: bool) -> Result<String, String> {
+    if input.starts_with('[') {
+        if !input.ends_with(']')
+            || !input[1..input.len() - 1]
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() || c == ':' || c == '.')
+        {
+            return Err("invalid IPv6 address".to_string());
+        }
+        return Ok(input.to_ascii_lowercase());
+    }
+    if !special {
+        if input.chars().any(|c| " #/:<>?@[\\]^|".contains(c)) {
+            return Err("invalid host".to_string());
+        }
+        return Ok(percent_encode(input, C0));
+    }
+    let decoded = percent_decode(input).to_lowercase();
+    if decoded.is_empty() {
+        return Err("empty host".to_string());
+    }
+    let mut labels = Vec::new();
+    for label in decoded.split('.') {
+        if label
+            .chars()
+            .any(|c| c <= ' ' || " #%/:<>?@[\\]^|".contains(c))
+        {
+            return Err("invalid host".to_string());
+        }
+        if label.is_ascii() {
+            labels.push(label.to_string());
+        } else {
+            match punycode(label) {
+                Some(encoded) => labels.push(format!("xn--{}", encoded)),
+                None => return Err("invalid host".to_string()),
+            }
+        }
+    }
+    Ok(labels.join("."))
+}
+
+fn is_single_dot(segment: &str) -> bool {
+    segment == "." || segment.eq_ignore_ascii_case("%2e")
+}
+
+fn is_double_dot(segment: &str) -> bool {
+    match segment.to_ascii_lowercase().as_str() {
+        ".." | ".%2e" | "%2e." | "%2e%2e" => true,
+        _ => false,
+    }
+}
+
+// appends `input` to `segments`, resolving dot segments
+fn push_path(segments: &mut Vec<String>, input: &str, special: bool) {
+    let parts = if special {
+        input
+            .split(|c| c == '/' || c == '\\')
+            .collect::<Vec<&str>>()
+    } else {
+        input.split('/').collect::<Vec<&str>>()
+    };
+    let last = parts.len() - 1;
+    for (i, part) in parts.into_iter().enumerate() {
+        if is_double_dot(part) {
+            segments.pop();
+            if i == last {
+                segments.push(String::new());
+            }
+        } else if is_single_dot(part) {
+            if i == last {
+                segments.push(String::new());
+            }
+        } else {
+            segments.push(percent_encode(part, PATH));
+        }
+    }
+}
+
+fn split_off(input: &str, c: char) -> (&str, Option<&str>) {
+    match input.find(c) {
+        Some(i) => (&input[..i], Some(&input[i + 1..])),
+        None => (input, None),
+    }
+}
+
+impl Url {
+    pub fn parse(input: &str, base: Option<&Url>) -> Result<Url, String> {
+        let input = input
+            .trim_matches(|c: char| c <= ' ')
+            .chars()
+            .filter(|&c| c != '\t' && c != '\n' && c != '\r')
+            .collect::<String>();
+
+        let scheme_end = input.find(':').filter(|&i| {
+            let b = input.as_bytes();
+            i > 0
+                && b[0].is_ascii_alphabetic()
+                && b[1..i]
+                    .iter()
+                    .all(|c| c.is_ascii_alphanumeric() || b"+-.".contains(c))
+        });
+
+        let (url, rest) = match scheme_end {
+            Some(i) => {
+                let scheme = input[..i].to_ascii_lowercase();
+                let rest = &input[i + 1..];
+                let special = is_special(&scheme);
+                match base {
+                    // `http:foo` is relative to an http base
+                    Some(base)
+                        if special
+                            && base.scheme == scheme
+                            && !rest.starts_with('/')
+                            && !rest.starts_with('\\') =>
+                    {
+                        return Url::parse_relative(rest, base);
+                    }
+                    _ => {}
+                }
+                let url = Url {
+                    scheme,
+                    username: String::new(),
+                    password: String::new(),
+                    host: None,
+                    port: None,
+                    path: Path::Segments(Vec::new()),
+                    query: None,
+                    fragment: None,
+                };
+                (url, rest.to_string())
+            }
+            None => match base {
+                Some(base) => return Url::parse_relative(&input, base),
+                None => return Err("relative URL without a base".to_string()),
+            },
+        };
+        Url::parse_after_scheme(url, &rest)
+    }
+
+    fn parse_after_scheme(mut url: Url, rest: &str) -> Result<Url, String> {
+        let special = url.is_special();
+        let (rest, fragment) = split_off(rest, '#');
+        let (rest, query) = split_off(rest, '?');
+        url.fragment = fragment.map(|f| percent_encode(f, FRAGMENT));
+        url.query = query.map(|q| percent_encode(q, if special { SPECIAL_QUERY } else { QUERY }));
+
+        let slashes = rest
+            .chars()
+            .take_while(|&c| c == '/' || (special && c == '\\'))
+            .count();
+        if slashes >= 2 || (special && url.scheme != "file") {
+            let rest = &rest[slashes..];
+            let end = rest
+                .find(|c| c == '/' || (special && c == '\\'))
+                .unwrap_or(rest.len());
+            url.parse_authority(&rest[..end])?;
+            let mut segments = Vec::new();
+            if end < rest.len() {
+                push_path(&mut segments, &rest[end + 1..], special);
+            } else if special {
+                segments.push(String::new());
+            }
+            url.path = Path::Segments(segments);
+        } else if special || rest.starts_with('/') {
+            if url.scheme == "file" {
+                url.host = Some(String::new());
+            }
+            let mut segments = Vec::new();
+            push_path(&mut segments, rest.trim_start_matches('/'), special);
+            url.path = Path::Segments(segments);
+        } else {
+            url.path = Path::Opaque(percent_encode(rest, C0));
+        }
+        Ok(url)
+    }
+
+    fn parse_authority(&mut self, authority: &str) -> Result<(), String> {
+        let special = self.is_special();
+        let host_port = match authority.rfind('@') {
+            Some(i) => {
+                let (username, password) = split_off(&authority[..i], ':');
+                self.username = percent_encode(username, USERINFO);
+                self.password = percent_encode(password.unwrap_or(""), USERINFO);
+                &authority[i + 1..]
+            }
+            None => authority,
+        };
+        let port_start = if host_port.starts_with('[') {
+            host_port.find(']').and_then(|i| {
+                if host_port[i + 1..].starts_with(':') {
+                    Some(i + 1)
+                } else {
+                    None
+                }
+            })
+        } else {
+            host_port.rfind(':')
+        };
+        let (host, port) = match port_start {
+            Some(i) => (&host_port[..i], &host_port[i + 1..]),
+            None => (host_port, ""),
+        };
+        if !port.is_empty() {
+            let port = match port.parse::<u16>() {
+                Ok(p) if port.chars().all(|c| c.is_ascii_digit()) => p,
+                _ => return Err("invalid port".to_string()),
+            };
+            if default_port(&self.scheme) != Some(port) {
+                self.port = Some(port);
+            }
+        }
+        if host.is_empty() {
+            if special && self.scheme != "file" {
+                return Err("empty host".to_string());
+            }
+            self.host = Some(String::new());
+        } else {
+            let host = parse_host(host, special)?;
+            self.host = Some(if self.scheme == "file" && host == "localhost" {
+                String::new()
+            } else {
+                host
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_relative(input: &str, base: &Url) -> Result<Url, String> {
+        let special = base.is_special();
+        let mut url = base.clone();
+        url.fragment = None;
+        if let Path::Opaque(..) = base.path {
+            if input.starts_with('#') {
+                url.fragment = Some(percent_encode(&input[1..], FRAGMENT));
+                return Ok(url);
+            }
+            return Err("relative URL with an opaque base".to_string());
+        }
+        let is_slash = |c: char| c == '/' || (special && c == '\\');
+        if input.starts_with("//")
+            || (special && input.len() > 1 && input.chars().take(2).all(is_slash))
+        {
+            url.username = String::new();
+            url.password = String::new();
+            url.host = None;
+            url.port = None;
+            url.query = None;
+            return Url::parse_after_scheme(url, input);
+        }
+        let (rest, fragment) = split_off(input, '#');
+        let (path, query) = split_off(rest, '?');
+        url.fragment = fragment.map(|f| percent_encode(f, FRAGMENT));
+        if path.is_empty() {
+            if let Some(q) = query {
+                url.query = Some(percent_encode(
+                    q,
+                    if special { SPECIAL_QUERY } else { QUERY },
+                ));
+            }
+            return Ok(url);
+        }
+        url.query = query.map(|q| percent_encode(q, if special { SPECIAL_QUERY } else { QUERY }));
+        let mut segments = match &base.path {
+            Path::Segments(s) => s.clone(),
+            Path::Opaque(..) => unreachable!(),
+        };
+        if path.starts_with(is_slash) {
+            segments.clear();
+            push_path(&mut segments, &path[1..], special);
+        } else {
+            segments.pop();
+            push_path(&mut segments, path, special);
+        }
+        url.path = Path::Segments(segments);
+        Ok(url)
+    }
+
+    pub fn is_special(&self) -> bool {
+        is_special(&self.scheme)
+    }
+
+    pub fn host(&self) -> String {
+        match (&self.host, self.port) {
+            (Some(h), Some(p)) => format!("{}:{}", h, p),
+            (Some(h), None) => h.clone(),
+            (None, _) => String::new(),
+        }
+    }
+
+    pub fn pathname(&self) -> String {
+        match &self.path {
+            Path::Opaque(p) => p.clone(),
+            Path::Segments(s) => s.iter().map(|s| format!("/{}", s)).collect(),
+        }
+    }
+
+    pub fn search(&self) -> String {
+        match &self.query {
+            Some(q) if !q.is_empty() => format!("?{}", q),
+            _ => String::new(),
+        }
+    }
+
+    pub fn hash(&self) -> String {
+        match &self.fragment {
+            Some(f) if !f.is_empty() => format!("#{}", f),
+            _ => String::new(),
+        }
+    }
+
+    pub fn origin(&self) -> String {
+        if self.is_special() && self.scheme != "file" {
+            format!("{}://{}", self.scheme, self.host())
+        } else {
+            "null".to_string()
+        }
+    }
+
+    pub fn href(&self) -> String {
+        let mut out = format!("{}:", self.scheme);
+        if let Some(host) = &self.host {
+            out += "//";
+            if !self.username.is_empty() || !self.password.is_empty() {
+                out += &self.username;
+                if !self.password.is_empty() {
+                    out.push(':');
+                    out += &self.password;
+                }
+                out.push('@');
+            }
+            out += host;
+            if let Some(port) = self.port {
+                out += &format!(":{}", port);
+            }
+        }
+        out += &self.pathname();
+        if let Some(q) = &self.query {
+            out.push('?');
+            out += q;
+        }
+        if let Some(f) = &self.fragment {
+            out.push('#');
+            out += f;
+        }
+        out
+    }
+}
+
+// application/x-www-form-urlencoded
+pub fn parse_form(input: &str) -> Vec<(String, String)> {
+    input
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .map(|pair| {
+            let (name, value) = split_off(pair, '=');
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            (decode(name), decode(value.unwrap_or("")))
+        })
+        .collect()
+}
+
+fn form_encode(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for &b in input.as_bytes() {
+        match b {
+            b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'*' | b'-' | b'.' | b'_' => {
+                out.push(b as char)
+            }
+            b' ' => out.push('+'),
+            _ => out += &format!("%{:02X}", b),
+        }
+    }
+    out
+}
+
+pub fn serialize_form(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", form_encode(name), form_encode(value)))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+fn set_property(agent: &Agent, o: &Value, name: &str, value: String) {
+    o.set(agent, ObjectKey::from(name), Value::from(value))
+        .unwrap();
+}
+
+fn get_url(agent: &Agent, value: &Value) -> Result<Url, Value> {
+    if value.has_slot("url href") {
+        if let Value::String(href) = value.get_slot("url href") {
+            return Ok(Url::parse(&href, None).unwrap());
+        }
+    }
+    Err(Value::new_error(agent, "invalid receiver"))
+}
+
+fn update_url(agent: &Agent, o: &Value, url: &Url) {
+    o.set_slot("url href", Value::from(url.href()));
+    set_property(agent, o, "href", url.href());
+    set_property(agent, o, "origin", url.origin());
+    set_property(agent, o, "protocol", format!("{}:", url.scheme));
+    set_property(agent, o, "username", url.username.clone());
+    set_property(agent, o, "password", url.password.clone());
+    set_property(agent, o, "host", url.host());
+    set_property(agent, o, "hostname", url.host.clone().unwrap_or_default());
+    set_property(
+        agent,
+        o,
+        "port",
+        url.port.map(|p| p.to_string()).unwrap_or_default(),
+    );
+    set_property(agent, o, "pathname", url.pathname());
+    set_property(agent, o, "search", url.search());
+    set_property(agent, o, "hash", url.hash());
+}
+
+pub fn new_url(agent: &Agent, url: &Url) -> Value {
+    let o = Value::new_custom_object(agent.intrinsics.url_prototype.clone());
+    update_url(agent, &o, url);
+    let params = new_search_params(agent, parse_form(url.query.as_ref().map_or("", |q| q)));
+    params.set_slot("url search params owner", o.clone());
+    o.set(agent, ObjectKey::from("searchParams"), params)
+        .unwrap();
+    o
+}
+
+fn base_argument(agent: &Agent, base: Option<&Value>) -> Result<Option<Url>, String> {
+    match base {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) if v.has_slot("url href") => Ok(Some(get_url(agent, v).unwrap())),
+        Some(Value::String(s)) => Url::parse(s, None).map(Some),
+        Some(_) => Err("base must be a string or URL".to_string()),
+    }
+}
+
+fn input_argument(agent: &Agent, input: Option<&Value>) -> Result<String, Value> {
+    match input {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        Some(v) if v.has_slot("url href") => Ok(get_url(agent, v)?.href()),
+        _ => Err(Value::new_error(agent, "url must be a string")),
+    }
+}
+
+fn url(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let input = input_argument(agent, args.get(0))?;
+    let parsed =
+        base_argument(agent, args.get(1)).and_then(|base| Url::parse(&input, base.as_ref()));
+    match parsed {
+        Ok(url) => Ok(new_url(agent, &url)),
+        Err(e) => Err(Value::new_error(
+            agent,
+            &format!("invalid URL '{}': {}", input, e),
+        )),
+    }
+}
+
+fn can_parse(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let input = input_argument(agent, args.get(0))?;
+    let parsed =
+        base_argument(agent, args.get(1)).and_then(|base| Url::parse(&input, base.as_ref()));
+    Ok(Value::from(parsed.is_ok()))
+}
+
+fn url_to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    Ok(Value::from(get_url(agent, &this)?.href()))
+}
+
+pub fn create_url_prototype(agent: &Agent) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    proto
+        .set(
+            agent,
+            ObjectKey::from("toString"),
+            Value::new_builtin_function(agent, url_to_string),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("toJSON"),
+            Value::new_builtin_function(agent, url_to_string),
+        )
+        .unwrap();
+
+    proto
+}
+
+pub fn create_url(agent: &Agent) -> Value {
+    let u = Value::new_builtin_function(agent, url);
+
+    u.set(
+        agent,
+        ObjectKey::from("prototype"),
+        agent.intrinsics.url_prototype.clone(),
+    )
+    .unwrap();
+    u.set(
+        agent,
+        ObjectKey::from("canParse"),
+        Value::new_builtin_function(agent, can_parse),
+    )
+    .unwrap();
+    agent
+        .intrinsics
+        .url_prototype
+        .set(agent, ObjectKey::from("constructor"), u.clone())
+        .unwrap();
+
+    u
+}
+
+fn get_pairs(agent: &Agent, this: &Value) -> Result<Vec<(String, String)>, Value> {
+    if !this.has_slot("url search params") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    match this.get_slot("url search params") {
+        Value::List(list) => Ok(list
+            .borrow()
+            .iter()
+            .map(|pair| match pair {
+                Value::Tuple(t) => match (&t[0], &t[1]) {
+                    (Value::String(n), Value::String(v)) => (n.to_string(), v.to_string()),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            })
+            .collect()),
+        _ => unreachable!(),
+    }
+}
+
+fn set_pairs(agent: &Agent, this: &Value, pairs: Vec<(String, String)>) {
+    if this.has_slot("url search params owner") {
+        let owner = this.get_slot("url search params owner");
+        // keep the owning URL in sync
+        let mut url = get_url(agent, &owner).unwrap();
+        url.query = if pairs.is_empty() {
+            None
+        } else {
+            Some(serialize_form(&pairs))
+        };
+        update_url(agent, &owner, &url);
+    }
+    this.set_slot(
+        "url search params",
+        Value::new_list_from_iter(
+            pairs
+                .into_iter()
+                .map(|(n, v)| Value::Tuple(Box::new(vec![Value::from(n), Value::from(v)]))),
+        ),
+    );
+}
+
+pub fn new_search_params(agent: &Agent, pairs: Vec<(String, String)>) -> Value {
+    let o = Value::new_custom_object(agent.intrinsics.url_search_params_prototype.clone());
+    set_pairs(agent, &o, pairs);
+    o
+}
+
+fn string_argument(agent: &Agent, args: &[Value], i: usize) -> Result<String, Value> {
+    match args.get(i) {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        Some(Value::Number(n)) => Ok(crate::num_util::to_string(*n)),
+        Some(Value::Boolean(b)) => Ok(b.to_string()),
+        _ => Err(Value::new_error(agent, "argument must be a string")),
+    }
+}
+
+fn url_search_params(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let init = args.get(0).unwrap_or(&Value::Null);
+    let pairs = match init {
+        Value::Null => Vec::new(),
+        Value::String(s) => parse_form(if s.starts_with('?') { &s[1..] } else { s }),
+        v if v.has_slot("url search params") => get_pairs(agent, v)?,
+        Value::Object(o) => {
+            let mut pairs = Vec::new();
+            if let ObjectKind::Array(items) = &o.kind {
+                for item in items.borrow().iter() {
+                    let name = item.get(agent, ObjectKey::from(0))?;
+                    let value = item.get(agent, ObjectKey::from(1))?;
+                    pairs.push((
+                        string_argument(agent, &[name], 0)?,
+                        string_argument(agent, &[value], 0)?,
+                    ));
+                }
+            } else {
+                for key in init.keys(agent)? {
+                    if let ObjectKey::Symbol(..) = key {
+                        continue;
+                    }
+                    let value = init.get(agent, key.clone())?;
+                    pairs.push((key.to_string(), string_argument(agent, &[value], 0)?));
+                }
+            }
+            pairs
+        }
+        _ => return Err(Value::new_error(agent, "invalid init")),
+    };
+    Ok(new_search_params(agent, pairs))
+}
+
+fn params_get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let name = string_argument(agent, &args, 0)?;
+    Ok(get_pairs(agent, &this)?
+        .into_iter()
+        .find(|(n, _)| *n == name)
+        .map_or(Value::Null, |(_, v)| Value::from(v)))
+}
+
+fn params_get_all(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let name = string_argument(agent, &args, 0)?;
+    let values = get_pairs(agent, &this)?
+        .into_iter()
+        .filter(|(n, _)| *n == name)
+        .map(|(_, v)| Value::from(v))
+        .collect();
+    Ok(Value::new_array_from_vec(agent, values))
+}
+
+fn params_has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let name = string_argument(agent, &args, 0)?;
+    Ok(Value::from(
+        get_pairs(agent, &this)?.iter().any(|(n, _)| *n == name),
+    ))
+}
+
+fn params_append(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let mut pairs = get_pairs(agent, &this)?;
+    pairs.push((
+        string_argument(agent, &args, 0)?,
+        string_argument(agent, &args, 1)?,
+    ));
+    set_pairs(agent, &this, pairs);
+    Ok(Value::Null)
+}
+
+fn params_set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let name = string_argument(agent, &args, 0)?;
+    let value = string_argument(agent, &args, 1)?;
+    let mut pairs = Vec::new();
+    let mut found = false;
+    for (n, v) in get_pairs(agent, &this)? {
+        if n != name {
+            pairs.push((n, v));
+        } else if !found {
+            // the first match is replaced in place, the rest are removed
+            found = true;
+            pairs.push((n, value.clone()));
+        }
+    }
+    if !found {
+        pairs.push((name, value));
+    }
+    set_pairs(agent, &this, pairs);
+    Ok(Value::Null)
+}
+
+fn params_delete(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let name = string_argument(agent, &args, 0)?;
+    let pairs = get_pairs(agent, &this)?
+        .into_iter()
+        .filter(|(n, _)| *n != name)
+        .collect();
+    set_pairs(agent, &this, pairs);
+    Ok(Value::Null)
+}
+
+fn params_sort(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let mut pairs = get_pairs(agent, &this)?;
+    // stable, so values of equal names keep their order
+    pairs.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+    set_pairs(agent, &this, pairs);
+    Ok(Value::Null)
+}
+
+fn params_to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    Ok(Value::from(serialize_form(&get_pairs(agent, &this)?)))
+}
+
+fn params_entries(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    let entries = get_pairs(agent, &this)?
+        .into_iter()
+        .map(|(n, v)| Value::Tuple(Box::new(vec![Value::from(n), Value::from(v)])))
+        .collect();
+    let array = Value::new_array_from_vec(agent, entries);
+    array
+        .get(agent, ObjectKey::well_known_symbol("iterator"))?
+        .call(agent, array, vec![])
+}
+
+pub fn create_url_search_params_prototype(agent: &Agent) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("get", params_get);
+    method!("getAll", params_get_all);
+    method!("has", params_has);
+    method!("append", params_append);
+    method!("set", params_set);
+    method!("delete", params_delete);
+    method!("sort", params_sort);
+    method!("toString", params_to_string);
+    method!("entries", params_entries);
+    proto
+        .set(
+            agent,
+            ObjectKey::well_known_symbol("iterator"),
+            Value::new_builtin_function(agent, params_entries),
+        )
+        .unwrap();
+
+    proto
+}
+
+pub fn create_url_search_params(agent: &Agent) -> Value {
+    let p = Value::new_builtin_function(agent, url_search_params);
+
+    p.set(
+        agent,
+        ObjectKey::from("prototype"),
+        agent.intrinsics.url_search_params_prototype.clone(),
+    )
+    .unwrap();
+    agent
+        .intrinsics
+        .url_search_params_prototype
+        .set(agent, ObjectKey::from("constructor"), p.clone())
+        .unwrap();
+
+    p
+}
@@ -47,15 +47,40 @@ macro_rules! custom_trace {
 }
 
 mod agent;
+mod array;
 mod builtins;
+mod diff;
+#[cfg(feature = "differential")]
+pub mod differential;
+#[cfg(feature = "os")]
+pub mod conformance;
+pub mod doc;
+#[cfg(feature = "os")]
+pub mod doctest;
+#[cfg(feature = "os")]
+pub mod graph;
+#[cfg(not(feature = "os"))]
+mod host;
+pub mod incremental;
+pub mod inspector;
 mod interpreter;
 mod intrinsics;
+#[cfg(feature = "os")]
 mod linked_list;
 mod module;
 mod num_util;
+#[cfg(feature = "os")]
+pub mod package;
 mod parser;
+#[cfg(feature = "os")]
+mod persistent;
+#[cfg(feature = "os")]
+pub mod repl;
 mod runtime;
 mod serde;
+mod shape;
+#[cfg(feature = "os")]
+pub mod snapshot;
 mod sort;
 mod value;
 
@@ -63,9 +88,16 @@ pub trait IntoValue: Sized {
     fn into_value(&self, _: &agent::Agent) -> value::Value;
 }
 
-pub use agent::Agent;
+pub use agent::{
+    Agent, AuditEvent, ErrorKind, ErrorReport, PendingOperation, PromiseHook, RealmCustomizer,
+    SlowJob,
+};
+#[cfg(not(feature = "os"))]
+pub use host::Host;
 pub use interpreter::{Context, Interpreter, Scope};
-pub use parser::Parser;
+pub use parser::{tokenize_with_spans, Feature, ParseOptions, Parser, SpannedToken, TokenKind};
+#[cfg(feature = "os")]
+pub use persistent::Persistent;
 pub use value::Value;
 
 pub fn disassemble(code: &str) {
@@ -2,7 +2,7 @@ use crate::interpreter::{Context, Interpreter, Scope};
 use crate::parser::{Node, Parser};
 use crate::{Agent, IntoValue, Value};
 use gc::{Gc, GcCell};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, PartialEq, Clone)]
 enum ModuleStatus {
@@ -17,6 +17,8 @@ enum ModuleStatus {
 pub struct Module {
     pub filename: String,
     imports: HashSet<String>,
+    /// The names of the bindings declared with `export`.
+    pub exports: Vec<String>,
     pub context: Gc<GcCell<Context>>,
     status: ModuleStatus,
     dfs_index: u32,
@@ -32,20 +34,37 @@ unsafe impl gc::Trace for Module {
 
 impl Module {
     pub fn new(filename: &str, source: &str, agent: &mut Agent) -> Result<Module, Value> {
-        let ast = match Parser::parse(&source) {
-            Ok(v) => v,
-            Err(e) => return Err(e.into_value(agent)),
-        };
+        match Parser::parse_with_options(&source, agent.parse_options(false)) {
+            Ok(ast) => Module::from_ast(filename, ast, agent),
+            Err(e) => Err(e.into_value(agent)),
+        }
+    }
 
+    /// Like `new`, but the module body may use `await`.
+    pub fn new_with_await(
+        filename: &str,
+        source: &str,
+        agent: &mut Agent,
+    ) -> Result<Module, Value> {
+        match Parser::parse_with_options(&source, agent.parse_options(true)) {
+            Ok(ast) => Module::from_ast(filename, ast, agent),
+            Err(e) => Err(e.into_value(agent)),
+        }
+    }
+
+    fn from_ast(filename: &str, ast: Node, agent: &mut Agent) -> Result<Module, Value> {
         let mut module = Module {
             filename: filename.to_string(),
             context: Context::new(Scope::new(Some(agent.root_scope.clone()))),
             imports: HashSet::new(),
+            exports: Vec::new(),
             status: ModuleStatus::Uninstantiated,
             dfs_index: 0,
             dfs_ancestor_index: 0,
             bytecode_position: agent.assembler.assemble(&ast),
         };
+        module.context.borrow_mut().module = Some(filename.to_string());
+        agent.record_module_code(module.bytecode_position, filename);
 
         if let Node::Block(_scope, stmts) = ast {
             for stmt in stmts {
@@ -73,26 +92,34 @@ impl Module {
                         module.imports.insert(specifier);
                     }
                     Node::ImportStandardDeclaration(specifier, names) => {
-                        match agent.builtins.get(&specifier) {
-                            Some(s) => {
-                                for name in names {
-                                    match s.get(&name) {
-                                        Some(v) => {
-                                            let ctx = module.context.borrow();
-                                            let mut scope = ctx.scope.borrow_mut();
-                                            scope.create(agent, &name, false)?;
-                                            scope.initialize(&name, v.clone());
-                                        }
-                                        None => {
-                                            return Err(Value::new_error(agent, "unknown export"));
-                                        }
-                                    }
+                        let mock = agent.module_mock(&format!("standard:{}", specifier));
+                        if mock.is_none() && !agent.has_builtin_module(&specifier) {
+                            return Err(Value::new_error(agent, "unknown standard module"));
+                        }
+                        for name in names {
+                            let value = match &mock {
+                                Some(exports) => exports.get(&name).cloned(),
+                                None => agent.builtin(&specifier, &name),
+                            };
+                            match value {
+                                Some(v) => {
+                                    let ctx = module.context.borrow();
+                                    let mut scope = ctx.scope.borrow_mut();
+                                    scope.create(agent, &name, false)?;
+                                    scope.initialize(&name, v);
+                                }
+                                None => {
+                                    return Err(Value::new_error(agent, "unknown export"));
                                 }
                             }
-                            None => return Err(Value::new_error(agent, "unknown standard module")),
                         }
                     }
-                    Node::ExportDeclaration(..) => {}
+                    Node::ExportDeclaration(decl) => match *decl {
+                        Node::LexicalInitialization(name, _)
+                        | Node::FunctionDeclaration(_, name, _, _)
+                        | Node::ClassDeclaration(name, _, _) => module.exports.push(name),
+                        _ => {}
+                    },
                     _ => {}
                 }
             }
@@ -103,6 +130,30 @@ impl Module {
         Ok(module)
     }
 
+    /// A module that is already evaluated, with `exports` as its bindings.
+    /// Imports of a mocked module get one of these instead, see
+    /// `Agent::mock_module`.
+    pub fn from_exports(filename: &str, exports: HashMap<String, Value>, agent: &Agent) -> Module {
+        let scope = Scope::new(Some(agent.root_scope.clone()));
+        for (name, value) in &exports {
+            let mut scope = scope.borrow_mut();
+            scope.create(agent, name, false).unwrap();
+            scope.initialize(name, value.clone());
+        }
+        let context = Context::new(scope);
+        context.borrow_mut().module = Some(filename.to_string());
+        Module {
+            filename: filename.to_string(),
+            imports: HashSet::new(),
+            exports: exports.into_keys().collect(),
+            context,
+            status: ModuleStatus::Evaluated,
+            dfs_index: 0,
+            dfs_ancestor_index: 0,
+            bytecode_position: 0,
+        }
+    }
+
     pub fn instantiate(agent: &mut Agent, module: Gc<GcCell<Module>>) -> Result<(), Value> {
         inner_module_instantiation(agent, module, &mut Vec::new(), 0)?;
         Ok(())
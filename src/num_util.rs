@@ -22,6 +22,14 @@ pub fn f64_bnot(a: f64) -> f64 {
     !(a as i64) as f64
 }
 
+// None when the product overflows or would be -0
+pub fn i32_mul(a: i32, b: i32) -> Option<i32> {
+    match a.checked_mul(b) {
+        Some(0) if a < 0 || b < 0 => None,
+        n => n,
+    }
+}
+
 pub fn to_string(n: f64) -> String {
     if n.is_nan() {
         return "NAN".to_string();
@@ -37,3 +45,50 @@ pub fn to_string(n: f64) -> String {
         s.to_string()
     }
 }
+
+/// `n` written in a radix between 2 and 36, with as many fraction digits as
+/// it takes to get back to `n`.
+pub fn to_radix_string(n: f64, radix: u32) -> String {
+    if radix == 10 || !n.is_finite() {
+        return to_string(n);
+    }
+    let mut out = String::new();
+    if n < 0.0 {
+        out.push('-');
+    }
+    let n = n.abs();
+    let mut int = n.trunc();
+    let mut fraction = n - int;
+
+    let mut digits = Vec::new();
+    loop {
+        let digit = (int % f64::from(radix)) as u32;
+        digits.push(std::char::from_digit(digit, radix).unwrap());
+        int = (int / f64::from(radix)).trunc();
+        if int == 0.0 {
+            break;
+        }
+    }
+    out.extend(digits.iter().rev());
+
+    if fraction > 0.0 {
+        out.push('.');
+        // half of the distance to the next float, the precision left to write
+        let mut delta = 0.5 * (next_up(n) - n);
+        if delta == 0.0 {
+            delta = std::f64::MIN_POSITIVE;
+        }
+        while fraction >= delta {
+            fraction *= f64::from(radix);
+            delta *= f64::from(radix);
+            let digit = fraction.trunc();
+            fraction -= digit;
+            out.push(std::char::from_digit(digit as u32, radix).unwrap());
+        }
+    }
+    out
+}
+
+fn next_up(n: f64) -> f64 {
+    f64::from_bits(n.to_bits() + 1)
+}
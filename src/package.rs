@@ -0,0 +1,755 @@
+//! The package manager behind `slither add`, `slither install` and
+//! `slither cache`.
+//!
+//! A project lists its dependencies in `slither.json`:
+//!
+//! ```json
+//! {
+//!   "registry": "http://packages.example.com",
+//!   "dependencies": {
+//!     "pad": "1.2.0",
+//!     "colors": "git+https://example.com/colors.git#v2"
+//!   }
+//! }
+//! ```
+//!
+//! A registry answers `GET <registry>/<name>` with `{"versions": [...]}` and
+//! `GET <registry>/<name>/<version>` with `{"files": {"module.sl": "..."}}`.
+//! Git dependencies are cloned with the `git` command, at a branch, tag or
+//! commit when the URL ends in `#<ref>`.
+//!
+//! Installing writes each package to `slither_packages/<name>`, where bare
+//! imports like `import pad from 'pad'` find it, and records its exact
+//! version or commit and an integrity hash in `slither.lock`. Later installs
+//! use the locked versions and fail if a package no longer matches its hash.
+//! After `slither vendor`, installs read locked packages from the vendor
+//! directory, so they work offline.
+//!
+//! The manifest's `permissions` narrow what packages and local modules may
+//! do, see `apply_permissions`.
+
+use crate::agent::Agent;
+use crate::builtins::crypto::{sha256, to_hex};
+use crate::builtins::http;
+use crate::builtins::json::{self, write_string};
+use crate::builtins::CAPABILITIES;
+use crate::value::{ObjectKey, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const MANIFEST: &str = "slither.json";
+pub const LOCKFILE: &str = "slither.lock";
+pub const PACKAGES: &str = "slither_packages";
+
+/// A package's files by their `/` separated path in the package.
+pub type Files = BTreeMap<String, Vec<u8>>;
+
+pub struct Config {
+    /// The directory holding `slither.json`.
+    pub root: PathBuf,
+    pub cache: PathBuf,
+    /// Used when the manifest names no registry.
+    pub registry: Option<String>,
+}
+
+impl Config {
+    /// Configuration for the project at `root`, the cache and registry
+    /// coming from `SLITHER_CACHE` and `SLITHER_REGISTRY`. The cache is
+    /// `~/.slither/cache` by default.
+    pub fn from_env(root: PathBuf) -> Config {
+        let cache = match std::env::var_os("SLITHER_CACHE") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(".slither")
+                .join("cache"),
+        };
+        Config {
+            root,
+            cache,
+            registry: std::env::var("SLITHER_REGISTRY").ok(),
+        }
+    }
+}
+
+/// An entry of the lockfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locked {
+    pub name: String,
+    /// The version as the manifest asks for it.
+    pub spec: String,
+    /// The registry version, or the git commit.
+    pub version: String,
+    /// The registry or repository it came from.
+    pub source: String,
+    pub integrity: String,
+}
+
+enum Spec<'a> {
+    /// The latest version when `None`.
+    Registry(Option<&'a str>),
+    Git(&'a str, Option<&'a str>),
+}
+
+fn parse_spec(spec: &str) -> Spec<'_> {
+    match spec.strip_prefix("git+") {
+        Some(url) => match url.split_once('#') {
+            Some((url, reference)) => Spec::Git(url, Some(reference)),
+            None => Spec::Git(url, None),
+        },
+        None if spec.is_empty() || spec == "*" || spec == "latest" => Spec::Registry(None),
+        None => Spec::Registry(Some(spec)),
+    }
+}
+
+// names become directories, so keep them to one plain path component
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid package name '{}'", name))
+    }
+}
+
+// versions and commits name a directory of the cache, like names do
+fn check_version(version: &str) -> Result<(), String> {
+    let valid = !version.is_empty()
+        && !version.starts_with('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid version '{}'", version))
+    }
+}
+
+/// The hash recorded in the lockfile, over every path and its contents.
+pub fn integrity(files: &Files) -> String {
+    let mut data = Vec::new();
+    for (path, contents) in files {
+        data.extend_from_slice(path.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        data.extend_from_slice(contents);
+    }
+    format!("sha256-{}", to_hex(&sha256(&data)))
+}
+
+fn read_json(agent: &Agent, path: &Path) -> Result<Option<Value>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => json::parse(agent, &text)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), agent.describe_exception(&e))),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn get_string(agent: &Agent, object: &Value, key: &str) -> Result<Option<String>, String> {
+    match object.get(agent, ObjectKey::from(key)) {
+        Ok(Value::Null) => Ok(None),
+        Ok(Value::String(s)) => Ok(Some(s.to_string())),
+        _ => Err(format!("'{}' must be a string", key)),
+    }
+}
+
+struct Manifest {
+    value: Value,
+    registry: Option<String>,
+    dependencies: Vec<(String, String)>,
+}
+
+fn read_manifest(agent: &Agent, config: &Config) -> Result<Manifest, String> {
+    let path = config.root.join(MANIFEST);
+    let value = match read_json(agent, &path)? {
+        Some(value) if value.type_of() == "object" => value,
+        Some(_) => return Err(format!("{}: expected an object", path.display())),
+        None => Value::new_object(agent.intrinsics.object_prototype.clone()),
+    };
+    let context = |e| format!("{}: {}", path.display(), e);
+    let registry = get_string(agent, &value, "registry")
+        .map_err(context)?
+        .or_else(|| config.registry.clone());
+    let mut dependencies = Vec::new();
+    let deps = value
+        .get(agent, ObjectKey::from("dependencies"))
+        .map_err(|e| context(agent.describe_exception(&e)))?;
+    if deps != Value::Null {
+        for key in deps.keys(agent).unwrap_or_default() {
+            if let ObjectKey::String(name) = &key {
+                let name = name.clone();
+                let spec = get_string(agent, &deps, &name)
+                    .map_err(context)?
+                    .ok_or_else(|| context(format!("'{}' must be a string", name)))?;
+                check_name(&name).map_err(context)?;
+                dependencies.push((name, spec));
+            }
+        }
+    }
+    dependencies.sort();
+    Ok(Manifest {
+        value,
+        registry,
+        dependencies,
+    })
+}
+
+struct Lock {
+    /// Where packages are vendored, relative to the project.
+    vendor: Option<String>,
+    packages: Vec<Locked>,
+}
+
+fn read_lock(agent: &Agent, config: &Config) -> Result<Lock, String> {
+    let path = config.root.join(LOCKFILE);
+    let mut lock = Lock {
+        vendor: None,
+        packages: Vec::new(),
+    };
+    let value = match read_json(agent, &path)? {
+        Some(value) => value,
+        None => return Ok(lock),
+    };
+    let context = |e| format!("{}: {}", path.display(), e);
+    lock.vendor = get_string(agent, &value, "vendor").map_err(context)?;
+    let packages = value
+        .get(agent, ObjectKey::from("packages"))
+        .map_err(|e| context(agent.describe_exception(&e)))?;
+    for key in packages.keys(agent).unwrap_or_default() {
+        if let ObjectKey::String(name) = &key {
+            let name = name.clone();
+            let entry = packages
+                .get(agent, ObjectKey::from(name.as_str()))
+                .map_err(|e| context(agent.describe_exception(&e)))?;
+            let field = |key| {
+                get_string(agent, &entry, key)
+                    .map_err(context)?
+                    .ok_or_else(|| context(format!("{} is missing '{}'", name, key)))
+            };
+            lock.packages.push(Locked {
+                spec: field("spec")?,
+                version: field("version")?,
+                source: field("source")?,
+                integrity: field("integrity")?,
+                name,
+            });
+        }
+    }
+    Ok(lock)
+}
+
+fn write_lock(config: &Config, lock: &Lock) -> Result<(), String> {
+    let mut out = String::from("{\n");
+    if let Some(vendor) = &lock.vendor {
+        out.push_str("  \"vendor\": ");
+        write_string(&mut out, vendor);
+        out.push_str(",\n");
+    }
+    out.push_str("  \"packages\": {");
+    for (i, entry) in lock.packages.iter().enumerate() {
+        out.push_str(if i > 0 { ",\n    " } else { "\n    " });
+        write_string(&mut out, &entry.name);
+        out.push_str(": {");
+        let fields = [
+            ("spec", &entry.spec),
+            ("version", &entry.version),
+            ("source", &entry.source),
+            ("integrity", &entry.integrity),
+        ];
+        for (j, (key, value)) in fields.iter().enumerate() {
+            out.push_str(if j > 0 { ",\n      " } else { "\n      " });
+            write_string(&mut out, key);
+            out.push_str(": ");
+            write_string(&mut out, value);
+        }
+        out.push_str("\n    }");
+    }
+    out.push_str(if lock.packages.is_empty() {
+        "}\n}\n"
+    } else {
+        "\n  }\n}\n"
+    });
+    let path = config.root.join(LOCKFILE);
+    std::fs::write(&path, out).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn read_tree(dir: &Path) -> std::io::Result<Files> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Files) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" {
+                continue;
+            }
+            let path = format!("{}{}", prefix, name);
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &format!("{}/", path), files)?;
+            } else {
+                files.insert(path, std::fs::read(entry.path())?);
+            }
+        }
+        Ok(())
+    }
+    let mut files = Files::new();
+    walk(dir, "", &mut files)?;
+    Ok(files)
+}
+
+// replaces whatever was in `dir`
+fn write_tree(dir: &Path, files: &Files) -> Result<(), String> {
+    let context = |e: std::io::Error| format!("{}: {}", dir.display(), e);
+    match std::fs::remove_dir_all(dir) {
+        Err(ref e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("{}: {}", dir.display(), e))
+        }
+        _ => {}
+    }
+    std::fs::create_dir_all(dir).map_err(context)?;
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).map_err(context)?;
+        std::fs::write(&path, contents).map_err(context)?;
+    }
+    Ok(())
+}
+
+fn fetch_json(agent: &Agent, url: &str) -> Result<Value, String> {
+    let response = http::get(url).map_err(|e| format!("{}: {}", url, e))?;
+    if response.status != 200 {
+        return Err(format!(
+            "{}: {} {}",
+            url, response.status, response.status_text
+        ));
+    }
+    let text = String::from_utf8(response.body).map_err(|_| format!("{}: not utf-8", url))?;
+    json::parse(agent, &text).map_err(|e| format!("{}: {}", url, agent.describe_exception(&e)))
+}
+
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parts = |v: &str| {
+        v.split('.')
+            .map(|p| p.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    };
+    parts(a).cmp(&parts(b)).then_with(|| a.cmp(b))
+}
+
+fn latest_version(agent: &Agent, registry: &str, name: &str) -> Result<String, String> {
+    let url = format!("{}/{}", registry.trim_end_matches('/'), name);
+    let versions = fetch_json(agent, &url)?
+        .get(agent, ObjectKey::from("versions"))
+        .map_err(|e| agent.describe_exception(&e))?;
+    let length = match versions.get(agent, ObjectKey::from("length")) {
+        Ok(Value::Number(n)) => n as usize,
+        _ => return Err(format!("{}: expected 'versions' to be an array", url)),
+    };
+    let mut latest: Option<String> = None;
+    for i in 0..length {
+        if let Ok(Value::String(v)) = versions.get(agent, ObjectKey::from(i)) {
+            if latest
+                .as_ref()
+                .is_none_or(|l| compare_versions(&v, l).is_gt())
+            {
+                latest = Some(v.to_string());
+            }
+        }
+    }
+    let latest = latest.ok_or_else(|| format!("{}: no versions of {}", url, name))?;
+    check_version(&latest).map_err(|e| format!("{}: {}", url, e))?;
+    Ok(latest)
+}
+
+fn registry_files(
+    agent: &Agent,
+    registry: &str,
+    name: &str,
+    version: &str,
+) -> Result<Files, String> {
+    let url = format!("{}/{}/{}", registry.trim_end_matches('/'), name, version);
+    let files = fetch_json(agent, &url)?
+        .get(agent, ObjectKey::from("files"))
+        .map_err(|e| agent.describe_exception(&e))?;
+    if files.type_of() != "object" {
+        return Err(format!("{}: expected 'files' to be an object", url));
+    }
+    let mut out = Files::new();
+    for key in files.keys(agent).unwrap_or_default() {
+        if let ObjectKey::String(path) = &key {
+            let path = path.clone();
+            // packages may only write inside their own directory
+            let escapes = path.starts_with('/')
+                || path
+                    .split('/')
+                    .any(|p| p.is_empty() || p == "." || p == "..");
+            if escapes {
+                return Err(format!("{}: invalid file path '{}'", url, path));
+            }
+            let contents = get_string(agent, &files, &path)
+                .map_err(|e| format!("{}: {}", url, e))?
+                .unwrap_or_default();
+            out.insert(path, contents.into_bytes());
+        }
+    }
+    Ok(out)
+}
+
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command
+        .args(args)
+        .output()
+        .map_err(|e| format!("git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {}: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// the commit checked out and its files
+fn git_files(
+    config: &Config,
+    url: &str,
+    reference: Option<&str>,
+) -> Result<(String, Files), String> {
+    let scratch = config.cache.join(".git-checkout");
+    let _ = std::fs::remove_dir_all(&scratch);
+    std::fs::create_dir_all(&config.cache)
+        .map_err(|e| format!("{}: {}", config.cache.display(), e))?;
+    let checkout = scratch.to_str().unwrap();
+    let result = git(None, &["clone", "--quiet", "--", url, checkout]).and_then(|_| {
+        if let Some(reference) = reference {
+            git(Some(&scratch), &["checkout", "--quiet", reference])?;
+        }
+        let commit = git(Some(&scratch), &["rev-parse", "HEAD"])?;
+        let files = read_tree(&scratch).map_err(|e| format!("{}: {}", checkout, e))?;
+        Ok((commit, files))
+    });
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+// the version, source and files of a dependency, fetched unless cached
+fn fetch(
+    agent: &Agent,
+    config: &Config,
+    registry: Option<&str>,
+    name: &str,
+    spec: &str,
+    locked: Option<&Locked>,
+) -> Result<(String, String, Files), String> {
+    let cached = |version: &str| read_tree(&config.cache.join(name).join(version)).ok();
+    let (version, source, files) = match parse_spec(spec) {
+        Spec::Registry(version) => {
+            let source = match (locked, registry) {
+                (Some(l), _) => l.source.clone(),
+                (None, Some(registry)) => registry.to_string(),
+                (None, None) => {
+                    return Err(format!(
+                        "{}: no registry, set \"registry\" in {} or SLITHER_REGISTRY",
+                        name, MANIFEST
+                    ))
+                }
+            };
+            let version = match (locked, version) {
+                (Some(l), _) => l.version.clone(),
+                (None, Some(v)) => v.to_string(),
+                (None, None) => latest_version(agent, &source, name)?,
+            };
+            check_version(&version).map_err(|e| format!("{}: {}", name, e))?;
+            if let Some(files) = cached(&version) {
+                return Ok((version, source, files));
+            }
+            let files = registry_files(agent, &source, name, &version)?;
+            (version, source, files)
+        }
+        Spec::Git(url, reference) => {
+            if let Some(l) = locked {
+                check_version(&l.version).map_err(|e| format!("{}: {}", name, e))?;
+            }
+            let (commit, files) = match locked {
+                Some(l) => match cached(&l.version) {
+                    Some(files) => return Ok((l.version.clone(), url.to_string(), files)),
+                    None => git_files(config, url, Some(&l.version))?,
+                },
+                None => git_files(config, url, reference)?,
+            };
+            check_version(&commit).map_err(|e| format!("{}: {}", name, e))?;
+            (commit, url.to_string(), files)
+        }
+    };
+    write_tree(&config.cache.join(name).join(&version), &files)?;
+    Ok((version, source, files))
+}
+
+/// Install every dependency of the manifest into `slither_packages` and
+/// rewrite the lockfile. Versions stay as locked unless the manifest asks
+/// for something else. Once vendored, locked packages are read from the
+/// vendor directory instead of the cache or network.
+pub fn install(config: &Config) -> Result<Vec<Locked>, String> {
+    let agent = Agent::new();
+    let vendor = read_lock(&agent, config)?.vendor;
+    install_with(&agent, config, vendor)
+}
+
+/// Copy every dependency into `dir` under the project and lock installs to
+/// it, so that later installs need no network access.
+pub fn vendor(config: &Config, dir: &str) -> Result<Vec<Locked>, String> {
+    let normal = |c| matches!(c, std::path::Component::Normal(_));
+    if dir.is_empty() || !Path::new(dir).components().all(normal) || dir == PACKAGES {
+        return Err(format!("invalid vendor directory '{}'", dir));
+    }
+    install_with(&Agent::new(), config, Some(dir.to_string()))
+}
+
+fn install_with(
+    agent: &Agent,
+    config: &Config,
+    vendor: Option<String>,
+) -> Result<Vec<Locked>, String> {
+    let manifest = read_manifest(agent, config)?;
+    let lock = read_lock(agent, config)?;
+    // both name directories that get replaced or removed below
+    for (name, _) in &manifest.dependencies {
+        check_name(name)?;
+    }
+    for l in &lock.packages {
+        check_name(&l.name).map_err(|e| format!("{}: {}", LOCKFILE, e))?;
+        check_version(&l.version).map_err(|e| format!("{}: {}: {}", LOCKFILE, l.name, e))?;
+    }
+    let vendor_dir = vendor.as_ref().map(|dir| config.root.join(dir));
+    let mut installed = Vec::new();
+    for (name, spec) in &manifest.dependencies {
+        let locked = lock
+            .packages
+            .iter()
+            .find(|l| &l.name == name && &l.spec == spec);
+        let vendored = match (&vendor_dir, locked, &lock.vendor) {
+            (Some(dir), Some(l), Some(_)) => read_tree(&dir.join(name))
+                .ok()
+                .map(|files| (l.version.clone(), l.source.clone(), files)),
+            _ => None,
+        };
+        let (version, source, files) = match vendored {
+            Some(vendored) => vendored,
+            None => fetch(
+                agent,
+                config,
+                manifest.registry.as_deref(),
+                name,
+                spec,
+                locked,
+            )?,
+        };
+        let integrity = integrity(&files);
+        if let Some(locked) = locked {
+            if locked.integrity != integrity {
+                return Err(format!(
+                    "{}@{}: integrity check failed, expected {} but got {}",
+                    name, version, locked.integrity, integrity
+                ));
+            }
+        }
+        if let Some(dir) = &vendor_dir {
+            write_tree(&dir.join(name), &files)?;
+        }
+        write_tree(&config.root.join(PACKAGES).join(name), &files)?;
+        installed.push(Locked {
+            name: name.clone(),
+            spec: spec.clone(),
+            version,
+            source,
+            integrity,
+        });
+    }
+    // packages dropped from the manifest leave the vendor directory too
+    if let Some(dir) = &vendor_dir {
+        for old in &lock.packages {
+            if !installed.iter().any(|l| l.name == old.name) {
+                let _ = std::fs::remove_dir_all(dir.join(&old.name));
+            }
+        }
+    }
+    write_lock(
+        config,
+        &Lock {
+            vendor,
+            packages: installed.clone(),
+        },
+    )?;
+    Ok(installed)
+}
+
+/// Add `package` to the manifest, creating it if needed, and install. The
+/// package is `name`, `name@version` or a `git+` URL. A bare name is
+/// recorded at its latest version.
+pub fn add(config: &Config, package: &str) -> Result<Locked, String> {
+    let agent = Agent::new();
+    let manifest = read_manifest(&agent, config)?;
+    let (name, spec) = match parse_spec(package) {
+        Spec::Git(url, _) => {
+            let name = url
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .trim_end_matches(".git");
+            (name.to_string(), package.to_string())
+        }
+        Spec::Registry(_) => match package.split_once('@') {
+            Some((name, version)) => (name.to_string(), version.to_string()),
+            None => {
+                check_name(package)?;
+                let registry = manifest.registry.as_deref().ok_or_else(|| {
+                    format!(
+                        "{}: no registry, set \"registry\" in {} or SLITHER_REGISTRY",
+                        package, MANIFEST
+                    )
+                })?;
+                let version = latest_version(&agent, registry, package)?;
+                (package.to_string(), version)
+            }
+        },
+    };
+    check_name(&name)?;
+
+    let value = manifest.value;
+    let mut deps = value
+        .get(&agent, ObjectKey::from("dependencies"))
+        .map_err(|e| agent.describe_exception(&e))?;
+    if deps == Value::Null {
+        deps = Value::new_object(agent.intrinsics.object_prototype.clone());
+    }
+    let updated = deps
+        .set(&agent, ObjectKey::from(name.as_str()), Value::from(spec))
+        .and_then(|_| value.set(&agent, ObjectKey::from("dependencies"), deps))
+        .and_then(|_| json::stringify(&agent, &value, false, Some("  ".to_string())));
+    let text = updated.map_err(|e| agent.describe_exception(&e))?;
+    let path = config.root.join(MANIFEST);
+    let previous = std::fs::read(&path).ok();
+    std::fs::write(&path, text + "\n").map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // a package that fails to install is not added
+    let mut installed = install(config).inspect_err(|_| {
+        let _ = match &previous {
+            Some(previous) => std::fs::write(&path, previous),
+            None => std::fs::remove_file(&path),
+        };
+    })?;
+    let index = installed.iter().position(|l| l.name == name).unwrap();
+    Ok(installed.remove(index))
+}
+
+/// Apply the `permissions` of the manifest nearest `entry`, which map
+/// package names or paths relative to the manifest to the capabilities
+/// their modules get, like `{"pad": [], "./scripts": ["fs"]}`.
+pub fn apply_permissions(agent: &Agent, entry: &Path) -> Result<(), String> {
+    let root = match entry
+        .ancestors()
+        .skip(1)
+        .find(|d| d.join(MANIFEST).is_file())
+    {
+        Some(root) => root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+        None => return Ok(()),
+    };
+    let path = root.join(MANIFEST);
+    let context = |e| format!("{}: {}", path.display(), e);
+    let value = read_json(agent, &path)?.unwrap();
+    let permissions = value
+        .get(agent, ObjectKey::from("permissions"))
+        .map_err(|e| context(agent.describe_exception(&e)))?;
+    if permissions == Value::Null {
+        return Ok(());
+    }
+    for key in permissions.keys(agent).unwrap_or_default() {
+        if let ObjectKey::String(key) = &key {
+            let list = permissions
+                .get(agent, ObjectKey::from(key.as_str()))
+                .map_err(|e| context(agent.describe_exception(&e)))?;
+            let length = match list.get(agent, ObjectKey::from("length")) {
+                Ok(Value::Number(n)) if list.type_of() == "object" => n as usize,
+                _ => return Err(context(format!("'{}' must be an array", key))),
+            };
+            let mut capabilities = Vec::new();
+            for i in 0..length {
+                match list.get(agent, ObjectKey::from(i)) {
+                    Ok(Value::String(c)) if CAPABILITIES.contains(&c.as_str()) => {
+                        capabilities.push(c.to_string())
+                    }
+                    _ => {
+                        return Err(context(format!(
+                            "'{}' grants an unknown capability, expected one of {}",
+                            key,
+                            CAPABILITIES.join(", ")
+                        )))
+                    }
+                }
+            }
+            let target = if key.starts_with('.') || key.starts_with('/') {
+                root.join(key)
+            } else {
+                check_name(key).map_err(context)?;
+                root.join(PACKAGES).join(key)
+            };
+            let target = target.canonicalize().unwrap_or(target);
+            let capabilities = capabilities.iter().map(String::as_str).collect::<Vec<_>>();
+            agent.grant(target.to_str().unwrap(), &capabilities);
+        }
+    }
+    Ok(())
+}
+
+/// The cached packages as (name, version or commit).
+pub fn cached(config: &Config) -> Result<Vec<(String, String)>, String> {
+    let context = |e: std::io::Error| format!("{}: {}", config.cache.display(), e);
+    let mut out = Vec::new();
+    let names = match std::fs::read_dir(&config.cache) {
+        Ok(names) => names,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(out),
+        Err(e) => return Err(context(e)),
+    };
+    for name in names {
+        let name = name.map_err(context)?;
+        // skips the scratch git checkout
+        if name.file_name().to_string_lossy().starts_with('.')
+            || !name.file_type().map_err(context)?.is_dir()
+        {
+            continue;
+        }
+        for version in std::fs::read_dir(name.path()).map_err(context)? {
+            let version = version.map_err(context)?;
+            out.push((
+                name.file_name().to_string_lossy().into_owned(),
+                version.file_name().to_string_lossy().into_owned(),
+            ));
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Remove the cache, returning how many package versions it held.
+pub fn clean_cache(config: &Config) -> Result<usize, String> {
+    let count = cached(config)?.len();
+    match std::fs::remove_dir_all(&config.cache) {
+        Err(ref e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("{}: {}", config.cache.display(), e))
+        }
+        _ => Ok(count),
+    }
+}
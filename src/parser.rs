@@ -1,10 +1,11 @@
 use crate::num_util::{f64_band, f64_bnot, f64_bor, f64_bxor, f64_shl, f64_shr};
+use crate::value::ObjectKey;
 use crate::{Agent, IntoValue, Value};
 use indexmap::IndexMap;
-use std::collections::VecDeque;
-use std::iter::Peekable;
+use std::collections::{HashSet, VecDeque};
 use std::ops::{Div, Mul, Rem, Sub};
 use std::str::Chars;
+use unic::ucd::ident::{is_xid_continue, is_xid_start};
 
 include!(concat!(env!("OUT_DIR"), "/unicode_name_map_gen.rs"));
 
@@ -65,10 +66,12 @@ enum Token {
     Question,
     Dot,
     At,
+    Hash,
     Comma,
     BackQuote,
     Ellipsis,
     Arrow,
+    Pipe,
 
     This,
     Function,
@@ -133,13 +136,13 @@ impl std::ops::BitOr for FunctionKind {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ScopeKind {
     TopLevel,
     Block,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Scope {
     pub kind: ScopeKind,
     pub bindings: IndexMap<String, bool>,
@@ -170,7 +173,7 @@ impl Scope {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Node {
     NullLiteral,
     TrueLiteral,
@@ -182,6 +185,10 @@ pub enum Node {
     ObjectLiteral(Vec<Node>),
     ArrayLiteral(Vec<Node>),
     TupleLiteral(Vec<Node>),
+    /// `#[...]`, a tuple of primitives, records and tuples.
+    ImmutableTupleLiteral(Vec<Node>),
+    /// `#{...}`, its fields being `Initializer`s.
+    RecordLiteral(Vec<Node>),
     TemplateLiteral(Vec<String>, Vec<Node>),
 
     Identifier(String),
@@ -205,7 +212,8 @@ pub enum Node {
     NewExpression(Box<Node>),
 
     MatchExpression(Box<Node>, Vec<Node>),
-    MatchArm(Box<Node>, Box<Node>),
+    PipelineExpression(Box<Node>, Box<Node>),
+    MatchArm(Box<Node>, Option<Box<Node>>, Box<Node>),
     ObjectPattern(IndexMap<String, Node>, bool),
     ArrayPattern(Vec<Node>, bool),
 
@@ -241,6 +249,10 @@ pub enum Node {
     ExportDeclaration(Box<Node>),
 
     Initializer(Box<Node>, Box<Node>),
+    Getter(Box<Node>, Box<Node>),
+    Setter(Box<Node>, Box<Node>),
+
+    Commented(Vec<Comment>, Box<Node>),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -266,27 +278,422 @@ pub enum Error {
 
 impl IntoValue for Error {
     fn into_value(&self, agent: &Agent) -> Value {
-        Value::new_error(agent, &format!("{:?}", self))
+        let e = Value::new_error(agent, &format!("{:?}", self));
+        e.set(agent, ObjectKey::from("name"), Value::from("SyntaxError"))
+            .unwrap();
+        e.set(
+            agent,
+            ObjectKey::from("stack"),
+            Value::from(format!("SyntaxError: {:?}", self)),
+        )
+        .unwrap();
+        e
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CommentKind {
+    Line,
+    Block,
+    /// `/** */` or `///`, documenting what follows.
+    Doc,
+    /// `/*! */` or `//!`, documenting the module it is in.
+    ModuleDoc,
+}
+
+/// A comment, without its markers. Lines of a doc comment also lose the
+/// leading `*` they are usually written with.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub kind: CommentKind,
+    pub text: String,
+}
+
+impl Comment {
+    fn line(text: String) -> Comment {
+        let (kind, text) = if text.starts_with('/') {
+            (CommentKind::Doc, &text[1..])
+        } else if text.starts_with('!') {
+            (CommentKind::ModuleDoc, &text[1..])
+        } else {
+            (CommentKind::Line, &text[..])
+        };
+        let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+        Comment {
+            kind,
+            text: text.to_string(),
+        }
+    }
+
+    fn block(text: String) -> Comment {
+        let kind = if text.starts_with('*') && text != "*" {
+            CommentKind::Doc
+        } else if text.starts_with('!') {
+            CommentKind::ModuleDoc
+        } else {
+            return Comment {
+                kind: CommentKind::Block,
+                text,
+            };
+        };
+        let lines = text[1..]
+            .lines()
+            .map(|l| {
+                let l = l.trim_start();
+                let l = l.strip_prefix('*').unwrap_or(l);
+                l.strip_prefix(' ').unwrap_or(l).trim_end()
+            })
+            .collect::<Vec<_>>();
+        let start = lines.iter().position(|l| !l.is_empty()).unwrap_or(0);
+        let end = lines
+            .iter()
+            .rposition(|l| !l.is_empty())
+            .map_or(0, |i| i + 1);
+        Comment {
+            kind,
+            text: lines[start..end.max(start)].join("\n"),
+        }
+    }
+}
+
+/// What a token is, for highlighting.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenKind {
+    Keyword,
+    Identifier,
+    /// A name after `.`, in an object key or anywhere else a keyword is
+    /// just a name.
+    Property,
+    /// `true`, `false` and `null`.
+    Literal,
+    Number,
+    /// Strings, and the text parts of template literals.
+    String,
+    Regex,
+    /// `:name`.
+    Symbol,
+    Comment,
+    Operator,
+    Punctuation,
+    /// Text that doesn't lex.
+    Invalid,
+}
+
+impl TokenKind {
+    fn of(token: &Token) -> TokenKind {
+        match token {
+            Token::Null | Token::True | Token::False => TokenKind::Literal,
+            Token::NumberLiteral(..) => TokenKind::Number,
+            Token::StringLiteral(..) | Token::BackQuote => TokenKind::String,
+            Token::Identifier(..) => TokenKind::Identifier,
+            Token::Operator(Operator::Typeof)
+            | Token::Operator(Operator::Void)
+            | Token::Operator(Operator::Has) => TokenKind::Keyword,
+            Token::Operator(..) | Token::Arrow | Token::Pipe | Token::Ellipsis => {
+                TokenKind::Operator
+            }
+            Token::LeftBrace
+            | Token::RightBrace
+            | Token::LeftBracket
+            | Token::RightBracket
+            | Token::LeftParen
+            | Token::RightParen
+            | Token::Semicolon
+            | Token::Colon
+            | Token::Question
+            | Token::Dot
+            | Token::At
+            | Token::Hash
+            | Token::Comma
+            | Token::EOF => TokenKind::Punctuation,
+            _ => TokenKind::Keyword,
+        }
+    }
+}
+
+/// A token and the bytes of the source it covers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SpannedToken {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Every token and comment in `code`, in order. The parser classifies them
+/// as it goes, so template literals, regexes and property names come out
+/// as the grammar reads them. Past a syntax error the rest is lexed on its
+/// own. Whitespace is all that's left out.
+pub fn tokenize_with_spans(code: &str) -> Vec<SpannedToken> {
+    let options = ParseOptions {
+        top_level_await: true,
+        features: Feature::ALL.iter().cloned().collect(),
+        comments: false,
+    };
+    let mut parser = Parser::new(code, options);
+    parser.lexer.spans = Some(Vec::new());
+    if parser.parse_program(true).is_err() {
+        // an unterminated template or regex the parser was reading
+        let end = parser
+            .lexer
+            .spans
+            .as_ref()
+            .unwrap()
+            .last()
+            .map_or(0, |s| s.end);
+        parser.lexer.record(TokenKind::Invalid, end);
+    }
+    // every char read makes progress, so this reaches the end
+    while parser.lexer.next() != Ok(Token::EOF) {}
+    parser.lexer.spans.unwrap()
+}
+
+// the characters of the source, knowing how far into it they are
+#[derive(Clone)]
+struct Source<'a> {
+    chars: Chars<'a>,
+    peeked: Option<Option<char>>,
+    len: usize,
+}
+
+impl<'a> Source<'a> {
+    fn peek(&mut self) -> Option<&char> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.chars.next());
+        }
+        self.peeked.as_ref().unwrap().as_ref()
+    }
+
+    // the byte offset of the next character
+    fn offset(&self) -> usize {
+        let peeked = match self.peeked {
+            Some(Some(c)) => c.len_utf8(),
+            _ => 0,
+        };
+        self.len - self.chars.as_str().len() - peeked
+    }
+}
+
+impl<'a> Iterator for Source<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self.peeked.take() {
+            Some(c) => c,
+            None => self.chars.next(),
+        }
     }
 }
 
 struct Lexer<'a> {
-    chars: Peekable<Chars<'a>>,
+    chars: Source<'a>,
     peeked: Option<Result<Token, Error>>,
+    // whether a line break came before the last token scanned
+    newline_before: bool,
+    // where scanning the last token began, before the whitespace and
+    // comments ahead of it
+    scan_start: usize,
+    // whether the last token taken was a semicolon
+    semicolon: bool,
+    // where the token being scanned begins
+    token_start: usize,
+    // every token and comment scanned, if recording for tokenize_with_spans
+    spans: Option<Vec<SpannedToken>>,
+    // comments scanned before the next token, see Parser::take_comments
+    comments: Vec<Comment>,
 }
 
 impl<'a> Lexer<'a> {
     fn new(code: &'a str) -> Lexer<'a> {
         Lexer {
-            chars: code.chars().peekable(),
+            chars: Source {
+                chars: code.chars(),
+                peeked: None,
+                len: code.len(),
+            },
             peeked: None,
+            newline_before: false,
+            scan_start: 0,
+            semicolon: false,
+            token_start: 0,
+            spans: None,
+            comments: Vec::new(),
+        }
+    }
+
+    // UPDATE parse_identifier WHEN YOU ADD TO THIS LIST!
+    fn keyword(ident: &str) -> Token {
+        match ident {
+            "true" => Token::True,
+            "false" => Token::False,
+            "null" => Token::Null,
+            "this" => Token::This,
+            "class" => Token::Class,
+            "extends" => Token::Extends,
+            "function" => Token::Function,
+            "let" => Token::Let,
+            "const" => Token::Const,
+            "throw" => Token::Throw,
+            "return" => Token::Return,
+            "try" => Token::Try,
+            "catch" => Token::Catch,
+            "finally" => Token::Finally,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "if" => Token::If,
+            "else" => Token::Else,
+            "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
+            "new" => Token::New,
+            "import" => Token::Import,
+            "export" => Token::Export,
+            "default" => Token::Default,
+            "from" => Token::From,
+            "async" => Token::Async,
+            "await" => Token::Await,
+            "gen" => Token::Gen,
+            "yield" => Token::Yield,
+            "match" => Token::Match,
+            "typeof" => Token::Operator(Operator::Typeof),
+            "void" => Token::Operator(Operator::Void),
+            "has" => Token::Operator(Operator::Has),
+            _ => Token::Identifier(ident.to_string()),
+        }
+    }
+
+    // after \u: either four hex digits, or one to six in braces. a
+    // surrogate pair written as two escapes makes one character.
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let mut n = self.hex_escape()?;
+        if (0xD800..0xDC00).contains(&n) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err(Error::UnexpectedToken);
+            }
+            let low = self.hex_escape()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(Error::UnexpectedToken);
+            }
+            n = 0x10000 + ((n - 0xD800) << 10) + (low - 0xDC00);
+        }
+        std::char::from_u32(n).ok_or(Error::UnexpectedToken)
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, Error> {
+        let braced = self.chars.peek() == Some(&'{');
+        if braced {
+            self.chars.next();
+        }
+        let mut n = String::new();
+        loop {
+            match self.chars.peek() {
+                Some('}') if braced => {
+                    self.chars.next();
+                    break;
+                }
+                Some(c) if c.is_ascii_hexdigit() && (braced || n.len() < 4) => {
+                    n.push(*c);
+                    self.chars.next();
+                }
+                _ if !braced && n.len() == 4 => break,
+                _ => return Err(Error::UnexpectedToken),
+            }
+        }
+        if n.is_empty() || n.len() > 6 {
+            return Err(Error::UnexpectedToken);
+        }
+        u32::from_str_radix(&n, 16).map_err(|_| Error::UnexpectedToken)
+    }
+
+    // digits of `radix`, possibly separated by single underscores
+    fn digits(&mut self, radix: u32, out: &mut String) -> Result<(), Error> {
+        let mut separated = true;
+        let mut any = false;
+        while let Some(&c) = self.chars.peek() {
+            if c == '_' {
+                // only one, and only between two digits
+                if separated {
+                    return Err(Error::UnexpectedToken);
+                }
+                separated = true;
+            } else if c.is_digit(radix) {
+                out.push(c);
+                separated = false;
+                any = true;
+            } else {
+                break;
+            }
+            self.chars.next();
+        }
+        if separated && any {
+            return Err(Error::UnexpectedToken);
+        }
+        Ok(())
+    }
+
+    // 0x, 0o and 0b literals, after the prefix
+    fn radix_number(&mut self, radix: u32) -> Result<Token, Error> {
+        let mut digits = String::new();
+        self.digits(radix, &mut digits)?;
+        if digits.is_empty() {
+            return Err(Error::UnexpectedToken);
+        }
+        // folded as floats so literals wider than 64 bits still round
+        let n = digits.chars().fold(0f64, |n, d| {
+            n * f64::from(radix) + f64::from(d.to_digit(radix).unwrap())
+        });
+        Ok(Token::NumberLiteral(n))
+    }
+
+    // 12, 1_000, 1.5, 2e10, 1.5E-3
+    fn decimal_number(&mut self, first: char) -> Result<Token, Error> {
+        let mut str = first.to_string();
+        if first != '0' {
+            if self.chars.peek() == Some(&'_') {
+                self.chars.next();
+                if !self.chars.peek().map_or(false, char::is_ascii_digit) {
+                    return Err(Error::UnexpectedToken);
+                }
+            }
+            self.digits(10, &mut str)?;
+        }
+        if self.chars.peek() == Some(&'.') {
+            self.chars.next();
+            str.push('.');
+            if self.chars.peek() == Some(&'_') {
+                return Err(Error::UnexpectedToken);
+            }
+            self.digits(10, &mut str)?;
+        }
+        if let Some('e') | Some('E') = self.chars.peek() {
+            self.chars.next();
+            str.push('e');
+            if let Some(&sign) = self.chars.peek() {
+                if sign == '+' || sign == '-' {
+                    self.chars.next();
+                    str.push(sign);
+                }
+            }
+            let len = str.len();
+            self.digits(10, &mut str)?;
+            if str.len() == len {
+                return Err(Error::UnexpectedToken);
+            }
+        }
+        match str.parse::<f64>() {
+            Ok(n) => Ok(Token::NumberLiteral(n)),
+            Err(_) => Err(Error::UnexpectedToken),
         }
     }
 
     fn inner_next(&mut self) -> Result<Token, Error> {
+        self.token_start = self.chars.offset();
         Ok(match self.chars.next() {
             Some(c) => match c {
-                ' ' | '\t' | '\r' | '\n' => self.next()?,
+                ' ' | '\t' | '\r' => self.inner_next()?,
+                '\n' => {
+                    self.newline_before = true;
+                    self.inner_next()?
+                }
                 '0' => {
                     let radix = match self.chars.peek() {
                         Some('b') | Some('B') => Some(2),
@@ -294,87 +701,15 @@ impl<'a> Lexer<'a> {
                         Some('x') | Some('X') => Some(16),
                         _ => None,
                     };
-                    if let Some(radix) = radix {
-                        self.chars.next();
-                        let mut str = String::new();
-                        while let Some(c) = self.chars.peek() {
-                            match c {
-                                '_' => {
-                                    self.chars.next().unwrap();
-                                    if self.chars.peek() == Some(&'_') {
-                                        return Err(Error::UnexpectedToken);
-                                    }
-                                }
-                                '0' | '1' => str.push(self.chars.next().unwrap()),
-                                '2'...'7' if radix > 7 => str.push(self.chars.next().unwrap()),
-                                '8' | '9' if radix > 15 => str.push(self.chars.next().unwrap()),
-                                'a'...'f' | 'A'...'F' if radix > 15 => {
-                                    str.push(self.chars.next().unwrap())
-                                }
-                                _ => break,
-                            }
-                        }
-                        match u64::from_str_radix(&str, radix) {
-                            Ok(n) => Token::NumberLiteral(n as f64),
-                            Err(_) => return Err(Error::UnexpectedToken),
-                        }
-                    } else {
-                        Token::NumberLiteral(0.0)
-                    }
-                }
-                '1'...'9' => {
-                    let mut str = c.to_string();
-                    let mut exp_str = String::new();
-                    let mut one_dot = false;
-                    let mut in_exp = false;
-                    while let Some(c) = self.chars.peek() {
-                        match c {
-                            '_' => {
-                                self.chars.next().unwrap();
-                                if self.chars.peek() == Some(&'_') {
-                                    return Err(Error::UnexpectedToken);
-                                }
-                                continue;
-                            }
-                            '0'...'9' => {
-                                if in_exp {
-                                    exp_str.push(self.chars.next().unwrap());
-                                } else {
-                                    str.push(self.chars.next().unwrap());
-                                }
-                            }
-                            'e' if !in_exp => {
-                                self.chars.next().unwrap();
-                                in_exp = true;
-                            }
-                            '.' if !in_exp => {
-                                if !one_dot {
-                                    one_dot = true;
-                                    str.push(self.chars.next().unwrap());
-                                    if self.chars.peek() == Some(&'_') {
-                                        return Err(Error::UnexpectedToken);
-                                    }
-                                } else {
-                                    break;
-                                }
-                            }
-                            _ => break,
-                        }
-                    }
-                    match str.parse::<f64>() {
-                        Ok(n) => {
-                            if in_exp {
-                                match exp_str.parse::<u32>() {
-                                    Ok(e) => Token::NumberLiteral(n * (10u64.pow(e) as f64)),
-                                    Err(_) => return Err(Error::UnexpectedToken),
-                                }
-                            } else {
-                                Token::NumberLiteral(n)
-                            }
+                    match radix {
+                        Some(radix) => {
+                            self.chars.next();
+                            self.radix_number(radix)?
                         }
-                        Err(_) => return Err(Error::UnexpectedToken),
+                        None => self.decimal_number('0')?,
                     }
                 }
+                '1'...'9' => self.decimal_number(c)?,
                 '"' | '\'' => {
                     let mut str = String::new();
                     while let Some(char) = self.chars.peek() {
@@ -390,38 +725,7 @@ impl<'a> Lexer<'a> {
                                 '"' => str.push('"'),
                                 '\'' => str.push('\''),
                                 '\\' => str.push('\\'),
-                                'u' => {
-                                    if Some('{') != self.chars.next() {
-                                        return Err(Error::UnexpectedToken);
-                                    }
-                                    let mut n = String::new();
-                                    macro_rules! digit {
-                                        () => {
-                                            let next = self.chars.next();
-                                            match next {
-                                                Some('0'...'9') | Some('a'...'f')
-                                                | Some('A'...'F') => {
-                                                    n.push(next.unwrap());
-                                                }
-                                                _ => return Err(Error::UnexpectedToken),
-                                            }
-                                        };
-                                    }
-                                    digit!();
-                                    digit!();
-                                    digit!();
-                                    digit!();
-                                    match u32::from_str_radix(n.as_str(), 16) {
-                                        Ok(n) => match std::char::from_u32(n) {
-                                            Some(c) => str.push(c),
-                                            None => return Err(Error::UnexpectedToken),
-                                        },
-                                        Err(_) => return Err(Error::UnexpectedToken),
-                                    }
-                                    if Some('}') != self.chars.next() {
-                                        return Err(Error::UnexpectedToken);
-                                    }
-                                }
+                                'u' => str.push(self.unicode_escape()?),
                                 'U' => {
                                     if Some('{') != self.chars.next() {
                                         return Err(Error::UnexpectedToken);
@@ -447,53 +751,43 @@ impl<'a> Lexer<'a> {
                     }
                     Token::StringLiteral(str)
                 }
-                'a'...'z' | 'A'...'Z' | '_' => {
-                    let mut ident = c.to_string();
-                    while let Some(c) = self.chars.peek() {
-                        match c {
-                            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => {
-                                ident.push(self.chars.next().unwrap())
+                c if c == '_' || c == '\\' || c.is_ascii_alphabetic() || is_xid_start(c) => {
+                    let mut escaped = false;
+                    let mut ident = String::new();
+                    let mut next = Some(c);
+                    while let Some(c) = next {
+                        let c = if c == '\\' {
+                            if self.chars.next() != Some('u') {
+                                return Err(Error::UnexpectedToken);
                             }
-                            _ => break,
+                            escaped = true;
+                            self.unicode_escape()?
+                        } else {
+                            c
+                        };
+                        let valid = if ident.is_empty() {
+                            c == '_' || is_xid_start(c)
+                        } else {
+                            // 200c and 200d are the joiners some scripts need
+                            c == '_' || c == '\u{200c}' || c == '\u{200d}' || is_xid_continue(c)
+                        };
+                        if !valid {
+                            return Err(Error::UnexpectedToken);
                         }
+                        ident.push(c);
+                        next = match self.chars.peek() {
+                            Some(&c) if c == '\\' || c == '\u{200c}' || c == '\u{200d}' => {
+                                self.chars.next()
+                            }
+                            Some(&c) if c == '_' || is_xid_continue(c) => self.chars.next(),
+                            _ => None,
+                        };
                     }
-                    // UPDATE parse_identifier WHEN YOU ADD TO THIS LIST!
-                    match ident.as_ref() {
-                        "true" => Token::True,
-                        "false" => Token::False,
-                        "null" => Token::Null,
-                        "this" => Token::This,
-                        "class" => Token::Class,
-                        "extends" => Token::Extends,
-                        "function" => Token::Function,
-                        "let" => Token::Let,
-                        "const" => Token::Const,
-                        "throw" => Token::Throw,
-                        "return" => Token::Return,
-                        "try" => Token::Try,
-                        "catch" => Token::Catch,
-                        "finally" => Token::Finally,
-                        "break" => Token::Break,
-                        "continue" => Token::Continue,
-                        "if" => Token::If,
-                        "else" => Token::Else,
-                        "while" => Token::While,
-                        "for" => Token::For,
-                        "in" => Token::In,
-                        "new" => Token::New,
-                        "import" => Token::Import,
-                        "export" => Token::Export,
-                        "default" => Token::Default,
-                        "from" => Token::From,
-                        "async" => Token::Async,
-                        "await" => Token::Await,
-                        "gen" => Token::Gen,
-                        "yield" => Token::Yield,
-                        "match" => Token::Match,
-                        "typeof" => Token::Operator(Operator::Typeof),
-                        "void" => Token::Operator(Operator::Void),
-                        "has" => Token::Operator(Operator::Has),
-                        _ => Token::Identifier(ident),
+                    match Lexer::keyword(&ident) {
+                        // a keyword can't be spelled with escapes
+                        Token::Identifier(..) => Token::Identifier(ident),
+                        _ if escaped => return Err(Error::UnexpectedToken),
+                        keyword => keyword,
                     }
                 }
                 '{' => Token::LeftBrace,
@@ -558,28 +852,41 @@ impl<'a> Lexer<'a> {
                         Token::Operator(Operator::DivAssign)
                     }
                     Some('*') => {
+                        self.chars.next();
+                        let mut text = String::new();
                         loop {
-                            if self.chars.peek() == None {
-                                return Err(Error::UnexpectedEOF);
-                            }
-                            if let Some('*') = self.chars.next() {
-                                if let Some('/') = self.chars.next() {
+                            match self.chars.next() {
+                                None => return Err(Error::UnexpectedEOF),
+                                Some('*') if self.chars.peek() == Some(&'/') => {
+                                    self.chars.next();
                                     break;
                                 }
+                                Some(c) => {
+                                    if c == '\n' {
+                                        self.newline_before = true;
+                                    }
+                                    text.push(c);
+                                }
                             }
                         }
-                        self.next()?
+                        self.record(TokenKind::Comment, self.token_start);
+                        self.comments.push(Comment::block(text));
+                        self.inner_next()?
                     }
                     Some('/') => {
-                        loop {
-                            if self.chars.peek() == None {
-                                return Err(Error::UnexpectedEOF);
-                            }
-                            if let Some('\n') = self.chars.next() {
+                        self.chars.next();
+                        let mut text = String::new();
+                        // a comment can end the file
+                        while let Some(c) = self.chars.next() {
+                            if c == '\n' {
+                                self.newline_before = true;
                                 break;
                             }
+                            text.push(c);
                         }
-                        self.next()?
+                        self.record(TokenKind::Comment, self.token_start);
+                        self.comments.push(Comment::line(text));
+                        self.inner_next()?
                     }
                     _ => Token::Operator(Operator::Div),
                 },
@@ -631,6 +938,10 @@ impl<'a> Lexer<'a> {
                         self.chars.next();
                         Token::Operator(Operator::LogicalOR)
                     }
+                    Some('>') => {
+                        self.chars.next();
+                        Token::Pipe
+                    }
                     _ => Token::Operator(Operator::BitwiseOR),
                 },
                 '^' => Token::Operator(Operator::BitwiseXOR),
@@ -647,6 +958,7 @@ impl<'a> Lexer<'a> {
                     _ => Token::Operator(Operator::Assign),
                 },
                 '@' => Token::At,
+                '#' => Token::Hash,
                 _ => return Err(Error::UnexpectedToken),
             },
             None => Token::EOF,
@@ -654,15 +966,71 @@ impl<'a> Lexer<'a> {
     }
 
     fn next(&mut self) -> Result<Token, Error> {
-        match self.peeked.take() {
+        // comments nobody took before the token they precede are dropped
+        self.comments.clear();
+        let token = match self.peeked.take() {
             Some(v) => v,
-            None => self.inner_next(),
+            None => self.scan(),
+        };
+        self.semicolon = token == Ok(Token::Semicolon);
+        token
+    }
+
+    fn scan(&mut self) -> Result<Token, Error> {
+        self.newline_before = false;
+        self.scan_start = self.chars.offset();
+        let token = self.inner_next();
+        if self.spans.is_some() {
+            let kind = match &token {
+                Ok(token) => TokenKind::of(token),
+                Err(_) => TokenKind::Invalid,
+            };
+            self.record(kind, self.token_start);
+        }
+        token
+    }
+
+    fn record(&mut self, kind: TokenKind, start: usize) {
+        if let Some(spans) = &mut self.spans {
+            let end = self.chars.offset();
+            if end > start {
+                spans.push(SpannedToken { kind, start, end });
+            }
+        }
+    }
+
+    // what the parser learned about the last token
+    fn retag_last(&mut self, kind: TokenKind) {
+        if let Some(last) = self.spans.as_mut().and_then(|spans| spans.last_mut()) {
+            last.kind = kind;
+        }
+    }
+
+    // the last token began something the parser reads itself
+    fn extend_last(&mut self, kind: TokenKind) {
+        let end = self.chars.offset();
+        self.retag_last(kind);
+        if let Some(last) = self.spans.as_mut().and_then(|spans| spans.last_mut()) {
+            last.end = end;
+        }
+    }
+
+    // the last two tokens are one thing, like `:name`
+    fn merge_last(&mut self, kind: TokenKind) {
+        if let Some(spans) = &mut self.spans {
+            if let Some(last) = spans.pop() {
+                if let Some(first) = spans.last_mut() {
+                    first.kind = kind;
+                    first.end = last.end;
+                }
+            }
         }
     }
 
     pub fn peek(&mut self) -> Result<&Token, Error> {
         if self.peeked.is_none() {
-            self.peeked = Some(self.next());
+            self.comments.clear();
+            self.peeked = Some(self.scan());
         }
         match self.peeked {
             Some(Ok(ref value)) => Ok(value),
@@ -679,17 +1047,21 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // `#!/usr/bin/env slither`, so scripts can be run directly
     fn skip_hashbang(&mut self) {
-        if self.chars.peek() == Some(&'#') {
+        if self.chars.peek() == Some(&'\u{feff}') {
             self.chars.next();
-            if self.chars.peek() == Some(&'!') {
-                loop {
-                    match self.chars.next() {
-                        Some('\n') | None => break,
-                        _ => {}
-                    }
+        }
+        let mut start = self.chars.clone();
+        if start.next() == Some('#') && start.next() == Some('!') {
+            let start = self.chars.offset();
+            loop {
+                match self.chars.next() {
+                    Some('\n') | None => break,
+                    _ => {}
                 }
             }
+            self.record(TokenKind::Comment, start);
         }
     }
 }
@@ -783,7 +1155,10 @@ fn constant_fold(op: Operator, left: &Node, right: &Node) -> Option<Node> {
             Node::NumberLiteral(..) => Some(Node::StringLiteral("number".to_string())),
             Node::StringLiteral(..) => Some(Node::StringLiteral("string".to_string())),
             Node::SymbolLiteral(..) => Some(Node::StringLiteral("symbol".to_string())),
-            Node::TupleLiteral(..) => Some(Node::StringLiteral("tuple".to_string())),
+            Node::TupleLiteral(..) | Node::ImmutableTupleLiteral(..) => {
+                Some(Node::StringLiteral("tuple".to_string()))
+            }
+            Node::RecordLiteral(..) => Some(Node::StringLiteral("record".to_string())),
             Node::ObjectLiteral(..) | Node::ArrayLiteral(..) => {
                 Some(Node::StringLiteral("object".to_string()))
             }
@@ -802,7 +1177,11 @@ fn constant_truthy(node: &Node) -> Option<bool> {
         Node::StringLiteral(s) => Some(!s.is_empty()),
         Node::NumberLiteral(n) => Some(*n != 0.0),
         Node::SymbolLiteral(..) => Some(true),
-        Node::ArrayLiteral(..) | Node::TupleLiteral(..) | Node::ObjectLiteral(..) => Some(true),
+        Node::ArrayLiteral(..)
+        | Node::TupleLiteral(..)
+        | Node::ImmutableTupleLiteral(..)
+        | Node::RecordLiteral(..)
+        | Node::ObjectLiteral(..) => Some(true),
         _ => None,
     }
 }
@@ -825,37 +1204,172 @@ macro_rules! binop_production {
     }
 }
 
+/// Opt-in syntax, so experiments can land behind a flag instead of
+/// changing the language for everyone.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Feature {
+    /// A line break, `}` or the end of input can end a statement.
+    OptionalSemicolons,
+    /// `value |> f` calls `f` with `value`. `value |> f(%, 1)` evaluates the
+    /// right side with `%` bound to `value` instead.
+    Pipeline,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 2] = [Feature::OptionalSemicolons, Feature::Pipeline];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::OptionalSemicolons => "optional-semicolons",
+            Feature::Pipeline => "pipeline",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Feature::ALL.iter().cloned().find(|f| f.name() == name)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// Allow `await` outside of any function, as in `slither -e`.
+    pub top_level_await: bool,
+    pub features: HashSet<Feature>,
+    /// Keep the comments before statements and class methods, as
+    /// `Node::Commented`. For tooling like `slither doc`; the assembler
+    /// ignores them.
+    pub comments: bool,
+}
+
+/// A statement of a program, as `Parser::parse_top_level` finds it.
+pub(crate) struct TopLevelStatement {
+    /// The byte offset it starts at, including the whitespace and comments
+    /// ahead of it.
+    pub start: usize,
+    pub node: Node,
+    /// The names it declares in the program's scope, and whether each is
+    /// mutable.
+    pub bindings: Vec<(String, bool)>,
+    /// Whether the next statement has to start after it. Without a
+    /// semicolon, `Feature::OptionalSemicolons` can let an expression
+    /// statement run on into what follows.
+    pub ends: bool,
+}
+
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     scope: Vec<Scope>,
     scope_bits: u8,
+    options: ParseOptions,
+    // while parsing the right side of `|>`, whether it used `%`
+    topic: Option<bool>,
 }
 
 impl<'a> Parser<'a> {
     pub fn parse(code: &'a str) -> Result<Node, Error> {
-        let mut parser = Parser {
+        Parser::parse_with_options(code, ParseOptions::default())
+    }
+
+    pub fn parse_with_options(code: &'a str, options: ParseOptions) -> Result<Node, Error> {
+        let (statements, _) = Parser::parse_top_level(code, options, true)?;
+        let mut bindings = IndexMap::new();
+        let mut nodes = Vec::with_capacity(statements.len());
+        for statement in statements {
+            bindings.extend(statement.bindings);
+            nodes.push(statement.node);
+        }
+        Ok(Parser::program(bindings, nodes))
+    }
+
+    /// Parse the statements of a program one by one, keeping where each
+    /// starts. Also returns whether every statement was read to its end,
+    /// rather than the program being stopped early by a stray `}` or a
+    /// block left open.
+    pub(crate) fn parse_top_level(
+        code: &'a str,
+        options: ParseOptions,
+        hashbang: bool,
+    ) -> Result<(Vec<TopLevelStatement>, bool), Error> {
+        Parser::new(code, options).parse_program(hashbang)
+    }
+
+    fn new(code: &'a str, options: ParseOptions) -> Parser<'a> {
+        Parser {
             lexer: Lexer::new(code),
             scope_bits: 0,
             scope: Vec::new(),
-        };
+            options,
+            topic: None,
+        }
+    }
 
-        parser.lexer.skip_hashbang();
+    fn parse_program(&mut self, hashbang: bool) -> Result<(Vec<TopLevelStatement>, bool), Error> {
+        if hashbang {
+            self.lexer.skip_hashbang();
+        }
 
-        if let Node::Block(scope, mut stmts) = parser.parse_block(ParseScope::TopLevel)? {
-            if let Some(Node::ExpressionStatement(..)) = stmts.last() {
-                // if the last item is an expression statement, replace it with the expression
-                // so that the value will be left on the stack to inspect in tests
-                if let Node::ExpressionStatement(expr) = stmts.pop().unwrap() {
-                    stmts.push(Node::ParenthesizedExpression(expr));
-                    Ok(Node::Block(scope, stmts))
-                } else {
-                    unreachable!();
-                }
-            } else {
-                Ok(Node::Block(scope, stmts))
+        let optional_semicolons = self.options.features.contains(&Feature::OptionalSemicolons);
+        self.scope_bits = self.nested_scope_bits(ParseScope::TopLevel);
+        self.scope.push(Scope::new(ParseScope::TopLevel));
+        let mut statements = Vec::new();
+        while !self.eat(Token::RightBrace) {
+            let start = self.lexer.scan_start;
+            let declared = self.scope[0].bindings.len();
+            if self.peek(Token::EOF) {
+                return Ok((statements, true));
             }
-        } else {
-            unreachable!();
+            let node = match self.parse_statement() {
+                Ok(node) => node,
+                // a block left open ends the program where it starts
+                Err(Error::NormalEOF) => return Ok((statements, false)),
+                Err(e) => return Err(e),
+            };
+            let bindings = self.scope[0]
+                .bindings
+                .iter()
+                .skip(declared)
+                .map(|(name, mutable)| (name.clone(), *mutable))
+                .collect();
+            let ends =
+                !optional_semicolons || self.lexer.semicolon || Parser::is_declaration(&node);
+            statements.push(TopLevelStatement {
+                start,
+                node,
+                bindings,
+                ends,
+            });
+        }
+        Ok((statements, false))
+    }
+
+    pub(crate) fn program(bindings: IndexMap<String, bool>, mut statements: Vec<Node>) -> Node {
+        let scope = Scope {
+            kind: ScopeKind::TopLevel,
+            bindings,
+        };
+        if let Some(Node::ExpressionStatement(..)) = statements.last() {
+            // if the last item is an expression statement, replace it with the expression
+            // so that the value will be left on the stack to inspect in tests
+            if let Node::ExpressionStatement(expr) = statements.pop().unwrap() {
+                statements.push(Node::ParenthesizedExpression(expr));
+            }
+        }
+        Node::Block(scope, statements)
+    }
+
+    // statements nothing after them can continue, even without a semicolon
+    fn is_declaration(node: &Node) -> bool {
+        match node {
+            Node::Commented(_, node) | Node::ExportDeclaration(node) => {
+                Parser::is_declaration(node)
+            }
+            Node::FunctionDeclaration(..)
+            | Node::ClassDeclaration(..)
+            | Node::ImportDeclaration(..)
+            | Node::ImportNamedDeclaration(..)
+            | Node::ImportDefaultDeclaration(..)
+            | Node::ImportStandardDeclaration(..) => true,
+            _ => false,
         }
     }
 
@@ -863,6 +1377,21 @@ impl<'a> Parser<'a> {
         (self.scope_bits & scope as u8) == scope as u8
     }
 
+    // a function body can't break out of the loops around it, and only its
+    // own kind decides whether it can await or yield
+    fn nested_scope_bits(&self, scope: ParseScope) -> u8 {
+        let mut bits = self.scope_bits;
+        if scope as u8 & ParseScope::Function as u8 != 0 {
+            bits &= ParseScope::TopLevel as u8 | ParseScope::Block as u8;
+        }
+        bits | scope as u8
+    }
+
+    fn can_await(&self) -> bool {
+        self.scope(ParseScope::AsyncFunction)
+            || (self.options.top_level_await && !self.scope(ParseScope::Function))
+    }
+
     fn declare(&mut self, name: &str, mutable: bool) -> Result<(), Error> {
         let scope = self.scope.last_mut().unwrap();
         if scope.declare(name, mutable) {
@@ -886,6 +1415,26 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn implicit_semicolon(&mut self) -> bool {
+        if !self.options.features.contains(&Feature::OptionalSemicolons) {
+            return false;
+        }
+        match self.lexer.peek() {
+            Ok(Token::RightBrace) | Ok(Token::EOF) => return true,
+            Err(_) => return false,
+            Ok(_) => {}
+        }
+        self.lexer.newline_before
+    }
+
+    fn expect_semicolon(&mut self) -> Result<(), Error> {
+        if self.eat(Token::Semicolon) || self.implicit_semicolon() {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedToken)
+        }
+    }
+
     fn expect(&mut self, token: Token) -> Result<Token, Error> {
         match self.lexer.next()? {
             ref t if t == &token => Ok(token),
@@ -909,8 +1458,31 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // the comments before the token just peeked
+    fn take_comments(&mut self) -> Vec<Comment> {
+        if self.options.comments {
+            std::mem::replace(&mut self.lexer.comments, Vec::new())
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn commented(comments: Vec<Comment>, node: Node) -> Node {
+        if comments.is_empty() {
+            node
+        } else {
+            Node::Commented(comments, Box::new(node))
+        }
+    }
+
     fn parse_statement(&mut self) -> Result<Node, Error> {
         self.lexer.peek()?;
+        let comments = self.take_comments();
+        let statement = self.parse_uncommented_statement()?;
+        Ok(Parser::commented(comments, statement))
+    }
+
+    fn parse_uncommented_statement(&mut self) -> Result<Node, Error> {
         match self.lexer.peek_immutable()? {
             Token::EOF => Err(Error::NormalEOF),
             Token::LeftBrace => self.parse_block(ParseScope::Block),
@@ -935,12 +1507,12 @@ impl<'a> Parser<'a> {
             Token::For => self.parse_for(),
             Token::Continue if self.scope(ParseScope::Loop) => {
                 self.lexer.next()?;
-                self.expect(Token::Semicolon)?;
+                self.expect_semicolon()?;
                 Ok(Node::ContinueStatement)
             }
             Token::Break if self.scope(ParseScope::Loop) => {
                 self.lexer.next()?;
-                self.expect(Token::Semicolon)?;
+                self.expect_semicolon()?;
                 Ok(Node::BreakStatement)
             }
             Token::Return if self.scope(ParseScope::Function) => self.parse_return(),
@@ -951,24 +1523,21 @@ impl<'a> Parser<'a> {
             Token::Export if self.scope(ParseScope::TopLevel) => self.parse_export(),
             _ => {
                 let r = self.parse_expression()?;
-                self.expect(Token::Semicolon)?;
+                self.expect_semicolon()?;
                 Ok(Node::ExpressionStatement(Box::new(r)))
             }
         }
     }
 
     fn parse_block(&mut self, scope: ParseScope) -> Result<Node, Error> {
-        if scope != ParseScope::TopLevel {
-            self.expect(Token::LeftBrace)?;
-        }
+        self.expect(Token::LeftBrace)?;
         let saved = self.scope_bits;
-        self.scope_bits |= scope as u8;
+        self.scope_bits = self.nested_scope_bits(scope);
         self.scope.push(Scope::new(scope));
         let mut statements = Vec::new();
         while !self.eat(Token::RightBrace) {
             match self.parse_statement() {
                 Ok(s) => statements.push(s),
-                Err(Error::NormalEOF) if scope == ParseScope::TopLevel => break,
                 Err(e) => {
                     self.scope_bits = saved;
                     self.scope.pop();
@@ -993,7 +1562,7 @@ impl<'a> Parser<'a> {
         self.declare(name.as_str(), mutable)?;
         self.expect(Token::Operator(Operator::Assign))?;
         let init = self.parse_expression()?;
-        self.expect(Token::Semicolon)?;
+        self.expect_semicolon()?;
         Ok(Node::LexicalInitialization(name, Box::new(init)))
     }
 
@@ -1068,7 +1637,7 @@ impl<'a> Parser<'a> {
 
     fn parse_for(&mut self) -> Result<Node, Error> {
         self.expect(Token::For)?;
-        let r#async = if self.scope(ParseScope::AsyncFunction) {
+        let r#async = if self.can_await() {
             self.eat(Token::Await)
         } else {
             false
@@ -1087,13 +1656,13 @@ impl<'a> Parser<'a> {
 
     fn parse_return(&mut self) -> Result<Node, Error> {
         self.expect(Token::Return)?;
-        if self.eat(Token::Semicolon) {
+        if self.eat(Token::Semicolon) || self.implicit_semicolon() {
             Ok(Node::ReturnStatement(None))
         } else if self.scope(ParseScope::GeneratorFunction) {
             Err(Error::UnexpectedToken)
         } else {
             let expr = self.parse_expression()?;
-            self.expect(Token::Semicolon)?;
+            self.expect_semicolon()?;
             Ok(Node::ReturnStatement(Some(Box::new(
                 if let Node::CallExpression(callee, arguments) = expr {
                     Node::TailCallExpression(callee, arguments)
@@ -1107,7 +1676,7 @@ impl<'a> Parser<'a> {
     fn parse_throw(&mut self) -> Result<Node, Error> {
         self.expect(Token::Throw)?;
         let expr = self.parse_expression()?;
-        self.expect(Token::Semicolon)?;
+        self.expect_semicolon()?;
         Ok(Node::ThrowStatement(Box::new(expr)))
     }
 
@@ -1180,7 +1749,7 @@ impl<'a> Parser<'a> {
             Token::StringLiteral(s) => {
                 let specifier = s.to_string();
                 self.lexer.next()?;
-                self.expect(Token::Semicolon)?;
+                self.expect_semicolon()?;
                 Ok(Node::ImportDeclaration(specifier))
             }
 
@@ -1193,13 +1762,13 @@ impl<'a> Parser<'a> {
                 match self.lexer.next()? {
                     Token::StringLiteral(c) => {
                         let specifier = c;
-                        self.expect(Token::Semicolon)?;
+                        self.expect_semicolon()?;
                         Ok(Node::ImportNamedDeclaration(specifier, bindings))
                     }
                     Token::Identifier(ref s) if s == "standard" => {
                         self.expect(Token::Colon)?;
                         let namespace = self.parse_identifier(true)?;
-                        self.expect(Token::Semicolon)?;
+                        self.expect_semicolon()?;
                         Ok(Node::ImportStandardDeclaration(namespace, bindings))
                     }
                     _ => Err(Error::UnexpectedToken),
@@ -1214,7 +1783,7 @@ impl<'a> Parser<'a> {
                     Token::StringLiteral(s) => s,
                     _ => unreachable!(),
                 };
-                self.expect(Token::Semicolon)?;
+                self.expect_semicolon()?;
                 Ok(Node::ImportDefaultDeclaration(specifier, binding))
             }
 
@@ -1240,7 +1809,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_assignment_expression(&mut self) -> Result<Node, Error> {
-        if self.eat(Token::Yield) && self.scope(ParseScope::GeneratorFunction) {
+        if self.scope(ParseScope::GeneratorFunction) && self.eat(Token::Yield) {
             match self.lexer.peek()? {
                 Token::Semicolon
                 | Token::RightBrace
@@ -1256,7 +1825,7 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        let mut lhs = self.parse_conditional_expression()?;
+        let mut lhs = self.parse_pipeline_expression()?;
 
         macro_rules! op_assign {
             ($op:expr) => {{
@@ -1282,6 +1851,28 @@ impl<'a> Parser<'a> {
         Ok(lhs)
     }
 
+    fn parse_pipeline_expression(&mut self) -> Result<Node, Error> {
+        let mut lhs = self.parse_conditional_expression()?;
+        if !self.options.features.contains(&Feature::Pipeline) {
+            return Ok(lhs);
+        }
+        while self.eat(Token::Pipe) {
+            let outer = self.topic.replace(false);
+            let rhs = self.parse_conditional_expression();
+            let used = self.topic.take() == Some(true);
+            self.topic = outer;
+            let rhs = rhs?;
+            // without a topic the right side is the function to call
+            let rhs = if used {
+                rhs
+            } else {
+                Node::CallExpression(Box::new(rhs), vec![Node::Identifier("%".to_string())])
+            };
+            lhs = Node::PipelineExpression(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
     fn check_assignment_target(&self, node: &Node) -> Result<(), Error> {
         match node {
             Node::Identifier(..) => Ok(()),
@@ -1417,7 +2008,7 @@ impl<'a> Parser<'a> {
                 let expr = self.parse_unary_expression()?;
                 Ok(self.build_unary(Operator::Void, expr))
             }
-            Token::Await if self.scope(ParseScope::AsyncFunction) => {
+            Token::Await if self.can_await() => {
                 self.lexer.next()?;
                 let expr = self.parse_unary_expression()?;
                 Ok(Node::AwaitExpression(Box::new(expr)))
@@ -1439,14 +2030,88 @@ impl<'a> Parser<'a> {
             } else if self.eat(Token::LeftParen) {
                 let (list, ..) = self.parse_expression_list(Token::RightParen)?;
                 base = Node::CallExpression(Box::new(base), list);
+            } else if self.eat(Token::BackQuote) {
+                // tag`a${b}c` calls tag(['a', 'c'], b)
+                let (quasis, expressions) = self.parse_template()?;
+                let mut list = vec![Node::ArrayLiteral(
+                    quasis.into_iter().map(Node::StringLiteral).collect(),
+                )];
+                list.extend(expressions);
+                base = Node::CallExpression(Box::new(base), list);
             } else {
                 return Ok(base);
             }
         }
     }
 
+    // the opening ` has already been consumed
+    fn parse_template(&mut self) -> Result<(Vec<String>, Vec<Node>), Error> {
+        let mut quasis = Vec::new();
+        let mut expressions = Vec::new();
+        let mut current = String::new();
+        let mut start = self.lexer.chars.offset();
+        loop {
+            match self.lexer.chars.next() {
+                Some('$') => {
+                    if self.lexer.chars.peek() == Some(&'{') {
+                        quasis.push(current);
+                        current = String::new();
+                        self.lexer.chars.next();
+                        self.lexer.record(TokenKind::String, start);
+                        let expr = self.parse_expression()?;
+                        expressions.push(expr);
+                        self.expect(Token::RightBrace)?;
+                        start = self.lexer.chars.offset();
+                    } else {
+                        current.push('$');
+                    }
+                }
+                Some('`') => break,
+                Some(c) => {
+                    if c == '\\' {
+                        match self.lexer.chars.next() {
+                            Some('n') => current.push('\n'),
+                            Some('t') => current.push('\t'),
+                            Some('\\') => current.push('\\'),
+                            Some('u') => current.push(self.lexer.unicode_escape()?),
+                            Some('U') => {
+                                if Some('{') != self.lexer.chars.next() {
+                                    return Err(Error::UnexpectedToken);
+                                }
+                                let mut name = String::new();
+                                loop {
+                                    match self.lexer.chars.next() {
+                                        Some('}') => break,
+                                        None => return Err(Error::UnexpectedEOF),
+                                        Some(c) => name.push(c),
+                                    }
+                                }
+                                match UNICODE_NAME_MAP.get(name.as_str()) {
+                                    Some(c) => current.push(*c),
+                                    None => return Err(Error::UnexpectedToken),
+                                };
+                            }
+                            None | _ => return Err(Error::UnexpectedEOF),
+                        }
+                    } else {
+                        current.push(c);
+                    }
+                }
+                None => return Err(Error::UnexpectedEOF),
+            }
+        }
+        self.lexer.record(TokenKind::String, start);
+        quasis.push(current);
+        Ok((quasis, expressions))
+    }
+
     fn parse_identifier(&mut self, allow_keyword: bool) -> Result<String, Error> {
-        match self.lexer.next()? {
+        let token = self.lexer.next()?;
+        if allow_keyword {
+            // properties and other names keywords can be
+            self.lexer.retag_last(TokenKind::Property);
+        }
+        match token {
             Token::Identifier(name) => Ok(name),
             Token::Throw if allow_keyword => Ok("throw".to_string()),
             Token::Catch if allow_keyword => Ok("catch".to_string()),
@@ -1488,6 +2153,24 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // the key of an object literal property: `[expr]`, a string, a number or
+    // a name
+    fn parse_property_key(&mut self) -> Result<Node, Error> {
+        if self.eat(Token::LeftBracket) {
+            let name = self.parse_expression()?;
+            self.expect(Token::RightBracket)?;
+            return Ok(name);
+        }
+        match self.lexer.peek()? {
+            Token::StringLiteral(..) | Token::NumberLiteral(..) => match self.lexer.next()? {
+                Token::StringLiteral(s) => Ok(Node::StringLiteral(s)),
+                Token::NumberLiteral(n) => Ok(Node::NumberLiteral(n)),
+                _ => unreachable!(),
+            },
+            _ => Ok(Node::StringLiteral(self.parse_identifier(true)?)),
+        }
+    }
+
     fn parse_primary_expression(&mut self) -> Result<Node, Error> {
         let token = self.lexer.next()?;
         match token {
@@ -1498,8 +2181,14 @@ impl<'a> Parser<'a> {
             Token::NumberLiteral(n) => Ok(Node::NumberLiteral(n)),
             Token::Colon => {
                 let name = self.parse_identifier(false)?;
+                self.lexer.merge_last(TokenKind::Symbol);
                 Ok(Node::SymbolLiteral(name))
             }
+            // the topic of the enclosing pipeline
+            Token::Operator(Operator::Mod) if self.topic.is_some() => {
+                self.topic = Some(true);
+                Ok(Node::Identifier("%".to_string()))
+            }
             Token::Operator(Operator::Div) => {
                 let mut pattern = String::new();
                 loop {
@@ -1515,12 +2204,31 @@ impl<'a> Parser<'a> {
                         None => return Err(Error::UnexpectedEOF),
                     }
                 }
+                self.lexer.extend_last(TokenKind::Regex);
                 Ok(Node::RegexLiteral(pattern))
             }
             Token::This => Ok(Node::ThisExpression),
             Token::New => {
-                let expr = self.parse_left_hand_side_expression()?;
-                Ok(Node::NewExpression(Box::new(expr)))
+                // `new a.b(c).d` constructs `a.b` and reads `d` from the result
+                let mut callee = self.parse_primary_expression()?;
+                loop {
+                    if self.eat(Token::Dot) {
+                        let property = self.parse_identifier(true)?;
+                        callee = Node::MemberExpression(Box::new(callee), property);
+                    } else if self.eat(Token::LeftBracket) {
+                        let property = self.parse_expression()?;
+                        self.expect(Token::RightBracket)?;
+                        callee =
+                            Node::ComputedMemberExpression(Box::new(callee), Box::new(property));
+                    } else {
+                        break;
+                    }
+                }
+                if self.eat(Token::LeftParen) {
+                    let (list, ..) = self.parse_expression_list(Token::RightParen)?;
+                    callee = Node::CallExpression(Box::new(callee), list);
+                }
+                Ok(Node::NewExpression(Box::new(callee)))
             }
             Token::Identifier(i) => Ok(Node::Identifier(i)),
             Token::LeftBracket => {
@@ -1539,18 +2247,41 @@ impl<'a> Parser<'a> {
                             break;
                         }
                     }
-                    let name = if self.eat(Token::LeftBracket) {
-                        let name = self.parse_expression()?;
-                        self.expect(Token::RightBracket)?;
-                        name
-                    } else {
-                        Node::StringLiteral(self.parse_identifier(true)?)
+                    let bare = match self.lexer.peek() {
+                        Ok(Token::Identifier(..)) => true,
+                        _ => false,
+                    };
+                    let name = self.parse_property_key()?;
+                    let accessor = match &name {
+                        Node::StringLiteral(n) if bare && (n == "get" || n == "set") => {
+                            !(self.peek(Token::Colon)
+                                || self.peek(Token::LeftParen)
+                                || self.peek(Token::Comma)
+                                || self.peek(Token::RightBrace))
+                        }
+                        _ => false,
                     };
+                    if accessor {
+                        let getter = name == Node::StringLiteral("get".to_string());
+                        let key = self.parse_property_key()?;
+                        let f = self.parse_function(true, FunctionKind::Normal)?;
+                        match &f {
+                            Node::FunctionExpression(_, _, args, _)
+                                if args.len() == if getter { 0 } else { 1 } => {}
+                            _ => return Err(Error::UnexpectedToken),
+                        }
+                        fields.push(if getter {
+                            Node::Getter(Box::new(key), Box::new(f))
+                        } else {
+                            Node::Setter(Box::new(key), Box::new(f))
+                        });
+                        continue;
+                    }
                     let init = if self.eat(Token::Colon) {
                         self.parse_expression()?
                     } else if self.peek(Token::LeftParen) {
                         self.parse_function(true, FunctionKind::Normal)?
-                    } else if let Node::StringLiteral(n) = &name {
+                    } else if let (true, Node::StringLiteral(n)) = (bare, &name) {
                         Node::Identifier(n.to_string())
                     } else {
                         return Err(Error::UnexpectedToken);
@@ -1559,6 +2290,36 @@ impl<'a> Parser<'a> {
                 }
                 Ok(Node::ObjectLiteral(fields))
             }
+            Token::Hash => {
+                if self.eat(Token::LeftBracket) {
+                    let (exprs, ..) = self.parse_expression_list(Token::RightBracket)?;
+                    return Ok(Node::ImmutableTupleLiteral(exprs));
+                }
+                self.expect(Token::LeftBrace)?;
+                let mut fields = Vec::new();
+                let mut first = true;
+                while !self.eat(Token::RightBrace) {
+                    if first {
+                        first = false;
+                    } else {
+                        self.expect(Token::Comma)?;
+                        if self.eat(Token::RightBrace) {
+                            break;
+                        }
+                    }
+                    let bare = matches!(self.lexer.peek(), Ok(Token::Identifier(..)));
+                    let name = self.parse_property_key()?;
+                    let init = if self.eat(Token::Colon) {
+                        self.parse_expression()?
+                    } else if let (true, Node::StringLiteral(n)) = (bare, &name) {
+                        Node::Identifier(n.to_string())
+                    } else {
+                        return Err(Error::UnexpectedToken);
+                    };
+                    fields.push(Node::Initializer(Box::new(name), Box::new(init)));
+                }
+                Ok(Node::RecordLiteral(fields))
+            }
             Token::LeftParen => {
                 let (mut list, trailing) = self.parse_expression_list(Token::RightParen)?;
                 if self.eat(Token::Arrow) {
@@ -1584,89 +2345,7 @@ impl<'a> Parser<'a> {
             }
             Token::Class => self.parse_class(true),
             Token::BackQuote => {
-                let mut quasis = Vec::new();
-                let mut expressions = Vec::new();
-                let mut current = String::new();
-                loop {
-                    match self.lexer.chars.next() {
-                        Some('$') => {
-                            if self.lexer.chars.peek() == Some(&'{') {
-                                quasis.push(current);
-                                current = String::new();
-                                self.lexer.chars.next();
-                                let expr = self.parse_expression()?;
-                                expressions.push(expr);
-                                self.expect(Token::RightBrace)?;
-                            } else {
-                                current.push('$');
-                            }
-                        }
-                        Some('`') => break,
-                        Some(c) => {
-                            if c == '\\' {
-                                match self.lexer.chars.next() {
-                                    Some('n') => current.push('\n'),
-                                    Some('t') => current.push('\t'),
-                                    Some('\\') => current.push('\\'),
-                                    Some('u') => {
-                                        if Some('{') != self.lexer.chars.next() {
-                                            return Err(Error::UnexpectedToken);
-                                        }
-                                        let mut n = String::new();
-                                        macro_rules! digit {
-                                            () => {
-                                                let next = self.lexer.chars.next();
-                                                match next {
-                                                    Some('0'...'9') | Some('a'...'f')
-                                                    | Some('A'...'F') => {
-                                                        n.push(next.unwrap());
-                                                    }
-                                                    _ => return Err(Error::UnexpectedToken),
-                                                }
-                                            };
-                                        }
-                                        digit!();
-                                        digit!();
-                                        digit!();
-                                        digit!();
-                                        match u32::from_str_radix(n.as_str(), 16) {
-                                            Ok(n) => match std::char::from_u32(n) {
-                                                Some(c) => current.push(c),
-                                                None => return Err(Error::UnexpectedToken),
-                                            },
-                                            Err(_) => return Err(Error::UnexpectedToken),
-                                        }
-                                        if Some('}') != self.lexer.chars.next() {
-                                            return Err(Error::UnexpectedToken);
-                                        }
-                                    }
-                                    Some('U') => {
-                                        if Some('{') != self.lexer.chars.next() {
-                                            return Err(Error::UnexpectedToken);
-                                        }
-                                        let mut name = String::new();
-                                        loop {
-                                            match self.lexer.chars.next() {
-                                                Some('}') => break,
-                                                None => return Err(Error::UnexpectedEOF),
-                                                Some(c) => name.push(c),
-                                            }
-                                        }
-                                        match UNICODE_NAME_MAP.get(name.as_str()) {
-                                            Some(c) => current.push(*c),
-                                            None => return Err(Error::UnexpectedToken),
-                                        };
-                                    }
-                                    None | _ => return Err(Error::UnexpectedEOF),
-                                }
-                            } else {
-                                current.push(c);
-                            }
-                        }
-                        None => return Err(Error::UnexpectedEOF),
-                    }
-                }
-                quasis.push(current);
+                let (quasis, expressions) = self.parse_template()?;
                 Ok(Node::TemplateLiteral(quasis, expressions))
             }
             Token::Match => {
@@ -1683,14 +2362,23 @@ impl<'a> Parser<'a> {
                             break;
                         }
                     }
-                    let pattern = self.parse_pattern()?;
+                    let pattern = self.parse_pattern(&mut Vec::new())?;
+                    let guard = if self.eat(Token::If) {
+                        Some(Box::new(self.parse_expression()?))
+                    } else {
+                        None
+                    };
                     self.expect(Token::Arrow)?;
                     let consequent = if self.peek(Token::LeftBrace) {
                         self.parse_block(ParseScope::Block)?
                     } else {
                         self.parse_expression()?
                     };
-                    arms.push(Node::MatchArm(Box::new(pattern), Box::new(consequent)));
+                    arms.push(Node::MatchArm(
+                        Box::new(pattern),
+                        guard,
+                        Box::new(consequent),
+                    ));
                 }
                 Ok(Node::MatchExpression(Box::new(expr), arms))
             }
@@ -1698,29 +2386,44 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_pattern(&mut self) -> Result<Node, Error> {
-        match self.lexer.peek()? {
+    // `bindings` collects the names bound so far, which must be distinct
+    fn parse_pattern(&mut self, bindings: &mut Vec<String>) -> Result<Node, Error> {
+        match self.lexer.next()? {
             // 1
+            // -1
             // "hi"
+            // true
+            // null
+            Token::NumberLiteral(n) => Ok(Node::NumberLiteral(n)),
+            Token::Operator(Operator::Sub) => match self.lexer.next()? {
+                Token::NumberLiteral(n) => Ok(Node::NumberLiteral(-n)),
+                _ => Err(Error::UnexpectedToken),
+            },
+            Token::StringLiteral(s) => Ok(Node::StringLiteral(s)),
+            Token::True => Ok(Node::TrueLiteral),
+            Token::False => Ok(Node::FalseLiteral),
+            Token::Null => Ok(Node::NullLiteral),
             // a
-            Token::NumberLiteral(..) | Token::StringLiteral(..) | Token::Identifier(..) => {
-                self.parse_expression()
+            // _
+            Token::Identifier(name) => {
+                if name != "_" {
+                    if bindings.contains(&name) {
+                        return Err(Error::DuplicateBinding);
+                    }
+                    bindings.push(name.clone());
+                }
+                Ok(Node::Identifier(name))
             }
             // { a }
             // { a: b }
             // { a: { c } }
             // { a: b, ... }
+            // { ... }
             Token::LeftBrace => {
-                self.lexer.next()?;
                 let mut patterns = IndexMap::new();
                 let mut first = true;
                 let mut wildcard = false;
                 while !self.eat(Token::RightBrace) {
-                    if !first && self.eat(Token::Ellipsis) {
-                        wildcard = true;
-                        self.expect(Token::RightBrace)?;
-                        break;
-                    }
                     if first {
                         first = false;
                     } else {
@@ -1729,13 +2432,24 @@ impl<'a> Parser<'a> {
                             break;
                         }
                     }
-                    let name = self.parse_identifier(false)?;
-                    if self.eat(Token::Colon) {
-                        let pattern = self.parse_pattern()?;
-                        patterns.insert(name, pattern);
-                    } else {
-                        patterns.insert(name.to_string(), Node::Identifier(name));
+                    if self.eat(Token::Ellipsis) {
+                        wildcard = true;
+                        self.expect(Token::RightBrace)?;
+                        break;
+                    }
+                    let name = self.parse_identifier(true)?;
+                    if patterns.contains_key(&name) {
+                        return Err(Error::DuplicateBinding);
                     }
+                    let pattern = if self.eat(Token::Colon) {
+                        self.parse_pattern(bindings)?
+                    } else if bindings.contains(&name) {
+                        return Err(Error::DuplicateBinding);
+                    } else {
+                        bindings.push(name.clone());
+                        Node::Identifier(name.clone())
+                    };
+                    patterns.insert(name, pattern);
                 }
                 Ok(Node::ObjectPattern(patterns, wildcard))
             }
@@ -1743,16 +2457,10 @@ impl<'a> Parser<'a> {
             // [{ b }]
             // [a, ...]
             Token::LeftBracket => {
-                self.lexer.next()?;
                 let mut patterns = Vec::new();
                 let mut first = true;
                 let mut wildcard = false;
                 while !self.eat(Token::RightBracket) {
-                    if !first && self.eat(Token::Ellipsis) {
-                        wildcard = true;
-                        self.expect(Token::RightBracket)?;
-                        break;
-                    }
                     if first {
                         first = false;
                     } else {
@@ -1761,7 +2469,12 @@ impl<'a> Parser<'a> {
                             break;
                         }
                     }
-                    let pattern = self.parse_pattern()?;
+                    if self.eat(Token::Ellipsis) {
+                        wildcard = true;
+                        self.expect(Token::RightBracket)?;
+                        break;
+                    }
+                    let pattern = self.parse_pattern(bindings)?;
                     patterns.push(pattern);
                 }
                 Ok(Node::ArrayPattern(patterns, wildcard))
@@ -1786,11 +2499,12 @@ impl<'a> Parser<'a> {
         self.expect(Token::LeftBrace)?;
         let mut fields = Vec::new();
         while !self.eat(Token::RightBrace) {
+            let comments = self.take_comments();
             let name = self.parse_identifier(false)?;
             let f = self.parse_function(true, FunctionKind::Normal)?;
             fields.push(Node::Initializer(
                 Box::new(Node::StringLiteral(name)),
-                Box::new(f),
+                Box::new(Parser::commented(comments, f)),
             ));
         }
         if expression {
@@ -1822,18 +2536,24 @@ impl<'a> Parser<'a> {
                 _ => return Err(Error::UnexpectedToken),
             }
         }
+        let scope = match kind {
+            FunctionKind::Normal => ParseScope::Function,
+            FunctionKind::Async => ParseScope::AsyncFunction,
+            FunctionKind::Generator => ParseScope::GeneratorFunction,
+            _ => unreachable!(),
+        };
         let body = if self.peek(Token::LeftBrace) {
-            self.parse_block(match kind {
-                FunctionKind::Normal => ParseScope::Function,
-                FunctionKind::Async => ParseScope::AsyncFunction,
-                FunctionKind::Generator => ParseScope::GeneratorFunction,
-                _ => unreachable!(),
-            })?
+            self.parse_block(scope)?
         } else {
-            let expr = self.parse_assignment_expression()?;
+            // an expression body is parsed as if it were in a block body, so
+            // `async (x) => await x` works
+            let saved = self.scope_bits;
+            self.scope_bits = self.nested_scope_bits(scope);
+            let expr = self.parse_assignment_expression();
+            self.scope_bits = saved;
             Node::Block(
                 Scope::new(ParseScope::Function),
-                vec![Node::ReturnStatement(Some(Box::new(expr)))],
+                vec![Node::ReturnStatement(Some(Box::new(expr?)))],
             )
         };
         Ok(Node::ArrowFunctionExpression(
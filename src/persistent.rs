@@ -0,0 +1,83 @@
+use crate::agent::Agent;
+use crate::value::Value;
+use mio::{Ready, Registration, SetReadiness};
+use std::sync::{Arc, Mutex};
+
+type Task = Box<dyn FnOnce(&Agent, Value) + Send>;
+
+/// The half of the handle machinery that other threads write to. The agent
+/// drains it whenever its registration becomes readable.
+pub(crate) struct Remote {
+    // one lock for both so a drain never sees a handle's release without
+    // the tasks it scheduled before being dropped
+    queue: Mutex<Queue>,
+    readiness: SetReadiness,
+}
+
+#[derive(Default)]
+struct Queue {
+    tasks: Vec<(usize, Task)>,
+    released: Vec<usize>,
+}
+
+impl Remote {
+    pub(crate) fn new() -> (Arc<Remote>, Registration) {
+        let (registration, readiness) = Registration::new2();
+        let remote = Remote {
+            queue: Mutex::new(Queue::default()),
+            readiness,
+        };
+        (Arc::new(remote), registration)
+    }
+
+    /// Take everything queued since the last call. Tasks come before
+    /// releases so a handle dropped right after scheduling still runs.
+    pub(crate) fn drain(&self) -> (Vec<(usize, Task)>, Vec<usize>) {
+        let _ = self.readiness.set_readiness(Ready::empty());
+        let queue = std::mem::take(&mut *self.queue.lock().unwrap());
+        (queue.tasks, queue.released)
+    }
+
+    fn wake(&self) {
+        let _ = self.readiness.set_readiness(Ready::readable());
+    }
+}
+
+/// A handle to a Value that can be sent to and held on other threads. The
+/// agent keeps the value alive until the handle is dropped. The value
+/// itself is only reachable on the agent's thread, through
+/// `Agent::redeem` or a closure passed to `schedule`.
+pub struct Persistent {
+    pub(crate) id: usize,
+    pub(crate) remote: Arc<Remote>,
+}
+
+impl Persistent {
+    /// Run `f` with the value on the agent's thread during its next event
+    /// loop turn.
+    pub fn schedule<F>(&self, f: F)
+    where
+        F: FnOnce(&Agent, Value) + Send + 'static,
+    {
+        self.remote
+            .queue
+            .lock()
+            .unwrap()
+            .tasks
+            .push((self.id, Box::new(f)));
+        self.remote.wake();
+    }
+}
+
+impl Drop for Persistent {
+    fn drop(&mut self) {
+        self.remote.queue.lock().unwrap().released.push(self.id);
+        self.remote.wake();
+    }
+}
+
+impl std::fmt::Debug for Persistent {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "Persistent({})", self.id)
+    }
+}
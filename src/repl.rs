@@ -0,0 +1,138 @@
+use crate::agent::Agent;
+use crate::inspector;
+use crate::interpreter::{Context, Scope};
+use crate::value::Value;
+use gc::{Gc, GcCell};
+
+/// The state of a REPL between lines: the bindings typed so far, the last
+/// result as `_`, and the lines that ran, so a session can be saved as a
+/// script. Lines are saved as typed, so ones that use `_` need it declared
+/// before the script runs on its own.
+pub struct Session {
+    context: Gc<GcCell<Context>>,
+    // holds `_`, between the globals and the session's own bindings so
+    // code typed in can still declare its own `_`
+    last: Gc<GcCell<Scope>>,
+    history: Vec<String>,
+    // the lines typed since `.editor`
+    editor: Option<Vec<String>>,
+}
+
+impl Session {
+    pub fn new(agent: &Agent) -> Session {
+        let last = Scope::new(Some(agent.root_scope.clone()));
+        last.borrow_mut()
+            .create(agent, "_", true)
+            .expect("a new scope has no bindings");
+        last.borrow_mut().initialize("_", Value::Null);
+        Session {
+            context: Context::new(Scope::new(Some(last.clone()))),
+            last,
+            history: Vec::new(),
+            editor: None,
+        }
+    }
+
+    /// The context lines are evaluated in.
+    pub fn context(&self) -> &Gc<GcCell<Context>> {
+        &self.context
+    }
+
+    /// The lines that ran without throwing, in order, as `.save` writes
+    /// them.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.editor.is_some() {
+            ".. "
+        } else {
+            ">> "
+        }
+    }
+
+    /// Run a line typed into the REPL and return what to print, if
+    /// anything. Besides code and the commands of `inspector::repl_command`
+    /// a line can be one of:
+    ///
+    /// - `.save <file>` to write the lines that ran so far to a file
+    /// - `.load <file>` to run a file as if it was typed in
+    /// - `.editor` to type several lines and run them together, ending with
+    ///   `.end`, or `.cancel` to discard them
+    pub fn input(&mut self, agent: &mut Agent, line: &str) -> Option<String> {
+        if let Some(lines) = &mut self.editor {
+            match line.trim() {
+                ".end" => {
+                    let source = lines.join("\n");
+                    self.editor = None;
+                    return Some(self.eval(agent, &source));
+                }
+                ".cancel" => {
+                    self.editor = None;
+                    return None;
+                }
+                _ => {
+                    lines.push(line.to_string());
+                    return None;
+                }
+            }
+        }
+
+        let trimmed = line.trim();
+        let (command, argument) = match trimmed.find(char::is_whitespace) {
+            Some(i) => (&trimmed[..i], trimmed[i..].trim()),
+            None => (trimmed, ""),
+        };
+        match command {
+            ".editor" => {
+                self.editor = Some(Vec::new());
+                Some("// editor mode, .end to run what was typed or .cancel to discard it".into())
+            }
+            ".save" if argument.is_empty() => Some("usage: .save <file>".into()),
+            ".save" => {
+                let mut source = self.history.join("\n");
+                source.push('\n');
+                Some(match std::fs::write(argument, source) {
+                    Ok(()) => format!("saved {} lines to {}", self.history.len(), argument),
+                    Err(e) => format!("could not save {}: {}", argument, e),
+                })
+            }
+            ".load" if argument.is_empty() => Some("usage: .load <file>".into()),
+            ".load" => Some(match std::fs::read_to_string(argument) {
+                Ok(source) => self.eval(agent, &source),
+                Err(e) => format!("could not load {}: {}", argument, e),
+            }),
+            _ => match inspector::repl_command(agent, line, &self.context) {
+                Some(Ok(out)) => Some(out),
+                Some(Err(e)) => Some(uncaught(agent, &e)),
+                None => Some(self.eval(agent, line)),
+            },
+        }
+    }
+
+    fn eval(&mut self, agent: &mut Agent, source: &str) -> String {
+        let result = agent.eval_in_context(source, &self.context);
+        agent.run_jobs();
+        match result {
+            Ok(v) => {
+                self.last.borrow_mut().overwrite("_", v.clone());
+                let mut source = source.trim_end().to_string();
+                if !source.is_empty() {
+                    // lines typed without a semicolon still run one after
+                    // another in a script
+                    if !source.ends_with(';') && !source.ends_with('}') {
+                        source.push(';');
+                    }
+                    self.history.push(source);
+                }
+                Value::inspect(agent, &v)
+            }
+            Err(e) => uncaught(agent, &e),
+        }
+    }
+}
+
+fn uncaught(agent: &Agent, e: &Value) -> String {
+    format!("Uncaught Exception: {}", agent.describe_exception(e))
+}
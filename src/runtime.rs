@@ -1,4 +1,4 @@
-use crate::value::ObjectKey;
+use crate::value::{ObjectKey, ObjectKind};
 use crate::{Agent, Value};
 
 macro_rules! intrinsics {
@@ -9,7 +9,8 @@ macro_rules! intrinsics {
             (GetAsyncIterator, get_async_iterator),
             (ObjectKeys, object_keys),
             (ListLength, list_length),
-            (ListPopFront, list_pop_front),
+            (ArrayLength, array_length),
+            (NoMatch, no_match),
         );
     };
 }
@@ -57,19 +58,29 @@ fn list_length(_agent: &Agent, accumulator: &mut Value) -> Result<(), Value> {
     Ok(())
 }
 
-fn list_pop_front(_agent: &Agent, accumulator: &mut Value) -> Result<(), Value> {
-    if let Value::List(list) = accumulator {
-        let item = list.borrow_mut().pop_front();
-        *accumulator = match item {
-            None => Value::Empty,
-            Some(v) => v,
-        };
-    } else {
-        unreachable!();
-    }
+// -1 for anything that isn't an array, so it never matches a length
+fn array_length(_agent: &Agent, accumulator: &mut Value) -> Result<(), Value> {
+    let len = match accumulator {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(items) => items.borrow().len() as f64,
+            _ => -1.0,
+        },
+        _ => -1.0,
+    };
+    *accumulator = Value::from(len);
     Ok(())
 }
 
+fn no_match(agent: &Agent, accumulator: &mut Value) -> Result<(), Value> {
+    Err(Value::new_error(
+        agent,
+        &format!(
+            "no match arm matched {}",
+            Value::inspect(agent, accumulator)
+        ),
+    ))
+}
+
 macro_rules! declare_enum {
     ( $( ( $name:ident, $fn:ident ), )* ) => (
         #[derive(Debug, PartialEq, Clone, Copy)]
@@ -264,7 +264,7 @@ impl<'a> serde::ser::SerializeTuple for TupleSerializer<'a> {
     }
 
     fn end(self) -> SerializerResult {
-        Ok(Value::Tuple(self.values))
+        Ok(Value::Tuple(Box::new(self.values)))
     }
 }
 
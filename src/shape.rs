@@ -0,0 +1,141 @@
+use crate::value::{ObjectKey, Value};
+use indexmap::{IndexMap, IndexSet};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+// objects that grow past this many properties are probably being used as
+// dictionaries, so they stop sharing shapes and keep their own map
+const MAX_SHAPED_PROPERTIES: usize = 32;
+
+/// The ordered keys shared by every object that had the same properties
+/// added in the same order. Each object only stores its values.
+///
+/// A shape keeps the shape it was made from alive, but only weakly links to
+/// the shapes made from it, so shapes no live object uses anymore are freed
+/// instead of piling up for every key sequence ever seen.
+pub struct Shape {
+    keys: IndexSet<ObjectKey>,
+    // only held so the shapes on the way to this one stay shared
+    _parent: Option<Rc<Shape>>,
+    transitions: RefCell<HashMap<ObjectKey, Weak<Shape>>>,
+}
+
+thread_local! {
+    static ROOT_SHAPE: Rc<Shape> = Rc::new(Shape {
+        keys: IndexSet::new(),
+        _parent: None,
+        transitions: RefCell::new(HashMap::new()),
+    });
+}
+
+impl std::fmt::Debug for Shape {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_set().entries(self.keys.iter()).finish()
+    }
+}
+
+impl Shape {
+    fn root() -> Rc<Shape> {
+        ROOT_SHAPE.with(Rc::clone)
+    }
+
+    fn with_key(shape: &Rc<Shape>, key: ObjectKey) -> Rc<Shape> {
+        if let Some(next) = shape.transitions.borrow().get(&key).and_then(Weak::upgrade) {
+            return next;
+        }
+        let mut keys = shape.keys.clone();
+        keys.insert(key.clone());
+        let next = Rc::new(Shape {
+            keys,
+            _parent: Some(shape.clone()),
+            transitions: RefCell::new(HashMap::new()),
+        });
+        let mut transitions = shape.transitions.borrow_mut();
+        // drop the links to freed shapes every time the map doubles, so
+        // objects with keys made from data don't grow it forever
+        if transitions.len().is_power_of_two() {
+            transitions.retain(|_, next| next.strong_count() > 0);
+        }
+        transitions.insert(key, Rc::downgrade(&next));
+        next
+    }
+}
+
+/// Own properties of an object, in insertion order.
+#[derive(Debug, Finalize)]
+pub enum PropertyMap {
+    Shaped(Rc<Shape>, Vec<Value>),
+    Dictionary(IndexMap<ObjectKey, Value>),
+}
+
+unsafe impl gc::Trace for PropertyMap {
+    custom_trace!(this, {
+        match this {
+            PropertyMap::Shaped(_, values) => mark(values),
+            PropertyMap::Dictionary(map) => mark(map),
+        }
+    });
+}
+
+impl PropertyMap {
+    pub fn new() -> PropertyMap {
+        PropertyMap::Shaped(Shape::root(), Vec::new())
+    }
+
+    pub fn get(&self, key: &ObjectKey) -> Option<&Value> {
+        match self {
+            PropertyMap::Shaped(shape, values) => shape.keys.get_full(key).map(|(i, _)| &values[i]),
+            PropertyMap::Dictionary(map) => map.get(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &ObjectKey) -> bool {
+        match self {
+            PropertyMap::Shaped(shape, _) => shape.keys.contains(key),
+            PropertyMap::Dictionary(map) => map.contains_key(key),
+        }
+    }
+
+    pub fn insert(&mut self, key: ObjectKey, value: Value) {
+        let map = match self {
+            PropertyMap::Shaped(shape, values) => {
+                if let Some((i, _)) = shape.keys.get_full(&key) {
+                    values[i] = value;
+                    return;
+                }
+                if values.len() < MAX_SHAPED_PROPERTIES {
+                    *shape = Shape::with_key(shape, key);
+                    values.push(value);
+                    return;
+                }
+                let mut map = shape
+                    .keys
+                    .iter()
+                    .cloned()
+                    .zip(values.drain(..))
+                    .collect::<IndexMap<ObjectKey, Value>>();
+                map.insert(key, value);
+                map
+            }
+            PropertyMap::Dictionary(map) => {
+                map.insert(key, value);
+                return;
+            }
+        };
+        *self = PropertyMap::Dictionary(map);
+    }
+
+    pub fn keys(&self) -> Box<dyn Iterator<Item = &ObjectKey> + '_> {
+        match self {
+            PropertyMap::Shaped(shape, _) => Box::new(shape.keys.iter()),
+            PropertyMap::Dictionary(map) => Box::new(map.keys()),
+        }
+    }
+}
+
+impl Default for PropertyMap {
+    fn default() -> Self {
+        PropertyMap::new()
+    }
+}
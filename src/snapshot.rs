@@ -0,0 +1,39 @@
+use std::path::Path;
+
+/// Set to rewrite snapshots that differ instead of failing.
+pub const UPDATE_ENV: &str = "SLITHER_UPDATE_SNAPSHOTS";
+
+/// Whether snapshots should be rewritten, as asked through `UPDATE_ENV`.
+pub fn update_requested() -> bool {
+    std::env::var_os(UPDATE_ENV).map_or(false, |v| v != "0")
+}
+
+/// Compare `actual` with the snapshot stored at `path`, returning a
+/// description of the difference. With `update`, a missing or different
+/// snapshot is written instead, so the change shows up in review.
+pub fn check(path: &Path, actual: &str, update: bool) -> Result<(), String> {
+    let expected = match std::fs::read_to_string(path) {
+        Ok(expected) => Some(expected),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    if expected.as_ref().map(String::as_str) == Some(actual) {
+        return Ok(());
+    }
+    if update {
+        return std::fs::write(path, actual).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    match expected {
+        None => Err(format!(
+            "{} is missing, set {}=1 to create it",
+            path.display(),
+            UPDATE_ENV
+        )),
+        Some(expected) => Err(format!(
+            "{} differs, set {}=1 to accept the change\n{}",
+            path.display(),
+            UPDATE_ENV,
+            crate::diff::unified(&expected, actual, 3, "snapshot", "output")
+        )),
+    }
+}
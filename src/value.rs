@@ -1,16 +1,36 @@
+use crate::array::ArrayStorage;
 use crate::interpreter::{AssemblerFunctionInfo, Context, Interpreter, Scope};
 use crate::intrinsics::{perform_await, promise::new_promise_capability};
 use crate::parser::FunctionKind;
+use crate::shape::PropertyMap;
 use crate::{Agent, IntoValue};
 use gc::{Gc, GcCell};
-use indexmap::IndexMap;
 use regex::Regex;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-type BuiltinFunction = fn(&Agent, Vec<Value>, &Context) -> Result<Value, Value>;
+pub(crate) type BuiltinFunction = fn(&Agent, Vec<Value>, &Context) -> Result<Value, Value>;
+type SliceBuiltinFunction = fn(&Agent, &[Value], &Context) -> Result<Value, Value>;
+
+/// How a builtin takes its arguments. The interpreter calls `Slice`
+/// builtins with up to four arguments without allocating a Vec.
+#[derive(Clone, Copy)]
+pub enum Builtin {
+    Vec(BuiltinFunction),
+    Slice(SliceBuiltinFunction),
+}
+
+impl Builtin {
+    fn invoke(self, agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+        match self {
+            Builtin::Vec(f) => f(agent, canonical_args(args), ctx),
+            Builtin::Slice(f) => f(agent, &canonical_args(args), ctx),
+        }
+    }
+}
 
 static SYMBOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
 #[derive(Debug, Clone, Trace, Finalize, Eq)]
@@ -100,6 +120,19 @@ impl ObjectKey {
         ObjectKey::Symbol(Symbol::new_registered(name))
     }
 
+    // like to_number, but only for the canonical form, so "01" stays a
+    // string key
+    fn array_index(&self) -> Option<usize> {
+        match self {
+            ObjectKey::Number(n) => Some(*n),
+            ObjectKey::String(s) => match s.parse::<usize>() {
+                Ok(n) if n.to_string() == *s => Some(n),
+                _ => None,
+            },
+            ObjectKey::Symbol(..) => None,
+        }
+    }
+
     fn to_number(&self) -> Option<usize> {
         match self {
             ObjectKey::Number(n) => Some(*n),
@@ -162,17 +195,18 @@ impl Ord for ObjectKey {
 
 impl Hash for ObjectKey {
     fn hash<H: Hasher>(&self, state: &mut H) {
+        // 1 and "1" are equal keys, so they have to hash the same
         match self {
             ObjectKey::Number(n) => {
                 0.hash(state);
                 n.to_string().hash(state);
             }
             ObjectKey::String(s) => {
-                1.hash(state);
+                0.hash(state);
                 s.hash(state);
             }
             ObjectKey::Symbol(s) => {
-                2.hash(state);
+                1.hash(state);
                 s.hash(state);
             }
         }
@@ -236,7 +270,7 @@ impl From<f64> for ObjectKey {
 #[derive(Finalize)]
 pub enum ObjectKind {
     Ordinary,
-    Array(GcCell<Vec<Value>>),
+    Array(GcCell<ArrayStorage>),
     Boolean(bool),
     String(Vec<char>),
     Number(f64),
@@ -246,11 +280,15 @@ pub enum ObjectKind {
     BytecodeFunction {
         kind: FunctionKind,
         parameters: Vec<String>,
+        length: usize,
         position: usize,
         scope: Gc<GcCell<Scope>>,
     },
-    BuiltinFunction(BuiltinFunction, GcCell<HashMap<String, Value>>),
+    BuiltinFunction(Builtin, GcCell<HashMap<String, Value>>),
     Custom(GcCell<HashMap<String, Value>>),
+    /// The getter and setter stored in place of an accessor property's
+    /// value, null where one is missing.
+    Accessor(Value, Value),
 }
 
 unsafe impl gc::Trace for ObjectKind {
@@ -265,6 +303,10 @@ unsafe impl gc::Trace for ObjectKind {
             ObjectKind::Custom(slots) | ObjectKind::BuiltinFunction(_, slots) => {
                 mark(slots);
             }
+            ObjectKind::Accessor(get, set) => {
+                mark(get);
+                mark(set);
+            }
             _ => {}
         }
     });
@@ -282,10 +324,16 @@ impl std::fmt::Debug for ObjectKind {
             ObjectKind::Symbol(s) => format!("Symbol({:?})", s),
             ObjectKind::Buffer(b) => format!("Buffer({:?})", b),
             ObjectKind::Custom(..) => "Custom".to_string(),
+            ObjectKind::Accessor(..) => "Accessor".to_string(),
             ObjectKind::BytecodeFunction { position, .. } => {
                 format!("CompiledFunction @ {}", position)
             }
-            ObjectKind::BuiltinFunction(f, ..) => format!("BuiltinFunction @ {:p}", f),
+            ObjectKind::BuiltinFunction(Builtin::Vec(f), ..) => {
+                format!("BuiltinFunction @ {:p}", f)
+            }
+            ObjectKind::BuiltinFunction(Builtin::Slice(f), ..) => {
+                format!("BuiltinFunction @ {:p}", f)
+            }
         };
         write!(fmt, "{}", r)
     }
@@ -294,7 +342,7 @@ impl std::fmt::Debug for ObjectKind {
 #[derive(Debug, Trace, Finalize)]
 pub struct ObjectInfo {
     pub kind: ObjectKind,
-    properties: GcCell<IndexMap<ObjectKey, Value>>,
+    properties: GcCell<PropertyMap>,
     prototype: Value,
 }
 
@@ -309,7 +357,7 @@ impl ObjectInfo {
                 return Value::from(values.borrow().len() as f64);
             }
             if let Some(n) = property.to_number() {
-                return values.borrow().get(n).unwrap_or(&Value::Null).clone();
+                return values.borrow().get(n);
             }
         }
         if let ObjectInfo {
@@ -338,6 +386,15 @@ impl ObjectInfo {
                     .map_or(Value::Null, |x| Value::from(x.to_string()));
             }
         }
+        if let ObjectInfo {
+            kind: ObjectKind::BytecodeFunction { length, .. },
+            ..
+        } = self
+        {
+            if ObjectKey::from("length") == property {
+                return Value::from(*length as f64);
+            }
+        }
         match self.properties.borrow().get(&property) {
             Some(v) => v.clone(),
             _ => {
@@ -368,19 +425,22 @@ impl ObjectInfo {
         } = self
         {
             if ObjectKey::from("length") == property {
-                if let Value::Number(len) = value {
-                    values.borrow_mut().resize(len as usize, Value::Null);
-                    return Ok(Value::Null);
-                } else {
-                    return Err(Value::new_error(agent, "invalid array length"));
-                }
+                return match value {
+                    Value::Number(len)
+                        if len >= 0.0 && len.fract() == 0.0 && len <= f64::from(u32::MAX) =>
+                    {
+                        values.borrow_mut().set_len(len as usize);
+                        Ok(Value::Null)
+                    }
+                    _ => Err(Value::new_range_error(agent, "invalid array length")),
+                };
             }
             if let Some(n) = property.to_number() {
-                let mut values = values.borrow_mut();
-                if values.len() <= n {
-                    values.resize(n + 1, Value::Null);
+                // the length has to stay within what the setter above allows
+                if n >= u32::MAX as usize {
+                    return Err(Value::new_range_error(agent, "invalid array index"));
                 }
-                values[n] = value.clone();
+                values.borrow_mut().set(n, value);
                 return Ok(Value::Null);
             }
         }
@@ -410,7 +470,18 @@ impl ObjectInfo {
         } else {
             false
         };
-        if own || self.properties.borrow().contains_key(&property) {
+        let existing = self.properties.borrow().get(&property).cloned();
+        if let Some((_, set)) = existing.as_ref().and_then(accessor) {
+            if set == Value::Null {
+                return Err(Value::new_error(
+                    agent,
+                    "cannot set a property that only has a getter",
+                ));
+            }
+            set.call(agent, Value::Object(receiver), vec![value.clone()])?;
+            return Ok(value);
+        }
+        if own || existing.is_some() {
             receiver
                 .properties
                 .borrow_mut()
@@ -438,7 +509,7 @@ impl ObjectInfo {
         } = self
         {
             if let Some(n) = key.to_number() {
-                if n < values.borrow().len() {
+                if values.borrow().contains(n) {
                     return true;
                 }
             }
@@ -454,48 +525,72 @@ impl ObjectInfo {
         }
     }
 
+    // own keys in the order JS uses: integer keys ascending, then string
+    // keys in insertion order, then symbols in insertion order
     fn keys(&self) -> Vec<ObjectKey> {
-        let mut keys = Vec::new();
+        let mut indices = Vec::new();
         if let ObjectKind::Array(values) = &self.kind {
-            for i in 0..(values.borrow().len()) {
-                keys.push(ObjectKey::Number(i));
+            for i in values.borrow().indices() {
+                indices.push((i, ObjectKey::Number(i)));
             }
         }
         if let ObjectKind::Buffer(values) = &self.kind {
             for i in 0..(values.borrow().len()) {
-                keys.push(ObjectKey::Number(i));
+                indices.push((i, ObjectKey::Number(i)));
             }
         }
+        let mut strings = Vec::new();
+        let mut symbols = Vec::new();
         let entries = self.properties.borrow();
         for key in entries.keys() {
-            if let ObjectKey::Symbol(Symbol::Unregistered { private: true, .. }) = key {
+            match key {
                 // private keys are unenumerable
-            } else {
-                keys.push(key.clone());
+                ObjectKey::Symbol(Symbol::Unregistered { private: true, .. }) => {}
+                ObjectKey::Symbol(..) => symbols.push(key.clone()),
+                _ => match key.array_index() {
+                    Some(i) => indices.push((i, key.clone())),
+                    None => strings.push(key.clone()),
+                },
             }
         }
-        keys.sort();
-        keys.dedup();
-        keys
+        indices.sort_by_key(|(i, _)| *i);
+        indices.dedup_by_key(|(i, _)| *i);
+        indices
+            .into_iter()
+            .map(|(_, key)| key)
+            .chain(strings)
+            .chain(symbols)
+            .collect()
     }
 }
 
+// every payload is at most a pointer wide so that a value stays 16 bytes.
+// larger payloads are boxed, and strings are shared so cloning a value
+// never copies the text
 #[derive(Debug, Finalize, Clone)]
 pub enum Value {
     // Language types
     Null,
     Boolean(bool),
-    String(String),
+    String(Rc<String>),
     Number(f64),
-    Symbol(Symbol),
+    // a number the interpreter knows fits in an i32. it never reaches
+    // builtins or object storage, see `Value::canonical`
+    Integer(i32),
+    // boxed because symbols are rare and would otherwise be the largest
+    // variant, making every value bigger
+    Symbol(Box<Symbol>),
     Object(Gc<ObjectInfo>),
-    Tuple(Vec<Value>),
+    Tuple(Box<Vec<Value>>),
+    // the fields of a record, sorted by name so equal records compare and
+    // hash the same whatever order they were written in
+    Record(Box<Vec<(String, Value)>>),
 
     // Internal types
     Empty,
     List(Gc<GcCell<VecDeque<Value>>>),
-    WrappedContext(Gc<GcCell<Context>>, Option<Box<Value>>),
-    Iterator(Box<Value>, Box<Value>),
+    WrappedContext(Box<(Gc<GcCell<Context>>, Option<Value>)>),
+    Iterator(Box<(Value, Value)>),
 }
 
 #[allow(non_upper_case_globals)]
@@ -512,19 +607,21 @@ unsafe impl gc::Trace for Value {
             | Value::Boolean(_)
             | Value::String(_)
             | Value::Number(_)
+            | Value::Integer(_)
             | Value::Symbol(_) => {}
             Value::Object(o) => mark(o),
             Value::Tuple(items, ..) => mark(items),
+            Value::Record(fields) => mark(fields),
 
             Value::Empty => {}
             Value::List(list) => mark(list),
-            Value::WrappedContext(c, p) => {
-                mark(c);
-                mark(p);
+            Value::WrappedContext(wrapped) => {
+                mark(&wrapped.0);
+                mark(&wrapped.1);
             }
-            Value::Iterator(i, n) => {
-                mark(i);
-                mark(n);
+            Value::Iterator(pair) => {
+                mark(&pair.0);
+                mark(&pair.1);
             }
         }
     });
@@ -532,6 +629,9 @@ unsafe impl gc::Trace for Value {
 
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        if let (Some(n), Some(bn)) = (self.as_f64(), other.as_f64()) {
+            return Some(n.partial_cmp(&bn).unwrap_or(std::cmp::Ordering::Equal));
+        }
         match self {
             Value::Null => match other {
                 Value::Null => Some(std::cmp::Ordering::Equal),
@@ -541,10 +641,7 @@ impl PartialOrd for Value {
                 Value::Boolean(..) => Some(std::cmp::Ordering::Equal),
                 _ => None,
             },
-            Value::Number(n) => match other {
-                Value::Number(bn) => Some(n.partial_cmp(bn).unwrap_or(std::cmp::Ordering::Equal)),
-                _ => None,
-            },
+            Value::Number(..) | Value::Integer(..) => None,
             Value::String(s) => match other {
                 Value::String(bs) => Some(s.cmp(bs)),
                 _ => None,
@@ -553,9 +650,11 @@ impl PartialOrd for Value {
                 Value::Symbol(..) => Some(std::cmp::Ordering::Equal),
                 _ => None,
             },
-            Value::Object(..) | Value::Tuple(..) => match other {
-                Value::Object(..) | Value::Tuple(..) => Some(std::cmp::Ordering::Equal),
-                _ => panic!(),
+            Value::Object(..) | Value::Tuple(..) | Value::Record(..) => match other {
+                Value::Object(..) | Value::Tuple(..) | Value::Record(..) => {
+                    Some(std::cmp::Ordering::Equal)
+                }
+                _ => None,
             },
             _ => None,
         }
@@ -564,21 +663,21 @@ impl PartialOrd for Value {
 
 impl Value {
     pub fn new_symbol(desc: Option<String>) -> Value {
-        Value::Symbol(Symbol::new_unregistered(false, desc))
+        Value::Symbol(Box::new(Symbol::new_unregistered(false, desc)))
     }
 
     pub fn new_private_symbol(desc: Option<String>) -> Value {
-        Value::Symbol(Symbol::new_unregistered(true, desc))
+        Value::Symbol(Box::new(Symbol::new_unregistered(true, desc)))
     }
 
     pub fn new_well_known_symbol(desc: &str) -> Value {
-        Value::Symbol(Symbol::new_registered(desc))
+        Value::Symbol(Box::new(Symbol::new_registered(desc)))
     }
 
     pub fn new_object(prototype: Value) -> Value {
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Ordinary,
-            properties: GcCell::new(IndexMap::new()),
+            properties: GcCell::new(PropertyMap::new()),
             prototype,
         }))
     }
@@ -586,17 +685,31 @@ impl Value {
     pub fn new_custom_object(prototype: Value) -> Value {
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Custom(GcCell::new(HashMap::new())),
-            properties: GcCell::new(IndexMap::new()),
+            properties: GcCell::new(PropertyMap::new()),
             prototype,
         }))
     }
 
     pub fn new_error(agent: &Agent, message: &str) -> Value {
-        let mut properties = IndexMap::new();
-        properties.insert(
-            ObjectKey::from("message"),
-            Value::String(message.to_string()),
-        );
+        Value::new_named_error(agent, "Error", message)
+    }
+
+    pub fn new_range_error(agent: &Agent, message: &str) -> Value {
+        Value::new_named_error(agent, "RangeError", message)
+    }
+
+    fn new_named_error(agent: &Agent, name: &str, message: &str) -> Value {
+        let mut properties = PropertyMap::new();
+        if name != "Error" {
+            properties.insert(ObjectKey::from("name"), Value::from(name));
+        }
+        properties.insert(ObjectKey::from("message"), Value::from(message));
+        let mut stack = format!("{}: {}", name, message);
+        for frame in agent.stack_trace() {
+            stack.push_str("\n    ");
+            stack.push_str(&frame);
+        }
+        properties.insert(ObjectKey::from("stack"), Value::from(stack));
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Ordinary,
             properties: GcCell::new(properties),
@@ -606,16 +719,16 @@ impl Value {
 
     pub fn new_array(agent: &Agent) -> Value {
         Value::Object(Gc::new(ObjectInfo {
-            kind: ObjectKind::Array(GcCell::new(Vec::new())),
-            properties: GcCell::new(IndexMap::new()),
+            kind: ObjectKind::Array(GcCell::new(ArrayStorage::new())),
+            properties: GcCell::new(PropertyMap::new()),
             prototype: agent.intrinsics.array_prototype.clone(),
         }))
     }
 
     pub fn new_array_from_vec(agent: &Agent, values: Vec<Value>) -> Value {
         Value::Object(Gc::new(ObjectInfo {
-            kind: ObjectKind::Array(GcCell::new(values)),
-            properties: GcCell::new(IndexMap::new()),
+            kind: ObjectKind::Array(GcCell::new(ArrayStorage::from(values))),
+            properties: GcCell::new(PropertyMap::new()),
             prototype: agent.intrinsics.array_prototype.clone(),
         }))
     }
@@ -629,7 +742,7 @@ impl Value {
         };
         Ok(Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Regex(re),
-            properties: GcCell::new(IndexMap::new()),
+            properties: GcCell::new(PropertyMap::new()),
             prototype: agent.intrinsics.regex_prototype.clone(),
         })))
     }
@@ -637,7 +750,7 @@ impl Value {
     pub fn new_buffer_from_vec(agent: &Agent, vec: Vec<u8>) -> Value {
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Buffer(GcCell::new(vec)),
-            properties: GcCell::new(IndexMap::new()),
+            properties: GcCell::new(PropertyMap::new()),
             prototype: agent.intrinsics.array_prototype.clone(),
         }))
     }
@@ -654,7 +767,11 @@ impl Value {
     }
 
     pub fn new_tuple() -> Value {
-        Value::Tuple(Vec::new())
+        Value::Tuple(Box::default())
+    }
+
+    pub fn new_record() -> Value {
+        Value::Record(Box::default())
     }
 
     pub fn new_bytecode_function(
@@ -667,17 +784,28 @@ impl Value {
                 kind: info.kind,
                 position: info.position,
                 parameters: info.parameters.clone(),
+                length: info.length,
                 scope,
             },
-            properties: GcCell::new(IndexMap::new()),
+            properties: GcCell::new(PropertyMap::new()),
             prototype: agent.intrinsics.function_prototype.clone(),
         }))
     }
 
     pub fn new_builtin_function(agent: &Agent, f: BuiltinFunction) -> Value {
         Value::Object(Gc::new(ObjectInfo {
-            kind: ObjectKind::BuiltinFunction(f, GcCell::new(HashMap::new())),
-            properties: GcCell::new(IndexMap::new()),
+            kind: ObjectKind::BuiltinFunction(Builtin::Vec(f), GcCell::new(HashMap::new())),
+            properties: GcCell::new(PropertyMap::new()),
+            prototype: agent.intrinsics.function_prototype.clone(),
+        }))
+    }
+
+    /// Like `new_builtin_function`, for builtins that only need to borrow
+    /// their arguments. Prefer this for small, frequently called functions.
+    pub fn new_slice_builtin_function(agent: &Agent, f: SliceBuiltinFunction) -> Value {
+        Value::Object(Gc::new(ObjectInfo {
+            kind: ObjectKind::BuiltinFunction(Builtin::Slice(f), GcCell::new(HashMap::new())),
+            properties: GcCell::new(PropertyMap::new()),
             prototype: agent.intrinsics.function_prototype.clone(),
         }))
     }
@@ -702,7 +830,7 @@ impl Value {
         match &self {
             Value::Null => "null",
             Value::Boolean(..) => "boolean",
-            Value::Number(..) => "number",
+            Value::Number(..) | Value::Integer(..) => "number",
             Value::String(..) => "string",
             Value::Symbol(..) => "symbol",
             Value::Object(o) => match o.kind {
@@ -711,6 +839,7 @@ impl Value {
                 _ => "object",
             },
             Value::Tuple(..) => "tuple",
+            Value::Record(..) => "record",
             _ => unreachable!(),
         }
     }
@@ -720,17 +849,55 @@ impl Value {
             Value::Null => false,
             Value::Boolean(b) => *b,
             Value::Number(n) => *n != 0.0,
+            Value::Integer(n) => *n != 0,
             Value::String(s) => !s.is_empty(),
             Value::Symbol(..) => true,
             Value::Object(..) => true,
             Value::Tuple(..) => true,
+            Value::Record(..) => true,
             _ => unreachable!(),
         }
     }
 
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::Integer(n) => Some(f64::from(*n)),
+            _ => None,
+        }
+    }
+
+    /// The form of a value that builtins and object storage work with:
+    /// integers become plain numbers, including inside tuples and records.
+    pub fn canonical(self) -> Value {
+        match self {
+            Value::Integer(n) => Value::Number(f64::from(n)),
+            Value::Tuple(mut items) => {
+                for item in items.iter_mut() {
+                    *item = std::mem::replace(item, Value::Null).canonical();
+                }
+                Value::Tuple(items)
+            }
+            Value::Record(mut fields) => {
+                for (_, value) in fields.iter_mut() {
+                    *value = std::mem::replace(value, Value::Null).canonical();
+                }
+                Value::Record(fields)
+            }
+            v => v,
+        }
+    }
+
     pub fn get(&self, agent: &Agent, key: ObjectKey) -> Result<Value, Value> {
         match self {
-            Value::Object(o) => Ok(o.get(key)),
+            Value::Object(o) => {
+                let value = o.get(key);
+                match accessor(&value) {
+                    Some((Value::Null, _)) => Ok(Value::Null),
+                    Some((get, _)) => get.call(agent, self.clone(), vec![]),
+                    None => Ok(value),
+                }
+            }
             Value::Tuple(t, ..) => {
                 if let Some(n) = key.to_number() {
                     Ok(t.get(n).unwrap_or(&Value::Null).clone())
@@ -740,23 +907,49 @@ impl Value {
                     Ok(Value::Null)
                 }
             }
+            Value::Record(fields) => Ok(record_field(fields, &key).unwrap_or(Value::Null)),
             _ => self.to_object(agent)?.get(agent, key),
         }
     }
 
     pub fn set(&self, agent: &Agent, key: ObjectKey, value: Value) -> Result<Value, Value> {
         match self {
-            Value::Object(o) => o.set(agent, key, value, o.clone()),
+            Value::Object(o) => o.set(agent, key, value.canonical(), o.clone()),
             _ => Err(Value::new_error(agent, "base must be an object")),
         }
     }
 
+    /// Give an own property a getter or setter, keeping the other half if
+    /// the property is already an accessor.
+    pub fn define_accessor(&self, key: ObjectKey, getter: bool, f: Value) {
+        if let Value::Object(o) = self {
+            let mut properties = o.properties.borrow_mut();
+            let (get, set) = properties
+                .get(&key)
+                .and_then(accessor)
+                .unwrap_or((Value::Null, Value::Null));
+            let (get, set) = if getter { (f, set) } else { (get, f) };
+            properties.insert(
+                key,
+                Value::Object(Gc::new(ObjectInfo {
+                    kind: ObjectKind::Accessor(get, set),
+                    properties: GcCell::new(PropertyMap::new()),
+                    prototype: Value::Null,
+                })),
+            );
+        }
+    }
+
     pub fn keys(&self, agent: &Agent) -> Result<Vec<ObjectKey>, Value> {
         match self {
             Value::Object(o) => Ok(o.keys()),
             Value::Tuple(vec) => Ok((0..vec.len())
                 .map(ObjectKey::from)
                 .collect::<Vec<ObjectKey>>()),
+            Value::Record(fields) => Ok(fields
+                .iter()
+                .map(|(name, _)| ObjectKey::from(name.as_str()))
+                .collect()),
             _ => Err(Value::new_error(agent, "base must be an object")),
         }
     }
@@ -768,10 +961,55 @@ impl Value {
                 Some(n) => Ok(vec.len() < n),
                 None => Ok(false),
             },
+            Value::Record(fields) => Ok(record_field(fields, &key).is_some()),
             _ => Err(Value::new_error(agent, "base must be an object")),
         }
     }
 
+    /// Add a field to a record being built, replacing one of the same name
+    /// written earlier. Only primitives, records and tuples can go in one,
+    /// which is what keeps records immutable all the way down.
+    pub fn insert_record_field(
+        &mut self,
+        agent: &Agent,
+        key: ObjectKey,
+        value: Value,
+    ) -> Result<(), Value> {
+        let name = match key {
+            ObjectKey::Symbol(..) => {
+                return Err(Value::new_error(agent, "record keys must be strings"))
+            }
+            key => key.to_string(),
+        };
+        let value = value.require_immutable(agent)?;
+        if let Value::Record(fields) = self {
+            match fields.binary_search_by(|(n, _)| n.as_str().cmp(&name)) {
+                Ok(i) => fields[i].1 = value,
+                Err(i) => fields.insert(i, (name, value)),
+            }
+        }
+        Ok(())
+    }
+
+    /// The value itself if it can go in a record or a `#[...]` tuple.
+    pub fn require_immutable(self, agent: &Agent) -> Result<Value, Value> {
+        fn immutable(value: &Value) -> bool {
+            match value {
+                Value::Object(..) => false,
+                Value::Tuple(items) => items.iter().all(immutable),
+                _ => true,
+            }
+        }
+        if immutable(&self) {
+            Ok(self.canonical())
+        } else {
+            Err(Value::new_error(
+                agent,
+                "records and tuples can only contain primitives, records and tuples",
+            ))
+        }
+    }
+
     pub fn get_slot(&self, key: &str) -> Value {
         if let Value::Object(o) = self {
             match &o.kind {
@@ -814,40 +1052,173 @@ impl Value {
         }
     }
 
+    /// The objects this one inherits from, nearest first.
+    pub fn prototype_chain(&self) -> Vec<Value> {
+        let mut chain = Vec::new();
+        let mut current = self.clone();
+        while let Value::Object(o) = current {
+            if o.prototype == Value::Null {
+                break;
+            }
+            chain.push(o.prototype.clone());
+            current = o.prototype.clone();
+        }
+        chain
+    }
+
+    /// The own properties of an object, without calling any getters, in
+    /// the order of `keys` followed by the private ones it leaves out. The
+    /// flag is whether the property is enumerable.
+    pub fn own_properties(&self) -> Vec<(ObjectKey, Property, bool)> {
+        let o = match self {
+            Value::Object(o) => o,
+            _ => return Vec::new(),
+        };
+        let private = o
+            .properties
+            .borrow()
+            .keys()
+            .filter(|key| match key {
+                ObjectKey::Symbol(Symbol::Unregistered { private, .. }) => *private,
+                _ => false,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let enumerable = o.keys();
+        let count = enumerable.len();
+        enumerable
+            .into_iter()
+            .chain(private)
+            .enumerate()
+            .map(|(i, key)| {
+                let value = o.get(key.clone());
+                let property = match accessor(&value) {
+                    Some((get, set)) => Property::Accessor(get, set),
+                    None => Property::Data(value),
+                };
+                (key, property, i < count)
+            })
+            .collect()
+    }
+
+    /// The state an object keeps outside of its properties: what kind of
+    /// object it is, what it wraps, and the slots builtins give it.
+    pub fn internal_slots(&self) -> Vec<(String, Value)> {
+        let o = match self {
+            Value::Object(o) => o,
+            _ => return Vec::new(),
+        };
+        let mut slots = Vec::new();
+        let kind = match &o.kind {
+            ObjectKind::Ordinary => "ordinary",
+            ObjectKind::Array(..) => "array",
+            ObjectKind::Boolean(b) => {
+                slots.push(("value".to_string(), Value::from(*b)));
+                "boolean"
+            }
+            ObjectKind::String(s) => {
+                slots.push((
+                    "value".to_string(),
+                    Value::from(s.iter().collect::<String>()),
+                ));
+                "string"
+            }
+            ObjectKind::Number(n) => {
+                slots.push(("value".to_string(), Value::from(*n)));
+                "number"
+            }
+            ObjectKind::Symbol(s) => {
+                slots.push(("value".to_string(), Value::Symbol(Box::new(s.clone()))));
+                "symbol"
+            }
+            ObjectKind::Regex(re) => {
+                slots.push(("source".to_string(), Value::from(re.to_string())));
+                "regex"
+            }
+            ObjectKind::Buffer(bytes) => {
+                slots.push((
+                    "byteLength".to_string(),
+                    Value::from(bytes.borrow().len() as f64),
+                ));
+                "buffer"
+            }
+            ObjectKind::BytecodeFunction {
+                kind, parameters, ..
+            } => {
+                let flags = [
+                    (FunctionKind::Async, "async"),
+                    (FunctionKind::Generator, "generator"),
+                    (FunctionKind::Arrow, "arrow"),
+                ]
+                .iter()
+                .filter(|(flag, _)| *kind as u8 & *flag as u8 != 0)
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>();
+                let flags = if flags.is_empty() {
+                    "normal".to_string()
+                } else {
+                    flags.join(" ")
+                };
+                slots.push(("functionKind".to_string(), Value::from(flags)));
+                slots.push(("parameters".to_string(), Value::from(parameters.join(", "))));
+                "function"
+            }
+            ObjectKind::BuiltinFunction(_, builtin_slots) | ObjectKind::Custom(builtin_slots) => {
+                let mut builtin_slots = builtin_slots
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<Vec<_>>();
+                builtin_slots.sort_by(|a, b| a.0.cmp(&b.0));
+                slots.extend(builtin_slots);
+                match o.kind {
+                    ObjectKind::Custom(..) => "custom",
+                    _ => "builtin function",
+                }
+            }
+            ObjectKind::Accessor(..) => "accessor",
+        };
+        slots.insert(0, ("kind".to_string(), Value::from(kind)));
+        slots
+    }
+
     pub fn to_object(&self, agent: &Agent) -> Result<Value, Value> {
         match self {
             Value::Null => Err(Value::new_error(agent, "cannot convert null to object")),
             Value::Boolean(b) => Ok(Value::Object(Gc::new(ObjectInfo {
                 kind: ObjectKind::Boolean(*b),
-                properties: GcCell::new(IndexMap::new()),
+                properties: GcCell::new(PropertyMap::new()),
                 prototype: agent.intrinsics.boolean_prototype.clone(),
             }))),
             Value::Object(_) => Ok(self.clone()),
+            Value::Integer(..) => self.clone().canonical().to_object(agent),
             Value::Number(n) => Ok(Value::Object(Gc::new(ObjectInfo {
                 kind: ObjectKind::Number(*n),
-                properties: GcCell::new(IndexMap::new()),
+                properties: GcCell::new(PropertyMap::new()),
                 prototype: agent.intrinsics.number_prototype.clone(),
             }))),
             Value::String(s) => Ok(Value::Object(Gc::new(ObjectInfo {
                 kind: ObjectKind::String(s.chars().collect()),
-                properties: GcCell::new(IndexMap::new()),
+                properties: GcCell::new(PropertyMap::new()),
                 prototype: agent.intrinsics.string_prototype.clone(),
             }))),
             Value::Symbol(s) => Ok(Value::Object(Gc::new(ObjectInfo {
-                kind: ObjectKind::Symbol(s.clone()),
-                properties: GcCell::new(IndexMap::new()),
+                kind: ObjectKind::Symbol((**s).clone()),
+                properties: GcCell::new(PropertyMap::new()),
                 prototype: agent.intrinsics.symbol_prototype.clone(),
             }))),
-            Value::Tuple(_) => Ok(self.clone()),
+            Value::Tuple(_) | Value::Record(_) => Ok(self.clone()),
             _ => unreachable!(),
         }
     }
 
     pub fn to_object_key(&self, agent: &Agent) -> Result<ObjectKey, Value> {
         match self {
-            Value::Symbol(s) => Ok(ObjectKey::Symbol(s.clone())),
+            Value::Symbol(s) => Ok(ObjectKey::Symbol((**s).clone())),
             Value::String(s) => Ok(ObjectKey::from(s.to_string())),
             Value::Number(n) => Ok(ObjectKey::from(*n)),
+            Value::Integer(n) if *n >= 0 => Ok(ObjectKey::Number(*n as usize)),
+            Value::Integer(n) => Ok(ObjectKey::from(f64::from(*n))),
             _ => Err(Value::new_error(agent, "cannot convert to object key")),
         }
     }
@@ -856,14 +1227,14 @@ impl Value {
         let iterator = self.get(agent, ObjectKey::well_known_symbol("iterator"))?;
         let iterator = iterator.call(agent, self.clone(), vec![])?;
         let next = iterator.get(agent, ObjectKey::from("next"))?;
-        Ok(Value::Iterator(Box::new(iterator), Box::new(next)))
+        Ok(Value::Iterator(Box::new((iterator, next))))
     }
 
     pub fn to_async_iterator(&self, agent: &Agent) -> Result<Value, Value> {
         let iterator = self.get(agent, ObjectKey::well_known_symbol("asyncIterator"))?;
         let iterator = iterator.call(agent, self.clone(), vec![])?;
         let next = iterator.get(agent, ObjectKey::from("next"))?;
-        Ok(Value::Iterator(Box::new(iterator), Box::new(next)))
+        Ok(Value::Iterator(Box::new((iterator, next))))
     }
 
     pub fn call(&self, agent: &Agent, this: Value, args: Vec<Value>) -> Result<Value, Value> {
@@ -874,11 +1245,11 @@ impl Value {
                     kind,
                     scope,
                     parameters,
-                    ..
+                    length,
                 } => {
                     let ctx = Context::new(Scope::new(Some(scope.clone())));
                     if *kind & FunctionKind::Arrow == FunctionKind::Arrow {
-                        // FIXME: doesn't have `this` vs inherited `this` needs to be clarified
+                        // arrows take `this` from the scope they were created in
                     } else {
                         ctx.borrow().scope.borrow_mut().this = Some(if this == Value::Null {
                             Value::Null
@@ -887,18 +1258,11 @@ impl Value {
                         });
                     }
                     ctx.borrow_mut().function = Some(self.clone());
-                    evaluate_body(agent, ctx, *position, *kind, args, parameters)
+                    evaluate_body(agent, ctx, *position, *kind, args, parameters, *length)
                 }
                 ObjectKind::BuiltinFunction(f, ..) => {
-                    let c = Context::new(Scope::new(None));
-                    let mut b = c.borrow_mut();
-                    b.scope.borrow_mut().this = Some(if this == Value::Null {
-                        Value::Null
-                    } else {
-                        this.to_object(agent)?
-                    });
-                    b.function = Some(self.clone());
-                    f(agent, args, &b)
+                    agent.check_capability(self, &args)?;
+                    self.with_builtin_context(agent, this, |c| f.invoke(agent, args, c))
                 }
                 _ => Err(Value::new_error(agent, "value is not a function")),
             },
@@ -906,6 +1270,38 @@ impl Value {
         }
     }
 
+    /// Call a `Builtin::Slice` function on arguments that are already
+    /// canonical, the interpreter's allocation-free path.
+    pub(crate) fn call_slice(
+        &self,
+        agent: &Agent,
+        f: SliceBuiltinFunction,
+        this: Value,
+        args: &[Value],
+    ) -> Result<Value, Value> {
+        agent.check_capability(self, args)?;
+        self.with_builtin_context(agent, this, |c| f(agent, args, c))
+    }
+
+    // builtins never keep their context, so one is borrowed from the agent
+    // for the call and handed back afterwards
+    fn with_builtin_context(
+        &self,
+        agent: &Agent,
+        this: Value,
+        f: impl FnOnce(&Context) -> Result<Value, Value>,
+    ) -> Result<Value, Value> {
+        let this = if this == Value::Null {
+            Value::Null
+        } else {
+            this.to_object(agent)?
+        };
+        let c = agent.take_builtin_context(self, this);
+        let result = f(&c.borrow());
+        agent.release_builtin_context(c);
+        result
+    }
+
     pub fn construct(
         &self,
         agent: &Agent,
@@ -919,7 +1315,7 @@ impl Value {
                     kind,
                     scope,
                     parameters,
-                    ..
+                    length,
                 } => {
                     if *kind != FunctionKind::Normal
                         || (*kind & FunctionKind::Arrow == FunctionKind::Arrow)
@@ -934,7 +1330,8 @@ impl Value {
                         let ctx = Context::new(Scope::new(Some(scope.clone())));
                         ctx.borrow().scope.borrow_mut().this = Some(this.clone());
                         ctx.borrow_mut().function = Some(self.clone());
-                        let r = evaluate_body(agent, ctx, *position, *kind, args, parameters)?;
+                        let r =
+                            evaluate_body(agent, ctx, *position, *kind, args, parameters, *length)?;
                         if r.type_of() == "object" {
                             Ok(r)
                         } else {
@@ -952,7 +1349,7 @@ impl Value {
                     let mut cb = c.borrow_mut();
                     cb.scope.borrow_mut().this = Some(this.clone());
                     cb.function = Some(self.clone());
-                    let r = f(agent, args, &cb)?;
+                    let r = f.invoke(agent, args, &cb)?;
                     if r.type_of() == "object" {
                         Ok(r)
                     } else {
@@ -971,6 +1368,15 @@ impl Value {
     }
 }
 
+fn canonical_args(mut args: Vec<Value>) -> Vec<Value> {
+    for arg in &mut args {
+        if let Value::Integer(..) | Value::Tuple(..) | Value::Record(..) = arg {
+            *arg = std::mem::replace(arg, Value::Null).canonical();
+        }
+    }
+    args
+}
+
 fn evaluate_body(
     agent: &Agent,
     ctx: Gc<GcCell<Context>>,
@@ -978,16 +1384,20 @@ fn evaluate_body(
     kind: FunctionKind,
     args: Vec<Value>,
     params: &[String],
+    length: usize,
 ) -> Result<Value, Value> {
     for (i, param) in params.iter().enumerate() {
         ctx.borrow()
             .scope
             .borrow_mut()
             .create(agent, param, false)?;
-        ctx.borrow()
-            .scope
-            .borrow_mut()
-            .initialize(param, args.get(i).unwrap_or(&Value::Empty).clone());
+        let value = match args.get(i) {
+            Some(v) => v.clone(),
+            None if i < length => Value::Null,
+            // filled in by the function's prologue
+            None => Value::Empty,
+        };
+        ctx.borrow().scope.borrow_mut().initialize(param, value);
     }
 
     let mut interpreter = Interpreter::new(position, ctx.clone());
@@ -997,7 +1407,10 @@ fn evaluate_body(
     } else if kind & FunctionKind::Generator == FunctionKind::Generator {
         ctx.borrow_mut().interpreter = Some(interpreter);
         let o = Value::new_custom_object(agent.intrinsics.generator_prototype.clone());
-        o.set_slot("generator context", Value::WrappedContext(ctx, None));
+        o.set_slot(
+            "generator context",
+            Value::WrappedContext(Box::new((ctx, None))),
+        );
         Ok(o)
     } else if kind & FunctionKind::Async == FunctionKind::Async {
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
@@ -1019,7 +1432,7 @@ fn evaluate_body(
                 let value = std::mem::replace(&mut c.0, Value::Null);
                 perform_await(
                     agent,
-                    Value::WrappedContext(ctx, Some(Box::new(promise.clone()))),
+                    Value::WrappedContext(Box::new((ctx, Some(promise.clone())))),
                     value,
                 )?;
             }
@@ -1050,9 +1463,9 @@ impl PartialEq for Value {
                 Value::String(vs) => s == vs,
                 _ => false,
             },
-            Value::Number(n) => match &other {
-                Value::Number(vn) => n == vn,
-                _ => false,
+            Value::Number(..) | Value::Integer(..) => match other.as_f64() {
+                Some(vn) => self.as_f64() == Some(vn),
+                None => false,
             },
             Value::Symbol(s) => match &other {
                 Value::Symbol(vs) => s == vs,
@@ -1068,6 +1481,10 @@ impl PartialEq for Value {
                 }
                 _ => false,
             },
+            Value::Record(fields) => match &other {
+                Value::Record(vfields) => fields == vfields,
+                _ => false,
+            },
             Value::Empty => match other {
                 Value::Empty => true,
                 _ => false,
@@ -1094,9 +1511,9 @@ impl Hash for Value {
                 2.hash(state);
                 s.hash(state);
             }
-            Value::Number(n) => {
+            Value::Number(..) | Value::Integer(..) => {
                 3.hash(state);
-                n.to_bits().hash(state);
+                self.as_f64().unwrap().to_bits().hash(state);
             }
             Value::Symbol(s) => {
                 4.hash(state);
@@ -1105,12 +1522,16 @@ impl Hash for Value {
             Value::Object(o) => {
                 5.hash(state);
                 // hash the memory address of the map sigh
-                (&*o.properties.borrow() as *const IndexMap<ObjectKey, Value>).hash(state);
+                (&*o.properties.borrow() as *const PropertyMap).hash(state);
             }
             Value::Tuple(items) => {
                 6.hash(state);
                 items.hash(state);
             }
+            Value::Record(fields) => {
+                7.hash(state);
+                fields.hash(state);
+            }
             _ => unreachable!(),
         }
     }
@@ -1118,13 +1539,13 @@ impl Hash for Value {
 
 impl From<&str> for Value {
     fn from(s: &str) -> Self {
-        Value::String(s.to_string())
+        Value::String(Rc::new(s.to_string()))
     }
 }
 
 impl From<String> for Value {
     fn from(s: String) -> Self {
-        Value::String(s)
+        Value::String(Rc::new(s))
     }
 }
 
@@ -1155,7 +1576,7 @@ impl From<&ObjectKey> for Value {
         match k {
             ObjectKey::Number(n) => Value::from(n.to_string()),
             ObjectKey::String(s) => Value::from(s.to_string()),
-            ObjectKey::Symbol(s) => Value::Symbol(s.clone()),
+            ObjectKey::Symbol(s) => Value::Symbol(Box::new(s.clone())),
         }
     }
 }
@@ -1172,25 +1593,66 @@ impl IntoValue for std::io::Error {
     }
 }
 
+/// An own property as `Value::own_properties` finds it.
+#[derive(Debug, Clone)]
+pub enum Property {
+    Data(Value),
+    /// The getter and setter, null where one is missing.
+    Accessor(Value, Value),
+}
+
+fn record_field(fields: &[(String, Value)], key: &ObjectKey) -> Option<Value> {
+    if let ObjectKey::Symbol(..) = key {
+        return None;
+    }
+    let name = key.to_string();
+    fields
+        .binary_search_by(|(n, _)| n.as_str().cmp(&name))
+        .ok()
+        .map(|i| fields[i].1.clone())
+}
+
+fn accessor(value: &Value) -> Option<(Value, Value)> {
+    match value {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Accessor(get, set) => Some((get.clone(), set.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 fn inspect(
     agent: &Agent,
     value: &Value,
     indent: usize,
-    inspected: &mut HashSet<*const IndexMap<ObjectKey, Value>>,
+    inspected: &mut HashSet<*const PropertyMap>,
 ) -> String {
     match value {
         Value::Null => "null".to_string(),
         Value::Boolean(b) => b.to_string(),
         Value::Number(n) => crate::num_util::to_string(*n),
+        Value::Integer(n) => n.to_string(),
         Value::String(s) => format!("'{}'", s),
         Value::Symbol(s) => format!("{}", s),
         Value::Tuple(items) => {
             let mut ins = Vec::new();
-            for item in items {
+            for item in items.iter() {
                 ins.push(inspect(agent, item, indent, inspected));
             }
             format!("({})", ins.join(", "))
         }
+        Value::Record(fields) => {
+            let mut ins = Vec::new();
+            for (name, value) in fields.iter() {
+                ins.push(format!(
+                    "{}: {}",
+                    name,
+                    inspect(agent, value, indent, inspected)
+                ));
+            }
+            format!("#{{{}}}", ins.join(", "))
+        }
         Value::Object(o) => {
             if let ObjectKind::Regex(re) = &o.kind {
                 return format!("/{}/", re);
@@ -1201,10 +1663,10 @@ fn inspect(
                     value.clone(),
                     vec![],
                 ) {
-                    return s;
+                    return s.to_string();
                 }
             }
-            let hash_key = &*o.properties.borrow() as *const IndexMap<ObjectKey, Value>;
+            let hash_key = &*o.properties.borrow() as *const PropertyMap;
             if inspected.contains(&hash_key) {
                 "[Circular]".to_string()
             } else {